@@ -174,6 +174,8 @@ pub enum AddressType {
     P2wsh,
     /// Pay to taproot.
     P2tr,
+    /// Pay to anchor.
+    P2a,
 }
 
 impl fmt::Display for AddressType {
@@ -184,6 +186,7 @@ impl fmt::Display for AddressType {
             AddressType::P2wpkh => "p2wpkh",
             AddressType::P2wsh => "p2wsh",
             AddressType::P2tr => "p2tr",
+            AddressType::P2a => "p2a",
         })
     }
 }
@@ -197,6 +200,7 @@ impl FromStr for AddressType {
             "p2wpkh" => Ok(AddressType::P2wpkh),
             "p2wsh" => Ok(AddressType::P2wsh),
             "p2tr" => Ok(AddressType::P2tr),
+            "p2a" => Ok(AddressType::P2a),
             _ => Err(Error::UnknownAddressType(s.to_owned())),
         }
     }
@@ -403,6 +407,9 @@ pub enum Payload {
     WitnessProgram(WitnessProgram),
 }
 
+/// The witness program of P2A (pay to anchor) outputs, following witness version 1.
+pub(crate) const P2A_PROGRAM: [u8; 2] = [0x4e, 0x73];
+
 /// Witness program as defined in BIP141.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct WitnessProgram {
@@ -438,6 +445,16 @@ impl WitnessProgram {
 
     /// Returns the witness program.
     pub fn program(&self) -> &PushBytes { &self.program }
+
+    /// Creates the witness program of a P2A (pay to anchor) output.
+    pub fn p2a() -> Self {
+        WitnessProgram { version: WitnessVersion::V1, program: P2A_PROGRAM.into() }
+    }
+
+    /// Returns true if this is the witness program of a P2A (pay to anchor) output.
+    pub fn is_p2a(&self) -> bool {
+        self.version == WitnessVersion::V1 && self.program.as_bytes() == P2A_PROGRAM
+    }
 }
 
 impl Payload {
@@ -551,6 +568,12 @@ impl Payload {
         Payload::WitnessProgram(prog)
     }
 
+    /// Create a pay to anchor payload.
+    ///
+    /// P2A outputs can be spent by anyone with an empty witness, they are used to bump the fee of
+    /// the transaction creating them with a child spending them (CPFP).
+    pub fn p2a() -> Payload { Payload::WitnessProgram(WitnessProgram::p2a()) }
+
     /// Returns a byte slice of the inner program of the payload. If the payload
     /// is a script hash or pubkey hash, a reference to the hash is returned.
     fn inner_prog_as_bytes(&self) -> &[u8] {
@@ -789,6 +812,7 @@ impl<V: NetworkValidation> Address<V> {
                         ),
                     },
                     WitnessVersion::V1 if prog.program().len() == 32 => Some(AddressType::P2tr),
+                    WitnessVersion::V1 if prog.is_p2a() => Some(AddressType::P2a),
                     _ => None,
                 }
             }
@@ -799,15 +823,17 @@ impl<V: NetworkValidation> Address<V> {
     fn fmt_internal(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         let p2pkh_prefix = match self.network {
             Network::Bitcoin => PUBKEY_ADDRESS_PREFIX_MAIN,
-            Network::Testnet | Network::Signet | Network::Regtest => PUBKEY_ADDRESS_PREFIX_TEST,
+            Network::Testnet | Network::Testnet4 | Network::Signet | Network::Regtest =>
+                PUBKEY_ADDRESS_PREFIX_TEST,
         };
         let p2sh_prefix = match self.network {
             Network::Bitcoin => SCRIPT_ADDRESS_PREFIX_MAIN,
-            Network::Testnet | Network::Signet | Network::Regtest => SCRIPT_ADDRESS_PREFIX_TEST,
+            Network::Testnet | Network::Testnet4 | Network::Signet | Network::Regtest =>
+                SCRIPT_ADDRESS_PREFIX_TEST,
         };
         let bech32_hrp = match self.network {
             Network::Bitcoin => "bc",
-            Network::Testnet | Network::Signet => "tb",
+            Network::Testnet | Network::Testnet4 | Network::Signet => "tb",
             Network::Regtest => "bcrt",
         };
         let encoding =
@@ -894,6 +920,11 @@ impl Address {
         Address::new(network, Payload::p2tr_tweaked(output_key))
     }
 
+    /// Creates a pay to anchor address.
+    ///
+    /// See [`Payload::p2a`] for details.
+    pub fn p2a(network: Network) -> Address { Address::new(network, Payload::p2a()) }
+
     /// Gets the address type of the address.
     ///
     /// # Returns
@@ -1011,6 +1042,7 @@ impl Address<NetworkUnchecked> {
     ///
     /// let address: Address<NetworkUnchecked> = "2N83imGV3gPwBzKJQvWJ7cRUY2SpUyU6A5e".parse().unwrap();
     /// assert!(address.is_valid_for_network(Network::Testnet));
+    /// assert!(address.is_valid_for_network(Network::Testnet4));
     /// assert!(address.is_valid_for_network(Network::Regtest));
     /// assert!(address.is_valid_for_network(Network::Signet));
     ///
@@ -1030,7 +1062,10 @@ impl Address<NetworkUnchecked> {
             (a, b) if a == b => true,
             (Network::Bitcoin, _) | (_, Network::Bitcoin) => false,
             (Network::Regtest, _) | (_, Network::Regtest) if !is_legacy => false,
-            (Network::Testnet, _) | (Network::Regtest, _) | (Network::Signet, _) => true,
+            (Network::Testnet, _)
+            | (Network::Testnet4, _)
+            | (Network::Regtest, _)
+            | (Network::Signet, _) => true,
         }
     }
 
@@ -1585,10 +1620,15 @@ mod tests {
             })
             .collect::<Vec<_>>();
 
-        const LEGACY_EQUIVALENCE_CLASSES: &[&[Network]] =
-            &[&[Network::Bitcoin], &[Network::Testnet, Network::Regtest, Network::Signet]];
-        const SEGWIT_EQUIVALENCE_CLASSES: &[&[Network]] =
-            &[&[Network::Bitcoin], &[Network::Regtest], &[Network::Testnet, Network::Signet]];
+        const LEGACY_EQUIVALENCE_CLASSES: &[&[Network]] = &[
+            &[Network::Bitcoin],
+            &[Network::Testnet, Network::Testnet4, Network::Regtest, Network::Signet],
+        ];
+        const SEGWIT_EQUIVALENCE_CLASSES: &[&[Network]] = &[
+            &[Network::Bitcoin],
+            &[Network::Regtest],
+            &[Network::Testnet, Network::Testnet4, Network::Signet],
+        ];
 
         fn test_addr_type(payloads: &[Payload], equivalence_classes: &[&[Network]]) {
             for pl in payloads {
@@ -1635,6 +1675,27 @@ mod tests {
         roundtrips(&address);
     }
 
+    #[test]
+    fn p2a() {
+        let address = Address::p2a(Network::Bitcoin);
+        assert_eq!(address.to_string(), "bc1pfeessrawgf");
+        assert_eq!(address.address_type(), Some(AddressType::P2a));
+        assert!(address.is_spend_standard());
+        assert_eq!(address.script_pubkey(), ScriptBuf::new_p2a());
+        assert_eq!(Address::from_script(&ScriptBuf::new_p2a(), Network::Bitcoin).unwrap(), address);
+        assert_eq!(Address::p2a(Network::Testnet).to_string(), "tb1pfees9rn5nz");
+        assert_eq!(Address::p2a(Network::Testnet4).to_string(), "tb1pfees9rn5nz");
+        assert_eq!(Address::p2a(Network::Regtest).to_string(), "bcrt1pfeesnyr2tx");
+        roundtrips(&address);
+
+        let program = WitnessProgram::new(WitnessVersion::V1, vec![0x4e, 0x74]).unwrap();
+        assert!(!program.is_p2a());
+        assert_eq!(
+            Address::new(Network::Bitcoin, Payload::WitnessProgram(program)).address_type(),
+            None
+        );
+    }
+
     #[test]
     fn test_is_related_to_pubkey_p2wpkh() {
         let address_string = "bc1qhvd6suvqzjcu9pxjhrwhtrlj85ny3n2mqql5w4";
@@ -1788,6 +1849,8 @@ mod tests {
     fn valid_address_parses_correctly() {
         let addr = AddressType::from_str("p2tr").expect("false negative while parsing address");
         assert_eq!(addr, AddressType::P2tr);
+        let addr = AddressType::from_str("p2a").expect("false negative while parsing address");
+        assert_eq!(addr, AddressType::P2a);
     }
 
     #[test]
@@ -640,7 +640,8 @@ impl ExtendedPrivKey {
         ret[0..4].copy_from_slice(
             &match self.network {
                 Network::Bitcoin => [0x04, 0x88, 0xAD, 0xE4],
-                Network::Testnet | Network::Signet | Network::Regtest => [0x04, 0x35, 0x83, 0x94],
+                Network::Testnet | Network::Testnet4 | Network::Signet | Network::Regtest =>
+                    [0x04, 0x35, 0x83, 0x94],
             }[..],
         );
         ret[4] = self.depth;
@@ -776,7 +777,8 @@ impl ExtendedPubKey {
         ret[0..4].copy_from_slice(
             &match self.network {
                 Network::Bitcoin => [0x04u8, 0x88, 0xB2, 0x1E],
-                Network::Testnet | Network::Signet | Network::Regtest => [0x04u8, 0x35, 0x87, 0xCF],
+                Network::Testnet | Network::Testnet4 | Network::Signet | Network::Regtest =>
+                    [0x04u8, 0x35, 0x87, 0xCF],
             }[..],
         );
         ret[4] = self.depth;
@@ -12,15 +12,18 @@
 use core::fmt;
 
 use super::Weight;
+use crate::blockdata::constants::{
+    MAX_BLOCK_SIGOPS_COST, MAX_BLOCK_WEIGHT, MAX_MONEY, WITNESS_SCALE_FACTOR,
+};
 use crate::blockdata::script;
 use crate::blockdata::transaction::Transaction;
-use crate::consensus::{encode, Decodable, Encodable};
+use crate::consensus::{encode, Decodable, Encodable, Params};
 use crate::error::Error::{self, BlockBadProofOfWork, BlockBadTarget};
 pub use crate::hash_types::BlockHash;
 use crate::hash_types::{TxMerkleNode, WitnessCommitment, WitnessMerkleNode, Wtxid};
-use crate::hashes::{Hash, HashEngine};
+use crate::hashes::{sha256d, Hash, HashEngine};
 use crate::internal_macros::impl_consensus_encoding;
-use crate::pow::{CompactTarget, Target, Work};
+use crate::pow::{max_target, CompactTarget, Target, Work};
 use crate::prelude::*;
 use crate::{io, merkle_tree, VarInt};
 
@@ -337,6 +340,129 @@ impl Block {
             _ => Err(Bip34Error::NotPresent),
         }
     }
+
+    /// Checks the consensus rules which don't depend on the chain, like `CheckBlock` of Bitcoin
+    /// Core.
+    ///
+    /// These are:
+    ///
+    /// - the header bits encode a positive target not exceeding the proof of work limit of
+    ///   `params` and the block hash meets it,
+    /// - the merkle root commits to the transactions, which contain no duplicates mutating the
+    ///   merkle tree without changing its root (CVE-2012-2459),
+    /// - the block contains transactions, doesn't exceed [`MAX_BLOCK_WEIGHT`] and its legacy
+    ///   sigops don't exceed [`MAX_BLOCK_SIGOPS_COST`],
+    /// - the first transaction, and only the first, is a coinbase transaction,
+    /// - each transaction passes `CheckTransaction`: it has inputs and outputs, output values
+    ///   don't exceed [`MAX_MONEY`], inputs are unique, the coinbase scriptSig has between 2 and
+    ///   100 bytes and other inputs don't spend the null outpoint.
+    ///
+    /// The witness commitment isn't checked, see [`Self::check_witness_commitment`].
+    ///
+    /// [`MAX_BLOCK_WEIGHT`]: crate::blockdata::constants::MAX_BLOCK_WEIGHT
+    /// [`MAX_BLOCK_SIGOPS_COST`]: crate::blockdata::constants::MAX_BLOCK_SIGOPS_COST
+    /// [`MAX_MONEY`]: crate::blockdata::constants::MAX_MONEY
+    pub fn validate_context_free(&self, params: &Params) -> Result<(), ValidationError> {
+        let target = checked_target(self.header.bits, params).ok_or(ValidationError::BadTarget)?;
+        if !target.is_met_by(self.block_hash()) {
+            return Err(ValidationError::BadProofOfWork);
+        }
+
+        if !self.check_merkle_root() && !self.txdata.is_empty() {
+            return Err(ValidationError::BadMerkleRoot);
+        }
+        if self.is_merkle_tree_mutated() {
+            return Err(ValidationError::DuplicateTransactions);
+        }
+
+        let max_weight = MAX_BLOCK_WEIGHT as usize;
+        if self.txdata.is_empty() {
+            return Err(ValidationError::NoTransactions);
+        }
+        if self.txdata.len() * WITNESS_SCALE_FACTOR > max_weight
+            || self.strippedsize() * WITNESS_SCALE_FACTOR > max_weight
+        {
+            return Err(ValidationError::TooLarge);
+        }
+        let weight = self.weight();
+        if weight.to_wu() > u64::from(MAX_BLOCK_WEIGHT) {
+            return Err(ValidationError::Overweight { weight });
+        }
+
+        if !self.txdata[0].is_coin_base() {
+            return Err(ValidationError::MissingCoinbase);
+        }
+        if let Some(index) = self.txdata.iter().skip(1).position(Transaction::is_coin_base) {
+            return Err(ValidationError::MultipleCoinbases { index: index + 1 });
+        }
+
+        for (index, tx) in self.txdata.iter().enumerate() {
+            check_transaction(index, tx)?;
+        }
+
+        let sigops = self.txdata.iter().map(Transaction::legacy_sigop_count).sum::<usize>();
+        if sigops * WITNESS_SCALE_FACTOR > MAX_BLOCK_SIGOPS_COST as usize {
+            return Err(ValidationError::TooManySigops { sigops });
+        }
+        Ok(())
+    }
+
+    /// Returns true if two equal hashes are merged at any level of the merkle tree, like the
+    /// `mutated` flag of `ComputeMerkleRoot` in Bitcoin Core.
+    fn is_merkle_tree_mutated(&self) -> bool {
+        let mut hashes = self.txdata.iter().map(|tx| tx.txid().to_raw_hash()).collect::<Vec<_>>();
+        while hashes.len() > 1 {
+            if hashes.chunks(2).any(|pair| pair.len() == 2 && pair[0] == pair[1]) {
+                return true;
+            }
+            hashes = hashes
+                .chunks(2)
+                .map(|pair| {
+                    let mut engine = sha256d::Hash::engine();
+                    engine.input(pair[0].as_ref());
+                    engine.input(pair[pair.len() - 1].as_ref());
+                    sha256d::Hash::from_engine(engine)
+                })
+                .collect();
+        }
+        false
+    }
+}
+
+/// Checks the transaction at `index` of a block, like `CheckTransaction` of Bitcoin Core.
+pub(crate) fn check_transaction(index: usize, tx: &Transaction) -> Result<(), ValidationError> {
+    if tx.input.is_empty() {
+        return Err(ValidationError::NoInputs { index });
+    }
+    if tx.output.is_empty() {
+        return Err(ValidationError::NoOutputs { index });
+    }
+    if tx.strippedsize() * WITNESS_SCALE_FACTOR > MAX_BLOCK_WEIGHT as usize {
+        return Err(ValidationError::TransactionTooLarge { index });
+    }
+
+    let mut total: u64 = 0;
+    for output in &tx.output {
+        total = total.saturating_add(output.value);
+        if output.value > MAX_MONEY || total > MAX_MONEY {
+            return Err(ValidationError::OutputValueTooLarge { index });
+        }
+    }
+
+    let mut outpoints = BTreeSet::new();
+    if !tx.input.iter().all(|input| outpoints.insert(input.previous_output)) {
+        return Err(ValidationError::DuplicateInputs { index });
+    }
+
+    if tx.is_coin_base() {
+        let len = tx.input[0].script_sig.len();
+        if !(2..=100).contains(&len) {
+            return Err(ValidationError::BadCoinbaseLength);
+        }
+    } else if tx.input.iter().any(|input| input.previous_output.is_null()) {
+        return Err(ValidationError::NullPrevout { index });
+    }
+    Ok(())
 }
 
 /// An error when looking up a BIP34 block height.
@@ -378,6 +504,142 @@ impl std::error::Error for Bip34Error {
     }
 }
 
+/// Decodes the header bits like `CheckProofOfWork` of Bitcoin Core.
+///
+/// Returns `None` if the target is negative, overflows 256 bits, is zero or exceeds the proof of
+/// work limit.
+fn checked_target(bits: CompactTarget, params: &Params) -> Option<Target> {
+    let bits = bits.to_consensus();
+    let size = bits >> 24;
+    let mut word = bits & 0x007f_ffff;
+    if size <= 3 {
+        word >>= 8 * (3 - size);
+    }
+    let negative = word != 0 && bits & 0x0080_0000 != 0;
+    let overflow = word != 0
+        && (size > 34 || (word > 0xff && size > 33) || (word > 0xffff && size > 32));
+    if negative || overflow {
+        return None;
+    }
+    // Clears the sign bit, it may be set while a small exponent shifts the mantissa to zero.
+    let target = Target::from_compact(CompactTarget::from_consensus(bits & !0x0080_0000));
+    if target == Target::ZERO || target > max_target(params) {
+        return None;
+    }
+    Some(target)
+}
+
+/// A block breaking a consensus rule checked by [`Block::validate_context_free`].
+///
+/// The variants document the reject reason used by Bitcoin Core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ValidationError {
+    /// The header bits encode a negative, overflowing or zero target, or one above the proof of
+    /// work limit (`high-hash`).
+    BadTarget,
+    /// The block hash doesn't meet the target encoded in the header bits (`high-hash`).
+    BadProofOfWork,
+    /// The merkle root in the header doesn't match the transactions (`bad-txnmrklroot`).
+    BadMerkleRoot,
+    /// Duplicate transactions mutate the merkle tree without changing its root
+    /// (`bad-txns-duplicate`).
+    DuplicateTransactions,
+    /// The block contains no transactions (`bad-blk-length`).
+    NoTransactions,
+    /// The block has too many transactions or is too large without witnesses (`bad-blk-length`).
+    TooLarge,
+    /// The block exceeds the maximum weight (`bad-blk-weight`).
+    Overweight {
+        /// The weight of the block.
+        weight: Weight,
+    },
+    /// The first transaction isn't a coinbase transaction (`bad-cb-missing`).
+    MissingCoinbase,
+    /// A transaction other than the first one is a coinbase transaction (`bad-cb-multiple`).
+    MultipleCoinbases {
+        /// The index of the offending transaction.
+        index: usize,
+    },
+    /// The legacy sigops of the block exceed the limit (`bad-blk-sigops`).
+    TooManySigops {
+        /// The number of legacy sigops in the block.
+        sigops: usize,
+    },
+    /// A transaction has no inputs (`bad-txns-vin-empty`).
+    NoInputs {
+        /// The index of the offending transaction.
+        index: usize,
+    },
+    /// A transaction has no outputs (`bad-txns-vout-empty`).
+    NoOutputs {
+        /// The index of the offending transaction.
+        index: usize,
+    },
+    /// A transaction is too large without witnesses (`bad-txns-oversize`).
+    TransactionTooLarge {
+        /// The index of the offending transaction.
+        index: usize,
+    },
+    /// An output value or the sum of the output values of a transaction exceeds the money supply
+    /// (`bad-txns-vout-toolarge` or `bad-txns-txouttotal-toolarge`).
+    OutputValueTooLarge {
+        /// The index of the offending transaction.
+        index: usize,
+    },
+    /// A transaction spends an outpoint twice (`bad-txns-inputs-duplicate`).
+    DuplicateInputs {
+        /// The index of the offending transaction.
+        index: usize,
+    },
+    /// The scriptSig of the coinbase transaction has less than 2 or more than 100 bytes
+    /// (`bad-cb-length`).
+    BadCoinbaseLength,
+    /// A transaction other than the coinbase transaction spends the null outpoint
+    /// (`bad-txns-prevout-null`).
+    NullPrevout {
+        /// The index of the offending transaction.
+        index: usize,
+    },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ValidationError::*;
+
+        match *self {
+            BadTarget => f.write_str("header bits encode an invalid target"),
+            BadProofOfWork => f.write_str("block hash doesn't meet the target"),
+            BadMerkleRoot => f.write_str("merkle root doesn't match the transactions"),
+            DuplicateTransactions => f.write_str("duplicate transactions mutate the merkle tree"),
+            NoTransactions => f.write_str("block contains no transactions"),
+            TooLarge => f.write_str("block size limits exceeded"),
+            Overweight { weight } => write!(f, "block weight {} exceeds the limit", weight),
+            MissingCoinbase => f.write_str("first transaction is not a coinbase transaction"),
+            MultipleCoinbases { index } =>
+                write!(f, "transaction {} is a second coinbase transaction", index),
+            TooManySigops { sigops } => write!(f, "{} legacy sigops exceed the limit", sigops),
+            NoInputs { index } => write!(f, "transaction {} has no inputs", index),
+            NoOutputs { index } => write!(f, "transaction {} has no outputs", index),
+            TransactionTooLarge { index } =>
+                write!(f, "transaction {} exceeds the size limit", index),
+            OutputValueTooLarge { index } =>
+                write!(f, "output values of transaction {} exceed the money supply", index),
+            DuplicateInputs { index } =>
+                write!(f, "transaction {} spends an outpoint twice", index),
+            BadCoinbaseLength => f.write_str("coinbase scriptSig length out of range"),
+            NullPrevout { index } =>
+                write!(f, "transaction {} spends the null outpoint", index),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+impl std::error::Error for ValidationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
+}
+
 impl From<Header> for BlockHash {
     fn from(header: Header) -> BlockHash { header.block_hash() }
 }
@@ -397,9 +659,11 @@ impl From<&Block> for BlockHash {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::blockdata::script::ScriptBuf;
     use crate::consensus::encode::{deserialize, serialize};
     use crate::hashes::hex::FromHex;
     use crate::internal_macros::hex;
+    use crate::Network;
 
     #[test]
     fn test_coinbase_and_bip34() {
@@ -503,6 +767,113 @@ mod tests {
         assert_eq!(serialize(&real_decode), segwit_block);
     }
 
+    #[test]
+    fn validate_context_free() {
+        use crate::blockdata::transaction::{OutPoint, TxIn, TxOut};
+        use crate::hash_types::Txid;
+
+        let raw = hex!("010000004ddccd549d28f385ab457e98d1b11ce80bfea2c5ab93015ade4973e400000000bf4473e53794beae34e64fccc471dace6ae544180816f89591894e0f417a914cd74d6e49ffff001d323b3a7b0201000000010000000000000000000000000000000000000000000000000000000000000000ffffffff0804ffff001d026e04ffffffff0100f2052a0100000043410446ef0102d1ec5240f0d061a4246c1bdef63fc3dbab7733052fbbf0ecd8f41fc26bf049ebb4f9527f374280259e7cfa99c48b0e3f39c51347a19a5819651503a5ac00000000010000000321f75f3139a013f50f315b23b0c9a2b6eac31e2bec98e5891c924664889942260000000049483045022100cb2c6b346a978ab8c61b18b5e9397755cbd17d6eb2fe0083ef32e067fa6c785a02206ce44e613f31d9a6b0517e46f3db1576e9812cc98d159bfdaf759a5014081b5c01ffffffff79cda0945903627c3da1f85fc95d0b8ee3e76ae0cfdc9a65d09744b1f8fc85430000000049483045022047957cdd957cfd0becd642f6b84d82f49b6cb4c51a91f49246908af7c3cfdf4a022100e96b46621f1bffcf5ea5982f88cef651e9354f5791602369bf5a82a6cd61a62501fffffffffe09f5fe3ffbf5ee97a54eb5e5069e9da6b4856ee86fc52938c2f979b0f38e82000000004847304402204165be9a4cbab8049e1af9723b96199bfd3e85f44c6b4c0177e3962686b26073022028f638da23fc003760861ad481ead4099312c60030d4cb57820ce4d33812a5ce01ffffffff01009d966b01000000434104ea1feff861b51fe3f5f8a3b12d0f4712db80e919548a80839fc47c6a21e66d957e9c5d8cd108c7a2d2324bad71f9904ac0ae7336507d785b17a2c115e427a32fac00000000");
+        let mut block: Block = deserialize(&raw).unwrap();
+        let params = Params::new(Network::Bitcoin);
+        assert_eq!(block.validate_context_free(&params), Ok(()));
+        block.header.nonce += 1;
+        assert_eq!(block.validate_context_free(&params), Err(ValidationError::BadProofOfWork));
+
+        // Overflowing, negative, zero and above the proof of work limit of mainnet.
+        for bits in &[0x2200ffff, 0xff123456, 0x04923456, 0x1d000000, 0x01803456, 0x1d010000] {
+            block.header.bits = CompactTarget::from_consensus(*bits);
+            assert_eq!(block.validate_context_free(&params), Err(ValidationError::BadTarget));
+        }
+
+        let raw = include_bytes!("../../tests/data/testnet_block_000000000000045e0b1660b6445b5e5c5ab63c9a4f956be7e1e69be04fa4497b.raw");
+        let mut block: Block = deserialize(raw).unwrap();
+        let params = Params::new(Network::Testnet);
+        assert_eq!(block.validate_context_free(&params), Ok(()));
+        // Duplicating the last of an odd number of transactions keeps the merkle root.
+        let last = block.txdata.last().unwrap().clone();
+        block.txdata.push(last);
+        assert!(block.check_merkle_root());
+        assert_eq!(
+            block.validate_context_free(&params),
+            Err(ValidationError::DuplicateTransactions)
+        );
+        block.txdata.swap(1, 2);
+        assert_eq!(block.validate_context_free(&params), Err(ValidationError::BadMerkleRoot));
+
+        // Mines a block with an easy target after updating the merkle root.
+        let mine = |txdata: Vec<Transaction>| {
+            let mut block = Block {
+                header: Header {
+                    version: Version::TWO,
+                    prev_blockhash: BlockHash::all_zeros(),
+                    merkle_root: TxMerkleNode::all_zeros(),
+                    time: 0,
+                    bits: CompactTarget::from_consensus(0x207fffff),
+                    nonce: 0,
+                },
+                txdata,
+            };
+            if let Some(merkle_root) = block.compute_merkle_root() {
+                block.header.merkle_root = merkle_root;
+            }
+            while !block.header.target().is_met_by(block.block_hash()) {
+                block.header.nonce += 1;
+            }
+            block.validate_context_free(&Params::new(Network::Regtest))
+        };
+        let input = |txid: Txid, script_sig: &[u8]| TxIn {
+            previous_output: OutPoint::new(txid, u32::max_value()),
+            script_sig: ScriptBuf::from_bytes(script_sig.to_vec()),
+            ..Default::default()
+        };
+        let tx = |input: Vec<TxIn>, value: u64| Transaction {
+            version: 1,
+            lock_time: crate::absolute::LockTime::ZERO,
+            input,
+            output: vec![TxOut { value, script_pubkey: ScriptBuf::new() }],
+        };
+        let coinbase = tx(vec![input(Txid::all_zeros(), &[1, 2])], 50);
+        let spend = tx(vec![input(Txid::hash(&[1]), &[])], 50);
+
+        assert_eq!(mine(vec![coinbase.clone(), spend.clone()]), Ok(()));
+        assert_eq!(mine(vec![]), Err(ValidationError::NoTransactions));
+        assert_eq!(mine(vec![spend.clone()]), Err(ValidationError::MissingCoinbase));
+        let other_coinbase = tx(vec![input(Txid::all_zeros(), &[3, 4])], 50);
+        assert_eq!(
+            mine(vec![coinbase.clone(), spend.clone(), other_coinbase]),
+            Err(ValidationError::MultipleCoinbases { index: 2 })
+        );
+        let short = tx(vec![input(Txid::all_zeros(), &[1])], 50);
+        assert_eq!(mine(vec![short]), Err(ValidationError::BadCoinbaseLength));
+        let null = tx(vec![input(Txid::hash(&[1]), &[]), input(Txid::all_zeros(), &[])], 50);
+        assert_eq!(
+            mine(vec![coinbase.clone(), null]),
+            Err(ValidationError::NullPrevout { index: 1 })
+        );
+        let duplicate = tx(vec![input(Txid::hash(&[1]), &[]), input(Txid::hash(&[1]), &[])], 50);
+        assert_eq!(
+            mine(vec![coinbase.clone(), duplicate]),
+            Err(ValidationError::DuplicateInputs { index: 1 })
+        );
+        let mut no_outputs = spend.clone();
+        no_outputs.output.clear();
+        assert_eq!(
+            mine(vec![coinbase.clone(), no_outputs]),
+            Err(ValidationError::NoOutputs { index: 1 })
+        );
+        let too_much = tx(vec![input(Txid::hash(&[1]), &[])], MAX_MONEY + 1);
+        assert_eq!(
+            mine(vec![coinbase.clone(), too_much]),
+            Err(ValidationError::OutputValueTooLarge { index: 1 })
+        );
+        let mut checksigs = spend;
+        checksigs.output[0].script_pubkey = ScriptBuf::from_bytes(vec![0xac; 20_001]);
+        assert_eq!(
+            mine(vec![coinbase, checksigs]),
+            Err(ValidationError::TooManySigops { sigops: 20_001 })
+        );
+    }
+
     #[test]
     fn block_version_test() {
         let block = hex!("ffffff7f0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000");
@@ -8,6 +8,7 @@
 //! single transaction.
 //!
 
+use core::convert::TryFrom;
 use core::default::Default;
 
 use bitcoin_internals::impl_array_newtype;
@@ -16,7 +17,7 @@ use hex_lit::hex;
 use crate::blockdata::block::{self, Block};
 use crate::blockdata::locktime::absolute;
 use crate::blockdata::opcodes::all::*;
-use crate::blockdata::script;
+use crate::blockdata::script::{self, PushBytes};
 use crate::blockdata::transaction::{OutPoint, Sequence, Transaction, TxIn, TxOut};
 use crate::blockdata::witness::Witness;
 use crate::hashes::{sha256d, Hash};
@@ -64,6 +65,21 @@ pub const MAX_MONEY: u64 = 21_000_000 * COIN_VALUE;
 
 /// Constructs and returns the coinbase (and only) transaction of the Bitcoin genesis block.
 fn bitcoin_genesis_tx() -> Transaction {
+    let pubkey = hex!("04678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5f");
+    genesis_tx(b"The Times 03/Jan/2009 Chancellor on brink of second bailout for banks", &pubkey)
+}
+
+/// Constructs and returns the coinbase (and only) transaction of the testnet4 genesis block.
+fn testnet4_genesis_tx() -> Transaction {
+    genesis_tx(
+        b"03/May/2024 000000000000000000001ebd58c244970b3aa9d783bb001011fbe8ea8e98e00e",
+        &[0; 33],
+    )
+}
+
+/// Constructs and returns a genesis coinbase transaction with the message `timestamp` paying to
+/// `pubkey`.
+fn genesis_tx(timestamp: &[u8], pubkey: &[u8]) -> Transaction {
     // Base
     let mut ret = Transaction {
         version: 1,
@@ -76,7 +92,7 @@ fn bitcoin_genesis_tx() -> Transaction {
     let in_script = script::Builder::new()
         .push_int(486604799)
         .push_int_non_minimal(4)
-        .push_slice(b"The Times 03/Jan/2009 Chancellor on brink of second bailout for banks")
+        .push_slice(<&PushBytes>::try_from(timestamp).expect("short message"))
         .into_script();
     ret.input.push(TxIn {
         previous_output: OutPoint::null(),
@@ -86,9 +102,9 @@ fn bitcoin_genesis_tx() -> Transaction {
     });
 
     // Outputs
-    let script_bytes = hex!("04678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5f");
+    let pubkey = <&PushBytes>::try_from(pubkey).expect("short public key");
     let out_script =
-        script::Builder::new().push_slice(script_bytes).push_opcode(OP_CHECKSIG).into_script();
+        script::Builder::new().push_slice(pubkey).push_opcode(OP_CHECKSIG).into_script();
     ret.output.push(TxOut { value: 50 * COIN_VALUE, script_pubkey: out_script });
 
     // end
@@ -97,7 +113,10 @@ fn bitcoin_genesis_tx() -> Transaction {
 
 /// Constructs and returns the genesis block.
 pub fn genesis_block(network: Network) -> Block {
-    let txdata = vec![bitcoin_genesis_tx()];
+    let txdata = match network {
+        Network::Testnet4 => vec![testnet4_genesis_tx()],
+        _ => vec![bitcoin_genesis_tx()],
+    };
     let hash: sha256d::Hash = txdata[0].txid().into();
     let merkle_root = hash.into();
     match network {
@@ -145,6 +164,17 @@ pub fn genesis_block(network: Network) -> Block {
             },
             txdata,
         },
+        Network::Testnet4 => Block {
+            header: block::Header {
+                version: block::Version::ONE,
+                prev_blockhash: Hash::all_zeros(),
+                merkle_root,
+                time: 1714777860,
+                bits: CompactTarget::from_consensus(0x1d00ffff),
+                nonce: 393743547,
+            },
+            txdata,
+        },
     }
 }
 
@@ -176,13 +206,18 @@ impl ChainHash {
         6, 34, 110, 70, 17, 26, 11, 89, 202, 175, 18, 96, 67, 235, 91, 191, 40, 195, 79, 58, 94,
         51, 42, 31, 199, 178, 183, 60, 241, 136, 145, 15,
     ]);
+    /// `ChainHash` for testnet4 bitcoin.
+    pub const TESTNET4: Self = Self([
+        67, 240, 139, 218, 176, 80, 227, 91, 86, 124, 134, 75, 145, 244, 127, 80, 174, 114, 90,
+        226, 222, 83, 188, 251, 186, 242, 132, 218, 0, 0, 0, 0,
+    ]);
 
     /// Returns the hash of the `network` genesis block for use as a chain hash.
     ///
     /// See [BOLT 0](https://github.com/lightning/bolts/blob/ffeece3dab1c52efdb9b53ae476539320fa44938/00-introduction.md#chain_hash)
     /// for specification.
     pub const fn using_genesis_block(network: Network) -> Self {
-        let hashes = [Self::BITCOIN, Self::TESTNET, Self::SIGNET, Self::REGTEST];
+        let hashes = [Self::BITCOIN, Self::TESTNET, Self::SIGNET, Self::REGTEST, Self::TESTNET4];
         hashes[network as usize]
     }
 }
@@ -275,6 +310,24 @@ mod test {
         );
     }
 
+    #[test]
+    fn testnet4_genesis_full_block() {
+        let gen = genesis_block(Network::Testnet4);
+        assert_eq!(gen.header.version, block::Version::ONE);
+        assert_eq!(gen.header.prev_blockhash, Hash::all_zeros());
+        assert_eq!(
+            gen.header.merkle_root.to_string(),
+            "7aa0a7ae1e223414cb807e40cd57e667b718e42aaf9306db9102fe28912b7b4e"
+        );
+        assert_eq!(gen.header.time, 1714777860);
+        assert_eq!(gen.header.bits, CompactTarget::from_consensus(0x1d00ffff));
+        assert_eq!(gen.header.nonce, 393743547);
+        assert_eq!(
+            gen.header.block_hash().to_string(),
+            "00000000da84f2bafbbc53dee25a72ae507ff4914b867c565be350b0da8bf043"
+        );
+    }
+
     // The *_chain_hash tests are sanity/regression tests, they verify that the const byte array
     // representing the genesis block is the same as that created by hashing the genesis block.
     fn chain_hash_and_genesis_block(network: Network) {
@@ -298,6 +351,7 @@ mod test {
             Network::Testnet => {},
             Network::Signet => {},
             Network::Regtest => {},
+            Network::Testnet4 => {},
             _ => panic!("Update ChainHash::using_genesis_block and chain_hash_genesis_block with new variants"),
         }
     }
@@ -318,6 +372,7 @@ mod test {
         testnet_chain_hash_genesis_block, Network::Testnet;
         signet_chain_hash_genesis_block, Network::Signet;
         regtest_chain_hash_genesis_block, Network::Regtest;
+        testnet4_chain_hash_genesis_block, Network::Testnet4;
     }
 
     // Test vector taken from: https://github.com/lightning/bolts/blob/master/00-introduction.md
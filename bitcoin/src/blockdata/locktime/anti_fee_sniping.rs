@@ -0,0 +1,225 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Discouraging fee sniping.
+//!
+//! A miner could try to reorganize the chain to take the fees of the transactions in the tip
+//! (fee sniping). Wallets discourage this by locking their transactions to the next block, so
+//! they can't be mined in a block replacing the tip. This module chooses lock times the way
+//! Bitcoin Core does, so that transactions don't stand out, and implements the alternative of
+//! [BIP-326] for taproot spends, which uses the relative lock time of an input instead.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[cfg(feature = "rand")] {
+//! use bitcoin::locktime::anti_fee_sniping::{self, SpentOutput};
+//! use bitcoin::{absolute, Sequence, Transaction, TxIn};
+//!
+//! let mut tx = Transaction {
+//!     version: 2,
+//!     lock_time: absolute::LockTime::ZERO,
+//!     input: vec![TxIn { sequence: Sequence::ENABLE_RBF_NO_LOCKTIME, ..Default::default() }],
+//!     output: vec![],
+//! };
+//! let spent = [SpentOutput { is_taproot: true, confirmations: 6 }];
+//! let tip = absolute::Height::from_consensus(800_000).unwrap();
+//! anti_fee_sniping::apply(&mut tx, tip, &spent, &mut bitcoin::secp256k1::rand::thread_rng());
+//! let locked = tx.lock_time != absolute::LockTime::ZERO;
+//! assert!(locked || tx.input[0].sequence.is_relative_lock_time());
+//! # }
+//! ```
+//!
+//! [BIP-326]: https://github.com/bitcoin/bips/blob/master/bip-0326.mediawiki
+//!
+
+use secp256k1::rand::Rng;
+
+use crate::blockdata::locktime::{absolute, relative};
+use crate::blockdata::transaction::{Sequence, Transaction};
+
+/// Returns the lock time discouraging fee sniping at `tip_height`, like Bitcoin Core.
+///
+/// The lock time is the tip height, but one in ten times it is set up to 99 blocks earlier so
+/// that transactions delayed before broadcasting don't stand out.
+pub fn lock_time<R: Rng + ?Sized>(tip_height: absolute::Height, rng: &mut R) -> absolute::LockTime {
+    let mut height = tip_height.to_consensus_u32();
+    if rng.gen_range(0..10) == 0 {
+        height = height.saturating_sub(rng.gen_range(0..100));
+    }
+    absolute::LockTime::from_height(height).expect("not higher than the tip height")
+}
+
+/// Returns the sequence number discouraging fee sniping for an input spending an output with
+/// `confirmations`, as described in BIP 326.
+///
+/// The relative lock time is the number of confirmations, but one in ten times it is set up to
+/// 99 blocks lower, like [`lock_time`], though not below one block.
+pub fn sequence<R: Rng + ?Sized>(confirmations: relative::Height, rng: &mut R) -> Sequence {
+    let mut height = confirmations.value();
+    if rng.gen_range(0..10) == 0 {
+        height = height.saturating_sub(rng.gen_range(0..100)).max(1);
+    }
+    Sequence::from_height(height)
+}
+
+/// Discourages fee sniping at `tip_height` with either the lock time or, as described in BIP 326,
+/// the sequence number of `tx`.
+///
+/// `spent` are the outputs spent by the inputs of `tx`. If the lock time of `tx` is set already it
+/// is kept, otherwise half of the time a random input gets the relative lock time from
+/// [`sequence`] if that is possible, and the lock time from [`lock_time`] is set otherwise. The
+/// sequence number is only used if:
+///
+/// - the transaction has version 2 or higher,
+/// - it signals replaceability, because the sequence number does,
+/// - all spent outputs are taproot outputs with between 1 and 65535 confirmations, and
+/// - no input has a relative lock time already, which may be required by `OP_CHECKSEQUENCEVERIFY`.
+///
+/// Sequence numbers disabling the lock time are changed to [`Sequence::ENABLE_LOCKTIME_NO_RBF`]
+/// if the lock time is used, other sequence numbers are kept.
+///
+/// # Panics
+///
+/// If the number of spent outputs doesn't match the inputs of `tx`.
+pub fn apply<R: Rng + ?Sized>(
+    tx: &mut Transaction,
+    tip_height: absolute::Height,
+    spent: &[SpentOutput],
+    rng: &mut R,
+) {
+    assert_eq!(tx.input.len(), spent.len(), "one spent output per input");
+
+    if tx.lock_time == absolute::LockTime::ZERO {
+        if can_use_sequence(tx, spent) && rng.gen_range(0..2) == 0 {
+            let index = rng.gen_range(0..tx.input.len());
+            let confirmations = relative::Height::from(spent[index].confirmations as u16);
+            tx.input[index].sequence = sequence(confirmations, rng);
+            return;
+        }
+        tx.lock_time = lock_time(tip_height, rng);
+    }
+    for txin in &mut tx.input {
+        if !txin.enables_lock_time() {
+            txin.sequence = Sequence::ENABLE_LOCKTIME_NO_RBF;
+        }
+    }
+}
+
+/// Returns whether the BIP 326 sequence number may be used to discourage fee sniping.
+fn can_use_sequence(tx: &Transaction, spent: &[SpentOutput]) -> bool {
+    tx.version >= 2
+        && !tx.input.is_empty()
+        && tx.is_explicitly_rbf()
+        && tx.input.iter().all(|txin| !txin.sequence.is_relative_lock_time())
+        && spent.iter().all(|spent| {
+            spent.is_taproot && spent.confirmations >= 1 && spent.confirmations <= 0xffff
+        })
+}
+
+/// The output spent by an input, as far as it matters for discouraging fee sniping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpentOutput {
+    /// Whether the output is a taproot output.
+    pub is_taproot: bool,
+    /// The number of confirmations of the output, zero if it is unconfirmed.
+    pub confirmations: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockdata::transaction::TxIn;
+    use crate::coin_selection::tests::TestRng;
+
+    fn tx(sequences: &[Sequence]) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::ZERO,
+            input: sequences
+                .iter()
+                .map(|&sequence| TxIn { sequence, ..Default::default() })
+                .collect(),
+            output: vec![],
+        }
+    }
+
+    fn taproot(confirmations: u32) -> SpentOutput {
+        SpentOutput { is_taproot: true, confirmations }
+    }
+
+    #[test]
+    fn lock_time_and_sequence() {
+        let tip = absolute::Height::from_consensus(800_000).unwrap();
+        let mut rng = TestRng::new();
+        let mut backdated = false;
+        for _ in 0..100 {
+            let height = match lock_time(tip, &mut rng) {
+                absolute::LockTime::Blocks(height) => height.to_consensus_u32(),
+                absolute::LockTime::Seconds(_) => panic!("lock time is a height"),
+            };
+            assert!(height > 800_000 - 100 && height <= 800_000);
+            backdated |= height < 800_000;
+
+            let sequence = sequence(relative::Height::from(50), &mut rng);
+            let height = match sequence.to_relative_lock_time() {
+                Some(relative::LockTime::Blocks(height)) => height.value(),
+                _ => panic!("relative lock time is a height"),
+            };
+            assert!((1..=50).contains(&height));
+        }
+        assert!(backdated);
+    }
+
+    #[test]
+    fn choose_method() {
+        let tip = absolute::Height::from_consensus(800_000).unwrap();
+        let rbf = [Sequence::ENABLE_RBF_NO_LOCKTIME; 2];
+        let mut rng = TestRng::new();
+        let (mut by_lock_time, mut by_sequence) = (0, 0);
+        for _ in 0..100 {
+            let mut tx = tx(&rbf);
+            apply(&mut tx, tip, &[taproot(10), taproot(20)], &mut rng);
+            if tx.lock_time == absolute::LockTime::ZERO {
+                by_sequence += 1;
+                let locked = tx.input.iter().filter(|txin| txin.sequence.is_relative_lock_time());
+                assert_eq!(locked.count(), 1);
+            } else {
+                by_lock_time += 1;
+                assert!(tx
+                    .input
+                    .iter()
+                    .all(|txin| txin.sequence == Sequence::ENABLE_RBF_NO_LOCKTIME));
+            }
+        }
+        assert!(by_lock_time > 0 && by_sequence > 0);
+
+        // Any of these conditions requires the lock time.
+        let csv = [Sequence::ENABLE_RBF_NO_LOCKTIME, Sequence::from_height(144)];
+        let cases: [(&[Sequence], [SpentOutput; 2]); 5] = [
+            (&rbf, [taproot(10), SpentOutput { is_taproot: false, confirmations: 10 }]),
+            (&rbf, [taproot(10), taproot(0)]),
+            (&rbf, [taproot(10), taproot(70_000)]),
+            (&[Sequence::MAX; 2], [taproot(10), taproot(20)]),
+            (&csv, [taproot(10), taproot(200)]),
+        ];
+        for (sequences, spent) in cases.iter() {
+            for _ in 0..10 {
+                let mut tx = tx(sequences);
+                apply(&mut tx, tip, spent, &mut rng);
+                assert_ne!(tx.lock_time, absolute::LockTime::ZERO);
+                assert!(tx.is_lock_time_enabled());
+                if sequences[1] != Sequence::MAX {
+                    assert_eq!(tx.input[1].sequence, sequences[1]);
+                }
+            }
+        }
+
+        // A lock time set already is kept.
+        let lock_time = absolute::LockTime::from_consensus(700_000);
+        let mut tx = tx(&[Sequence::MAX]);
+        tx.lock_time = lock_time;
+        apply(&mut tx, tip, &[taproot(10)], &mut rng);
+        assert_eq!(tx.lock_time, lock_time);
+        assert_eq!(tx.input[0].sequence, Sequence::ENABLE_LOCKTIME_NO_RBF);
+    }
+}
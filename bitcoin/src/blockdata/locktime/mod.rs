@@ -5,4 +5,7 @@
 //!
 
 pub mod absolute;
+#[cfg(feature = "rand")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rand")))]
+pub mod anti_fee_sniping;
 pub mod relative;
@@ -9,9 +9,10 @@ use core::ops::{Index, Range, RangeFrom, RangeFull, RangeInclusive, RangeTo, Ran
 
 use secp256k1::{Secp256k1, Verification};
 
-use crate::address::WitnessVersion;
+use crate::address::{WitnessVersion, P2A_PROGRAM};
 use crate::blockdata::opcodes::all::*;
 use crate::blockdata::opcodes::{self};
+use crate::blockdata::script::interpreter::MAX_PUBKEYS_PER_MULTISIG;
 #[cfg(feature = "bitcoinconsensus")]
 use crate::blockdata::script::Error;
 use crate::blockdata::script::{
@@ -211,7 +212,7 @@ impl Script {
 
     /// Returns the bytes of the (possibly invalid) public key if this script is P2PK.
     #[inline]
-    pub(in crate::blockdata::script) fn p2pk_pubkey_bytes(&self) -> Option<&[u8]> {
+    pub(crate) fn p2pk_pubkey_bytes(&self) -> Option<&[u8]> {
         match self.len() {
             67 if self.0[0] == OP_PUSHBYTES_65.to_u8() && self.0[66] == OP_CHECKSIG.to_u8() =>
                 Some(&self.0[1..66]),
@@ -273,6 +274,15 @@ impl Script {
             && self.0[1] == OP_PUSHBYTES_32.to_u8()
     }
 
+    /// Checks whether a script pubkey is a P2A (pay to anchor) output.
+    #[inline]
+    pub fn is_p2a(&self) -> bool {
+        self.0.len() == 4
+            && self.witness_version() == Some(WitnessVersion::V1)
+            && self.0[1] == OP_PUSHBYTES_2.to_u8()
+            && self.0[2..] == P2A_PROGRAM
+    }
+
     /// Check if this is an OP_RETURN output.
     #[inline]
     pub fn is_op_return(&self) -> bool {
@@ -319,6 +329,50 @@ impl Script {
         crate::Amount::from_sat(sats)
     }
 
+    /// Counts the sigops of this script using accurate counting.
+    ///
+    /// An `OP_CHECKMULTISIG` preceded by `OP_PUSHNUM_N` counts as N sigops, which is how Bitcoin
+    /// Core counts P2SH redeem scripts and P2WSH witness scripts (`GetSigOpCount(true)`).
+    pub fn count_sigops(&self) -> usize { self.count_sigops_internal(true) }
+
+    /// Counts the sigops of this script using legacy counting.
+    ///
+    /// Every `OP_CHECKMULTISIG` counts as [`MAX_PUBKEYS_PER_MULTISIG`] sigops, which is how
+    /// Bitcoin Core counts scriptSigs and output scripts (`GetSigOpCount(false)`).
+    ///
+    /// [`MAX_PUBKEYS_PER_MULTISIG`]: super::interpreter::MAX_PUBKEYS_PER_MULTISIG
+    pub fn count_sigops_legacy(&self) -> usize { self.count_sigops_internal(false) }
+
+    fn count_sigops_internal(&self, accurate: bool) -> usize {
+        use crate::blockdata::opcodes::{Class, ClassifyContext};
+
+        let mut n = 0;
+        let mut last_opcode: Option<opcodes::All> = None;
+        // Counting stops at the first instruction that can't be parsed.
+        for instruction in self.instructions().take_while(Result::is_ok) {
+            let opcode = match instruction {
+                Ok(Instruction::Op(opcode)) => opcode,
+                _ => {
+                    last_opcode = None;
+                    continue;
+                }
+            };
+            match opcode {
+                OP_CHECKSIG | OP_CHECKSIGVERIFY => n += 1,
+                OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
+                    let last_class = last_opcode.map(|op| op.classify(ClassifyContext::Legacy));
+                    n += match last_class {
+                        Some(Class::PushNum(keys)) if accurate && keys > 0 => keys as usize,
+                        _ => MAX_PUBKEYS_PER_MULTISIG as usize,
+                    };
+                }
+                _ => (),
+            }
+            last_opcode = Some(opcode);
+        }
+        n
+    }
+
     /// Iterates over the script instructions.
     ///
     /// Each returned item is a nested enum covering opcodes, datapushes and errors.
@@ -0,0 +1,454 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Step-through script debugger.
+//!
+//! Verifies a transaction input with the [`interpreter`](super::interpreter) one instruction at a
+//! time, exposing the state of the script being executed after every step. All the scripts of an
+//! input are covered: the scriptSig, the scriptPubKey of the spent output, P2SH redeem scripts,
+//! P2WSH witness scripts (and the script implied by P2WPKH) and tapscript leaves.
+//!
+//! # Examples
+//!
+//! ```
+//! # use bitcoin::{absolute, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness};
+//! use bitcoin::script::debugger::Debugger;
+//! use bitcoin::script::interpreter::VerifyFlags;
+//! use bitcoin::secp256k1::Secp256k1;
+//!
+//! # let script_pubkey = ScriptBuf::from_hex("5287").unwrap(); // OP_2 OP_EQUAL
+//! # let script_sig = ScriptBuf::from_hex("52").unwrap(); // OP_2
+//! # let input = TxIn {
+//! #     previous_output: OutPoint::null(),
+//! #     script_sig,
+//! #     sequence: Sequence::MAX,
+//! #     witness: Witness::default(),
+//! # };
+//! # let tx = Transaction { version: 2, lock_time: absolute::LockTime::ZERO, input: vec![input], output: vec![] };
+//! let secp = Secp256k1::verification_only();
+//! let spent = [TxOut { value: 10_000, script_pubkey }];
+//! let mut debugger = Debugger::new(&secp, &tx, 0, &spent, VerifyFlags::STANDARD).unwrap();
+//! for step in &mut debugger {
+//!     println!("{:?} {} {:?}: {:?}", step.script_kind, step.position, step.opcode, step.stack);
+//! }
+//! assert_eq!(debugger.result(), Some(Ok(())));
+//! ```
+
+use core::fmt;
+
+use secp256k1::{Secp256k1, Verification};
+
+use crate::blockdata::opcodes;
+use crate::blockdata::script::interpreter::{
+    Error, ScriptKind, TransactionSignatureChecker, Verifier, VerifyFlags,
+};
+use crate::blockdata::script::Script;
+use crate::blockdata::transaction::{Transaction, TxOut};
+use crate::prelude::*;
+
+/// Verifies a transaction input one step at a time.
+///
+/// Every step either executes an instruction or finishes a script, running the checks done
+/// between scripts (for example that the top stack element is true) and moving on to the next
+/// script. The debugger is an [`Iterator`] over the [`Step`]s, it is exhausted once verification
+/// succeeds or fails.
+pub struct Debugger<'a, C: Verification> {
+    verifier: Verifier<'a>,
+    checker: TransactionSignatureChecker<'a, C>,
+}
+
+impl<'a, C: Verification> Debugger<'a, C> {
+    /// Creates a debugger for input `input_index` of `tx`.
+    ///
+    /// `spent` must contain the outputs spent by all inputs of `tx`, in order.
+    ///
+    /// # Errors
+    ///
+    /// If `input_index` is out of bounds or the number of outputs in `spent` differs from the
+    /// number of inputs of `tx`.
+    pub fn new(
+        secp: &'a Secp256k1<C>,
+        tx: &'a Transaction,
+        input_index: usize,
+        spent: &'a [TxOut],
+        flags: VerifyFlags,
+    ) -> Result<Self, DebuggerError> {
+        let input = tx.input.get(input_index).ok_or(DebuggerError::IndexOutOfInputsBounds {
+            index: input_index,
+            inputs_size: tx.input.len(),
+        })?;
+        if spent.len() != tx.input.len() {
+            return Err(DebuggerError::PrevoutsSize {
+                prevouts_size: spent.len(),
+                inputs_size: tx.input.len(),
+            });
+        }
+        let verifier = Verifier::new(
+            &input.script_sig,
+            &spent[input_index].script_pubkey,
+            &input.witness,
+            flags,
+        );
+        let checker = TransactionSignatureChecker::new(secp, tx, input_index, spent);
+        Ok(Debugger { verifier, checker })
+    }
+
+    /// Returns the kind of the script being executed.
+    ///
+    /// Once verification ended this is the last executed script, `None` if verification failed
+    /// before executing any script.
+    pub fn script_kind(&self) -> Option<ScriptKind> {
+        self.verifier.current().map(|(kind, _)| kind)
+    }
+
+    /// Returns the script being executed.
+    pub fn script(&self) -> Option<&Script> {
+        self.verifier.current().map(|(_, exec)| exec.script())
+    }
+
+    /// Returns the position of the next instruction in the script being executed.
+    ///
+    /// Positions are byte offsets, the same as the ones returned by
+    /// [`Script::instruction_indices`]. Once all instructions are executed this is the length of
+    /// the script.
+    pub fn position(&self) -> usize {
+        self.verifier.current().map(|(_, exec)| exec.position()).unwrap_or(0)
+    }
+
+    /// Returns the opcode of the next instruction, `None` if all instructions are executed.
+    pub fn next_opcode(&self) -> Option<opcodes::All> {
+        let (_, exec) = self.verifier.current()?;
+        exec.script().as_bytes().get(exec.position()).map(|op| opcodes::All::from(*op))
+    }
+
+    /// Returns the main stack, the top element is the last one.
+    pub fn stack(&self) -> &[Vec<u8>] {
+        self.verifier.current().map(|(_, exec)| exec.stack()).unwrap_or(&[])
+    }
+
+    /// Returns the alt stack, the top element is the last one.
+    pub fn altstack(&self) -> &[Vec<u8>] {
+        self.verifier.current().map(|(_, exec)| exec.altstack()).unwrap_or(&[])
+    }
+
+    /// Returns the conditions of the open `OP_IF` and `OP_NOTIF` blocks, innermost last.
+    ///
+    /// Instructions are only executed if all the conditions are `true`.
+    pub fn condition_stack(&self) -> &[bool] {
+        self.verifier.current().map(|(_, exec)| exec.conditions()).unwrap_or(&[])
+    }
+
+    /// Returns the result of the verification, `None` while it is still running.
+    pub fn result(&self) -> Option<Result<(), Error>> { self.verifier.result() }
+
+    /// Executes the next step, returns `None` if verification already ended.
+    pub fn step(&mut self) -> Option<Step> {
+        if self.verifier.result().is_some() {
+            return None;
+        }
+        let (script_kind, exec) = self.verifier.current()?;
+        let position = exec.position();
+        let finishing = exec.is_done();
+        let opcode = exec.script().as_bytes().get(position).map(|op| opcodes::All::from(*op));
+        let executed = finishing || exec.is_executing();
+        // Finishing a script doesn't modify it, but its state is replaced by the next script.
+        let before = if finishing { Some(self.snapshot()) } else { None };
+
+        self.verifier.step(&mut self.checker);
+
+        let (stack, altstack, condition_stack) = match before {
+            Some(state) => state,
+            None => self.snapshot(),
+        };
+        let error = match self.verifier.result() {
+            Some(Err(e)) => Some(e),
+            _ => None,
+        };
+        Some(Step {
+            script_kind,
+            position,
+            opcode,
+            executed,
+            stack,
+            altstack,
+            condition_stack,
+            error,
+        })
+    }
+
+    /// Runs the verification to the end and returns its result.
+    pub fn run(&mut self) -> Result<(), Error> {
+        while self.step().is_some() {}
+        self.result().expect("verification ended")
+    }
+
+    fn snapshot(&self) -> (Vec<Vec<u8>>, Vec<Vec<u8>>, Vec<bool>) {
+        (self.stack().to_vec(), self.altstack().to_vec(), self.condition_stack().to_vec())
+    }
+}
+
+impl<'a, C: Verification> Iterator for Debugger<'a, C> {
+    type Item = Step;
+
+    fn next(&mut self) -> Option<Self::Item> { self.step() }
+}
+
+/// A step of the [`Debugger`] and the state of the script after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    /// The kind of the script the step belongs to.
+    pub script_kind: ScriptKind,
+    /// The position of the executed instruction in the script, the length of the script for the
+    /// step finishing it.
+    pub position: usize,
+    /// The opcode of the executed instruction, `None` for the step finishing the script.
+    ///
+    /// For pushes this is the push opcode, the pushed data ends up on [`Self::stack`].
+    pub opcode: Option<opcodes::All>,
+    /// Whether the instruction is in an executed branch, always `true` when finishing a script.
+    pub executed: bool,
+    /// The main stack after the step.
+    pub stack: Vec<Vec<u8>>,
+    /// The alt stack after the step.
+    pub altstack: Vec<Vec<u8>>,
+    /// The `OP_IF` condition stack after the step, see [`Debugger::condition_stack`].
+    pub condition_stack: Vec<bool>,
+    /// The reason verification failed at this step.
+    pub error: Option<Error>,
+}
+
+/// An error creating a [`Debugger`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DebuggerError {
+    /// The input index is greater than or equal to the number of transaction inputs.
+    IndexOutOfInputsBounds {
+        /// The requested input index.
+        index: usize,
+        /// The number of transaction inputs.
+        inputs_size: usize,
+    },
+    /// The number of spent outputs differs from the number of transaction inputs.
+    PrevoutsSize {
+        /// The number of spent outputs.
+        prevouts_size: usize,
+        /// The number of transaction inputs.
+        inputs_size: usize,
+    },
+}
+
+impl fmt::Display for DebuggerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use DebuggerError::*;
+
+        match *self {
+            IndexOutOfInputsBounds { index, inputs_size } => write!(
+                f,
+                "input index {} out of bounds for a transaction with {} inputs",
+                index, inputs_size
+            ),
+            PrevoutsSize { prevouts_size, inputs_size } => write!(
+                f,
+                "{} spent outputs provided for a transaction with {} inputs",
+                prevouts_size, inputs_size
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+impl std::error::Error for DebuggerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::TryFrom;
+
+    use secp256k1::XOnlyPublicKey;
+
+    use super::*;
+    use crate::blockdata::locktime::absolute;
+    use crate::blockdata::opcodes::all::*;
+    use crate::blockdata::script::{Builder, PushBytes, ScriptBuf};
+    use crate::blockdata::transaction::{OutPoint, Sequence, TxIn};
+    use crate::blockdata::witness::Witness;
+    use crate::hashes::{sha256, Hash};
+    use crate::taproot::{LeafVersion, TaprootBuilder};
+
+    fn spending_tx(script_sig: ScriptBuf, witness: Witness) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig,
+                sequence: Sequence::MAX,
+                witness,
+            }],
+            output: vec![],
+        }
+    }
+
+    fn spent(script_pubkey: ScriptBuf) -> [TxOut; 1] { [TxOut { value: 10_000, script_pubkey }] }
+
+    /// A hash lock with a time locked refund.
+    fn htlc(hash: sha256::Hash) -> ScriptBuf {
+        Builder::new()
+            .push_opcode(OP_IF)
+            .push_opcode(OP_SHA256)
+            .push_slice(hash.as_byte_array())
+            .push_opcode(OP_EQUAL)
+            .push_opcode(OP_ELSE)
+            .push_int(100)
+            .push_opcode(OP_CLTV)
+            .push_opcode(OP_DROP)
+            .push_int(0)
+            .push_opcode(OP_ENDIF)
+            .into_script()
+    }
+
+    #[test]
+    fn p2sh_htlc() {
+        let secp = Secp256k1::verification_only();
+        let preimage = [0xab; 32];
+        let redeem_script = htlc(sha256::Hash::hash(&preimage));
+        let script_sig = Builder::new()
+            .push_slice(preimage)
+            .push_int(1)
+            .push_slice(<&PushBytes>::try_from(redeem_script.as_bytes()).unwrap())
+            .into_script();
+        let tx = spending_tx(script_sig, Witness::default());
+        let spent = spent(redeem_script.to_p2sh());
+        let mut debugger = Debugger::new(&secp, &tx, 0, &spent, VerifyFlags::STANDARD).unwrap();
+
+        assert_eq!(debugger.script_kind(), Some(ScriptKind::ScriptSig));
+        assert_eq!(debugger.next_opcode(), Some(OP_PUSHBYTES_32));
+        let steps = debugger.by_ref().collect::<Vec<_>>();
+        assert_eq!(debugger.result(), Some(Ok(())));
+        assert!(steps.iter().all(|step| step.error.is_none()));
+
+        // The steps of every script are its instructions followed by the finishing step.
+        let scripts = [
+            (ScriptKind::ScriptSig, &tx.input[0].script_sig),
+            (ScriptKind::ScriptPubKey, &spent[0].script_pubkey),
+            (ScriptKind::RedeemScript, &redeem_script),
+        ];
+        let mut steps_iter = steps.iter();
+        for (kind, script) in scripts.iter() {
+            for (pos, _) in script.instruction_indices().map(Result::unwrap) {
+                let step = steps_iter.next().unwrap();
+                assert_eq!((step.script_kind, step.position), (*kind, pos));
+                assert_eq!(step.opcode, Some(opcodes::All::from(script.as_bytes()[pos])));
+            }
+            let step = steps_iter.next().unwrap();
+            assert_eq!((step.script_kind, step.position, step.opcode), (*kind, script.len(), None));
+        }
+        assert!(steps_iter.next().is_none());
+
+        let redeem_steps = steps
+            .iter()
+            .filter(|step| step.script_kind == ScriptKind::RedeemScript)
+            .collect::<Vec<_>>();
+        // OP_IF took the hash lock branch.
+        assert_eq!(redeem_steps[0].condition_stack, [true]);
+        assert_eq!(redeem_steps[1].opcode, Some(OP_SHA256));
+        assert_eq!(redeem_steps[1].stack, [sha256::Hash::hash(&preimage).to_byte_array().to_vec()]);
+        // The refund branch is skipped.
+        assert_eq!(redeem_steps[4].opcode, Some(OP_ELSE));
+        assert_eq!(redeem_steps[4].condition_stack, [false]);
+        assert!(!redeem_steps[5].executed);
+        assert_eq!(redeem_steps[9].opcode, Some(OP_ENDIF));
+        assert!(redeem_steps[9].condition_stack.is_empty());
+        assert_eq!(redeem_steps[9].stack, [vec![1]]);
+        assert!(debugger.step().is_none());
+    }
+
+    #[test]
+    fn failure() {
+        let secp = Secp256k1::verification_only();
+        let preimage = [0xab; 32];
+        let redeem_script = htlc(sha256::Hash::hash(&preimage));
+        let script_sig = Builder::new()
+            .push_int(0)
+            .push_slice(<&PushBytes>::try_from(redeem_script.as_bytes()).unwrap())
+            .into_script();
+        let tx = spending_tx(script_sig, Witness::default());
+        let spent = spent(redeem_script.to_p2sh());
+
+        // The refund branch fails because the lock time isn't satisfied.
+        let mut debugger = Debugger::new(&secp, &tx, 0, &spent, VerifyFlags::STANDARD).unwrap();
+        assert_eq!(debugger.run(), Err(Error::UnsatisfiedLockTime));
+        assert_eq!(debugger.script_kind(), Some(ScriptKind::RedeemScript));
+        assert_eq!(debugger.condition_stack(), [true]);
+        assert_eq!(debugger.stack(), [vec![100]]);
+
+        // Without CHECKLOCKTIMEVERIFY the script fails when finishing the redeem script.
+        let flags = VerifyFlags::P2SH;
+        let debugger = Debugger::new(&secp, &tx, 0, &spent, flags).unwrap();
+        let last = debugger.last().unwrap();
+        assert_eq!(last.script_kind, ScriptKind::RedeemScript);
+        assert_eq!(last.opcode, None);
+        assert_eq!(last.stack, [Vec::<u8>::new()]);
+        assert_eq!(last.error, Some(Error::EvalFalse));
+    }
+
+    #[test]
+    fn tapscript() {
+        let secp = Secp256k1::new();
+        let preimage = [0xcd; 32];
+        let leaf = Builder::new()
+            .push_opcode(OP_SHA256)
+            .push_slice(sha256::Hash::hash(&preimage).as_byte_array())
+            .push_opcode(OP_EQUAL)
+            .into_script();
+        let internal_key = XOnlyPublicKey::from_slice(&[
+            0x50, 0x92, 0x9b, 0x74, 0xc1, 0xa0, 0x49, 0x54, 0xb7, 0x8b, 0x4b, 0x60, 0x35, 0xe9,
+            0x7a, 0x5e, 0x07, 0x8a, 0x5a, 0x0f, 0x28, 0xec, 0x96, 0xd5, 0x47, 0xbf, 0xee, 0x9a,
+            0xce, 0x80, 0x3a, 0xc0,
+        ])
+        .unwrap();
+        let info = TaprootBuilder::new()
+            .add_leaf(0, leaf.clone())
+            .unwrap()
+            .finalize(&secp, internal_key)
+            .unwrap();
+        let control_block = info.control_block(&(leaf.clone(), LeafVersion::TapScript)).unwrap();
+        let witness =
+            Witness::from_slice(&[preimage.to_vec(), leaf.to_bytes(), control_block.serialize()]);
+        let tx = spending_tx(ScriptBuf::new(), witness);
+        let spent = spent(ScriptBuf::new_v1_p2tr_tweaked(info.output_key()));
+
+        let debugger = Debugger::new(&secp, &tx, 0, &spent, VerifyFlags::STANDARD).unwrap();
+        let steps = debugger
+            .filter(|step| step.script_kind == ScriptKind::Tapscript)
+            .map(|step| (step.opcode, step.stack))
+            .collect::<Vec<_>>();
+        let hash = sha256::Hash::hash(&preimage).to_byte_array().to_vec();
+        assert_eq!(
+            steps,
+            [
+                (Some(OP_SHA256), vec![hash.clone()]),
+                (Some(OP_PUSHBYTES_32), vec![hash.clone(), hash]),
+                (Some(OP_EQUAL), vec![vec![1]]),
+                (None, vec![vec![1]]),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        let secp = Secp256k1::verification_only();
+        let tx = spending_tx(ScriptBuf::new(), Witness::default());
+        let spent = spent(ScriptBuf::new());
+
+        let err = Debugger::new(&secp, &tx, 1, &spent, VerifyFlags::STANDARD).err();
+        assert_eq!(err, Some(DebuggerError::IndexOutOfInputsBounds { index: 1, inputs_size: 1 }));
+        let err = Debugger::new(&secp, &tx, 0, &[], VerifyFlags::STANDARD).err();
+        assert_eq!(err, Some(DebuggerError::PrevoutsSize { prevouts_size: 0, inputs_size: 1 }));
+        assert_eq!(
+            err.unwrap().to_string(),
+            "0 spent outputs provided for a transaction with 1 inputs"
+        );
+    }
+}
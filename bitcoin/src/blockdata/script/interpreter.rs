@@ -0,0 +1,2518 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Bitcoin script interpreter.
+//!
+//! A native implementation of script verification as done by Bitcoin Core (`VerifyScript` in
+//! `script/interpreter.cpp`), covering legacy, P2SH, segwit v0 and taproot (BIP 341 and BIP 342)
+//! spends. Unlike the `bitcoinconsensus` feature it does not depend on any C++ code so it can be
+//! used in `no_std` and WASM environments.
+//!
+//! Most users want [`Transaction::verify_scripts`](crate::Transaction::verify_scripts), the
+//! lower level [`verify_script`] allows verifying a single input with a custom
+//! [`SignatureChecker`].
+//!
+//! To inspect the execution of the scripts of an input step by step use the
+//! [`Debugger`](super::debugger::Debugger).
+//!
+
+use core::{fmt, ops};
+
+use secp256k1::{ecdsa, schnorr, Message, Parity, Scalar, Secp256k1, Verification, XOnlyPublicKey};
+
+use crate::blockdata::constants::MAX_SCRIPT_ELEMENT_SIZE;
+use crate::blockdata::locktime::absolute::LOCK_TIME_THRESHOLD;
+use crate::blockdata::opcodes::all::*;
+use crate::blockdata::opcodes::{self, ClassifyContext};
+use crate::blockdata::script::{read_scriptbool, write_scriptint, Instruction, Script, ScriptBuf};
+use crate::blockdata::transaction::{Sequence, Transaction, TxOut};
+use crate::blockdata::witness::Witness;
+use crate::consensus::Encodable;
+use crate::hashes::{hash160, ripemd160, sha1, sha256, sha256d, Hash, HashEngine};
+use crate::prelude::*;
+use crate::sighash::{Annex, Prevouts, SighashCache, TapSighashType};
+use crate::taproot::{
+    TapLeafHash, TapNodeHash, TapTweakHash, TAPROOT_ANNEX_PREFIX, TAPROOT_CONTROL_BASE_SIZE,
+    TAPROOT_CONTROL_MAX_SIZE, TAPROOT_CONTROL_NODE_SIZE, TAPROOT_LEAF_MASK, TAPROOT_LEAF_TAPSCRIPT,
+};
+
+/// Maximum number of non-push operations per script (not applicable to tapscript).
+pub const MAX_OPS_PER_SCRIPT: usize = 201;
+
+/// Maximum number of public keys per multisig.
+pub const MAX_PUBKEYS_PER_MULTISIG: i64 = 20;
+
+/// Maximum script length in bytes (not applicable to tapscript).
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+
+/// Maximum number of elements on the main and alt stacks combined.
+pub const MAX_STACK_SIZE: usize = 1000;
+
+/// Validation weight consumed by every executed signature check in tapscript.
+const VALIDATION_WEIGHT_PER_SIGOP_PASSED: i64 = 50;
+
+/// Validation weight budget on top of the serialized witness size.
+const VALIDATION_WEIGHT_OFFSET: i64 = 50;
+
+/// Script verification flags, each flag enables additional checks.
+///
+/// The bit values match the `SCRIPT_VERIFY_*` flags of Bitcoin Core (and
+/// `bitcoinconsensus::VERIFY_*` for the flags available there).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct VerifyFlags(u32);
+
+impl VerifyFlags {
+    /// No additional checks.
+    pub const NONE: VerifyFlags = VerifyFlags(0);
+
+    /// Evaluate P2SH subscripts (BIP 16).
+    pub const P2SH: VerifyFlags = VerifyFlags(1 << 0);
+
+    /// Require strict encoding of signatures and public keys passed to signature checks.
+    pub const STRICTENC: VerifyFlags = VerifyFlags(1 << 1);
+
+    /// Require strict DER encoding of signatures (BIP 66).
+    pub const DERSIG: VerifyFlags = VerifyFlags(1 << 2);
+
+    /// Require the S value of ECDSA signatures to be in the lower half of the curve order.
+    pub const LOW_S: VerifyFlags = VerifyFlags(1 << 3);
+
+    /// Require the dummy element consumed by `OP_CHECKMULTISIG` to be empty (BIP 147).
+    pub const NULLDUMMY: VerifyFlags = VerifyFlags(1 << 4);
+
+    /// Require the scriptSig to contain only push operations.
+    pub const SIGPUSHONLY: VerifyFlags = VerifyFlags(1 << 5);
+
+    /// Require minimal encodings for pushes and numbers.
+    pub const MINIMALDATA: VerifyFlags = VerifyFlags(1 << 6);
+
+    /// Fail on the upgradable `OP_NOP` opcodes.
+    pub const DISCOURAGE_UPGRADABLE_NOPS: VerifyFlags = VerifyFlags(1 << 7);
+
+    /// Require exactly one element on the stack after evaluation.
+    pub const CLEANSTACK: VerifyFlags = VerifyFlags(1 << 8);
+
+    /// Enable `OP_CHECKLOCKTIMEVERIFY` (BIP 65).
+    pub const CHECKLOCKTIMEVERIFY: VerifyFlags = VerifyFlags(1 << 9);
+
+    /// Enable `OP_CHECKSEQUENCEVERIFY` (BIP 112).
+    pub const CHECKSEQUENCEVERIFY: VerifyFlags = VerifyFlags(1 << 10);
+
+    /// Evaluate segregated witness programs (BIP 141, BIP 143).
+    pub const WITNESS: VerifyFlags = VerifyFlags(1 << 11);
+
+    /// Fail on witness programs of unknown versions.
+    pub const DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM: VerifyFlags = VerifyFlags(1 << 12);
+
+    /// Require the argument of `OP_IF` and `OP_NOTIF` in segwit v0 scripts to be minimal.
+    pub const MINIMALIF: VerifyFlags = VerifyFlags(1 << 13);
+
+    /// Require signatures of failed signature checks to be empty.
+    pub const NULLFAIL: VerifyFlags = VerifyFlags(1 << 14);
+
+    /// Require compressed public keys in segwit v0 scripts.
+    pub const WITNESS_PUBKEYTYPE: VerifyFlags = VerifyFlags(1 << 15);
+
+    /// Fail on `OP_CODESEPARATOR` and on signatures found in the script code of legacy scripts.
+    pub const CONST_SCRIPTCODE: VerifyFlags = VerifyFlags(1 << 16);
+
+    /// Evaluate taproot spends (BIP 341, BIP 342).
+    pub const TAPROOT: VerifyFlags = VerifyFlags(1 << 17);
+
+    /// Fail on taproot leaf versions other than tapscript.
+    pub const DISCOURAGE_UPGRADABLE_TAPROOT_VERSION: VerifyFlags = VerifyFlags(1 << 18);
+
+    /// Fail on `OP_SUCCESS` opcodes in tapscript.
+    pub const DISCOURAGE_OP_SUCCESS: VerifyFlags = VerifyFlags(1 << 19);
+
+    /// Fail on tapscript public keys that are neither empty nor 32 bytes long.
+    pub const DISCOURAGE_UPGRADABLE_PUBKEYTYPE: VerifyFlags = VerifyFlags(1 << 20);
+
+    /// The checks enforced by consensus for transactions in new blocks.
+    pub const CONSENSUS: VerifyFlags = VerifyFlags(
+        VerifyFlags::P2SH.0
+            | VerifyFlags::DERSIG.0
+            | VerifyFlags::NULLDUMMY.0
+            | VerifyFlags::CHECKLOCKTIMEVERIFY.0
+            | VerifyFlags::CHECKSEQUENCEVERIFY.0
+            | VerifyFlags::WITNESS.0
+            | VerifyFlags::TAPROOT.0,
+    );
+
+    /// The checks Bitcoin Core applies to transactions before relaying them
+    /// (`STANDARD_SCRIPT_VERIFY_FLAGS`).
+    pub const STANDARD: VerifyFlags = VerifyFlags(
+        VerifyFlags::CONSENSUS.0
+            | VerifyFlags::STRICTENC.0
+            | VerifyFlags::LOW_S.0
+            | VerifyFlags::MINIMALDATA.0
+            | VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS.0
+            | VerifyFlags::CLEANSTACK.0
+            | VerifyFlags::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM.0
+            | VerifyFlags::MINIMALIF.0
+            | VerifyFlags::NULLFAIL.0
+            | VerifyFlags::WITNESS_PUBKEYTYPE.0
+            | VerifyFlags::CONST_SCRIPTCODE.0
+            | VerifyFlags::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION.0
+            | VerifyFlags::DISCOURAGE_OP_SUCCESS.0
+            | VerifyFlags::DISCOURAGE_UPGRADABLE_PUBKEYTYPE.0,
+    );
+
+    /// Checks whether all of `flags` are included in this one.
+    pub fn has(self, flags: VerifyFlags) -> bool { (self.0 | flags.0) == self.0 }
+
+    /// Gets the integer representation of these [`VerifyFlags`].
+    pub fn to_u32(self) -> u32 { self.0 }
+}
+
+impl From<u32> for VerifyFlags {
+    fn from(f: u32) -> Self { VerifyFlags(f) }
+}
+
+impl From<VerifyFlags> for u32 {
+    fn from(flags: VerifyFlags) -> Self { flags.0 }
+}
+
+impl ops::BitOr for VerifyFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self { VerifyFlags(self.0 | rhs.0) }
+}
+
+impl ops::BitOrAssign for VerifyFlags {
+    fn bitor_assign(&mut self, rhs: Self) { self.0 |= rhs.0; }
+}
+
+impl fmt::LowerHex for VerifyFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt::LowerHex::fmt(&self.0, f) }
+}
+
+impl fmt::UpperHex for VerifyFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt::UpperHex::fmt(&self.0, f) }
+}
+
+/// The context a script is executed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SigVersion {
+    /// Bare scripts, scriptSigs and P2SH redeem scripts.
+    Base,
+    /// Segwit v0 witness scripts and P2WPKH.
+    WitnessV0,
+    /// Taproot key path spends.
+    Taproot,
+    /// Tapscript leaves (BIP 342).
+    Tapscript,
+}
+
+/// Data about the taproot spend being verified, used for computing signature hashes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExecutionData {
+    /// The hash of the executed tapscript leaf, `None` for key path spends.
+    pub leaf_hash: Option<TapLeafHash>,
+    /// The opcode position of the last executed `OP_CODESEPARATOR`, `0xFFFFFFFF` if none.
+    pub code_separator_pos: u32,
+    /// The annex of the witness including its `0x50` prefix, if present.
+    pub annex: Option<Vec<u8>>,
+}
+
+impl Default for ExecutionData {
+    fn default() -> Self {
+        ExecutionData { leaf_hash: None, code_separator_pos: 0xFFFFFFFF, annex: None }
+    }
+}
+
+/// Checks signatures and lock times on behalf of the interpreter.
+///
+/// The default implementations fail every check, which is what Bitcoin Core's
+/// `BaseSignatureChecker` does.
+pub trait SignatureChecker {
+    /// Checks an ECDSA signature including its trailing sighash type byte.
+    ///
+    /// `script_code` is the part of the executed script after the last executed
+    /// `OP_CODESEPARATOR`, with the signatures already removed for [`SigVersion::Base`].
+    fn check_ecdsa_signature(
+        &mut self,
+        _sig: &[u8],
+        _pubkey: &[u8],
+        _script_code: &Script,
+        _sig_version: SigVersion,
+    ) -> bool {
+        false
+    }
+
+    /// Checks a BIP 340 signature, optionally followed by a sighash type byte.
+    fn check_schnorr_signature(
+        &mut self,
+        _sig: &[u8],
+        _pubkey: &[u8],
+        _sig_version: SigVersion,
+        _exec_data: &ExecutionData,
+    ) -> Result<(), Error> {
+        Err(Error::SchnorrSig)
+    }
+
+    /// Checks the argument of `OP_CHECKLOCKTIMEVERIFY` against the transaction.
+    fn check_lock_time(&self, _lock_time: i64) -> bool { false }
+
+    /// Checks the argument of `OP_CHECKSEQUENCEVERIFY` against the transaction.
+    fn check_sequence(&self, _sequence: i64) -> bool { false }
+}
+
+/// Checks signatures and lock times against an input of a transaction.
+pub struct TransactionSignatureChecker<'a, C: Verification> {
+    secp: &'a Secp256k1<C>,
+    cache: SighashCache<&'a Transaction>,
+    input_index: usize,
+    prevouts: &'a [TxOut],
+}
+
+impl<'a, C: Verification> TransactionSignatureChecker<'a, C> {
+    /// Creates a checker for input `input_index` of `tx`.
+    ///
+    /// `prevouts` must contain the outputs spent by all inputs of `tx`, in order. Checks of
+    /// signatures whose signature hash can not be computed fail.
+    pub fn new(
+        secp: &'a Secp256k1<C>,
+        tx: &'a Transaction,
+        input_index: usize,
+        prevouts: &'a [TxOut],
+    ) -> Self {
+        TransactionSignatureChecker { secp, cache: SighashCache::new(tx), input_index, prevouts }
+    }
+
+    /// Makes the checker check signatures of input `input_index` of the same transaction.
+    ///
+    /// The transaction wide data of the signature hashes computed so far is kept, so a single
+    /// checker should be used to verify all inputs of a transaction.
+    pub fn set_input_index(&mut self, input_index: usize) { self.input_index = input_index; }
+
+    fn tx(&self) -> &Transaction { self.cache.transaction() }
+}
+
+impl<'a, C: Verification> SignatureChecker for TransactionSignatureChecker<'a, C> {
+    fn check_ecdsa_signature(
+        &mut self,
+        sig: &[u8],
+        pubkey: &[u8],
+        script_code: &Script,
+        sig_version: SigVersion,
+    ) -> bool {
+        let pubkey = match secp256k1::PublicKey::from_slice(pubkey) {
+            Ok(pubkey) => pubkey,
+            Err(_) => return false,
+        };
+        let (hash_type, der) = match sig.split_last() {
+            Some((hash_type, der)) => (u32::from(*hash_type), der),
+            None => return false,
+        };
+        let mut sig = match ecdsa::Signature::from_der_lax(der) {
+            Ok(sig) => sig,
+            Err(_) => return false,
+        };
+        sig.normalize_s();
+
+        let msg = match sig_version {
+            SigVersion::Base => {
+                let script_code = remove_code_separators(script_code.as_bytes());
+                match self.cache.legacy_signature_hash(
+                    self.input_index,
+                    Script::from_bytes(&script_code),
+                    hash_type,
+                ) {
+                    Ok(hash) => Message::from(hash),
+                    Err(_) => return false,
+                }
+            }
+            SigVersion::WitnessV0 => {
+                let value = match self.prevouts.get(self.input_index) {
+                    Some(prevout) => prevout.value,
+                    None => return false,
+                };
+                match self.cache.segwit_signature_hash_raw(
+                    self.input_index,
+                    script_code,
+                    value,
+                    hash_type,
+                ) {
+                    Ok(hash) => Message::from(hash),
+                    Err(_) => return false,
+                }
+            }
+            SigVersion::Taproot | SigVersion::Tapscript => return false,
+        };
+        self.secp.verify_ecdsa(&msg, &sig, &pubkey).is_ok()
+    }
+
+    fn check_schnorr_signature(
+        &mut self,
+        sig: &[u8],
+        pubkey: &[u8],
+        sig_version: SigVersion,
+        exec_data: &ExecutionData,
+    ) -> Result<(), Error> {
+        let (sig, hash_type) = match sig.len() {
+            64 => (sig, TapSighashType::Default),
+            65 if sig[64] == 0x00 => return Err(Error::SchnorrSigHashType),
+            65 => (
+                &sig[..64],
+                TapSighashType::from_consensus_u8(sig[64])
+                    .map_err(|_| Error::SchnorrSigHashType)?,
+            ),
+            _ => return Err(Error::SchnorrSigSize),
+        };
+        let leaf_hash_code_separator = match sig_version {
+            SigVersion::Taproot => None,
+            SigVersion::Tapscript => match exec_data.leaf_hash {
+                Some(leaf_hash) => Some((leaf_hash, exec_data.code_separator_pos)),
+                None => return Err(Error::SchnorrSigHashType),
+            },
+            SigVersion::Base | SigVersion::WitnessV0 => return Err(Error::SchnorrSigHashType),
+        };
+        let annex = match exec_data.annex {
+            Some(ref annex) => Some(Annex::new(annex).map_err(|_| Error::SchnorrSigHashType)?),
+            None => None,
+        };
+        let sighash = self
+            .cache
+            .taproot_signature_hash(
+                self.input_index,
+                &Prevouts::All(self.prevouts),
+                annex,
+                leaf_hash_code_separator,
+                hash_type,
+            )
+            .map_err(|_| Error::SchnorrSigHashType)?;
+
+        let pubkey = XOnlyPublicKey::from_slice(pubkey).map_err(|_| Error::SchnorrSig)?;
+        let sig = schnorr::Signature::from_slice(sig).map_err(|_| Error::SchnorrSig)?;
+        self.secp
+            .verify_schnorr(&sig, &Message::from(sighash), &pubkey)
+            .map_err(|_| Error::SchnorrSig)
+    }
+
+    fn check_lock_time(&self, lock_time: i64) -> bool {
+        let tx_lock_time = i64::from(self.tx().lock_time.to_consensus_u32());
+        let threshold = i64::from(LOCK_TIME_THRESHOLD);
+        // The lock time and the argument must be of the same type, heights or timestamps.
+        if (tx_lock_time < threshold) != (lock_time < threshold) {
+            return false;
+        }
+        if lock_time > tx_lock_time {
+            return false;
+        }
+        // The lock time is ignored if the input is final so `OP_CHECKLOCKTIMEVERIFY` could be
+        // bypassed.
+        match self.tx().input.get(self.input_index) {
+            Some(input) => input.sequence != Sequence::MAX,
+            None => false,
+        }
+    }
+
+    fn check_sequence(&self, sequence: i64) -> bool {
+        const DISABLE_FLAG: i64 = 1 << 31;
+        const TYPE_FLAG: i64 = 1 << 22;
+        const MASK: i64 = TYPE_FLAG | 0xffff;
+
+        let tx_sequence = match self.tx().input.get(self.input_index) {
+            Some(input) => i64::from(input.sequence.to_consensus_u32()),
+            None => return false,
+        };
+        // Relative lock times are only enforced for version 2 transactions (BIP 68).
+        if (self.tx().version as u32) < 2 {
+            return false;
+        }
+        if tx_sequence & DISABLE_FLAG != 0 {
+            return false;
+        }
+        let tx_sequence = tx_sequence & MASK;
+        let sequence = sequence & MASK;
+        if (tx_sequence < TYPE_FLAG) != (sequence < TYPE_FLAG) {
+            return false;
+        }
+        sequence <= tx_sequence
+    }
+}
+
+/// Verifies that `script_sig` and `witness` satisfy `script_pubkey`.
+///
+/// This is `VerifyScript` of Bitcoin Core, signature and lock time checks are delegated to
+/// `checker`.
+pub fn verify_script<S: SignatureChecker + ?Sized>(
+    script_sig: &Script,
+    script_pubkey: &Script,
+    witness: &Witness,
+    flags: VerifyFlags,
+    checker: &mut S,
+) -> Result<(), Error> {
+    let mut verifier = Verifier::new(script_sig, script_pubkey, witness, flags);
+    loop {
+        if let Some(result) = verifier.result() {
+            return result;
+        }
+        verifier.step(checker);
+    }
+}
+
+/// Executes `script` on an empty stack and returns the resulting stack.
+///
+/// This is `EvalScript` of Bitcoin Core with a `BaseSignatureChecker`, i.e. all signature and
+/// lock time checks fail. Policy checks use it to get at the redeem script pushed by a scriptSig.
+pub(crate) fn eval_script(script: &Script, flags: VerifyFlags) -> Result<Vec<Vec<u8>>, Error> {
+    struct BaseSignatureChecker;
+    impl SignatureChecker for BaseSignatureChecker {}
+
+    let script = Cow::Borrowed(script);
+    let mut exec =
+        Exec::new(script, Vec::new(), flags, SigVersion::Base, ExecutionData::default(), 0)?;
+    while !exec.is_done() {
+        exec.step(&mut BaseSignatureChecker)?;
+    }
+    if !exec.cond_stack.is_empty() {
+        return Err(Error::UnbalancedConditional);
+    }
+    Ok(exec.stack)
+}
+
+/// The scripts executed when verifying a transaction input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ScriptKind {
+    /// The scriptSig of the input.
+    ScriptSig,
+    /// The scriptPubKey of the spent output.
+    ScriptPubKey,
+    /// The P2SH redeem script, the last element pushed by the scriptSig.
+    RedeemScript,
+    /// The P2WSH witness script or the script implied by a P2WPKH program.
+    WitnessScript,
+    /// The tapscript leaf of a taproot script path spend.
+    Tapscript,
+}
+
+/// The verification of an input, advanced one step at a time.
+///
+/// A step either executes an instruction of the current script or, once all of them are
+/// executed, finishes the script and moves on to the next one. The last executed script is kept
+/// around after verification ended so that its state can be inspected.
+pub(super) struct Verifier<'s> {
+    script_sig: &'s Script,
+    script_pubkey: &'s Script,
+    witness: &'s Witness,
+    flags: VerifyFlags,
+    /// The stack after executing the scriptSig, which is the input of the P2SH redeem script.
+    p2sh_stack: Vec<Vec<u8>>,
+    had_witness: bool,
+    current: Option<(ScriptKind, Exec<'s>)>,
+    result: Option<Result<(), Error>>,
+}
+
+impl<'s> Verifier<'s> {
+    pub(super) fn new(
+        script_sig: &'s Script,
+        script_pubkey: &'s Script,
+        witness: &'s Witness,
+        flags: VerifyFlags,
+    ) -> Self {
+        let mut verifier = Verifier {
+            script_sig,
+            script_pubkey,
+            witness,
+            flags,
+            p2sh_stack: Vec::new(),
+            had_witness: false,
+            current: None,
+            result: None,
+        };
+        if let Err(e) = verifier.start() {
+            verifier.result = Some(Err(e));
+        }
+        verifier
+    }
+
+    fn start(&mut self) -> Result<(), Error> {
+        // Flag combinations that would make soft fork upgrades hard fork.
+        if (self.flags.has(VerifyFlags::CLEANSTACK) && !self.flags.has(VerifyFlags::WITNESS))
+            || (self.flags.has(VerifyFlags::WITNESS) && !self.flags.has(VerifyFlags::P2SH))
+        {
+            return Err(Error::InvalidFlags);
+        }
+        if self.flags.has(VerifyFlags::SIGPUSHONLY) && !is_push_only(self.script_sig) {
+            return Err(Error::SigPushOnly);
+        }
+        let script_sig = Cow::Borrowed(self.script_sig);
+        self.begin(
+            ScriptKind::ScriptSig,
+            script_sig,
+            Vec::new(),
+            SigVersion::Base,
+            ExecutionData::default(),
+            0,
+        )
+    }
+
+    /// Returns the result of the verification, `None` while it is still running.
+    pub(super) fn result(&self) -> Option<Result<(), Error>> { self.result }
+
+    /// Returns the kind and execution state of the current script.
+    pub(super) fn current(&self) -> Option<(ScriptKind, &Exec<'s>)> {
+        self.current.as_ref().map(|(kind, exec)| (*kind, exec))
+    }
+
+    /// Executes the next instruction or finishes the current script if there is none left.
+    pub(super) fn step<S: SignatureChecker + ?Sized>(&mut self, checker: &mut S) {
+        if self.result.is_some() {
+            return;
+        }
+        let result = match self.current {
+            Some((_, ref mut exec)) if !exec.is_done() => exec.step(checker),
+            _ => self.advance(checker),
+        };
+        if let Err(e) = result {
+            self.result = Some(Err(e));
+        }
+    }
+
+    fn begin(
+        &mut self,
+        kind: ScriptKind,
+        script: Cow<'s, Script>,
+        stack: Vec<Vec<u8>>,
+        sig_version: SigVersion,
+        exec_data: ExecutionData,
+        validation_weight: i64,
+    ) -> Result<(), Error> {
+        let exec = Exec::new(script, stack, self.flags, sig_version, exec_data, validation_weight)?;
+        self.current = Some((kind, exec));
+        Ok(())
+    }
+
+    /// Runs the checks done after the last script, `stack_len` is the size of the final stack.
+    fn end(&mut self, stack_len: usize) -> Result<(), Error> {
+        // Only checked after P2SH and witness evaluation, their inputs obviously remain on the
+        // stack after evaluating the scriptPubKey.
+        if self.flags.has(VerifyFlags::CLEANSTACK) && stack_len != 1 {
+            return Err(Error::CleanStack);
+        }
+        if self.flags.has(VerifyFlags::WITNESS) && !self.had_witness && !self.witness.is_empty() {
+            return Err(Error::WitnessUnexpected);
+        }
+        self.result = Some(Ok(()));
+        Ok(())
+    }
+
+    /// Finishes the current script and begins executing the next one, if any.
+    fn advance<S: SignatureChecker + ?Sized>(&mut self, checker: &mut S) -> Result<(), Error> {
+        let (kind, exec) = self.current.as_ref().expect("verification started with the scriptSig");
+        if !exec.cond_stack.is_empty() {
+            return Err(Error::UnbalancedConditional);
+        }
+        let kind = *kind;
+        let mut stack = exec.stack.clone();
+
+        match kind {
+            ScriptKind::ScriptSig => {
+                if self.flags.has(VerifyFlags::P2SH) {
+                    self.p2sh_stack = stack.clone();
+                }
+                // scriptSig and scriptPubKey are evaluated sequentially on the same stack rather
+                // than being concatenated, see CVE-2010-5141.
+                let script_pubkey = Cow::Borrowed(self.script_pubkey);
+                self.begin(
+                    ScriptKind::ScriptPubKey,
+                    script_pubkey,
+                    stack,
+                    SigVersion::Base,
+                    ExecutionData::default(),
+                    0,
+                )
+            }
+            ScriptKind::ScriptPubKey => {
+                if !stack.last().map(|top| read_scriptbool(top)).unwrap_or(false) {
+                    return Err(Error::EvalFalse);
+                }
+                let script_pubkey = self.script_pubkey;
+                if self.flags.has(VerifyFlags::WITNESS) {
+                    if let Some((version, program)) = witness_program(script_pubkey) {
+                        self.had_witness = true;
+                        // The scriptSig must be empty, otherwise we reintroduce malleability.
+                        if !self.script_sig.is_empty() {
+                            return Err(Error::WitnessMalleated);
+                        }
+                        return self.begin_witness(version, program, false, checker);
+                    }
+                }
+                if self.flags.has(VerifyFlags::P2SH) && script_pubkey.is_p2sh() {
+                    if !is_push_only(self.script_sig) {
+                        return Err(Error::SigPushOnly);
+                    }
+                    stack = core::mem::take(&mut self.p2sh_stack);
+                    // The stack can't be empty here, otherwise the P2SH script would have failed.
+                    let redeem_script = stack.pop().expect("P2SH script ran on a non-empty stack");
+                    return self.begin(
+                        ScriptKind::RedeemScript,
+                        Cow::Owned(ScriptBuf::from(redeem_script)),
+                        stack,
+                        SigVersion::Base,
+                        ExecutionData::default(),
+                        0,
+                    );
+                }
+                self.end(stack.len())
+            }
+            ScriptKind::RedeemScript => {
+                if !stack.last().map(|top| read_scriptbool(top)).unwrap_or(false) {
+                    return Err(Error::EvalFalse);
+                }
+                if self.flags.has(VerifyFlags::WITNESS) {
+                    let redeem_script = exec.script.clone().into_owned();
+                    if let Some((version, program)) = witness_program(&redeem_script) {
+                        self.had_witness = true;
+                        // The scriptSig must be exactly a push of the redeem script, otherwise
+                        // we reintroduce malleability.
+                        let script_sig = self.script_sig.as_bytes();
+                        if script_sig.first() != Some(&(redeem_script.len() as u8))
+                            || &script_sig[1..] != redeem_script.as_bytes()
+                        {
+                            return Err(Error::WitnessMalleatedP2sh);
+                        }
+                        return self.begin_witness(version, program, true, checker);
+                    }
+                }
+                self.end(stack.len())
+            }
+            ScriptKind::WitnessScript | ScriptKind::Tapscript => {
+                // Witness scripts implicitly require a clean stack.
+                if stack.len() != 1 {
+                    return Err(Error::CleanStack);
+                }
+                if !read_scriptbool(&stack[0]) {
+                    return Err(Error::EvalFalse);
+                }
+                // The stack of witness programs is obviously not clean, the witness replaces it.
+                self.end(1)
+            }
+        }
+    }
+
+    /// Verifies a witness program, beginning the execution of its script if it has one.
+    fn begin_witness<S: SignatureChecker + ?Sized>(
+        &mut self,
+        version: u8,
+        program: &[u8],
+        is_p2sh: bool,
+        checker: &mut S,
+    ) -> Result<(), Error> {
+        let witness = self.witness;
+        let mut stack: Vec<&'s [u8]> = witness.iter().collect();
+
+        if version == 0 {
+            if program.len() == 32 {
+                // P2WSH, the program is the SHA256 of the witness script.
+                let script = stack.pop().ok_or(Error::WitnessProgramWitnessEmpty)?;
+                if sha256::Hash::hash(script).as_byte_array()[..] != program[..] {
+                    return Err(Error::WitnessProgramMismatch);
+                }
+                self.begin_witness_script(
+                    ScriptKind::WitnessScript,
+                    Cow::Borrowed(Script::from_bytes(script)),
+                    stack,
+                    SigVersion::WitnessV0,
+                    ExecutionData::default(),
+                    0,
+                )
+            } else if program.len() == 20 {
+                // P2WPKH, the program is the HASH160 of the public key.
+                if stack.len() != 2 {
+                    return Err(Error::WitnessProgramMismatch);
+                }
+                let mut script = vec![OP_DUP.to_u8(), OP_HASH160.to_u8(), OP_PUSHBYTES_20.to_u8()];
+                script.extend_from_slice(program);
+                script.push(OP_EQUALVERIFY.to_u8());
+                script.push(OP_CHECKSIG.to_u8());
+                self.begin_witness_script(
+                    ScriptKind::WitnessScript,
+                    Cow::Owned(ScriptBuf::from(script)),
+                    stack,
+                    SigVersion::WitnessV0,
+                    ExecutionData::default(),
+                    0,
+                )
+            } else {
+                Err(Error::WitnessProgramWrongLength)
+            }
+        } else if version == 1 && program.len() == 32 && !is_p2sh {
+            if !self.flags.has(VerifyFlags::TAPROOT) {
+                return self.end(1);
+            }
+            if stack.is_empty() {
+                return Err(Error::WitnessProgramWitnessEmpty);
+            }
+            let mut exec_data = ExecutionData::default();
+            if stack.len() >= 2
+                && stack.last().and_then(|e| e.first()) == Some(&TAPROOT_ANNEX_PREFIX)
+            {
+                exec_data.annex = stack.pop().map(|annex| annex.to_vec());
+            }
+
+            if stack.len() == 1 {
+                // Key path spend.
+                checker.check_schnorr_signature(
+                    stack[0],
+                    program,
+                    SigVersion::Taproot,
+                    &exec_data,
+                )?;
+                return self.end(1);
+            }
+
+            // Script path spend.
+            let control = stack.pop().expect("stack has at least two elements");
+            let script = stack.pop().expect("stack has at least two elements");
+            if control.len() < TAPROOT_CONTROL_BASE_SIZE
+                || control.len() > TAPROOT_CONTROL_MAX_SIZE
+                || (control.len() - TAPROOT_CONTROL_BASE_SIZE) % TAPROOT_CONTROL_NODE_SIZE != 0
+            {
+                return Err(Error::TaprootWrongControlSize);
+            }
+            let leaf_version = control[0] & TAPROOT_LEAF_MASK;
+            let leaf_hash = tap_leaf_hash(leaf_version, script);
+            if !verify_taproot_commitment(control, program, leaf_hash) {
+                return Err(Error::WitnessProgramMismatch);
+            }
+            exec_data.leaf_hash = Some(leaf_hash);
+
+            if leaf_version == TAPROOT_LEAF_TAPSCRIPT {
+                let validation_weight = witness.serialized_len() as i64 + VALIDATION_WEIGHT_OFFSET;
+                return self.begin_witness_script(
+                    ScriptKind::Tapscript,
+                    Cow::Borrowed(Script::from_bytes(script)),
+                    stack,
+                    SigVersion::Tapscript,
+                    exec_data,
+                    validation_weight,
+                );
+            }
+            if self.flags.has(VerifyFlags::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION) {
+                return Err(Error::DiscourageUpgradableTaprootVersion);
+            }
+            self.end(1)
+        } else {
+            if self.flags.has(VerifyFlags::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM) {
+                return Err(Error::DiscourageUpgradableWitnessProgram);
+            }
+            // Other versions and sizes are valid for future soft fork compatibility.
+            self.end(1)
+        }
+    }
+
+    fn begin_witness_script(
+        &mut self,
+        kind: ScriptKind,
+        script: Cow<'s, Script>,
+        stack: Vec<&[u8]>,
+        sig_version: SigVersion,
+        exec_data: ExecutionData,
+        validation_weight: i64,
+    ) -> Result<(), Error> {
+        if sig_version == SigVersion::Tapscript {
+            // OP_SUCCESSx overrides everything, including stack element size limits.
+            for instruction in script.instructions() {
+                match instruction {
+                    Ok(Instruction::Op(op))
+                        if op.classify(ClassifyContext::TapScript) == opcodes::Class::SuccessOp =>
+                    {
+                        if self.flags.has(VerifyFlags::DISCOURAGE_OP_SUCCESS) {
+                            return Err(Error::DiscourageOpSuccess);
+                        }
+                        return self.end(1);
+                    }
+                    Ok(_) => {}
+                    Err(_) => return Err(Error::BadOpcode),
+                }
+            }
+            if stack.len() > MAX_STACK_SIZE {
+                return Err(Error::StackSize);
+            }
+        }
+        if stack.iter().any(|elem| elem.len() > MAX_SCRIPT_ELEMENT_SIZE) {
+            return Err(Error::PushSize);
+        }
+        let stack = stack.into_iter().map(|elem| elem.to_vec()).collect();
+        self.begin(kind, script, stack, sig_version, exec_data, validation_weight)
+    }
+}
+
+/// Returns the version and program if `script` is a witness program.
+fn witness_program(script: &Script) -> Option<(u8, &[u8])> {
+    if script.is_witness_program() {
+        let version = script.witness_version().expect("witness programs have a version");
+        Some((version.to_num(), &script.as_bytes()[2..]))
+    } else {
+        None
+    }
+}
+
+/// Checks that `script` only contains pushes, counting `OP_RESERVED` as a push like Bitcoin Core.
+pub(crate) fn is_push_only(script: &Script) -> bool {
+    script.instructions().all(|instruction| match instruction {
+        Ok(Instruction::PushBytes(_)) => true,
+        Ok(Instruction::Op(op)) => op.to_u8() <= OP_PUSHNUM_16.to_u8(),
+        Err(_) => false,
+    })
+}
+
+/// Computes the leaf hash of `script`, unlike [`TapLeafHash::from_script`] this accepts leaf
+/// versions that can't be used in a taproot tree.
+fn tap_leaf_hash(leaf_version: u8, script: &[u8]) -> TapLeafHash {
+    let mut eng = TapLeafHash::engine();
+    eng.input(&[leaf_version]);
+    Script::from_bytes(script).consensus_encode(&mut eng).expect("engines don't error");
+    TapLeafHash::from_engine(eng)
+}
+
+/// Checks that the output key `program` commits to the leaf using the `control` block.
+fn verify_taproot_commitment(control: &[u8], program: &[u8], leaf_hash: TapLeafHash) -> bool {
+    let secp = Secp256k1::verification_only();
+    let internal_key = match XOnlyPublicKey::from_slice(&control[1..TAPROOT_CONTROL_BASE_SIZE]) {
+        Ok(key) => key,
+        Err(_) => return false,
+    };
+    let output_key = match XOnlyPublicKey::from_slice(program) {
+        Ok(key) => key,
+        Err(_) => return false,
+    };
+    let merkle_root = control[TAPROOT_CONTROL_BASE_SIZE..]
+        .chunks(TAPROOT_CONTROL_NODE_SIZE)
+        .map(|node| TapNodeHash::from_slice(node).expect("chunks have the node size"))
+        .fold(TapNodeHash::from(leaf_hash), TapNodeHash::from_node_hashes);
+    let tweak = TapTweakHash::from_key_and_tweak(internal_key, Some(merkle_root));
+    let tweak = match Scalar::from_be_bytes(tweak.to_byte_array()) {
+        Ok(tweak) => tweak,
+        Err(_) => return false,
+    };
+    let parity = if control[0] & 1 == 1 { Parity::Odd } else { Parity::Even };
+    internal_key.tweak_add_check(&secp, &output_key, parity, tweak)
+}
+
+/// The `OP_IF` condition stack.
+///
+/// Tracks the number of false entries so that checking whether we are executing is cheap.
+#[derive(Debug, Clone, Default)]
+struct ConditionStack {
+    conditions: Vec<bool>,
+    false_count: usize,
+}
+
+impl ConditionStack {
+    fn all_true(&self) -> bool { self.false_count == 0 }
+
+    fn is_empty(&self) -> bool { self.conditions.is_empty() }
+
+    fn push(&mut self, condition: bool) {
+        if !condition {
+            self.false_count += 1;
+        }
+        self.conditions.push(condition);
+    }
+
+    fn pop(&mut self) -> Option<bool> {
+        let condition = self.conditions.pop()?;
+        if !condition {
+            self.false_count -= 1;
+        }
+        Some(condition)
+    }
+
+    fn toggle_top(&mut self) -> Option<()> {
+        let top = self.conditions.last_mut()?;
+        *top = !*top;
+        if *top {
+            self.false_count -= 1;
+        } else {
+            self.false_count += 1;
+        }
+        Some(())
+    }
+}
+
+/// The execution state of a single script, executed one instruction at a time.
+pub(super) struct Exec<'s> {
+    script: Cow<'s, Script>,
+    /// Position of the next instruction.
+    pc: usize,
+    /// Start of the script code, the position after the last executed `OP_CODESEPARATOR`.
+    code_start: usize,
+    /// Index of the next instruction.
+    opcode_pos: u32,
+    stack: Vec<Vec<u8>>,
+    altstack: Vec<Vec<u8>>,
+    cond_stack: ConditionStack,
+    op_count: usize,
+    flags: VerifyFlags,
+    sig_version: SigVersion,
+    exec_data: ExecutionData,
+    validation_weight_left: i64,
+}
+
+impl<'s> Exec<'s> {
+    fn new(
+        script: Cow<'s, Script>,
+        stack: Vec<Vec<u8>>,
+        flags: VerifyFlags,
+        sig_version: SigVersion,
+        mut exec_data: ExecutionData,
+        validation_weight_left: i64,
+    ) -> Result<Self, Error> {
+        if sig_version != SigVersion::Tapscript && script.len() > MAX_SCRIPT_SIZE {
+            return Err(Error::ScriptSize);
+        }
+        exec_data.code_separator_pos = 0xFFFFFFFF;
+        Ok(Exec {
+            script,
+            pc: 0,
+            code_start: 0,
+            opcode_pos: 0,
+            stack,
+            altstack: Vec::new(),
+            cond_stack: ConditionStack::default(),
+            op_count: 0,
+            flags,
+            sig_version,
+            exec_data,
+            validation_weight_left,
+        })
+    }
+
+    pub(super) fn script(&self) -> &Script { &self.script }
+
+    /// Returns the position of the next instruction, the script length once all are executed.
+    pub(super) fn position(&self) -> usize { self.pc }
+
+    pub(super) fn stack(&self) -> &[Vec<u8>] { &self.stack }
+
+    pub(super) fn altstack(&self) -> &[Vec<u8>] { &self.altstack }
+
+    pub(super) fn conditions(&self) -> &[bool] { &self.cond_stack.conditions }
+
+    /// Returns whether the next instruction is in an executed branch.
+    pub(super) fn is_executing(&self) -> bool { self.cond_stack.all_true() }
+
+    pub(super) fn is_done(&self) -> bool { self.pc >= self.script.len() }
+
+    fn is_legacy(&self) -> bool {
+        self.sig_version == SigVersion::Base || self.sig_version == SigVersion::WitnessV0
+    }
+
+    fn require_minimal(&self) -> bool { self.flags.has(VerifyFlags::MINIMALDATA) }
+
+    /// Returns the stack element `depth` positions from the top, starting at 1.
+    fn top(&self, depth: usize) -> &[u8] { &self.stack[self.stack.len() - depth] }
+
+    fn require(&self, n: usize) -> Result<(), Error> {
+        if self.stack.len() < n {
+            Err(Error::InvalidStackOperation)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn pop(&mut self) { self.stack.pop(); }
+
+    fn num(&self, depth: usize, max_len: usize) -> Result<i64, Error> {
+        read_num(self.top(depth), self.require_minimal(), max_len)
+    }
+
+    fn push_num(&mut self, n: i64) { self.stack.push(encode_num(n)); }
+
+    fn push_bool(&mut self, b: bool) { self.stack.push(if b { vec![1] } else { vec![] }); }
+
+    /// Executes the next instruction.
+    fn step<S: SignatureChecker + ?Sized>(&mut self, checker: &mut S) -> Result<(), Error> {
+        let pos = self.pc;
+        let mut instructions = Script::from_bytes(&self.script.as_bytes()[pos..]).instructions();
+        let instruction = match instructions.next() {
+            Some(Ok(instruction)) => instruction,
+            Some(Err(_)) => return Err(Error::BadOpcode),
+            None => return Ok(()),
+        };
+        self.pc = self.script.len() - instructions.as_script().len();
+        let executing = self.cond_stack.all_true();
+
+        match instruction {
+            Instruction::PushBytes(data) => {
+                if data.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                    return Err(Error::PushSize);
+                }
+                if executing {
+                    let opcode = self.script.as_bytes()[pos];
+                    if self.require_minimal() && !is_minimal_push(data.as_bytes(), opcode) {
+                        return Err(Error::MinimalData);
+                    }
+                    self.stack.push(data.as_bytes().to_vec());
+                }
+            }
+            Instruction::Op(op) => {
+                if self.is_legacy() && op.to_u8() > OP_PUSHNUM_16.to_u8() {
+                    self.op_count += 1;
+                    if self.op_count > MAX_OPS_PER_SCRIPT {
+                        return Err(Error::OpCount);
+                    }
+                }
+                // Disabled opcodes fail even in unexecuted branches.
+                if op.classify(ClassifyContext::Legacy) == opcodes::Class::IllegalOp
+                    && op != OP_VERIF
+                    && op != OP_VERNOTIF
+                    && op != OP_INVALIDOPCODE
+                {
+                    return Err(Error::DisabledOpcode);
+                }
+                if op == OP_CODESEPARATOR
+                    && self.sig_version == SigVersion::Base
+                    && self.flags.has(VerifyFlags::CONST_SCRIPTCODE)
+                {
+                    return Err(Error::OpCodeSeparator);
+                }
+                let is_conditional = op.to_u8() >= OP_IF.to_u8() && op.to_u8() <= OP_ENDIF.to_u8();
+                if executing || is_conditional {
+                    self.execute(op, executing, pos, checker)?;
+                }
+            }
+        }
+
+        if self.stack.len() + self.altstack.len() > MAX_STACK_SIZE {
+            return Err(Error::StackSize);
+        }
+        self.opcode_pos += 1;
+        Ok(())
+    }
+
+    fn execute<S: SignatureChecker + ?Sized>(
+        &mut self,
+        op: opcodes::All,
+        executing: bool,
+        pos: usize,
+        checker: &mut S,
+    ) -> Result<(), Error> {
+        match op {
+            OP_PUSHNUM_NEG1 => self.push_num(-1),
+            op if op.to_u8() >= OP_PUSHNUM_1.to_u8() && op.to_u8() <= OP_PUSHNUM_16.to_u8() =>
+                self.push_num(i64::from(op.to_u8() - OP_PUSHNUM_1.to_u8() + 1)),
+
+            // Control
+            OP_NOP => {}
+            OP_CLTV => {
+                if !self.flags.has(VerifyFlags::CHECKLOCKTIMEVERIFY) {
+                    return self.upgradable_nop();
+                }
+                self.require(1)?;
+                // Lock times can be up to 5 bytes long, which is enough for the full range of
+                // the 32 bit lock time field.
+                let lock_time = self.num(1, 5)?;
+                if lock_time < 0 {
+                    return Err(Error::NegativeLockTime);
+                }
+                if !checker.check_lock_time(lock_time) {
+                    return Err(Error::UnsatisfiedLockTime);
+                }
+            }
+            OP_CSV => {
+                if !self.flags.has(VerifyFlags::CHECKSEQUENCEVERIFY) {
+                    return self.upgradable_nop();
+                }
+                self.require(1)?;
+                let sequence = self.num(1, 5)?;
+                if sequence < 0 {
+                    return Err(Error::NegativeLockTime);
+                }
+                // With the disable flag set the argument behaves as a NOP.
+                if sequence & (1 << 31) == 0 && !checker.check_sequence(sequence) {
+                    return Err(Error::UnsatisfiedLockTime);
+                }
+            }
+            OP_NOP1 | OP_NOP4 | OP_NOP5 | OP_NOP6 | OP_NOP7 | OP_NOP8 | OP_NOP9 | OP_NOP10 =>
+                return self.upgradable_nop(),
+            OP_IF | OP_NOTIF => {
+                let mut value = false;
+                if executing {
+                    if self.stack.is_empty() {
+                        return Err(Error::UnbalancedConditional);
+                    }
+                    let top = self.top(1);
+                    let is_minimal = top.is_empty() || top == [1];
+                    if self.sig_version == SigVersion::Tapscript && !is_minimal {
+                        return Err(Error::TapscriptMinimalIf);
+                    }
+                    if self.sig_version == SigVersion::WitnessV0
+                        && self.flags.has(VerifyFlags::MINIMALIF)
+                        && !is_minimal
+                    {
+                        return Err(Error::MinimalIf);
+                    }
+                    value = read_scriptbool(top);
+                    if op == OP_NOTIF {
+                        value = !value;
+                    }
+                    self.pop();
+                }
+                self.cond_stack.push(value);
+            }
+            OP_ELSE => self.cond_stack.toggle_top().ok_or(Error::UnbalancedConditional)?,
+            OP_ENDIF => {
+                self.cond_stack.pop().ok_or(Error::UnbalancedConditional)?;
+            }
+            OP_VERIFY => {
+                self.require(1)?;
+                if !read_scriptbool(self.top(1)) {
+                    return Err(Error::Verify);
+                }
+                self.pop();
+            }
+            OP_RETURN => return Err(Error::OpReturn),
+
+            // Stack operations
+            OP_TOALTSTACK => {
+                self.require(1)?;
+                let top = self.stack.pop().expect("checked above");
+                self.altstack.push(top);
+            }
+            OP_FROMALTSTACK => {
+                let top = self.altstack.pop().ok_or(Error::InvalidAltstackOperation)?;
+                self.stack.push(top);
+            }
+            OP_2DROP => {
+                self.require(2)?;
+                self.pop();
+                self.pop();
+            }
+            OP_2DUP => {
+                self.require(2)?;
+                let len = self.stack.len();
+                self.stack.extend_from_within_range(len - 2..len);
+            }
+            OP_3DUP => {
+                self.require(3)?;
+                let len = self.stack.len();
+                self.stack.extend_from_within_range(len - 3..len);
+            }
+            OP_2OVER => {
+                self.require(4)?;
+                let len = self.stack.len();
+                self.stack.extend_from_within_range(len - 4..len - 2);
+            }
+            OP_2ROT => {
+                self.require(6)?;
+                let len = self.stack.len();
+                let moved: Vec<_> = self.stack.drain(len - 6..len - 4).collect();
+                self.stack.extend(moved);
+            }
+            OP_2SWAP => {
+                self.require(4)?;
+                let len = self.stack.len();
+                self.stack.swap(len - 4, len - 2);
+                self.stack.swap(len - 3, len - 1);
+            }
+            OP_IFDUP => {
+                self.require(1)?;
+                if read_scriptbool(self.top(1)) {
+                    let top = self.top(1).to_vec();
+                    self.stack.push(top);
+                }
+            }
+            OP_DEPTH => self.push_num(self.stack.len() as i64),
+            OP_DROP => {
+                self.require(1)?;
+                self.pop();
+            }
+            OP_DUP => {
+                self.require(1)?;
+                let top = self.top(1).to_vec();
+                self.stack.push(top);
+            }
+            OP_NIP => {
+                self.require(2)?;
+                let len = self.stack.len();
+                self.stack.remove(len - 2);
+            }
+            OP_OVER => {
+                self.require(2)?;
+                let elem = self.top(2).to_vec();
+                self.stack.push(elem);
+            }
+            OP_PICK | OP_ROLL => {
+                self.require(2)?;
+                let n = self.num(1, 4)?;
+                self.pop();
+                if n < 0 || n as usize >= self.stack.len() {
+                    return Err(Error::InvalidStackOperation);
+                }
+                let index = self.stack.len() - 1 - n as usize;
+                let elem = if op == OP_ROLL {
+                    self.stack.remove(index)
+                } else {
+                    self.stack[index].clone()
+                };
+                self.stack.push(elem);
+            }
+            OP_ROT => {
+                self.require(3)?;
+                let len = self.stack.len();
+                self.stack.swap(len - 3, len - 2);
+                self.stack.swap(len - 2, len - 1);
+            }
+            OP_SWAP => {
+                self.require(2)?;
+                let len = self.stack.len();
+                self.stack.swap(len - 2, len - 1);
+            }
+            OP_TUCK => {
+                self.require(2)?;
+                let len = self.stack.len();
+                let top = self.top(1).to_vec();
+                self.stack.insert(len - 2, top);
+            }
+            OP_SIZE => {
+                self.require(1)?;
+                self.push_num(self.top(1).len() as i64);
+            }
+
+            // Bitwise logic
+            OP_EQUAL | OP_EQUALVERIFY => {
+                self.require(2)?;
+                let equal = self.top(2) == self.top(1);
+                self.pop();
+                self.pop();
+                self.push_bool(equal);
+                if op == OP_EQUALVERIFY {
+                    if !equal {
+                        return Err(Error::EqualVerify);
+                    }
+                    self.pop();
+                }
+            }
+
+            // Numeric
+            OP_1ADD | OP_1SUB | OP_NEGATE | OP_ABS | OP_NOT | OP_0NOTEQUAL => {
+                self.require(1)?;
+                let n = self.num(1, 4)?;
+                let result = match op {
+                    OP_1ADD => n + 1,
+                    OP_1SUB => n - 1,
+                    OP_NEGATE => -n,
+                    OP_ABS => n.abs(),
+                    OP_NOT => (n == 0) as i64,
+                    _ => (n != 0) as i64,
+                };
+                self.pop();
+                self.push_num(result);
+            }
+            OP_ADD
+            | OP_SUB
+            | OP_BOOLAND
+            | OP_BOOLOR
+            | OP_NUMEQUAL
+            | OP_NUMEQUALVERIFY
+            | OP_NUMNOTEQUAL
+            | OP_LESSTHAN
+            | OP_GREATERTHAN
+            | OP_LESSTHANOREQUAL
+            | OP_GREATERTHANOREQUAL
+            | OP_MIN
+            | OP_MAX => {
+                self.require(2)?;
+                let a = self.num(2, 4)?;
+                let b = self.num(1, 4)?;
+                let result = match op {
+                    OP_ADD => a + b,
+                    OP_SUB => a - b,
+                    OP_BOOLAND => (a != 0 && b != 0) as i64,
+                    OP_BOOLOR => (a != 0 || b != 0) as i64,
+                    OP_NUMEQUAL | OP_NUMEQUALVERIFY => (a == b) as i64,
+                    OP_NUMNOTEQUAL => (a != b) as i64,
+                    OP_LESSTHAN => (a < b) as i64,
+                    OP_GREATERTHAN => (a > b) as i64,
+                    OP_LESSTHANOREQUAL => (a <= b) as i64,
+                    OP_GREATERTHANOREQUAL => (a >= b) as i64,
+                    OP_MIN => a.min(b),
+                    _ => a.max(b),
+                };
+                self.pop();
+                self.pop();
+                self.push_num(result);
+                if op == OP_NUMEQUALVERIFY {
+                    if result == 0 {
+                        return Err(Error::NumEqualVerify);
+                    }
+                    self.pop();
+                }
+            }
+            OP_WITHIN => {
+                self.require(3)?;
+                let x = self.num(3, 4)?;
+                let min = self.num(2, 4)?;
+                let max = self.num(1, 4)?;
+                self.pop();
+                self.pop();
+                self.pop();
+                self.push_bool(min <= x && x < max);
+            }
+
+            // Crypto
+            OP_RIPEMD160 | OP_SHA1 | OP_SHA256 | OP_HASH160 | OP_HASH256 => {
+                self.require(1)?;
+                let hash = {
+                    let data = self.top(1);
+                    match op {
+                        OP_RIPEMD160 => ripemd160::Hash::hash(data).to_byte_array().to_vec(),
+                        OP_SHA1 => sha1::Hash::hash(data).to_byte_array().to_vec(),
+                        OP_SHA256 => sha256::Hash::hash(data).to_byte_array().to_vec(),
+                        OP_HASH160 => hash160::Hash::hash(data).to_byte_array().to_vec(),
+                        _ => sha256d::Hash::hash(data).to_byte_array().to_vec(),
+                    }
+                };
+                self.pop();
+                self.stack.push(hash);
+            }
+            OP_CODESEPARATOR => {
+                // The script code starts after the separator.
+                self.code_start = pos + 1;
+                self.exec_data.code_separator_pos = self.opcode_pos;
+            }
+            OP_CHECKSIG | OP_CHECKSIGVERIFY => {
+                self.require(2)?;
+                let (sig, pubkey) = (self.top(2).to_vec(), self.top(1).to_vec());
+                let success = self.eval_checksig(sig, pubkey, checker)?;
+                self.pop();
+                self.pop();
+                self.push_bool(success);
+                if op == OP_CHECKSIGVERIFY {
+                    if !success {
+                        return Err(Error::CheckSigVerify);
+                    }
+                    self.pop();
+                }
+            }
+            OP_CHECKSIGADD => {
+                if self.is_legacy() {
+                    return Err(Error::BadOpcode);
+                }
+                self.require(3)?;
+                let (sig, pubkey) = (self.top(3).to_vec(), self.top(1).to_vec());
+                let n = self.num(2, 4)?;
+                let success = self.eval_checksig(sig, pubkey, checker)?;
+                self.pop();
+                self.pop();
+                self.pop();
+                self.push_num(n + success as i64);
+            }
+            OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
+                let success = self.eval_checkmultisig(checker)?;
+                self.push_bool(success);
+                if op == OP_CHECKMULTISIGVERIFY {
+                    if !success {
+                        return Err(Error::CheckMultisigVerify);
+                    }
+                    self.pop();
+                }
+            }
+
+            _ => return Err(Error::BadOpcode),
+        }
+        Ok(())
+    }
+
+    fn upgradable_nop(&self) -> Result<(), Error> {
+        if self.flags.has(VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS) {
+            Err(Error::DiscourageUpgradableNops)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn script_code(&self) -> &[u8] { &self.script.as_bytes()[self.code_start..] }
+
+    /// Evaluates a signature check, returns whether the signature is valid.
+    ///
+    /// Errors are returned for failures that make the whole script fail.
+    fn eval_checksig<S: SignatureChecker + ?Sized>(
+        &mut self,
+        sig: Vec<u8>,
+        pubkey: Vec<u8>,
+        checker: &mut S,
+    ) -> Result<bool, Error> {
+        match self.sig_version {
+            SigVersion::Base | SigVersion::WitnessV0 => {
+                let mut script_code = self.script_code().to_vec();
+                if self.sig_version == SigVersion::Base {
+                    let found = find_and_delete(&mut script_code, &sig);
+                    if found > 0 && self.flags.has(VerifyFlags::CONST_SCRIPTCODE) {
+                        return Err(Error::SigFindAndDelete);
+                    }
+                }
+                check_signature_encoding(&sig, self.flags)?;
+                check_pubkey_encoding(&pubkey, self.flags, self.sig_version)?;
+                let success = checker.check_ecdsa_signature(
+                    &sig,
+                    &pubkey,
+                    Script::from_bytes(&script_code),
+                    self.sig_version,
+                );
+                if !success && self.flags.has(VerifyFlags::NULLFAIL) && !sig.is_empty() {
+                    return Err(Error::NullFail);
+                }
+                Ok(success)
+            }
+            SigVersion::Tapscript => {
+                // Empty signatures are a compact way to fail a check and don't use up the
+                // validation weight budget.
+                if !sig.is_empty() {
+                    self.validation_weight_left -= VALIDATION_WEIGHT_PER_SIGOP_PASSED;
+                    if self.validation_weight_left < 0 {
+                        return Err(Error::TapscriptValidationWeight);
+                    }
+                }
+                if pubkey.is_empty() {
+                    return Err(Error::TapscriptEmptyPubkey);
+                }
+                if pubkey.len() == 32 {
+                    if !sig.is_empty() {
+                        checker.check_schnorr_signature(
+                            &sig,
+                            &pubkey,
+                            self.sig_version,
+                            &self.exec_data,
+                        )?;
+                    }
+                } else if self.flags.has(VerifyFlags::DISCOURAGE_UPGRADABLE_PUBKEYTYPE) {
+                    return Err(Error::DiscourageUpgradablePubkeyType);
+                }
+                Ok(!sig.is_empty())
+            }
+            SigVersion::Taproot => unreachable!("key path spends don't execute scripts"),
+        }
+    }
+
+    fn eval_checkmultisig<S: SignatureChecker + ?Sized>(
+        &mut self,
+        checker: &mut S,
+    ) -> Result<bool, Error> {
+        if self.sig_version == SigVersion::Tapscript {
+            return Err(Error::TapscriptCheckMultisig);
+        }
+
+        let mut i = 1;
+        self.require(i)?;
+        let mut keys_count = self.num(i, 4)?;
+        if !(0..=MAX_PUBKEYS_PER_MULTISIG).contains(&keys_count) {
+            return Err(Error::PubkeyCount);
+        }
+        self.op_count += keys_count as usize;
+        if self.op_count > MAX_OPS_PER_SCRIPT {
+            return Err(Error::OpCount);
+        }
+        i += 1;
+        let mut ikey = i;
+        // Depth of the last key plus one, used for the NULLFAIL check when cleaning up.
+        let mut ikey2 = keys_count as usize + 2;
+        i += keys_count as usize;
+        self.require(i)?;
+
+        let mut sigs_count = self.num(i, 4)?;
+        if sigs_count < 0 || sigs_count > keys_count {
+            return Err(Error::SigCount);
+        }
+        i += 1;
+        let mut isig = i;
+        i += sigs_count as usize;
+        self.require(i)?;
+
+        let mut script_code = self.script_code().to_vec();
+        if self.sig_version == SigVersion::Base {
+            for k in 0..sigs_count as usize {
+                let found = find_and_delete(&mut script_code, self.top(isig + k));
+                if found > 0 && self.flags.has(VerifyFlags::CONST_SCRIPTCODE) {
+                    return Err(Error::SigFindAndDelete);
+                }
+            }
+        }
+
+        let mut success = true;
+        while success && sigs_count > 0 {
+            let sig = self.top(isig);
+            let pubkey = self.top(ikey);
+            check_signature_encoding(sig, self.flags)?;
+            check_pubkey_encoding(pubkey, self.flags, self.sig_version)?;
+            if checker.check_ecdsa_signature(
+                sig,
+                pubkey,
+                Script::from_bytes(&script_code),
+                self.sig_version,
+            ) {
+                isig += 1;
+                sigs_count -= 1;
+            }
+            ikey += 1;
+            keys_count -= 1;
+            // Fail early if there are more signatures left than keys.
+            if sigs_count > keys_count {
+                success = false;
+            }
+        }
+
+        // Clean up the arguments, if the check failed all signatures must be empty.
+        while i > 1 {
+            i -= 1;
+            if !success
+                && self.flags.has(VerifyFlags::NULLFAIL)
+                && ikey2 == 0
+                && !self.top(1).is_empty()
+            {
+                return Err(Error::NullFail);
+            }
+            ikey2 = ikey2.saturating_sub(1);
+            self.pop();
+        }
+
+        // A bug makes OP_CHECKMULTISIG consume one extra argument.
+        self.require(1)?;
+        if self.flags.has(VerifyFlags::NULLDUMMY) && !self.top(1).is_empty() {
+            return Err(Error::SigNullDummy);
+        }
+        self.pop();
+        Ok(success)
+    }
+}
+
+/// Extension to clone a range of a `Vec` to its end, `Vec::extend_from_within` needs Rust 1.53.
+trait ExtendFromWithin {
+    fn extend_from_within_range(&mut self, range: ops::Range<usize>);
+}
+
+impl ExtendFromWithin for Vec<Vec<u8>> {
+    fn extend_from_within_range(&mut self, range: ops::Range<usize>) {
+        for i in range {
+            let elem = self[i].clone();
+            self.push(elem);
+        }
+    }
+}
+
+/// Decodes a script number, numbers are at most `max_len` bytes long.
+fn read_num(bytes: &[u8], require_minimal: bool, max_len: usize) -> Result<i64, Error> {
+    if bytes.len() > max_len {
+        return Err(Error::NumericOverflow);
+    }
+    let (last, rest) = match bytes.split_last() {
+        Some(split) => split,
+        None => return Ok(0),
+    };
+    // The most significant byte can only be zero (excluding the sign bit) if the sign bit of the
+    // byte before it is set.
+    if require_minimal && last & 0x7f == 0 && rest.last().map(|b| b & 0x80 == 0).unwrap_or(true) {
+        return Err(Error::NonMinimalNumber);
+    }
+    let mut n = bytes.iter().rev().fold(0i64, |acc, b| (acc << 8) | i64::from(*b));
+    if last & 0x80 != 0 {
+        n &= !(0x80i64 << (8 * (bytes.len() - 1)));
+        n = -n;
+    }
+    Ok(n)
+}
+
+fn encode_num(n: i64) -> Vec<u8> {
+    let mut buf = [0u8; 8];
+    let len = write_scriptint(&mut buf, n);
+    buf[..len].to_vec()
+}
+
+/// Checks that `data` was pushed using the smallest possible push opcode.
+fn is_minimal_push(data: &[u8], opcode: u8) -> bool {
+    match data.len() {
+        0 => opcode == OP_PUSHBYTES_0.to_u8(),
+        // Should have used OP_PUSHNUM_1 to OP_PUSHNUM_16 or OP_PUSHNUM_NEG1.
+        1 if (data[0] >= 1 && data[0] <= 16) || data[0] == 0x81 => false,
+        len if len <= 75 => usize::from(opcode) == len,
+        len if len <= 255 => opcode == OP_PUSHDATA1.to_u8(),
+        len if len <= 65535 => opcode == OP_PUSHDATA2.to_u8(),
+        _ => true,
+    }
+}
+
+/// Removes all occurrences of a push of `sig` from `script_code`, returns the number of removed
+/// occurrences.
+///
+/// Occurrences are only matched at instruction boundaries, like `FindAndDelete` of Bitcoin Core.
+fn find_and_delete(script_code: &mut Vec<u8>, sig: &[u8]) -> usize {
+    let mut pattern = Vec::with_capacity(sig.len() + 5);
+    if sig.len() < OP_PUSHDATA1.to_u8() as usize {
+        pattern.push(sig.len() as u8);
+    } else if sig.len() <= 0xff {
+        pattern.push(OP_PUSHDATA1.to_u8());
+        pattern.push(sig.len() as u8);
+    } else if sig.len() <= 0xffff {
+        pattern.push(OP_PUSHDATA2.to_u8());
+        pattern.extend_from_slice(&(sig.len() as u16).to_le_bytes());
+    } else {
+        pattern.push(OP_PUSHDATA4.to_u8());
+        pattern.extend_from_slice(&(sig.len() as u32).to_le_bytes());
+    }
+    pattern.extend_from_slice(sig);
+
+    let mut found = 0;
+    let mut result = Vec::with_capacity(script_code.len());
+    let mut pos = 0;
+    let mut copied = 0;
+    loop {
+        result.extend_from_slice(&script_code[copied..pos]);
+        while script_code[pos..].starts_with(&pattern) {
+            pos += pattern.len();
+            found += 1;
+        }
+        copied = pos;
+        let mut instructions = Script::from_bytes(&script_code[pos..]).instructions();
+        match instructions.next() {
+            Some(Ok(_)) => pos = script_code.len() - instructions.as_script().len(),
+            _ => break,
+        }
+    }
+    if found > 0 {
+        result.extend_from_slice(&script_code[copied..]);
+        *script_code = result;
+    }
+    found
+}
+
+/// Removes the `OP_CODESEPARATOR`s from a legacy script code before computing the signature hash.
+///
+/// Like Bitcoin Core, the data of a truncated push at the end of the script is dropped as well.
+fn remove_code_separators(script_code: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(script_code.len());
+    let mut copied = 0;
+    let mut pos = 0;
+    while pos < script_code.len() {
+        let opcode = script_code[pos];
+        pos += 1;
+        let len_size = match opcode {
+            0x4c => 1,
+            0x4d => 2,
+            0x4e => 4,
+            _ => 0,
+        };
+        let len = if opcode < OP_PUSHDATA1.to_u8() {
+            usize::from(opcode)
+        } else {
+            match script_code.get(pos..pos + len_size) {
+                Some(bytes) => bytes.iter().rev().fold(0, |acc, b| (acc << 8) | usize::from(*b)),
+                None => break,
+            }
+        };
+        pos += len_size;
+        if script_code.len() - pos < len {
+            break;
+        }
+        pos += len;
+        if opcode == OP_CODESEPARATOR.to_u8() {
+            result.extend_from_slice(&script_code[copied..pos - 1]);
+            copied = pos;
+        }
+    }
+    result.extend_from_slice(&script_code[copied..pos]);
+    result
+}
+
+/// Checks the encoding of an ECDSA signature according to the flags.
+fn check_signature_encoding(sig: &[u8], flags: VerifyFlags) -> Result<(), Error> {
+    // An empty signature is a compact way to provide an invalid signature.
+    if sig.is_empty() {
+        return Ok(());
+    }
+    if (flags.has(VerifyFlags::DERSIG)
+        || flags.has(VerifyFlags::LOW_S)
+        || flags.has(VerifyFlags::STRICTENC))
+        && !is_valid_signature_encoding(sig)
+    {
+        return Err(Error::SigDer);
+    }
+    if flags.has(VerifyFlags::LOW_S) && !is_low_s(&sig[..sig.len() - 1]) {
+        return Err(Error::SigHighS);
+    }
+    if flags.has(VerifyFlags::STRICTENC) {
+        let hash_type = sig[sig.len() - 1] & !0x80;
+        if !(0x01..=0x03).contains(&hash_type) {
+            return Err(Error::SigHashType);
+        }
+    }
+    Ok(())
+}
+
+/// Checks that `sig` is a strict DER signature followed by a sighash byte (BIP 66).
+fn is_valid_signature_encoding(sig: &[u8]) -> bool {
+    // Format: 0x30 [total-length] 0x02 [R-length] [R] 0x02 [S-length] [S] [sighash]
+    let len = sig.len();
+    if !(9..=73).contains(&len) || sig[0] != 0x30 || usize::from(sig[1]) != len - 3 {
+        return false;
+    }
+    let len_r = usize::from(sig[3]);
+    if 5 + len_r >= len {
+        return false;
+    }
+    let len_s = usize::from(sig[5 + len_r]);
+    if len_r + len_s + 7 != len {
+        return false;
+    }
+    // R must be a positive integer without excess padding.
+    if sig[2] != 0x02 || len_r == 0 || sig[4] & 0x80 != 0 {
+        return false;
+    }
+    if len_r > 1 && sig[4] == 0x00 && sig[5] & 0x80 == 0 {
+        return false;
+    }
+    // Same for S.
+    if sig[len_r + 4] != 0x02 || len_s == 0 || sig[len_r + 6] & 0x80 != 0 {
+        return false;
+    }
+    if len_s > 1 && sig[len_r + 6] == 0x00 && sig[len_r + 7] & 0x80 == 0 {
+        return false;
+    }
+    true
+}
+
+fn is_low_s(der: &[u8]) -> bool {
+    match ecdsa::Signature::from_der_lax(der) {
+        Ok(sig) => {
+            let mut normalized = sig;
+            normalized.normalize_s();
+            normalized == sig
+        }
+        Err(_) => false,
+    }
+}
+
+/// Checks the encoding of a public key according to the flags.
+fn check_pubkey_encoding(
+    pubkey: &[u8],
+    flags: VerifyFlags,
+    sig_version: SigVersion,
+) -> Result<(), Error> {
+    let is_compressed = pubkey.len() == 33 && (pubkey[0] == 0x02 || pubkey[0] == 0x03);
+    let is_uncompressed = pubkey.len() == 65 && pubkey[0] == 0x04;
+    if flags.has(VerifyFlags::STRICTENC) && !is_compressed && !is_uncompressed {
+        return Err(Error::PubkeyType);
+    }
+    // Only compressed keys are accepted in segwit.
+    if flags.has(VerifyFlags::WITNESS_PUBKEYTYPE)
+        && sig_version == SigVersion::WitnessV0
+        && !is_compressed
+    {
+        return Err(Error::WitnessPubkeyType);
+    }
+    Ok(())
+}
+
+/// Reasons a script fails verification.
+///
+/// The variants correspond to the `SCRIPT_ERR_*` codes of Bitcoin Core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Error {
+    /// The script finished with an empty or false top stack element.
+    EvalFalse,
+    /// `OP_RETURN` was executed.
+    OpReturn,
+    /// The script is larger than [`MAX_SCRIPT_SIZE`].
+    ScriptSize,
+    /// A pushed element is larger than 520 bytes.
+    PushSize,
+    /// The script has more than [`MAX_OPS_PER_SCRIPT`] non-push operations.
+    OpCount,
+    /// The stacks have more than [`MAX_STACK_SIZE`] elements.
+    StackSize,
+    /// The signature count of `OP_CHECKMULTISIG` is negative or exceeds the key count.
+    SigCount,
+    /// The public key count of `OP_CHECKMULTISIG` is out of range.
+    PubkeyCount,
+    /// `OP_VERIFY` failed.
+    Verify,
+    /// `OP_EQUALVERIFY` failed.
+    EqualVerify,
+    /// `OP_CHECKMULTISIGVERIFY` failed.
+    CheckMultisigVerify,
+    /// `OP_CHECKSIGVERIFY` failed.
+    CheckSigVerify,
+    /// `OP_NUMEQUALVERIFY` failed.
+    NumEqualVerify,
+    /// An invalid or truncated opcode was encountered.
+    BadOpcode,
+    /// A disabled opcode was encountered.
+    DisabledOpcode,
+    /// An operation needed more stack elements than available.
+    InvalidStackOperation,
+    /// `OP_FROMALTSTACK` was executed with an empty alt stack.
+    InvalidAltstackOperation,
+    /// `OP_IF` and `OP_ENDIF` are unbalanced.
+    UnbalancedConditional,
+    /// The lock time argument is negative.
+    NegativeLockTime,
+    /// The lock time requirement is not satisfied by the transaction.
+    UnsatisfiedLockTime,
+    /// The signature hash type is undefined.
+    SigHashType,
+    /// The signature is not strictly DER encoded.
+    SigDer,
+    /// Data was not pushed with the smallest possible opcode.
+    MinimalData,
+    /// The scriptSig contains non-push operations.
+    SigPushOnly,
+    /// The S value of the signature is not in the lower half of the curve order.
+    SigHighS,
+    /// The dummy element of `OP_CHECKMULTISIG` is not empty.
+    SigNullDummy,
+    /// The public key is neither compressed nor uncompressed.
+    PubkeyType,
+    /// The stack does not contain exactly one element after evaluation.
+    CleanStack,
+    /// The argument of `OP_IF` or `OP_NOTIF` is not minimal.
+    MinimalIf,
+    /// The signature of a failed signature check is not empty.
+    NullFail,
+    /// An upgradable `OP_NOP` was executed.
+    DiscourageUpgradableNops,
+    /// A witness program of an unknown version was spent.
+    DiscourageUpgradableWitnessProgram,
+    /// A taproot leaf of an unknown version was spent.
+    DiscourageUpgradableTaprootVersion,
+    /// The tapscript contains an `OP_SUCCESS` opcode.
+    DiscourageOpSuccess,
+    /// A tapscript signature check used a public key of an unknown type.
+    DiscourageUpgradablePubkeyType,
+    /// A segwit v0 program has an invalid length.
+    WitnessProgramWrongLength,
+    /// The witness of a witness program is empty.
+    WitnessProgramWitnessEmpty,
+    /// The witness does not match the witness program.
+    WitnessProgramMismatch,
+    /// The scriptSig of a native witness program is not empty.
+    WitnessMalleated,
+    /// The scriptSig of a P2SH wrapped witness program is not a single push of the redeem script.
+    WitnessMalleatedP2sh,
+    /// A witness was provided for an input that is not a witness program.
+    WitnessUnexpected,
+    /// The public key in a segwit v0 script is not compressed.
+    WitnessPubkeyType,
+    /// The schnorr signature has an invalid size.
+    SchnorrSigSize,
+    /// The schnorr signature hash type is invalid.
+    SchnorrSigHashType,
+    /// The schnorr signature is invalid.
+    SchnorrSig,
+    /// The taproot control block has an invalid size.
+    TaprootWrongControlSize,
+    /// The tapscript executed too many signature checks for its witness size.
+    TapscriptValidationWeight,
+    /// `OP_CHECKMULTISIG` was executed in tapscript.
+    TapscriptCheckMultisig,
+    /// The argument of `OP_IF` or `OP_NOTIF` in tapscript is not minimal.
+    TapscriptMinimalIf,
+    /// A tapscript signature check used an empty public key.
+    TapscriptEmptyPubkey,
+    /// `OP_CODESEPARATOR` was used in a legacy script.
+    OpCodeSeparator,
+    /// A signature was found in the script code of a legacy script.
+    SigFindAndDelete,
+    /// A number argument is longer than allowed.
+    NumericOverflow,
+    /// A number argument is not minimally encoded.
+    NonMinimalNumber,
+    /// The verification flags are inconsistent, `CLEANSTACK` requires `WITNESS` which requires
+    /// `P2SH`.
+    InvalidFlags,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Error::*;
+
+        let s = match *self {
+            EvalFalse => "script finished with a false or empty top stack element",
+            OpReturn => "OP_RETURN was encountered",
+            ScriptSize => "script is too big",
+            PushSize => "push value size limit exceeded",
+            OpCount => "operation limit exceeded",
+            StackSize => "stack size limit exceeded",
+            SigCount => "signature count negative or greater than pubkey count",
+            PubkeyCount => "pubkey count negative or limit exceeded",
+            Verify => "OP_VERIFY failed",
+            EqualVerify => "OP_EQUALVERIFY failed",
+            CheckMultisigVerify => "OP_CHECKMULTISIGVERIFY failed",
+            CheckSigVerify => "OP_CHECKSIGVERIFY failed",
+            NumEqualVerify => "OP_NUMEQUALVERIFY failed",
+            BadOpcode => "opcode missing or not understood",
+            DisabledOpcode => "attempted to use a disabled opcode",
+            InvalidStackOperation => "operation not valid with the current stack size",
+            InvalidAltstackOperation => "operation not valid with the current altstack size",
+            UnbalancedConditional => "invalid OP_IF construction",
+            NegativeLockTime => "negative locktime",
+            UnsatisfiedLockTime => "locktime requirement not satisfied",
+            SigHashType => "signature hash type missing or not understood",
+            SigDer => "non-canonical DER signature",
+            MinimalData => "data push larger than necessary",
+            SigPushOnly => "only push operators allowed in signatures",
+            SigHighS => "non-canonical signature: S value is unnecessarily high",
+            SigNullDummy => "dummy CHECKMULTISIG argument must be zero",
+            PubkeyType => "public key is neither compressed or uncompressed",
+            CleanStack => "stack size must be exactly one after execution",
+            MinimalIf => "OP_IF/NOTIF argument must be minimal",
+            NullFail => "signature must be zero for failed CHECK(MULTI)SIG operation",
+            DiscourageUpgradableNops => "NOPx reserved for soft-fork upgrades",
+            DiscourageUpgradableWitnessProgram => "witness version reserved for soft-fork upgrades",
+            DiscourageUpgradableTaprootVersion => "taproot version reserved for soft-fork upgrades",
+            DiscourageOpSuccess => "OP_SUCCESSx reserved for soft-fork upgrades",
+            DiscourageUpgradablePubkeyType => "public key version reserved for soft-fork upgrades",
+            WitnessProgramWrongLength => "witness program has incorrect length",
+            WitnessProgramWitnessEmpty => "witness program was passed an empty witness",
+            WitnessProgramMismatch => "witness program hash mismatch",
+            WitnessMalleated => "witness requires empty scriptSig",
+            WitnessMalleatedP2sh => "witness requires only-redeemscript scriptSig",
+            WitnessUnexpected => "witness provided for non-witness script",
+            WitnessPubkeyType => "using non-compressed keys in segwit",
+            SchnorrSigSize => "invalid schnorr signature size",
+            SchnorrSigHashType => "invalid schnorr signature hash type",
+            SchnorrSig => "invalid schnorr signature",
+            TaprootWrongControlSize => "invalid taproot control block size",
+            TapscriptValidationWeight => "too much signature validation relative to witness weight",
+            TapscriptCheckMultisig => "OP_CHECKMULTISIG(VERIFY) is not available in tapscript",
+            TapscriptMinimalIf => "OP_IF/NOTIF argument must be minimal in tapscript",
+            TapscriptEmptyPubkey => "empty public key in tapscript",
+            OpCodeSeparator => "using OP_CODESEPARATOR in non-witness script",
+            SigFindAndDelete => "signature is found in scriptCode",
+            NumericOverflow => "script number overflow",
+            NonMinimalNumber => "non-minimally encoded script number",
+            InvalidFlags => "inconsistent script verification flags",
+        };
+        f.write_str(s)
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::TryFrom;
+    use core::str::FromStr;
+
+    use serde_json::Value;
+
+    use super::*;
+    use crate::blockdata::block::check_transaction;
+    use crate::blockdata::locktime::absolute;
+    use crate::blockdata::script::{Builder, PushBytesBuf, ScriptBuf};
+    use crate::blockdata::transaction::{OutPoint, TxIn};
+    use crate::consensus::deserialize;
+    use crate::hash_types::Txid;
+    use crate::hashes::hex::FromHex;
+    use crate::internal_macros::hex;
+    use crate::taproot::{LeafVersion, TaprootBuilder, TaprootSpendInfo};
+
+    /// All flags known to the test vectors.
+    const FLAG_NAMES: &[(&str, VerifyFlags)] = &[
+        ("P2SH", VerifyFlags::P2SH),
+        ("STRICTENC", VerifyFlags::STRICTENC),
+        ("DERSIG", VerifyFlags::DERSIG),
+        ("LOW_S", VerifyFlags::LOW_S),
+        ("SIGPUSHONLY", VerifyFlags::SIGPUSHONLY),
+        ("MINIMALDATA", VerifyFlags::MINIMALDATA),
+        ("NULLDUMMY", VerifyFlags::NULLDUMMY),
+        ("DISCOURAGE_UPGRADABLE_NOPS", VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS),
+        ("CLEANSTACK", VerifyFlags::CLEANSTACK),
+        ("MINIMALIF", VerifyFlags::MINIMALIF),
+        ("NULLFAIL", VerifyFlags::NULLFAIL),
+        ("CHECKLOCKTIMEVERIFY", VerifyFlags::CHECKLOCKTIMEVERIFY),
+        ("CHECKSEQUENCEVERIFY", VerifyFlags::CHECKSEQUENCEVERIFY),
+        ("WITNESS", VerifyFlags::WITNESS),
+        (
+            "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM",
+            VerifyFlags::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM,
+        ),
+        ("WITNESS_PUBKEYTYPE", VerifyFlags::WITNESS_PUBKEYTYPE),
+        ("CONST_SCRIPTCODE", VerifyFlags::CONST_SCRIPTCODE),
+        ("TAPROOT", VerifyFlags::TAPROOT),
+        ("DISCOURAGE_UPGRADABLE_PUBKEYTYPE", VerifyFlags::DISCOURAGE_UPGRADABLE_PUBKEYTYPE),
+        ("DISCOURAGE_OP_SUCCESS", VerifyFlags::DISCOURAGE_OP_SUCCESS),
+        (
+            "DISCOURAGE_UPGRADABLE_TAPROOT_VERSION",
+            VerifyFlags::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION,
+        ),
+    ];
+
+    fn all_flags() -> VerifyFlags {
+        FLAG_NAMES.iter().fold(VerifyFlags::NONE, |acc, &(_, flag)| acc | flag)
+    }
+
+    fn without(flags: VerifyFlags, remove: VerifyFlags) -> VerifyFlags {
+        VerifyFlags::from(flags.to_u32() & !remove.to_u32())
+    }
+
+    /// Parses a comma separated list of flag names like Bitcoin Core's `ParseScriptFlags`.
+    fn parse_flags(s: &str) -> VerifyFlags {
+        if s.is_empty() || s == "NONE" {
+            return VerifyFlags::NONE;
+        }
+        s.split(',').fold(VerifyFlags::NONE, |acc, name| {
+            let flag = FLAG_NAMES
+                .iter()
+                .find(|&&(n, _)| n == name)
+                .unwrap_or_else(|| panic!("unknown verification flag {}", name))
+                .1;
+            acc | flag
+        })
+    }
+
+    /// Adds the flags implied by the set ones (`FillFlags` in Bitcoin Core).
+    fn fill_flags(mut flags: VerifyFlags) -> VerifyFlags {
+        if flags.has(VerifyFlags::CLEANSTACK) {
+            flags |= VerifyFlags::WITNESS;
+        }
+        if flags.has(VerifyFlags::WITNESS) {
+            flags |= VerifyFlags::P2SH;
+        }
+        flags
+    }
+
+    /// Removes the flags whose requirements are unset (`TrimFlags` in Bitcoin Core).
+    fn trim_flags(mut flags: VerifyFlags) -> VerifyFlags {
+        if !flags.has(VerifyFlags::P2SH) {
+            flags = without(flags, VerifyFlags::WITNESS);
+        }
+        if !flags.has(VerifyFlags::WITNESS) {
+            flags = without(flags, VerifyFlags::CLEANSTACK);
+        }
+        flags
+    }
+
+    fn opcode_by_name(name: &str) -> Option<opcodes::All> {
+        let name = match name {
+            "CHECKLOCKTIMEVERIFY" | "NOP2" => "CLTV",
+            "CHECKSEQUENCEVERIFY" | "NOP3" => "CSV",
+            name => name,
+        };
+        (OP_RESERVED.to_u8()..=OP_CHECKSIGADD.to_u8())
+            .chain(core::iter::once(OP_INVALIDOPCODE.to_u8()))
+            .map(opcodes::All::from)
+            .filter(|op| *op == OP_RESERVED || op.to_u8() >= OP_NOP.to_u8())
+            .find(|op| {
+                let op_name = op.to_string();
+                op_name == name || op_name[3..] == *name
+            })
+    }
+
+    /// Parses the script notation of the Bitcoin Core test vectors (`ParseScript`).
+    fn parse_script(s: &str) -> ScriptBuf {
+        let mut script = Vec::new();
+        for word in s.split_whitespace() {
+            let is_number = {
+                let digits = if word.starts_with('-') { &word[1..] } else { word };
+                !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+            };
+            if is_number {
+                let n = i64::from_str(word).unwrap();
+                let builder = Builder::new().push_int(n);
+                script.extend_from_slice(builder.as_bytes());
+            } else if word.starts_with("0x") && word.len() > 2 {
+                script.extend(Vec::<u8>::from_hex(&word[2..]).unwrap());
+            } else if word.len() >= 2 && word.starts_with('\'') && word.ends_with('\'') {
+                let data =
+                    PushBytesBuf::try_from(word.as_bytes()[1..word.len() - 1].to_vec()).unwrap();
+                script.extend_from_slice(Builder::new().push_slice(data).as_bytes());
+            } else {
+                let op =
+                    opcode_by_name(word).unwrap_or_else(|| panic!("unknown script word {}", word));
+                script.push(op.to_u8());
+            }
+        }
+        ScriptBuf::from(script)
+    }
+
+    /// The name of the error in the Bitcoin Core test vectors.
+    fn error_name(error: Result<(), Error>) -> &'static str {
+        use Error::*;
+
+        let error = match error {
+            Ok(()) => return "OK",
+            Err(error) => error,
+        };
+        match error {
+            EvalFalse => "EVAL_FALSE",
+            OpReturn => "OP_RETURN",
+            ScriptSize => "SCRIPT_SIZE",
+            PushSize => "PUSH_SIZE",
+            OpCount => "OP_COUNT",
+            StackSize => "STACK_SIZE",
+            SigCount => "SIG_COUNT",
+            PubkeyCount => "PUBKEY_COUNT",
+            Verify => "VERIFY",
+            EqualVerify => "EQUALVERIFY",
+            CheckMultisigVerify => "CHECKMULTISIGVERIFY",
+            CheckSigVerify => "CHECKSIGVERIFY",
+            NumEqualVerify => "NUMEQUALVERIFY",
+            BadOpcode => "BAD_OPCODE",
+            DisabledOpcode => "DISABLED_OPCODE",
+            InvalidStackOperation => "INVALID_STACK_OPERATION",
+            InvalidAltstackOperation => "INVALID_ALTSTACK_OPERATION",
+            UnbalancedConditional => "UNBALANCED_CONDITIONAL",
+            NegativeLockTime => "NEGATIVE_LOCKTIME",
+            UnsatisfiedLockTime => "UNSATISFIED_LOCKTIME",
+            SigHashType => "SIG_HASHTYPE",
+            SigDer => "SIG_DER",
+            MinimalData => "MINIMALDATA",
+            SigPushOnly => "SIG_PUSHONLY",
+            SigHighS => "SIG_HIGH_S",
+            SigNullDummy => "SIG_NULLDUMMY",
+            PubkeyType => "PUBKEYTYPE",
+            CleanStack => "CLEANSTACK",
+            MinimalIf => "MINIMALIF",
+            NullFail => "NULLFAIL",
+            DiscourageUpgradableNops => "DISCOURAGE_UPGRADABLE_NOPS",
+            DiscourageUpgradableWitnessProgram => "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM",
+            DiscourageUpgradableTaprootVersion => "DISCOURAGE_UPGRADABLE_TAPROOT_VERSION",
+            DiscourageOpSuccess => "DISCOURAGE_OP_SUCCESS",
+            DiscourageUpgradablePubkeyType => "DISCOURAGE_UPGRADABLE_PUBKEYTYPE",
+            WitnessProgramWrongLength => "WITNESS_PROGRAM_WRONG_LENGTH",
+            WitnessProgramWitnessEmpty => "WITNESS_PROGRAM_WITNESS_EMPTY",
+            WitnessProgramMismatch => "WITNESS_PROGRAM_MISMATCH",
+            WitnessMalleated => "WITNESS_MALLEATED",
+            WitnessMalleatedP2sh => "WITNESS_MALLEATED_P2SH",
+            WitnessUnexpected => "WITNESS_UNEXPECTED",
+            WitnessPubkeyType => "WITNESS_PUBKEYTYPE",
+            SchnorrSigSize => "SCHNORR_SIG_SIZE",
+            SchnorrSigHashType => "SCHNORR_SIG_HASHTYPE",
+            SchnorrSig => "SCHNORR_SIG",
+            TaprootWrongControlSize => "TAPROOT_WRONG_CONTROL_SIZE",
+            TapscriptValidationWeight => "TAPSCRIPT_VALIDATION_WEIGHT",
+            TapscriptCheckMultisig => "TAPSCRIPT_CHECKMULTISIG",
+            TapscriptMinimalIf => "TAPSCRIPT_MINIMALIF",
+            TapscriptEmptyPubkey => "TAPSCRIPT_EMPTY_PUBKEY",
+            OpCodeSeparator => "OP_CODESEPARATOR",
+            SigFindAndDelete => "SIG_FINDANDDELETE",
+            // Bitcoin Core throws an exception for invalid numbers, reported as an unknown error.
+            NumericOverflow | NonMinimalNumber | InvalidFlags => "UNKNOWN_ERROR",
+        }
+    }
+
+    /// Builds the transaction funding the output spent by a script test (Bitcoin Core's
+    /// `BuildCreditingTransaction`).
+    fn crediting_tx(script_pubkey: ScriptBuf, value: u64) -> Transaction {
+        Transaction {
+            version: 1,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: Builder::new().push_int(0).push_int(0).into_script(),
+                sequence: Sequence::MAX,
+                witness: Witness::default(),
+            }],
+            output: vec![TxOut { value, script_pubkey }],
+        }
+    }
+
+    /// Builds the transaction spending the output of `credit` (Bitcoin Core's
+    /// `BuildSpendingTransaction`).
+    fn spending_tx(script_sig: ScriptBuf, witness: Witness, credit: &Transaction) -> Transaction {
+        Transaction {
+            version: 1,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(credit.txid(), 0),
+                script_sig,
+                sequence: Sequence::MAX,
+                witness,
+            }],
+            output: vec![TxOut { value: credit.output[0].value, script_pubkey: ScriptBuf::new() }],
+        }
+    }
+
+    /// Parses the witness stack items of a script test.
+    ///
+    /// Besides hex, items are `#SCRIPT#` followed by a script and `#CONTROLBLOCK#` for the control
+    /// block of the last such script as the only leaf of a tree with the NUMS internal key of
+    /// BIP 341, whose spend info is returned for the `#TAPROOTOUTPUT#` script pubkey.
+    fn parse_witness(
+        secp: &Secp256k1<secp256k1::VerifyOnly>,
+        items: &[Value],
+    ) -> (Witness, Option<TaprootSpendInfo>) {
+        const SCRIPT_FLAG: &str = "#SCRIPT#";
+        let nums = XOnlyPublicKey::from_slice(&hex!(
+            "50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0"
+        ))
+        .unwrap();
+
+        let mut witness = Witness::new();
+        let mut script = None;
+        let mut spend_info = None;
+        for item in items {
+            let item = item.as_str().unwrap();
+            if item.starts_with(SCRIPT_FLAG) {
+                let leaf = parse_script(&item[SCRIPT_FLAG.len()..]);
+                witness.push(leaf.as_bytes());
+                script = Some(leaf);
+            } else if item == "#CONTROLBLOCK#" {
+                let leaf = script.clone().expect("#CONTROLBLOCK# without #SCRIPT#");
+                let info = TaprootBuilder::new()
+                    .add_leaf(0, leaf.clone())
+                    .unwrap()
+                    .finalize(secp, nums)
+                    .unwrap();
+                let control_block = info.control_block(&(leaf, LeafVersion::TapScript)).unwrap();
+                witness.push(control_block.serialize());
+                spend_info = Some(info);
+            } else {
+                witness.push(Vec::<u8>::from_hex(item).unwrap());
+            }
+        }
+        (witness, spend_info)
+    }
+
+    /// Runs the script tests in `data`, which has the format of `script_tests.json` of Bitcoin
+    /// Core.
+    fn run_script_tests(data: &str) {
+        let secp = Secp256k1::verification_only();
+        let tests: Vec<Value> = serde_json::from_str(data).unwrap();
+
+        let mut count = 0;
+        for test in tests {
+            let test = test.as_array().unwrap();
+            // Single element arrays are comments.
+            if test.len() == 1 {
+                continue;
+            }
+            let (witness, spend_info, value, pos) = match test[0].as_array() {
+                Some(witness) => {
+                    let (value, items) = witness.split_last().unwrap();
+                    let (witness, spend_info) = parse_witness(&secp, items);
+                    let value = (value.as_f64().unwrap() * 100_000_000.0).round() as u64;
+                    (witness, spend_info, value, 1)
+                }
+                None => (Witness::default(), None, 0, 0),
+            };
+            let script_sig = parse_script(test[pos].as_str().unwrap());
+            let script_pubkey = match test[pos + 1].as_str().unwrap() {
+                "0x51 0x20 #TAPROOTOUTPUT#" => ScriptBuf::new_v1_p2tr_tweaked(
+                    spend_info.expect("#TAPROOTOUTPUT# without #CONTROLBLOCK#").output_key(),
+                ),
+                script_pubkey => parse_script(script_pubkey),
+            };
+            let mut flags = parse_flags(test[pos + 2].as_str().unwrap());
+            let expected = test[pos + 3].as_str().unwrap();
+            if flags.has(VerifyFlags::CLEANSTACK) {
+                flags |= VerifyFlags::P2SH | VerifyFlags::WITNESS;
+            }
+
+            let credit = crediting_tx(script_pubkey.clone(), value);
+            let spend = spending_tx(script_sig.clone(), witness.clone(), &credit);
+            let mut checker = TransactionSignatureChecker::new(&secp, &spend, 0, &credit.output);
+            let result = verify_script(&script_sig, &script_pubkey, &witness, flags, &mut checker);
+            assert_eq!(error_name(result), expected, "test {:?}", test);
+            count += 1;
+        }
+        assert!(count > 0);
+    }
+
+    #[test]
+    fn script_tests_local() {
+        run_script_tests(include_str!("../../../tests/data/script_tests_local.json"));
+    }
+
+    /// Parses a transaction test, returning the transaction, the spent outputs and the flags.
+    fn parse_tx_test(test: &[Value]) -> (Transaction, Vec<TxOut>, VerifyFlags) {
+        let tx: Transaction =
+            deserialize(&Vec::<u8>::from_hex(test[1].as_str().unwrap()).unwrap()).unwrap();
+        let spent = test[0]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|input| {
+                let input = input.as_array().unwrap();
+                let txid = Txid::from_str(input[0].as_str().unwrap()).unwrap();
+                let vout = input[1].as_i64().unwrap() as u32;
+                let script_pubkey = parse_script(input[2].as_str().unwrap());
+                let value = input.get(3).map(|v| v.as_u64().unwrap()).unwrap_or(0);
+                (OutPoint::new(txid, vout), TxOut { value, script_pubkey })
+            })
+            .collect::<Vec<_>>();
+        let prevouts = tx
+            .input
+            .iter()
+            .map(|input| {
+                spent
+                    .iter()
+                    .find(|(outpoint, _)| *outpoint == input.previous_output)
+                    .expect("missing spent output")
+                    .1
+                    .clone()
+            })
+            .collect();
+        (tx, prevouts, parse_flags(test[2].as_str().unwrap()))
+    }
+
+    fn verify_tx(tx: &Transaction, prevouts: &[TxOut], flags: VerifyFlags) -> bool {
+        let secp = Secp256k1::verification_only();
+        let mut spent = prevouts.iter();
+        tx.verify_scripts(&secp, |_| spent.next().cloned(), flags).is_ok()
+    }
+
+    fn tx_tests(data: &str) -> Vec<Vec<Value>> {
+        let tests: Vec<Value> = serde_json::from_str(data).unwrap();
+        tests
+            .into_iter()
+            .map(|test| test.as_array().unwrap().clone())
+            // Tests start with the spent outputs, other entries are comments.
+            .filter(|test| test[0].is_array())
+            .collect()
+    }
+
+    /// Runs the valid transaction tests in `data`, which has the format of `tx_valid.json` of
+    /// Bitcoin Core.
+    fn run_tx_valid(data: &str) {
+        let tests = tx_tests(data);
+        assert!(!tests.is_empty());
+        for test in tests {
+            // The flags of valid transaction tests are the ones to exclude.
+            let (tx, prevouts, excluded) = parse_tx_test(&test);
+            assert!(check_transaction(0, &tx).is_ok(), "tx not well formed {:?}", test);
+            let flags = without(all_flags(), excluded);
+            assert_eq!(flags, fill_flags(flags), "bad test flags {:?}", test);
+            assert!(verify_tx(&tx, &prevouts, flags), "tx unexpectedly failed {:?}", test);
+
+            for &(_, flag) in FLAG_NAMES {
+                // Removing flags must not invalidate a valid transaction. Unenforced lock time
+                // opcodes are upgradable NOPs again, so their discouragement goes with them.
+                let mut remove = flag;
+                if flag == VerifyFlags::CHECKLOCKTIMEVERIFY
+                    || flag == VerifyFlags::CHECKSEQUENCEVERIFY
+                {
+                    remove |= VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS;
+                }
+                let flags = trim_flags(without(flags, remove));
+                assert!(verify_tx(&tx, &prevouts, flags), "tx unexpectedly failed {:?}", test);
+
+                // Every excluded flag must be required for the transaction to be valid.
+                let excluding_one = trim_flags(without(excluded, flag));
+                if excluding_one != excluded {
+                    let flags = without(all_flags(), excluding_one);
+                    assert!(!verify_tx(&tx, &prevouts, flags), "too many flags unset {:?}", test);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tx_valid_local() { run_tx_valid(include_str!("../../../tests/data/tx_valid_local.json")); }
+
+    /// Runs the invalid transaction tests in `data`, which has the format of `tx_invalid.json` of
+    /// Bitcoin Core.
+    fn run_tx_invalid(data: &str) {
+        let tests = tx_tests(data);
+        assert!(!tests.is_empty());
+        for test in tests {
+            // Transactions failing `CheckTransaction` are marked as such instead of with flags.
+            let bad_tx = test[2].as_str() == Some("BADTX");
+            let tx: Transaction =
+                deserialize(&Vec::<u8>::from_hex(test[1].as_str().unwrap()).unwrap()).unwrap();
+            assert_eq!(check_transaction(0, &tx).is_err(), bad_tx, "BADTX mismatch {:?}", test);
+            if bad_tx {
+                continue;
+            }
+            let (tx, prevouts, flags) = parse_tx_test(&test);
+            assert_eq!(flags, fill_flags(flags), "bad test flags {:?}", test);
+            assert!(!verify_tx(&tx, &prevouts, flags), "tx unexpectedly passed {:?}", test);
+
+            for &(_, flag) in FLAG_NAMES {
+                // Adding flags must not validate an invalid transaction.
+                let more = fill_flags(flags | flag);
+                assert!(!verify_tx(&tx, &prevouts, more), "tx unexpectedly passed {:?}", test);
+
+                // Every set flag must be required for the transaction to be invalid.
+                let excluding_one = trim_flags(without(flags, flag));
+                if excluding_one != flags {
+                    assert!(
+                        verify_tx(&tx, &prevouts, excluding_one),
+                        "too many flags set {:?}",
+                        test
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tx_invalid_local() {
+        run_tx_invalid(include_str!("../../../tests/data/tx_invalid_local.json"));
+    }
+
+    /// Reads a test vector file of Bitcoin Core from `tests/data`.
+    fn core_vectors(name: &str) -> String {
+        let path = format!("{}/tests/data/{}", env!("CARGO_MANIFEST_DIR"), name);
+        std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e))
+    }
+
+    #[test]
+    #[ignore = "Bitcoin Core's script_tests.json is not vendored yet, see tests/data/README.md"]
+    fn script_tests_core() { run_script_tests(&core_vectors("script_tests.json")); }
+
+    #[test]
+    #[ignore = "Bitcoin Core's tx_valid.json is not vendored yet, see tests/data/README.md"]
+    fn tx_valid_core() { run_tx_valid(&core_vectors("tx_valid.json")); }
+
+    #[test]
+    #[ignore = "Bitcoin Core's tx_invalid.json is not vendored yet, see tests/data/README.md"]
+    fn tx_invalid_core() { run_tx_invalid(&core_vectors("tx_invalid.json")); }
+
+    #[test]
+    fn read_num_minimal() {
+        assert_eq!(read_num(&[], true, 4), Ok(0));
+        assert_eq!(read_num(&[0x81], true, 4), Ok(-1));
+        assert_eq!(read_num(&[0xff, 0x00], true, 4), Ok(255));
+        assert_eq!(read_num(&[0x01, 0x00], true, 4), Err(Error::NonMinimalNumber));
+        assert_eq!(read_num(&[0x01, 0x00], false, 4), Ok(1));
+        assert_eq!(read_num(&[0x80], true, 4), Err(Error::NonMinimalNumber));
+        assert_eq!(read_num(&[0x01; 5], true, 4), Err(Error::NumericOverflow));
+        assert_eq!(read_num(&encode_num(-0x7fffffff), true, 4), Ok(-0x7fffffff));
+    }
+
+    #[test]
+    fn find_and_delete_instruction_boundaries() {
+        let mut code = vec![0x02, 0xab, 0xcd, 0x02, 0xab, 0xcd, 0x51];
+        assert_eq!(find_and_delete(&mut code, &[0xab, 0xcd]), 2);
+        assert_eq!(code, [0x51]);
+
+        // The pattern inside a push is not an instruction of its own.
+        let mut code = vec![0x03, 0x02, 0xab, 0xcd];
+        assert_eq!(find_and_delete(&mut code, &[0xab, 0xcd]), 0);
+        assert_eq!(code, [0x03, 0x02, 0xab, 0xcd]);
+    }
+
+    #[test]
+    fn inconsistent_flags() {
+        struct NoSignatures;
+        impl SignatureChecker for NoSignatures {}
+
+        let one = Builder::new().push_int(1).into_script();
+        let empty = Witness::default();
+        let flags = VerifyFlags::WITNESS;
+        let res = verify_script(&one, &one, &empty, flags, &mut NoSignatures);
+        assert_eq!(res, Err(Error::InvalidFlags));
+        let flags = VerifyFlags::P2SH | VerifyFlags::CLEANSTACK;
+        let res = verify_script(&one, &one, &empty, flags, &mut NoSignatures);
+        assert_eq!(res, Err(Error::InvalidFlags));
+        let res = verify_script(&one, &one, &empty, VerifyFlags::STANDARD, &mut NoSignatures);
+        assert_eq!(res, Err(Error::CleanStack));
+        let res = verify_script(
+            &ScriptBuf::new(),
+            &one,
+            &empty,
+            VerifyFlags::STANDARD,
+            &mut NoSignatures,
+        );
+        assert_eq!(res, Ok(()));
+    }
+}
@@ -68,7 +68,9 @@ use crate::{io, OutPoint};
 
 mod borrowed;
 mod builder;
+pub mod debugger;
 mod instruction;
+pub mod interpreter;
 mod owned;
 mod push_bytes;
 #[cfg(test)]
@@ -692,6 +694,13 @@ pub enum Error {
     UnknownSpentOutput(OutPoint),
     /// Can not serialize the spending transaction.
     Serialization,
+    /// Script verification of an input failed.
+    Interpreter {
+        /// The index of the input that failed verification.
+        input_index: usize,
+        /// The reason the input failed verification.
+        error: interpreter::Error,
+    },
 }
 
 // If bitcoinonsensus-std is off but bitcoinconsensus is present we patch the error type to
@@ -734,7 +743,6 @@ mod bitcoinconsensus_hack {
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        #[cfg(feature = "bitcoinconsensus")]
         use bitcoin_internals::write_err;
 
         match *self {
@@ -748,6 +756,8 @@ impl fmt::Display for Error {
             Error::UnknownSpentOutput(ref point) => write!(f, "unknown spent output: {}", point),
             Error::Serialization =>
                 f.write_str("can not serialize the spending transaction in Transaction::verify()"),
+            Error::Interpreter { input_index, ref error } =>
+                write_err!(f, "script verification of input {} failed", input_index; error),
         }
     }
 }
@@ -766,6 +776,7 @@ impl std::error::Error for Error {
             | Serialization => None,
             #[cfg(feature = "bitcoinconsensus")]
             BitcoinConsensus(ref e) => Some(bitcoinconsensus_hack::wrap_error(e)),
+            Interpreter { ref error, .. } => Some(error),
         }
     }
 }
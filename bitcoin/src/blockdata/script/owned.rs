@@ -6,7 +6,7 @@ use core::ops::Deref;
 
 use secp256k1::{Secp256k1, Verification};
 
-use crate::address::{WitnessProgram, WitnessVersion};
+use crate::address::{WitnessProgram, WitnessVersion, P2A_PROGRAM};
 use crate::blockdata::opcodes::all::*;
 use crate::blockdata::opcodes::{self};
 use crate::blockdata::script::{opcode_to_verify, Builder, Instruction, PushBytes, Script};
@@ -126,6 +126,12 @@ impl ScriptBuf {
         ScriptBuf::new_witness_program_unchecked(WitnessVersion::V1, output_key.serialize())
     }
 
+    /// Generates P2A (pay to anchor) output, which anyone can spend with an empty witness.
+    pub fn new_p2a() -> Self {
+        // The program is 2 bytes long, so it's safe to use `new_witness_program_unchecked` (Segwitv1)
+        ScriptBuf::new_witness_program_unchecked(WitnessVersion::V1, P2A_PROGRAM)
+    }
+
     /// Generates P2WSH-type of scriptPubkey with a given [`WitnessProgram`].
     pub fn new_witness_program(witness_program: &WitnessProgram) -> Self {
         Builder::new()
@@ -137,8 +143,8 @@ impl ScriptBuf {
     /// Generates P2WSH-type of scriptPubkey with a given [`WitnessVersion`] and the program bytes.
     /// Does not do any checks on version or program length.
     ///
-    /// Convenience method used by `new_v0_p2wpkh`, `new_v0_p2wsh`, `new_v1_p2tr`, and
-    /// `new_v1_p2tr_tweaked`.
+    /// Convenience method used by `new_v0_p2wpkh`, `new_v0_p2wsh`, `new_v1_p2tr`,
+    /// `new_v1_p2tr_tweaked` and `new_p2a`.
     fn new_witness_program_unchecked<T: AsRef<PushBytes>>(
         version: WitnessVersion,
         program: T,
@@ -230,6 +230,13 @@ fn script_generators() {
         op_return.to_hex_string(),
         "6a24aa21a9ed20280f53f2d21663cac89e6bd2ad19edbabb048cda08e73ed19e9268d0afea2a"
     );
+
+    let p2a = ScriptBuf::new_p2a();
+    assert!(p2a.is_p2a());
+    assert!(p2a.is_witness_program());
+    assert_eq!(p2a.to_hex_string(), "51024e73");
+    assert!(!ScriptBuf::from_hex("51024e74").unwrap().is_p2a());
+    assert!(!ScriptBuf::from_hex("52024e73").unwrap().is_p2a());
 }
 
 #[test]
@@ -693,3 +700,37 @@ fn read_scriptbool_non_zero_is_true() {
     let v: Vec<u8> = vec![0x01, 0x00, 0x00, 0x80]; // With sign bit set.
     assert!(read_scriptbool(&v));
 }
+
+#[test]
+fn count_sigops() {
+    let script = ScriptBuf::new();
+    assert_eq!(script.count_sigops(), 0);
+    assert_eq!(script.count_sigops_legacy(), 0);
+
+    // Mirrors the GetSigOpCount test of Bitcoin Core.
+    let builder = Builder::new()
+        .push_int(1)
+        .push_slice([0; 20])
+        .push_slice([0; 20])
+        .push_int(2)
+        .push_opcode(OP_CHECKMULTISIG);
+    assert_eq!(builder.as_script().count_sigops(), 2);
+    assert_eq!(builder.as_script().count_sigops_legacy(), 20);
+
+    let script = builder
+        .push_opcode(OP_IF)
+        .push_opcode(OP_CHECKSIG)
+        .push_opcode(OP_ENDIF)
+        .push_opcode(OP_CHECKSIGVERIFY)
+        .into_script();
+    assert_eq!(script.count_sigops(), 4);
+    assert_eq!(script.count_sigops_legacy(), 22);
+
+    // Multisig without a preceding key count and counting stops at the first invalid push.
+    let script =
+        Builder::new().push_slice([2; 33]).push_opcode(OP_CHECKMULTISIGVERIFY).into_script();
+    assert_eq!(script.count_sigops(), 20);
+    let mut bytes = script.into_bytes();
+    bytes.extend_from_slice(&[OP_PUSHBYTES_2.to_u8(), OP_CHECKSIG.to_u8()]);
+    assert_eq!(ScriptBuf::from(bytes).count_sigops(), 20);
+}
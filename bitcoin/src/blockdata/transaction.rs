@@ -17,14 +17,15 @@ use core::default::Default;
 use core::{cmp, fmt, str};
 
 use bitcoin_internals::write_err;
+use secp256k1::{Secp256k1, Verification};
 
 use super::Weight;
 use crate::blockdata::constants::WITNESS_SCALE_FACTOR;
 use crate::blockdata::locktime::absolute::{self, Height, Time};
 use crate::blockdata::locktime::relative;
-#[cfg(feature = "bitcoinconsensus")]
-use crate::blockdata::script;
-use crate::blockdata::script::{Script, ScriptBuf};
+use crate::blockdata::opcodes::all::OP_PUSHNUM_16;
+use crate::blockdata::script::interpreter::{self, TransactionSignatureChecker, VerifyFlags};
+use crate::blockdata::script::{self, Script, ScriptBuf};
 use crate::blockdata::witness::Witness;
 use crate::consensus::{encode, Decodable, Encodable};
 use crate::crypto::sighash::LegacySighash;
@@ -978,6 +979,96 @@ impl Transaction {
         Ok(())
     }
 
+    /// Verifies that this transaction is able to spend its inputs using the native script
+    /// interpreter.
+    ///
+    /// Unlike [`Self::verify_with_flags`] this does not require the `bitcoinconsensus` feature.
+    /// The `spent` closure is called once for every input, in order, and taproot signature hashes
+    /// commit to all spent outputs so all of them must be known.
+    pub fn verify_scripts<C, S>(
+        &self,
+        secp: &Secp256k1<C>,
+        mut spent: S,
+        flags: VerifyFlags,
+    ) -> Result<(), script::Error>
+    where
+        C: Verification,
+        S: FnMut(&OutPoint) -> Option<TxOut>,
+    {
+        let prevouts = self
+            .input
+            .iter()
+            .map(|input| {
+                spent(&input.previous_output)
+                    .ok_or(script::Error::UnknownSpentOutput(input.previous_output))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut checker = TransactionSignatureChecker::new(secp, self, 0, &prevouts);
+        for (idx, (input, prevout)) in self.input.iter().zip(prevouts.iter()).enumerate() {
+            checker.set_input_index(idx);
+            interpreter::verify_script(
+                &input.script_sig,
+                &prevout.script_pubkey,
+                &input.witness,
+                flags,
+                &mut checker,
+            )
+            .map_err(|error| script::Error::Interpreter { input_index: idx, error })?;
+        }
+        Ok(())
+    }
+
+    /// Counts the sigops of the scriptSigs and output scripts using legacy counting.
+    ///
+    /// This is `GetLegacySigOpCount` of Bitcoin Core, it does not account for P2SH and witness
+    /// sigops, see [`Self::sigop_cost`] for those.
+    pub fn legacy_sigop_count(&self) -> usize {
+        let input_sigops = self.input.iter().map(|input| input.script_sig.count_sigops_legacy());
+        let output_sigops =
+            self.output.iter().map(|output| output.script_pubkey.count_sigops_legacy());
+        input_sigops.chain(output_sigops).sum()
+    }
+
+    /// Computes the sigop cost of this transaction, used for the block sigop limit and the
+    /// virtual size computed by [`policy::get_virtual_tx_size`](crate::policy::get_virtual_tx_size).
+    ///
+    /// This is `GetTransactionSigOpCost` of Bitcoin Core with P2SH and segwit active. Legacy
+    /// sigops and the accurately counted sigops of P2SH redeem scripts are scaled by
+    /// [`WITNESS_SCALE_FACTOR`], the sigops of P2WPKH and P2WSH spends (including the ones nested
+    /// in P2SH) are not.
+    ///
+    /// The `spent` closure is called once for every input and should return the output spent by
+    /// it, the P2SH and witness sigops of inputs for which it returns `None` are not counted.
+    pub fn sigop_cost<S>(&self, mut spent: S) -> usize
+    where
+        S: FnMut(&OutPoint) -> Option<TxOut>,
+    {
+        let mut cost = self.legacy_sigop_count() * WITNESS_SCALE_FACTOR;
+        if self.is_coin_base() {
+            return cost;
+        }
+        for input in &self.input {
+            let prevout = match spent(&input.previous_output) {
+                Some(prevout) => prevout,
+                None => continue,
+            };
+            let script_pubkey = &prevout.script_pubkey;
+            let redeem_script =
+                if script_pubkey.is_p2sh() { last_pushed_script(&input.script_sig) } else { None };
+            if let Some(redeem_script) = redeem_script {
+                cost += redeem_script.count_sigops() * WITNESS_SCALE_FACTOR;
+            }
+            if script_pubkey.is_witness_program() {
+                cost += witness_sigop_count(script_pubkey, &input.witness);
+            } else if let Some(redeem_script) = redeem_script {
+                if redeem_script.is_witness_program() {
+                    cost += witness_sigop_count(redeem_script, &input.witness);
+                }
+            }
+        }
+        cost
+    }
+
     /// Checks if this is a coinbase transaction.
     ///
     /// The first transaction in the block distributes the mining reward and is called the coinbase
@@ -1001,6 +1092,16 @@ impl Transaction {
         self.input.iter().any(|input| input.sequence.is_rbf())
     }
 
+    /// Returns `true` if the transaction opted in to the topologically restricted until
+    /// confirmation (TRUC) policy by having version 3 ([BIP-431]).
+    ///
+    /// TRUC transactions are replaceable without signaling it with a sequence number, their
+    /// unconfirmed ancestors and descendants are restricted, see [`policy::truc`].
+    ///
+    /// [BIP-431]: https://github.com/bitcoin/bips/blob/master/bip-0431.mediawiki
+    /// [`policy::truc`]: crate::policy::truc
+    pub fn is_truc(&self) -> bool { self.version == 3 }
+
     /// Returns true if this [`Transaction`]'s absolute timelock is satisfied at `height`/`time`.
     ///
     /// # Returns
@@ -1015,6 +1116,70 @@ impl Transaction {
         self.lock_time.is_satisfied_by(height, time)
     }
 
+    /// Returns true if this transaction may be included in the block at `height` whose preceding
+    /// block has the median time past `mtp`, as far as the absolute lock time is concerned.
+    ///
+    /// Unlike [`Self::is_absolute_timelock_satisfied`] this follows `IsFinalTx` of Bitcoin Core:
+    /// the lock time has to be strictly below the height of the including block or the median time
+    /// past of the block before it ([BIP-113]). To check whether the transaction may be mined in the
+    /// next block pass the tip height plus one and the median time past of the tip.
+    ///
+    /// [BIP-113]: https://github.com/bitcoin/bips/blob/master/bip-0113.mediawiki
+    pub fn is_final_at(&self, height: Height, mtp: Time) -> bool {
+        use absolute::LockTime::*;
+
+        let unlocked = match self.lock_time {
+            Blocks(n) => n < height,
+            Seconds(n) => n < mtp,
+        };
+        self.lock_time == absolute::LockTime::ZERO || unlocked || !self.is_lock_time_enabled()
+    }
+
+    /// Returns the relative lock times of the inputs as described in [BIP-68], like
+    /// `CalculateSequenceLocks` of Bitcoin Core.
+    ///
+    /// `prevout_heights` are the heights of the blocks containing the outputs spent by the inputs
+    /// and `prevout_mtps` the median time past of the blocks preceding those blocks. Outputs of
+    /// unconfirmed transactions are treated as if they were contained in the next block. The
+    /// transaction may be included in a block once the locks of all inputs are satisfied, see
+    /// [`SequenceLock::is_satisfied_at`]. Transactions with a version below 2 aren't restricted.
+    ///
+    /// # Panics
+    ///
+    /// If the number of heights or times doesn't match the inputs.
+    ///
+    /// [BIP-68]: https://github.com/bitcoin/bips/blob/master/bip-0068.mediawiki
+    pub fn sequence_locks(
+        &self,
+        prevout_heights: &[Height],
+        prevout_mtps: &[Time],
+    ) -> Vec<SequenceLock> {
+        assert_eq!(prevout_heights.len(), self.input.len(), "one height per input");
+        assert_eq!(prevout_mtps.len(), self.input.len(), "one time per input");
+
+        // Bitcoin Core compares the version as unsigned integer, so negative versions are enforced.
+        if (self.version as u32) < 2 {
+            return vec![SequenceLock::default(); self.input.len()];
+        }
+        self.input
+            .iter()
+            .zip(prevout_heights.iter().zip(prevout_mtps))
+            .map(|(txin, (height, mtp))| match txin.sequence.to_relative_lock_time() {
+                None => SequenceLock::default(),
+                Some(relative::LockTime::Blocks(n)) => {
+                    let min = height.to_consensus_u32().saturating_add(u32::from(n.value()));
+                    let min = Height::from_consensus(min).unwrap_or(Height::MAX);
+                    SequenceLock { height: Some(min), time: None }
+                }
+                Some(relative::LockTime::Time(n)) => {
+                    let min = mtp.to_consensus_u32().saturating_add(u32::from(n.value()) << 9);
+                    let min = Time::from_consensus(min).expect("not lower than mtp");
+                    SequenceLock { height: None, time: Some(min) }
+                }
+            })
+            .collect()
+    }
+
     /// Returns `true` if this transactions nLockTime is enabled ([BIP-65]).
     ///
     /// [BIP-65]: https://github.com/bitcoin/bips/blob/master/bip-0065.mediawiki
@@ -1028,6 +1193,76 @@ impl Transaction {
     pub fn script_pubkey_lens(&self) -> impl Iterator<Item = usize> + '_ {
         self.output.iter().map(|txout| txout.script_pubkey.len())
     }
+
+    /// Sorts the inputs and outputs of this transaction canonically as described by [BIP-69].
+    ///
+    /// Inputs are sorted by the txid of the previous output, as displayed in hex, and then by its
+    /// index. Outputs are sorted by value and then by script pubkey. Signatures committing to the
+    /// order are invalidated, so this should be done before signing.
+    ///
+    /// [BIP-69]: https://github.com/bitcoin/bips/blob/master/bip-0069.mediawiki
+    pub fn sort_bip69(&mut self) {
+        self.input.sort_by(|a, b| cmp_bip69_outpoints(&a.previous_output, &b.previous_output));
+        self.output.sort_by(cmp_bip69_outputs);
+    }
+}
+
+/// Orders outpoints by txid, as displayed in hex, and then by vout as described in BIP 69.
+pub(crate) fn cmp_bip69_outpoints(a: &OutPoint, b: &OutPoint) -> cmp::Ordering {
+    let a_txid = a.txid.as_byte_array().iter().rev();
+    let b_txid = b.txid.as_byte_array().iter().rev();
+    a_txid.cmp(b_txid).then(a.vout.cmp(&b.vout))
+}
+
+/// Orders outputs by value and then by script pubkey as described in BIP 69.
+pub(crate) fn cmp_bip69_outputs(a: &TxOut, b: &TxOut) -> cmp::Ordering {
+    a.value.cmp(&b.value).then_with(|| a.script_pubkey.as_bytes().cmp(b.script_pubkey.as_bytes()))
+}
+
+/// The earliest block which may include a transaction according to the relative lock time of an
+/// input, see [`Transaction::sequence_locks`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SequenceLock {
+    /// The minimum height of the including block, `None` if not restricted.
+    pub height: Option<Height>,
+    /// The minimum median time past of the block preceding the including block, `None` if not
+    /// restricted.
+    pub time: Option<Time>,
+}
+
+impl SequenceLock {
+    /// Returns true if the lock is satisfied by the block at `height` whose preceding block has the
+    /// median time past `mtp`.
+    pub fn is_satisfied_at(&self, height: Height, mtp: Time) -> bool {
+        self.height.map_or(true, |min| height >= min) && self.time.map_or(true, |min| mtp >= min)
+    }
+}
+
+/// Returns the script pushed last by `script_sig`, `None` if it contains non-push opcodes.
+///
+/// Like Bitcoin Core, `OP_PUSHNUM_N` pushes an empty script here.
+fn last_pushed_script(script_sig: &Script) -> Option<&Script> {
+    let mut last = Script::empty();
+    for instruction in script_sig.instructions() {
+        match instruction {
+            Ok(script::Instruction::PushBytes(data)) => last = Script::from_bytes(data.as_bytes()),
+            Ok(script::Instruction::Op(op)) if op.to_u8() <= OP_PUSHNUM_16.to_u8() =>
+                last = Script::empty(),
+            _ => return None,
+        }
+    }
+    Some(last)
+}
+
+/// Counts the sigops of spending the witness `program`, only P2WPKH and P2WSH have any.
+fn witness_sigop_count(program: &Script, witness: &Witness) -> usize {
+    if program.is_v0_p2wpkh() {
+        1
+    } else if program.is_v0_p2wsh() {
+        witness.last().map(|script| Script::from_bytes(script).count_sigops()).unwrap_or(0)
+    } else {
+        0
+    }
 }
 
 impl_consensus_encoding!(TxOut, value, script_pubkey);
@@ -1317,8 +1552,8 @@ pub const fn predict_weight_from_slices(
 /// It can only be created using the [`new`](InputWeightPrediction::new) function.
 #[derive(Copy, Clone, Debug)]
 pub struct InputWeightPrediction {
-    script_size: usize,
-    witness_size: usize,
+    pub(crate) script_size: usize,
+    pub(crate) witness_size: usize,
 }
 
 impl InputWeightPrediction {
@@ -1351,6 +1586,13 @@ impl InputWeightPrediction {
     pub const P2TR_KEY_NON_DEFAULT_SIGHASH: Self =
         InputWeightPrediction { script_size: 0, witness_size: 1 + 1 + 65 };
 
+    /// Input weight prediction corresponding to spending of a P2A (pay to anchor) output, which
+    /// has an empty scriptSig and an empty witness.
+    ///
+    /// If the input in your transaction spends a P2A output you can use this instead of
+    /// [`InputWeightPrediction::new`].
+    pub const P2A: Self = InputWeightPrediction { script_size: 1, witness_size: 0 };
+
     /// Input weight prediction corresponding to spending of P2WPKH output using [signature
     /// grinding].
     ///
@@ -1377,6 +1619,14 @@ impl InputWeightPrediction {
         InputWeightPrediction { script_size: 0, witness_size }
     }
 
+    /// Returns the weight the input adds to a transaction, including the outpoint and sequence.
+    ///
+    /// This doesn't include the segwit marker and flag or the empty witness of inputs without a
+    /// witness in a segwit transaction, [`predict_weight`] accounts for those.
+    pub const fn weight(&self) -> Weight {
+        Weight::from_wu(((32 + 4 + 4 + self.script_size) * 4 + self.witness_size) as u64)
+    }
+
     /// Computes the prediction for a single input.
     pub fn new<T>(input_script_len: usize, witness_element_lengths: T) -> Self
     where
@@ -1834,6 +2084,76 @@ mod tests {
         }
     }
 
+    #[test]
+    fn sigop_cost() {
+        use crate::blockdata::opcodes::all::*;
+        use crate::blockdata::script::{Builder, PushBytesBuf};
+        use crate::hash_types::WPubkeyHash;
+
+        // A 1-of-2 multisig, the cases mirror the GetTxSigOpCost test of Bitcoin Core.
+        let multisig = Builder::new()
+            .push_int(1)
+            .push_slice([2; 33])
+            .push_slice([3; 33])
+            .push_int(2)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script();
+        let p2wpkh = ScriptBuf::new_v0_p2wpkh(&WPubkeyHash::all_zeros());
+        let push = |script: &ScriptBuf| {
+            let script = PushBytesBuf::try_from(script.to_bytes()).unwrap();
+            Builder::new().push_slice(script).into_script()
+        };
+        let spend = |script_sig: ScriptBuf, witness: Witness| Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::all_zeros(), 0),
+                script_sig,
+                sequence: Sequence::MAX,
+                witness,
+            }],
+            output: vec![TxOut { value: 0, script_pubkey: ScriptBuf::new() }],
+        };
+        let cost = |tx: &Transaction, script_pubkey: &ScriptBuf| {
+            tx.sigop_cost(|_| Some(TxOut { value: 0, script_pubkey: script_pubkey.clone() }))
+        };
+
+        // Creating a bare multisig counts legacy sigops, spending it doesn't.
+        let mut create = spend(ScriptBuf::new(), Witness::new());
+        create.output[0].script_pubkey = multisig.clone();
+        assert_eq!(create.legacy_sigop_count(), 20);
+        assert_eq!(create.sigop_cost(|_| None), 20 * WITNESS_SCALE_FACTOR);
+        let tx = spend(Builder::new().push_int(0).push_int(0).into_script(), Witness::new());
+        assert_eq!(cost(&tx, &multisig), 0);
+
+        // P2SH redeem scripts are counted accurately.
+        let mut script_sig = Builder::new().push_int(0).push_int(0).into_script().into_bytes();
+        script_sig.extend_from_slice(push(&multisig).as_bytes());
+        let tx = spend(script_sig.into(), Witness::new());
+        assert_eq!(cost(&tx, &multisig.to_p2sh()), 2 * WITNESS_SCALE_FACTOR);
+        // Unless the spent output is unknown.
+        assert_eq!(tx.sigop_cost(|_| None), 0);
+
+        let witness = Witness::from_slice(&[[0; 72], [2; 72]]);
+        let tx = spend(ScriptBuf::new(), witness.clone());
+        assert_eq!(cost(&tx, &p2wpkh), 1);
+        let tx = spend(push(&p2wpkh), witness);
+        assert_eq!(cost(&tx, &p2wpkh.to_p2sh()), 1);
+
+        let witness = Witness::from_slice(&[&[][..], &[0; 72], multisig.as_bytes()]);
+        let p2wsh = multisig.to_v0_p2wsh();
+        let tx = spend(ScriptBuf::new(), witness.clone());
+        assert_eq!(cost(&tx, &p2wsh), 2);
+        let tx = spend(push(&p2wsh), witness);
+        assert_eq!(cost(&tx, &p2wsh.to_p2sh()), 2);
+
+        // Witness programs spent with a non-push scriptSig have no witness sigops.
+        let mut script_sig = push(&p2wpkh).into_bytes();
+        script_sig.push(OP_NOP.to_u8());
+        let tx = spend(script_sig.into(), Witness::from_slice(&[[0; 72], [2; 72]]));
+        assert_eq!(cost(&tx, &p2wpkh.to_p2sh()), 0);
+    }
+
     #[test]
     fn test_huge_witness() {
         deserialize::<Transaction>(&hex!(include_str!("../../tests/data/huge_witness.hex").trim()))
@@ -1986,6 +2306,93 @@ mod tests {
             assert_eq!(calculated_size, tx.check_weight().to_wu() as usize);
         }
     }
+
+    #[test]
+    fn is_final_at() {
+        let height = |n| Height::from_consensus(n).unwrap();
+        let time = |n| Time::from_consensus(n).unwrap();
+        let mut tx = Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::from_consensus(100),
+            input: vec![TxIn { sequence: Sequence::ENABLE_RBF_NO_LOCKTIME, ..Default::default() }],
+            output: vec![],
+        };
+        assert!(!tx.is_final_at(height(100), time(600_000_000)));
+        assert!(tx.is_final_at(height(101), time(600_000_000)));
+        // Unlike a lock time satisfying a timelock, the lock time has to be below the height.
+        assert!(tx.is_absolute_timelock_satisfied(height(100), time(600_000_000)));
+
+        tx.lock_time = absolute::LockTime::from_consensus(600_000_000);
+        assert!(!tx.is_final_at(height(101), time(600_000_000)));
+        assert!(tx.is_final_at(height(0), time(600_000_001)));
+
+        tx.input[0].sequence = Sequence::MAX;
+        assert!(tx.is_final_at(height(0), time(600_000_000)));
+        tx.lock_time = absolute::LockTime::ZERO;
+        tx.input[0].sequence = Sequence::ZERO;
+        assert!(tx.is_final_at(height(0), Time::MIN));
+    }
+
+    #[test]
+    fn sequence_locks() {
+        let height = |n| Height::from_consensus(n).unwrap();
+        let time = |n| Time::from_consensus(n).unwrap();
+        let txin = |sequence| TxIn { sequence, ..Default::default() };
+        let mut tx = Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![
+                txin(Sequence::from_height(10)),
+                txin(Sequence::from_512_second_intervals(2)),
+                txin(Sequence::ENABLE_RBF_NO_LOCKTIME),
+            ],
+            output: vec![],
+        };
+        let heights = [height(100), height(200), height(300)];
+        let mtps = [time(600_000_000), time(700_000_000), time(800_000_000)];
+
+        let locks = tx.sequence_locks(&heights, &mtps);
+        assert_eq!(locks[0], SequenceLock { height: Some(height(110)), time: None });
+        assert_eq!(locks[1], SequenceLock { height: None, time: Some(time(700_001_024)) });
+        assert_eq!(locks[2], SequenceLock::default());
+        assert!(!locks[0].is_satisfied_at(height(109), Time::MAX));
+        assert!(locks[0].is_satisfied_at(height(110), Time::MIN));
+        assert!(!locks[1].is_satisfied_at(Height::MAX, time(700_001_023)));
+        assert!(locks[1].is_satisfied_at(Height::MIN, time(700_001_024)));
+
+        tx.version = 1;
+        let locks = tx.sequence_locks(&heights, &mtps);
+        assert!(locks.iter().all(|lock| *lock == SequenceLock::default()));
+        tx.version = -1;
+        assert_eq!(tx.sequence_locks(&heights, &mtps)[0].height, Some(height(110)));
+    }
+
+    #[test]
+    fn sort_bip69() {
+        // Txids are compared as displayed in hex, not in byte order.
+        let mut low = [0; 32];
+        low[0] = 1;
+        let mut high = [0; 32];
+        high[31] = 1;
+        let low = Txid::from_byte_array(low);
+        let high = Txid::from_byte_array(high);
+        let txin =
+            |txid, vout| TxIn { previous_output: OutPoint::new(txid, vout), ..Default::default() };
+        let txout = |value, script: &[u8]| TxOut {
+            value,
+            script_pubkey: ScriptBuf::from_bytes(script.to_vec()),
+        };
+
+        let mut tx = Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![txin(high, 0), txin(low, 1), txin(low, 0)],
+            output: vec![txout(2, &[0]), txout(1, &[1]), txout(1, &[0, 1])],
+        };
+        tx.sort_bip69();
+        assert_eq!(tx.input, vec![txin(low, 0), txin(low, 1), txin(high, 0)]);
+        assert_eq!(tx.output, vec![txout(1, &[0, 1]), txout(1, &[1]), txout(2, &[0])]);
+    }
 }
 
 #[cfg(bench)]
@@ -0,0 +1,204 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Branch and Bound coin selection.
+//!
+//! This is `SelectCoinsBnB` of Bitcoin Core, see Murch's "An Evaluation of Coin Selection
+//! Strategies" for a description of the algorithm.
+
+use core::cmp::Reverse;
+
+use super::{check_funds, finish, utxos, Candidate, Error, Params, Selection, Utxo};
+use crate::prelude::*;
+
+/// The maximum number of search steps, limits the time spent on large candidate sets.
+const TOTAL_TRIES: usize = 100_000;
+
+/// Searches for a selection that doesn't need a change output.
+///
+/// The selected effective value exceeds the target by less than the cost of change, which is the
+/// excess added to the fee. Of all the selections found, the one with the lowest waste is
+/// returned.
+///
+/// # Errors
+///
+/// Returns [`Error::NoChangelessSolution`] if there is no such selection or it isn't found within
+/// the search limit.
+pub fn select_coins_bnb(candidates: &[Candidate], params: &Params) -> Result<Selection, Error> {
+    let mut pool = utxos(candidates, params);
+    check_funds(&pool, params)?;
+    // Exploring the largest candidates first finds solutions faster.
+    pool.sort_by_key(|utxo| Reverse(utxo.effective_value));
+
+    let target = params.selection_target();
+    let upper_bound = target + params.cost_of_change();
+    let mut available: u64 = pool.iter().map(|utxo| utxo.effective_value).sum();
+    let is_fee_rate_high = params.fee_rate > params.long_term_fee_rate;
+
+    let mut value = 0;
+    let mut waste = 0i64;
+    let mut selection: Vec<usize> = Vec::new();
+    let mut best: Option<(i64, Vec<usize>)> = None;
+
+    let mut index = 0;
+    for _ in 0..TOTAL_TRIES {
+        let best_waste = best.as_ref().map(|(waste, _)| *waste);
+        let mut backtrack = false;
+        if value + available < target
+            || value > upper_bound
+            || (is_fee_rate_high && best_waste.map(|best| waste > best).unwrap_or(false))
+        {
+            // The target can't be reached anymore, the value is out of range or the waste only
+            // increases by adding more inputs.
+            backtrack = true;
+        } else if value >= target {
+            let excess = (value - target) as i64;
+            if best_waste.map(|best| waste + excess <= best).unwrap_or(true) {
+                best = Some((waste + excess, selection.clone()));
+            }
+            backtrack = true;
+        }
+
+        if backtrack {
+            let last = match selection.last() {
+                Some(&last) => last,
+                // Walked back to the first candidate, the whole tree is searched.
+                None => break,
+            };
+            // Add the omitted candidates back before trying the omission branch of the last
+            // selected one.
+            index -= 1;
+            while index > last {
+                available += pool[index].effective_value;
+                index -= 1;
+            }
+            let utxo = &pool[index];
+            value -= utxo.effective_value;
+            waste -= utxo.fee as i64 - utxo.long_term_fee as i64;
+            selection.pop();
+        } else {
+            let utxo = &pool[index];
+            available -= utxo.effective_value;
+            // Skip the inclusion branch of a candidate equivalent to the previous one which was
+            // omitted, it was searched already.
+            let is_duplicate = index > 0
+                && selection.last() != Some(&(index - 1))
+                && is_equivalent(utxo, &pool[index - 1]);
+            if !is_duplicate {
+                selection.push(index);
+                value += utxo.effective_value;
+                waste += utxo.fee as i64 - utxo.long_term_fee as i64;
+            }
+        }
+        index += 1;
+    }
+
+    match best {
+        Some((_, selection)) => {
+            let selected = selection.into_iter().map(|index| &pool[index]);
+            Ok(finish(selected, candidates, params, false))
+        }
+        None => Err(Error::NoChangelessSolution),
+    }
+}
+
+/// Checks whether two candidates have the same effective value and waste.
+fn is_equivalent(a: &Utxo, b: &Utxo) -> bool {
+    a.effective_value == b.effective_value && a.fee == b.fee
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockdata::fee_rate::FeeRate;
+    use crate::blockdata::weight::Weight;
+    use crate::coin_selection::tests::{self, candidates};
+    use crate::Amount;
+
+    fn params(target: u64, cost_of_change: u64) -> Params {
+        let mut params = tests::params(target);
+        params.change_policy.spend_weight = Weight::from_wu(cost_of_change);
+        params
+    }
+
+    fn selected_values(candidates: &[Candidate], selection: &Selection) -> Vec<u64> {
+        let mut values =
+            selection.selected.iter().map(|&i| candidates[i].value.to_sat()).collect::<Vec<_>>();
+        values.sort_unstable();
+        values
+    }
+
+    #[test]
+    fn exact_match() {
+        // Mirrors the bnb_search_test of Bitcoin Core.
+        let pool = candidates(&[1, 2, 3, 4]);
+        let mut params = params(1, 0);
+
+        let selection = select_coins_bnb(&pool, &params).unwrap();
+        assert_eq!(selected_values(&pool, &selection), [1]);
+        assert_eq!(selection.change, None);
+        assert_eq!(selection.fee, Amount::ZERO);
+
+        // Of the equally wasteful selections the last one found is returned.
+        params.target = Amount::from_sat(5);
+        let selection = select_coins_bnb(&pool, &params).unwrap();
+        assert_eq!(selected_values(&pool, &selection), [2, 3]);
+
+        params.target = Amount::from_sat(10);
+        let selection = select_coins_bnb(&pool, &params).unwrap();
+        assert_eq!(selected_values(&pool, &selection), [1, 2, 3, 4]);
+
+        params.target = Amount::from_sat(11);
+        assert!(matches_insufficient(select_coins_bnb(&pool, &params)));
+    }
+
+    #[test]
+    fn cost_of_change_range() {
+        let pool = candidates(&[5, 10]);
+        // 7 can't be hit exactly, 10 is within the cost of change of 3.
+        let selection = select_coins_bnb(&pool, &params(7, 3)).unwrap();
+        assert_eq!(selected_values(&pool, &selection), [10]);
+        assert_eq!(selection.fee, Amount::from_sat(3));
+        assert_eq!(selection.waste.to_sat(), 3);
+
+        assert_eq!(select_coins_bnb(&pool, &params(7, 2)), Err(Error::NoChangelessSolution));
+    }
+
+    #[test]
+    fn many_equal_candidates() {
+        // The search would take forever without skipping equivalent branches.
+        let mut values = vec![5; 1000];
+        values.push(3);
+        let pool = candidates(&values);
+        let selection = select_coins_bnb(&pool, &params(23, 0)).unwrap();
+        assert_eq!(selected_values(&pool, &selection), [3, 5, 5, 5, 5]);
+    }
+
+    #[test]
+    fn waste() {
+        let fee_rate = FeeRate::from_sat_per_vb_unchecked(10);
+        let candidate =
+            |value| Candidate { value: Amount::from_sat(value), weight: Weight::from_wu(400) };
+        let pool = [candidate(3000), candidate(2000), candidate(2000)];
+        let mut params = params(2000, 100);
+        params.fee_rate = fee_rate;
+
+        // High feerate, the single input is less wasteful.
+        params.long_term_fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+        let selection = select_coins_bnb(&pool, &params).unwrap();
+        assert_eq!(selection.selected, [0]);
+        assert_eq!(selection.waste.to_sat(), 1000 - 100);
+
+        // Low feerate, consolidating is less wasteful.
+        params.long_term_fee_rate = FeeRate::from_sat_per_vb_unchecked(20);
+        let selection = select_coins_bnb(&pool, &params).unwrap();
+        assert_eq!(selection.selected, [1, 2]);
+        assert_eq!(selection.waste.to_sat(), 2 * (1000 - 2000));
+    }
+
+    fn matches_insufficient(result: Result<Selection, Error>) -> bool {
+        match result {
+            Err(Error::InsufficientFunds { .. }) => true,
+            _ => false,
+        }
+    }
+}
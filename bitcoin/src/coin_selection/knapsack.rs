@@ -0,0 +1,205 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Knapsack coin selection.
+//!
+//! This is `KnapsackSolver` of Bitcoin Core, which used it exclusively before Branch and Bound.
+
+use core::cmp::Reverse;
+
+use secp256k1::rand::seq::SliceRandom;
+use secp256k1::rand::Rng;
+
+use super::{check_funds, finish, utxos, Candidate, Error, Params, Selection, Utxo};
+use crate::prelude::*;
+
+/// The number of random subsets tried when approximating the best subset.
+const ITERATIONS: usize = 1000;
+
+/// Selects candidates whose effective value is closest to the target, or to the target plus the
+/// minimum change if the target can't be hit exactly.
+///
+/// A single candidate exceeding the target plus the minimum change is selected if no better
+/// subset of the smaller candidates is found.
+#[cfg_attr(docsrs, doc(cfg(feature = "rand")))]
+pub fn select_coins_knapsack<R: Rng + ?Sized>(
+    candidates: &[Candidate],
+    params: &Params,
+    rng: &mut R,
+) -> Result<Selection, Error> {
+    let mut pool = utxos(candidates, params);
+    check_funds(&pool, params)?;
+    pool.shuffle(rng);
+
+    let target = params.selection_target();
+    let change_target = params.change_fee() + params.change_policy.min_value.to_sat();
+
+    // Candidates smaller than the target plus change, larger ones only cause overshooting.
+    let mut applicable = Vec::new();
+    let mut total_lower = 0;
+    let mut lowest_larger: Option<Utxo> = None;
+    for utxo in pool {
+        if utxo.effective_value == target {
+            return Ok(finish(Some(&utxo), candidates, params, true));
+        } else if utxo.effective_value < target + change_target {
+            total_lower += utxo.effective_value;
+            applicable.push(utxo);
+        } else if lowest_larger.map(|l| utxo.effective_value < l.effective_value).unwrap_or(true) {
+            lowest_larger = Some(utxo);
+        }
+    }
+
+    if total_lower == target {
+        return Ok(finish(&applicable, candidates, params, true));
+    }
+    if total_lower < target {
+        let utxo = lowest_larger.expect("funds are sufficient");
+        return Ok(finish(Some(&utxo), candidates, params, true));
+    }
+
+    applicable.sort_by_key(|utxo| Reverse(utxo.effective_value));
+    let (mut best, mut best_value) = approximate_best_subset(&applicable, total_lower, target, rng);
+    if best_value != target && total_lower >= target + change_target {
+        let subset = approximate_best_subset(&applicable, total_lower, target + change_target, rng);
+        best = subset.0;
+        best_value = subset.1;
+    }
+
+    // Prefer the larger candidate if no good subset was found or if it is closer to the target.
+    if let Some(larger) = lowest_larger {
+        if (best_value != target && best_value < target + change_target)
+            || larger.effective_value <= best_value
+        {
+            return Ok(finish(Some(&larger), candidates, params, true));
+        }
+    }
+    let selected = applicable.iter().zip(best).filter(|(_, included)| *included).map(|(u, _)| u);
+    Ok(finish(selected, candidates, params, true))
+}
+
+/// Approximates the subset of `utxos` with the smallest value reaching `target` by randomly
+/// including them.
+///
+/// Returns which `utxos` are included and their value.
+fn approximate_best_subset<R: Rng + ?Sized>(
+    utxos: &[Utxo],
+    total: u64,
+    target: u64,
+    rng: &mut R,
+) -> (Vec<bool>, u64) {
+    // The worst case is all of them.
+    let mut best = vec![true; utxos.len()];
+    let mut best_value = total;
+
+    for _ in 0..ITERATIONS {
+        if best_value == target {
+            break;
+        }
+        let mut included = vec![false; utxos.len()];
+        let mut value = 0;
+        let mut reached_target = false;
+        // First randomly include candidates, then include all the others in order.
+        for pass in 0..2 {
+            if reached_target {
+                break;
+            }
+            for (i, utxo) in utxos.iter().enumerate() {
+                let include = if pass == 0 { rng.gen::<bool>() } else { !included[i] };
+                if !include {
+                    continue;
+                }
+                value += utxo.effective_value;
+                included[i] = true;
+                if value >= target {
+                    reached_target = true;
+                    if value < best_value {
+                        best_value = value;
+                        best = included.clone();
+                    }
+                    // Try replacing the candidate with the next ones.
+                    value -= utxo.effective_value;
+                    included[i] = false;
+                }
+            }
+        }
+    }
+    (best, best_value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockdata::fee_rate::FeeRate;
+    use crate::blockdata::weight::Weight;
+    use crate::coin_selection::tests::{candidates, params, TestRng};
+    use crate::Amount;
+
+    fn params_with_min_change(target: u64, min_change: u64) -> Params {
+        let mut params = params(target);
+        params.change_policy.min_value = Amount::from_sat(min_change);
+        params
+    }
+
+    #[test]
+    fn exact_match() {
+        let pool = candidates(&[6, 7, 8, 20, 30]);
+        let mut rng = TestRng::new();
+
+        let selection = select_coins_knapsack(&pool, &params(8), &mut rng).unwrap();
+        assert_eq!(selection.selected, [2]);
+        assert_eq!(selection.change, None);
+
+        // All candidates smaller than the target.
+        let pool = candidates(&[6, 7, 8, 30]);
+        let selection = select_coins_knapsack(&pool, &params(21), &mut rng).unwrap();
+        assert_eq!(selection.selected, [0, 1, 2]);
+
+        // A subset of them.
+        let pool = candidates(&[6, 7, 8, 20, 30]);
+        let selection =
+            select_coins_knapsack(&pool, &params_with_min_change(14, 10), &mut rng).unwrap();
+        assert_eq!(selection.selected, [0, 2]);
+        assert_eq!(selection.fee, Amount::ZERO);
+    }
+
+    #[test]
+    fn change() {
+        let pool = candidates(&[6, 7, 8, 20, 30]);
+        let mut rng = TestRng::new();
+
+        // The smallest larger candidate is closer than any subset reaching the target plus change.
+        let selection =
+            select_coins_knapsack(&pool, &params_with_min_change(9, 10), &mut rng).unwrap();
+        assert_eq!(selection.selected, [3]);
+        assert_eq!(selection.change, Some(Amount::from_sat(11)));
+
+        // The subset closest to the target plus change.
+        let selection =
+            select_coins_knapsack(&pool, &params_with_min_change(25, 1), &mut rng).unwrap();
+        assert_eq!(selection.selected, [0, 3]);
+        assert_eq!(selection.change, Some(Amount::from_sat(1)));
+
+        let err = select_coins_knapsack(&pool, &params(72), &mut rng).unwrap_err();
+        assert_eq!(
+            err,
+            Error::InsufficientFunds {
+                available: Amount::from_sat(71),
+                required: Amount::from_sat(72)
+            }
+        );
+    }
+
+    #[test]
+    fn fees() {
+        // 100 sat fee for every input, the second candidate is not worth spending.
+        let pool = [
+            Candidate { value: Amount::from_sat(1_100), weight: Weight::from_wu(200) },
+            Candidate { value: Amount::from_sat(50), weight: Weight::from_wu(200) },
+            Candidate { value: Amount::from_sat(2_100), weight: Weight::from_wu(200) },
+        ];
+        let mut params = params(3_000);
+        params.fee_rate = FeeRate::from_sat_per_vb_unchecked(2);
+        let selection = select_coins_knapsack(&pool, &params, &mut TestRng::new()).unwrap();
+        assert_eq!(selection.selected, [0, 2]);
+        assert_eq!(selection.fee, Amount::from_sat(200));
+    }
+}
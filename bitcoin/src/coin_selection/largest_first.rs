@@ -0,0 +1,73 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Largest first coin selection.
+
+use core::cmp::Reverse;
+
+use super::{check_funds, finish, utxos, Candidate, Error, Params, Selection};
+
+/// Selects the candidates with the largest effective value until the target is reached.
+///
+/// This minimizes the number of inputs but usually creates change and, unlike the other
+/// algorithms, doesn't take the waste into account.
+pub fn select_coins_largest_first(
+    candidates: &[Candidate],
+    params: &Params,
+) -> Result<Selection, Error> {
+    let mut pool = utxos(candidates, params);
+    check_funds(&pool, params)?;
+    pool.sort_by_key(|utxo| Reverse(utxo.effective_value));
+
+    let target = params.selection_target();
+    let mut value = 0;
+    let count = pool
+        .iter()
+        .position(|utxo| {
+            value += utxo.effective_value;
+            value >= target
+        })
+        .expect("funds are sufficient")
+        + 1;
+    Ok(finish(&pool[..count], candidates, params, true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockdata::fee_rate::FeeRate;
+    use crate::blockdata::weight::Weight;
+    use crate::coin_selection::tests::{candidates, params};
+    use crate::Amount;
+
+    #[test]
+    fn select() {
+        let pool = candidates(&[10, 40, 20, 30]);
+        let selection = select_coins_largest_first(&pool, &params(50)).unwrap();
+        assert_eq!(selection.selected, [1, 3]);
+        assert_eq!(selection.change, Some(Amount::from_sat(20)));
+        assert_eq!(selection.fee, Amount::ZERO);
+
+        let selection = select_coins_largest_first(&pool, &params(100)).unwrap();
+        assert_eq!(selection.selected, [0, 1, 2, 3]);
+        assert_eq!(selection.change, None);
+        assert!(select_coins_largest_first(&pool, &params(101)).is_err());
+    }
+
+    #[test]
+    fn fees() {
+        // The fee for spending the largest candidate exceeds the difference in value.
+        let pool = [
+            Candidate { value: Amount::from_sat(1_000), weight: Weight::from_wu(400) },
+            Candidate { value: Amount::from_sat(1_050), weight: Weight::from_wu(1_000) },
+        ];
+        let mut params = params(400);
+        params.fee_rate = FeeRate::from_sat_per_kwu(1000);
+        params.base_weight = Weight::from_wu(100);
+        params.change_policy.output_weight = Weight::from_wu(50);
+        let selection = select_coins_largest_first(&pool, &params).unwrap();
+        assert_eq!(selection.selected, [0]);
+        // 100 sat for the base weight, 400 sat for the input and 50 sat for the change.
+        assert_eq!(selection.change, Some(Amount::from_sat(1_000 - 400 - 550)));
+        assert_eq!(selection.fee, Amount::from_sat(550));
+    }
+}
@@ -0,0 +1,466 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Coin selection.
+//!
+//! Algorithms choosing which of the available outputs (the candidates) a transaction spends to
+//! pay for its outputs and fee. They mirror the ones of the Bitcoin Core wallet:
+//!
+//! * [`select_coins_bnb`] - Branch and Bound, searches for a selection that needs no change.
+//! * [`select_coins_knapsack`] - the stochastic subset sum solver Bitcoin Core used before BnB.
+//! * [`select_coins_srd`] - Single Random Draw, spends randomly picked candidates.
+//! * [`select_coins_largest_first`] - spends the largest candidates first.
+//!
+//! [`select_coins`] runs the first three and picks the selection with the lowest waste. The waste
+//! metric compares the fee paid for the inputs at the current feerate to what spending them would
+//! cost at the long term feerate, and adds the cost of creating a change output or the excess
+//! value given up to fees when there is none.
+//!
+//! All amounts are effective values, the value of a candidate minus the fee of spending it.
+//! Candidates with a non-positive effective value are never selected.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[cfg(feature = "rand")] {
+//! use bitcoin::coin_selection::{select_coins, Candidate, ChangePolicy, Params};
+//! use bitcoin::blockdata::transaction::{predict_weight, InputWeightPrediction};
+//! use bitcoin::hashes::Hash;
+//! use bitcoin::{Amount, FeeRate, ScriptBuf, WPubkeyHash};
+//!
+//! let candidates = [
+//!     Candidate::new(Amount::from_sat(40_000), InputWeightPrediction::P2WPKH_MAX),
+//!     Candidate::new(Amount::from_sat(70_000), InputWeightPrediction::P2WPKH_MAX),
+//!     Candidate::new(Amount::from_sat(100_000), InputWeightPrediction::P2WPKH_MAX),
+//! ];
+//! let recipient = ScriptBuf::new_v0_p2wpkh(&WPubkeyHash::all_zeros());
+//! let change = ScriptBuf::new_v0_p2wpkh(&WPubkeyHash::all_zeros());
+//! let params = Params {
+//!     target: Amount::from_sat(100_000),
+//!     base_weight: predict_weight(None, Some(recipient.len())),
+//!     fee_rate: FeeRate::from_sat_per_vb_unchecked(10),
+//!     long_term_fee_rate: FeeRate::from_sat_per_vb_unchecked(5),
+//!     change_policy: ChangePolicy::new(&change, InputWeightPrediction::P2WPKH_MAX),
+//! };
+//!
+//! let selection = select_coins(&candidates, &params, &mut bitcoin::secp256k1::rand::thread_rng()).unwrap();
+//! assert!(selection.fee >= params.fee_rate * params.base_weight);
+//! # }
+//! ```
+
+mod bnb;
+#[cfg(feature = "rand")]
+mod knapsack;
+mod largest_first;
+#[cfg(feature = "rand")]
+mod srd;
+
+use core::fmt;
+
+#[cfg(feature = "rand")]
+use secp256k1::rand::Rng;
+
+pub use self::bnb::select_coins_bnb;
+#[cfg(feature = "rand")]
+pub use self::knapsack::select_coins_knapsack;
+pub use self::largest_first::select_coins_largest_first;
+#[cfg(feature = "rand")]
+pub use self::srd::select_coins_srd;
+use crate::amount::{Amount, SignedAmount};
+use crate::blockdata::fee_rate::FeeRate;
+use crate::blockdata::script::Script;
+use crate::blockdata::transaction::InputWeightPrediction;
+use crate::blockdata::weight::Weight;
+use crate::prelude::*;
+use crate::VarInt;
+
+/// An output that can be spent by the transaction being built.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Candidate {
+    /// The value of the output.
+    pub value: Amount,
+    /// The weight the input spending the output adds to the transaction.
+    pub weight: Weight,
+}
+
+impl Candidate {
+    /// Creates a candidate spent by an input with the predicted weight.
+    pub fn new(value: Amount, prediction: InputWeightPrediction) -> Self {
+        Candidate { value, weight: prediction.weight() }
+    }
+
+    /// Returns the value of the candidate minus the fee of spending it, `None` if the fee exceeds
+    /// the value.
+    pub fn effective_value(&self, fee_rate: FeeRate) -> Option<Amount> {
+        self.value.checked_sub(fee_rate * self.weight)
+    }
+}
+
+/// How the change output is created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChangePolicy {
+    /// The weight of the change output.
+    pub output_weight: Weight,
+    /// The weight of the input spending the change output later on.
+    pub spend_weight: Weight,
+    /// The smallest change output to create, any smaller excess is added to the fee.
+    pub min_value: Amount,
+}
+
+impl ChangePolicy {
+    /// Creates the policy for change paid to `script_pubkey` and spent by an input with the
+    /// predicted weight.
+    ///
+    /// The minimum change value is the dust limit of the script, see [`Script::dust_value`].
+    pub fn new(script_pubkey: &Script, spend: InputWeightPrediction) -> Self {
+        let script_len = script_pubkey.len();
+        let output_size = 8 + VarInt(script_len as u64).len() + script_len;
+        ChangePolicy {
+            output_weight: Weight::from_non_witness_data_size(output_size as u64),
+            spend_weight: spend.weight(),
+            min_value: script_pubkey.dust_value(),
+        }
+    }
+
+    /// Returns the fee for creating the change output now and spending it later on at
+    /// `long_term_fee_rate`.
+    ///
+    /// Selections whose excess is below the cost of change are better off without a change
+    /// output.
+    pub fn cost_of_change(&self, fee_rate: FeeRate, long_term_fee_rate: FeeRate) -> Amount {
+        fee_rate * self.output_weight + long_term_fee_rate * self.spend_weight
+    }
+}
+
+/// Parameters of a coin selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Params {
+    /// The amount paid to the recipients.
+    pub target: Amount,
+    /// The weight of the transaction without inputs and change output, e.g. computed by
+    /// [`predict_weight`](crate::blockdata::transaction::predict_weight) with no inputs.
+    pub base_weight: Weight,
+    /// The feerate of the transaction.
+    pub fee_rate: FeeRate,
+    /// The feerate expected to be paid in the long term, selections spend more inputs when the
+    /// feerate is below it and fewer when it is above.
+    pub long_term_fee_rate: FeeRate,
+    /// How the change output is created.
+    pub change_policy: ChangePolicy,
+}
+
+impl Params {
+    /// Returns the effective value the selected candidates must at least have.
+    fn selection_target(&self) -> u64 { (self.target + self.fee_rate * self.base_weight).to_sat() }
+
+    /// Returns the fee for the change output.
+    fn change_fee(&self) -> u64 { (self.fee_rate * self.change_policy.output_weight).to_sat() }
+
+    fn cost_of_change(&self) -> u64 {
+        self.change_policy.cost_of_change(self.fee_rate, self.long_term_fee_rate).to_sat()
+    }
+}
+
+/// The result of a coin selection.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Selection {
+    /// The indices of the selected candidates in ascending order.
+    pub selected: Vec<usize>,
+    /// The value of the change output, `None` if the excess is added to the fee.
+    pub change: Option<Amount>,
+    /// The fee paid by the transaction.
+    pub fee: Amount,
+    /// The waste of the selection, lower is better.
+    ///
+    /// This is the fee of the inputs at the current feerate minus their fee at the long term
+    /// feerate, plus the cost of change if there is a change output or the excess added to the fee
+    /// otherwise.
+    pub waste: SignedAmount,
+}
+
+/// Runs Branch and Bound, knapsack and Single Random Draw and returns the selection with the
+/// lowest waste.
+///
+/// Ties are broken in favour of the selection spending more inputs, like Bitcoin Core does.
+#[cfg(feature = "rand")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rand")))]
+pub fn select_coins<R: Rng + ?Sized>(
+    candidates: &[Candidate],
+    params: &Params,
+    rng: &mut R,
+) -> Result<Selection, Error> {
+    let results = [
+        select_coins_bnb(candidates, params),
+        select_coins_knapsack(candidates, params, rng),
+        select_coins_srd(candidates, params, rng),
+    ];
+    let mut best: Option<&Selection> = None;
+    for selection in results.iter().filter_map(|result| result.as_ref().ok()) {
+        best = match best {
+            Some(best)
+                if (best.waste, core::cmp::Reverse(best.selected.len()))
+                    <= (selection.waste, core::cmp::Reverse(selection.selected.len())) =>
+                Some(best),
+            _ => Some(selection),
+        };
+    }
+    match best {
+        Some(best) => Ok(best.clone()),
+        // Knapsack only fails if the funds are insufficient.
+        None => Err(results[1].clone().expect_err("no selection found")),
+    }
+}
+
+/// A candidate that can be selected, with its effective value and fees in satoshis.
+#[derive(Debug, Clone, Copy)]
+struct Utxo {
+    /// The index into the candidates.
+    index: usize,
+    effective_value: u64,
+    fee: u64,
+    long_term_fee: u64,
+}
+
+/// Returns the candidates with a positive effective value.
+fn utxos(candidates: &[Candidate], params: &Params) -> Vec<Utxo> {
+    candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(index, candidate)| {
+            let effective_value = candidate.effective_value(params.fee_rate)?.to_sat();
+            if effective_value == 0 {
+                return None;
+            }
+            Some(Utxo {
+                index,
+                effective_value,
+                fee: (params.fee_rate * candidate.weight).to_sat(),
+                long_term_fee: (params.long_term_fee_rate * candidate.weight).to_sat(),
+            })
+        })
+        .collect()
+}
+
+/// Checks that the effective value of all the `utxos` reaches the selection target.
+fn check_funds(utxos: &[Utxo], params: &Params) -> Result<(), Error> {
+    let available = utxos.iter().map(|utxo| utxo.effective_value).sum::<u64>();
+    let required = params.selection_target();
+    if available < required {
+        Err(Error::InsufficientFunds {
+            available: Amount::from_sat(available),
+            required: Amount::from_sat(required),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Computes the change, fee and waste of selecting `utxos`, which must reach the selection target.
+///
+/// A change output is only created if `allow_change` is set and its value is at least the
+/// minimum change value.
+fn finish<'a, I>(
+    utxos: I,
+    candidates: &[Candidate],
+    params: &Params,
+    allow_change: bool,
+) -> Selection
+where
+    I: IntoIterator<Item = &'a Utxo>,
+{
+    let mut selected = Vec::new();
+    let mut effective_value = 0;
+    let mut waste = 0i64;
+    for utxo in utxos {
+        selected.push(utxo.index);
+        effective_value += utxo.effective_value;
+        waste += utxo.fee as i64 - utxo.long_term_fee as i64;
+    }
+    selected.sort_unstable();
+
+    let excess = effective_value - params.selection_target();
+    let change = excess.checked_sub(params.change_fee()).map(Amount::from_sat).filter(|change| {
+        allow_change && *change > Amount::ZERO && *change >= params.change_policy.min_value
+    });
+    match change {
+        Some(_) => waste += params.cost_of_change() as i64,
+        None => waste += excess as i64,
+    }
+
+    let value = selected.iter().map(|&index| candidates[index].value).sum::<Amount>();
+    Selection {
+        selected,
+        change,
+        fee: value - params.target - change.unwrap_or(Amount::ZERO),
+        waste: SignedAmount::from_sat(waste),
+    }
+}
+
+/// Error returned when no selection is found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The effective value of the candidates doesn't reach the target plus the fees.
+    InsufficientFunds {
+        /// The effective value of all candidates.
+        available: Amount,
+        /// The target plus the fee of the transaction without inputs.
+        required: Amount,
+    },
+    /// Branch and Bound found no selection that doesn't need a change output.
+    NoChangelessSolution,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InsufficientFunds { available, required } => write!(
+                f,
+                "insufficient funds, available {} but at least {} required",
+                available, required
+            ),
+            Error::NoChangelessSolution => f.write_str("no selection without change found"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::hashes::Hash;
+    use crate::{ScriptBuf, WPubkeyHash};
+
+    /// Returns candidates without weight, their effective values equal their values.
+    pub(crate) fn candidates(values: &[u64]) -> Vec<Candidate> {
+        values
+            .iter()
+            .map(|&value| Candidate { value: Amount::from_sat(value), weight: Weight::ZERO })
+            .collect()
+    }
+
+    /// Returns parameters without fees and a long term feerate of 1 sat/wu, so that the cost of
+    /// change is the spend weight of the change.
+    pub(crate) fn params(target: u64) -> Params {
+        Params {
+            target: Amount::from_sat(target),
+            base_weight: Weight::ZERO,
+            fee_rate: FeeRate::ZERO,
+            long_term_fee_rate: FeeRate::from_sat_per_kwu(1000),
+            change_policy: ChangePolicy {
+                output_weight: Weight::ZERO,
+                spend_weight: Weight::ZERO,
+                min_value: Amount::ZERO,
+            },
+        }
+    }
+
+    /// A deterministic xorshift generator.
+    #[cfg(feature = "rand")]
+    pub(crate) struct TestRng(u64);
+
+    #[cfg(feature = "rand")]
+    impl TestRng {
+        pub(crate) fn new() -> Self { TestRng(0x2545_f491_4f6c_dd1d) }
+    }
+
+    #[cfg(feature = "rand")]
+    impl secp256k1::rand::RngCore for TestRng {
+        fn next_u32(&mut self) -> u32 { self.next_u64() as u32 }
+
+        fn next_u64(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            for chunk in dest.chunks_mut(8) {
+                let bytes = self.next_u64().to_le_bytes();
+                chunk.copy_from_slice(&bytes[..chunk.len()]);
+            }
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), secp256k1::rand::Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn change_policy() {
+        let script = ScriptBuf::new_v0_p2wpkh(&WPubkeyHash::all_zeros());
+        let policy = ChangePolicy::new(&script, InputWeightPrediction::P2WPKH_MAX);
+        assert_eq!(policy.output_weight, Weight::from_wu(31 * 4));
+        assert_eq!(policy.spend_weight, InputWeightPrediction::P2WPKH_MAX.weight());
+        assert_eq!(policy.min_value, Amount::from_sat(294));
+
+        let fee_rate = FeeRate::from_sat_per_vb_unchecked(10);
+        let long_term_fee_rate = FeeRate::from_sat_per_vb_unchecked(2);
+        // 31 vB now and 67.25 vB later on, rounded up.
+        assert_eq!(
+            policy.cost_of_change(fee_rate, long_term_fee_rate),
+            Amount::from_sat(310 + 135)
+        );
+    }
+
+    #[test]
+    fn change_below_minimum() {
+        let pool = candidates(&[10, 40]);
+        let mut params = params(45);
+        params.change_policy.min_value = Amount::from_sat(6);
+        params.change_policy.spend_weight = Weight::from_wu(2);
+
+        let selection = select_coins_largest_first(&pool, &params).unwrap();
+        assert_eq!(selection.change, None);
+        assert_eq!(selection.fee, Amount::from_sat(5));
+        // The excess is wasted.
+        assert_eq!(selection.waste, SignedAmount::from_sat(5));
+
+        params.change_policy.min_value = Amount::from_sat(5);
+        let selection = select_coins_largest_first(&pool, &params).unwrap();
+        assert_eq!(selection.change, Some(Amount::from_sat(5)));
+        assert_eq!(selection.fee, Amount::ZERO);
+        // Only the cost of change is wasted.
+        assert_eq!(selection.waste, SignedAmount::from_sat(2));
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn lowest_waste() {
+        let mut rng = TestRng::new();
+        let mut params = params(30);
+        params.change_policy.spend_weight = Weight::from_wu(10);
+
+        // Branch and Bound finds the changeless solution.
+        let pool = candidates(&[5, 10, 20, 50]);
+        let selection = select_coins(&pool, &params, &mut rng).unwrap();
+        assert_eq!(selection.selected, [1, 2]);
+        assert_eq!(selection.waste, SignedAmount::ZERO);
+
+        // Otherwise the selection with the lowest waste, here the one with change.
+        let pool = candidates(&[45, 100]);
+        let selection = select_coins(&pool, &params, &mut rng).unwrap();
+        assert_eq!(selection.selected, [0]);
+        assert_eq!(selection.change, Some(Amount::from_sat(15)));
+
+        let err = select_coins(
+            &pool,
+            &{
+                params.target = Amount::from_sat(200);
+                params
+            },
+            &mut rng,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "insufficient funds, available 0.00000145 BTC but at least 0.000002 BTC required"
+        );
+    }
+}
@@ -0,0 +1,74 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Single Random Draw coin selection.
+//!
+//! This is `SelectCoinsSRD` of Bitcoin Core.
+
+use secp256k1::rand::seq::SliceRandom;
+use secp256k1::rand::Rng;
+
+use super::{finish, utxos, Candidate, Error, Params, Selection};
+use crate::Amount;
+
+/// Selects randomly picked candidates until their effective value reaches the target plus the
+/// change.
+///
+/// The change is the fee for the change output plus the minimum change value, this avoids creating
+/// tiny change outputs when the selection barely reaches the target.
+#[cfg_attr(docsrs, doc(cfg(feature = "rand")))]
+pub fn select_coins_srd<R: Rng + ?Sized>(
+    candidates: &[Candidate],
+    params: &Params,
+    rng: &mut R,
+) -> Result<Selection, Error> {
+    let mut pool = utxos(candidates, params);
+    pool.shuffle(rng);
+
+    let target =
+        params.selection_target() + params.change_fee() + params.change_policy.min_value.to_sat();
+    let mut value = 0;
+    for (i, utxo) in pool.iter().enumerate() {
+        value += utxo.effective_value;
+        if value >= target {
+            return Ok(finish(&pool[..=i], candidates, params, true));
+        }
+    }
+    Err(Error::InsufficientFunds {
+        available: Amount::from_sat(value),
+        required: Amount::from_sat(target),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coin_selection::tests::{candidates, params, TestRng};
+
+    #[test]
+    fn select() {
+        let pool = candidates(&[10, 20, 30, 40]);
+        let mut params = params(45);
+        params.change_policy.min_value = Amount::from_sat(5);
+
+        let mut rng = TestRng::new();
+        for _ in 0..100 {
+            let selection = select_coins_srd(&pool, &params, &mut rng).unwrap();
+            let value = selection.selected.iter().map(|&i| pool[i].value).sum::<Amount>();
+            assert!(value >= Amount::from_sat(50));
+            // Every selected candidate is needed to reach the target, except the last drawn one
+            // which we don't know.
+            assert!(selection.selected.len() >= 2);
+            assert_eq!(selection.change, Some(value - Amount::from_sat(45)));
+        }
+
+        params.target = Amount::from_sat(96);
+        let err = select_coins_srd(&pool, &params, &mut rng).unwrap_err();
+        assert_eq!(
+            err,
+            Error::InsufficientFunds {
+                available: Amount::from_sat(100),
+                required: Amount::from_sat(101)
+            }
+        );
+    }
+}
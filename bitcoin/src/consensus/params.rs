@@ -47,6 +47,9 @@ pub struct Params {
     pub allow_min_difficulty_blocks: bool,
     /// Determines whether retargeting is disabled for this network or not.
     pub no_pow_retargeting: bool,
+    /// Determines whether the timewarp protection and the difficulty adjustment of BIP94 are
+    /// enforced for this network or not.
+    pub enforce_bip94: bool,
 }
 
 impl Params {
@@ -66,6 +69,7 @@ impl Params {
                 pow_target_timespan: 14 * 24 * 60 * 60, // 2 weeks.
                 allow_min_difficulty_blocks: false,
                 no_pow_retargeting: false,
+                enforce_bip94: false,
             },
             Network::Testnet => Params {
                 network: Network::Testnet,
@@ -80,6 +84,7 @@ impl Params {
                 pow_target_timespan: 14 * 24 * 60 * 60, // 2 weeks.
                 allow_min_difficulty_blocks: true,
                 no_pow_retargeting: false,
+                enforce_bip94: false,
             },
             Network::Signet => Params {
                 network: Network::Signet,
@@ -94,6 +99,7 @@ impl Params {
                 pow_target_timespan: 14 * 24 * 60 * 60, // 2 weeks.
                 allow_min_difficulty_blocks: false,
                 no_pow_retargeting: false,
+                enforce_bip94: false,
             },
             Network::Regtest => Params {
                 network: Network::Regtest,
@@ -108,6 +114,22 @@ impl Params {
                 pow_target_timespan: 14 * 24 * 60 * 60, // 2 weeks.
                 allow_min_difficulty_blocks: true,
                 no_pow_retargeting: true,
+                enforce_bip94: false,
+            },
+            Network::Testnet4 => Params {
+                network: Network::Testnet4,
+                bip16_time: 1333238400, // Apr 1 2012
+                bip34_height: 1,
+                bip65_height: 1,
+                bip66_height: 1,
+                rule_change_activation_threshold: 1512, // 75%
+                miner_confirmation_window: 2016,
+                pow_limit: Work::TESTNET4_MIN,
+                pow_target_spacing: 10 * 60,            // 10 minutes.
+                pow_target_timespan: 14 * 24 * 60 * 60, // 2 weeks.
+                allow_min_difficulty_blocks: true,
+                no_pow_retargeting: false,
+                enforce_bip94: true,
             },
         }
     }
@@ -8,6 +8,7 @@
 
 pub mod ecdsa;
 pub mod key;
+pub mod musig2;
 pub mod sighash;
 // Contents re-exported in `bitcoin::taproot`.
 pub(crate) mod taproot;
@@ -0,0 +1,1112 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! MuSig2 multi-signatures.
+//!
+//! Implementation of [BIP 327](https://github.com/bitcoin/bips/blob/master/bip-0327.mediawiki),
+//! a two round multi-signature scheme producing BIP 340 Schnorr signatures under an aggregated
+//! public key, e.g. for taproot key path spends.
+//!
+//! Signing works as follows:
+//!
+//! 1. The signers' public keys are aggregated with [`KeyAggContext::new`], optionally after
+//!    sorting them with [`sort_public_keys`] and tweaking the result with
+//!    [`KeyAggContext::tap_tweak`].
+//! 2. Each signer generates a nonce pair with [`SecretNonce::generate`] and shares the
+//!    [`PublicNonce`]. The public nonces are aggregated with [`AggregatedNonce::new`].
+//! 3. Each signer creates a [`Session`] for the message and produces a [`PartialSignature`] with
+//!    [`Session::partial_sign`], the partial signatures are combined with [`Session::aggregate`].
+//!
+//! A [`SecretNonce`] must never be used for more than one signature, doing so leaks the secret
+//! key. [`Session::partial_sign`] consumes the nonce to make accidental reuse harder.
+//!
+
+use core::fmt;
+use core::str::FromStr;
+
+use bitcoin_internals::hex::display::DisplayHex;
+use secp256k1::{
+    constants, schnorr, PublicKey, Scalar, Secp256k1, SecretKey, Signing, Verification,
+    XOnlyPublicKey,
+};
+
+use crate::crypto::taproot;
+use crate::hashes::hex::FromHex;
+use crate::hashes::{sha256t_hash_newtype, Hash, HashEngine};
+use crate::prelude::*;
+use crate::sighash::TapSighashType;
+use crate::taproot::{TapNodeHash, TapTweakHash};
+
+/// The SHA-256 midstate value for the [`KeyAggListHash`].
+const MIDSTATE_KEYAGG_LIST: [u8; 32] = [
+    179, 153, 213, 224, 200, 255, 243, 2, 107, 173, 172, 113, 7, 197, 183, 241, 151, 1, 226, 239,
+    42, 114, 236, 248, 32, 26, 76, 123, 171, 20, 138, 56,
+];
+// b399d5e0c8fff3026badac7107c5b7f19701e2ef2a72ecf8201a4c7bab148a38
+
+/// The SHA-256 midstate value for the [`KeyAggCoeffHash`].
+const MIDSTATE_KEYAGG_COEFF: [u8; 32] = [
+    110, 240, 44, 90, 6, 164, 128, 222, 31, 41, 134, 101, 29, 17, 52, 242, 86, 160, 176, 99, 82,
+    218, 65, 71, 242, 128, 217, 212, 68, 132, 190, 21,
+];
+// 6ef02c5a06a480de1f2986651d1134f256a0b06352da4147f280d9d44484be15
+
+/// The SHA-256 midstate value for the [`MusigAuxHash`].
+const MIDSTATE_MUSIG_AUX: [u8; 32] = [
+    161, 158, 136, 75, 244, 99, 254, 126, 47, 24, 249, 162, 190, 176, 249, 255, 15, 55, 232, 176,
+    6, 235, 210, 111, 227, 178, 67, 210, 82, 47, 177, 80,
+];
+// a19e884bf463fe7e2f18f9a2beb0f9ff0f37e8b006ebd26fe3b243d2522fb150
+
+/// The SHA-256 midstate value for the [`MusigNonceHash`].
+const MIDSTATE_MUSIG_NONCE: [u8; 32] = [
+    7, 16, 27, 100, 24, 0, 52, 20, 3, 145, 188, 67, 14, 98, 88, 238, 41, 210, 107, 114, 131, 67,
+    147, 126, 183, 160, 164, 251, 255, 86, 138, 48,
+];
+// 07101b64180034140391bc430e6258ee29d26b728343937eb7a0a4fbff568a30
+
+/// The SHA-256 midstate value for the [`MusigNonceCoeffHash`].
+const MIDSTATE_MUSIG_NONCECOEF: [u8; 32] = [
+    44, 125, 90, 69, 6, 191, 126, 83, 137, 190, 104, 166, 151, 18, 84, 192, 96, 172, 18, 210, 114,
+    132, 109, 205, 108, 129, 33, 47, 222, 122, 37, 0,
+];
+// 2c7d5a4506bf7e5389be68a6971254c060ac12d272846dcd6c81212fde7a2500
+
+/// The SHA-256 midstate value for the [`Bip340ChallengeHash`].
+const MIDSTATE_BIP340_CHALLENGE: [u8; 32] = [
+    156, 236, 186, 17, 35, 146, 83, 129, 17, 103, 145, 18, 209, 98, 126, 15, 151, 200, 117, 80, 0,
+    60, 199, 101, 144, 246, 17, 100, 51, 233, 182, 106,
+];
+// 9cecba112392538111679112d1627e0f97c87550003cc76590f6116433e9b66a
+
+#[rustfmt::skip]
+sha256t_hash_newtype!(KeyAggListHash, KeyAggListTag, MIDSTATE_KEYAGG_LIST, 64,
+    doc="Tagged hash with tag \"KeyAgg list\", the hash of all public keys being aggregated.", forward
+);
+#[rustfmt::skip]
+sha256t_hash_newtype!(KeyAggCoeffHash, KeyAggCoeffTag, MIDSTATE_KEYAGG_COEFF, 64,
+    doc="Tagged hash with tag \"KeyAgg coefficient\", used to compute key aggregation coefficients.", forward
+);
+#[rustfmt::skip]
+sha256t_hash_newtype!(MusigAuxHash, MusigAuxTag, MIDSTATE_MUSIG_AUX, 64,
+    doc="Tagged hash with tag \"MuSig/aux\", used to mask the secret key during nonce generation.", forward
+);
+#[rustfmt::skip]
+sha256t_hash_newtype!(MusigNonceHash, MusigNonceTag, MIDSTATE_MUSIG_NONCE, 64,
+    doc="Tagged hash with tag \"MuSig/nonce\", used to derive secret nonces.", forward
+);
+#[rustfmt::skip]
+sha256t_hash_newtype!(MusigNonceCoeffHash, MusigNonceCoeffTag, MIDSTATE_MUSIG_NONCECOEF, 64,
+    doc="Tagged hash with tag \"MuSig/noncecoef\", used to compute the nonce coefficient.", forward
+);
+#[rustfmt::skip]
+sha256t_hash_newtype!(Bip340ChallengeHash, Bip340ChallengeTag, MIDSTATE_BIP340_CHALLENGE, 64,
+    doc="Tagged hash with tag \"BIP0340/challenge\", the BIP 340 signature challenge.", forward
+);
+
+/// Sorts `pubkeys` lexicographically by their compressed serialization (`KeySort` in BIP 327).
+pub fn sort_public_keys(pubkeys: &mut [PublicKey]) { pubkeys.sort_by_key(|pk| pk.serialize()); }
+
+/// The aggregated public key of a set of signers, including any tweaks applied to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyAggContext {
+    pubkeys: Vec<PublicKey>,
+    /// The hash of all public keys, `L` in BIP 327.
+    keys_hash: KeyAggListHash,
+    /// The first public key different from the first one, its coefficient is 1.
+    second_key: Option<PublicKey>,
+    /// The aggregated public key.
+    agg_pk: PublicKey,
+    /// Accumulated sign flips from x-only tweaks.
+    gacc: ModScalar,
+    /// Accumulated tweak.
+    tacc: ModScalar,
+}
+
+impl KeyAggContext {
+    /// Aggregates `pubkeys` in the given order (`KeyAgg` in BIP 327).
+    ///
+    /// The aggregated key depends on the order of the keys, use [`sort_public_keys`] first if the
+    /// signers do not agree on an order otherwise.
+    ///
+    /// # Errors
+    ///
+    /// If `pubkeys` is empty or the aggregated key is the point at infinity.
+    pub fn new<C: Verification>(
+        secp: &Secp256k1<C>,
+        pubkeys: &[PublicKey],
+    ) -> Result<KeyAggContext, Error> {
+        let mut eng = KeyAggListHash::engine();
+        for pk in pubkeys {
+            eng.input(&pk.serialize());
+        }
+        let keys_hash = KeyAggListHash::from_engine(eng);
+        let second_key = pubkeys.iter().find(|pk| Some(*pk) != pubkeys.first()).copied();
+
+        let mut ctx = KeyAggContext {
+            pubkeys: pubkeys.to_vec(),
+            keys_hash,
+            second_key,
+            agg_pk: generator(),
+            gacc: ModScalar::one(),
+            tacc: ModScalar::ZERO,
+        };
+        let agg_pk = pubkeys
+            .iter()
+            .fold(None, |acc, pk| add_points(acc, mul_point(secp, Some(*pk), ctx.coefficient(pk))));
+        ctx.agg_pk = agg_pk.ok_or(Error::InfiniteAggregatedKey)?;
+        Ok(ctx)
+    }
+
+    /// Returns the aggregated public key, including the tweaks applied so far.
+    pub fn public_key(&self) -> PublicKey { self.agg_pk }
+
+    /// Returns the x-only aggregated public key, including the tweaks applied so far.
+    ///
+    /// After [`Self::tap_tweak`] this is the taproot output key.
+    pub fn x_only_public_key(&self) -> XOnlyPublicKey { self.agg_pk.x_only_public_key().0 }
+
+    /// Returns the public keys that were aggregated, in aggregation order.
+    pub fn pubkeys(&self) -> &[PublicKey] { &self.pubkeys }
+
+    /// Adds `tweak` to the aggregated public key, as done by BIP 32 derivation.
+    ///
+    /// # Errors
+    ///
+    /// If the tweaked key is the point at infinity.
+    pub fn add_plain_tweak<C: Verification>(
+        self,
+        secp: &Secp256k1<C>,
+        tweak: &Scalar,
+    ) -> Result<KeyAggContext, Error> {
+        self.add_tweak(secp, tweak, false)
+    }
+
+    /// Adds `tweak` to the x-only aggregated public key, as done by BIP 341 taproot tweaking.
+    ///
+    /// # Errors
+    ///
+    /// If the tweaked key is the point at infinity.
+    pub fn add_xonly_tweak<C: Verification>(
+        self,
+        secp: &Secp256k1<C>,
+        tweak: &Scalar,
+    ) -> Result<KeyAggContext, Error> {
+        self.add_tweak(secp, tweak, true)
+    }
+
+    /// Tweaks the aggregated public key with the [`TapTweakHash`] of `merkle_root`, making
+    /// [`Self::x_only_public_key`] the output key of a taproot output with the aggregated key as
+    /// internal key.
+    ///
+    /// # Errors
+    ///
+    /// If the tweaked key is the point at infinity.
+    pub fn tap_tweak<C: Verification>(
+        self,
+        secp: &Secp256k1<C>,
+        merkle_root: Option<TapNodeHash>,
+    ) -> Result<KeyAggContext, Error> {
+        let tweak = TapTweakHash::from_key_and_tweak(self.x_only_public_key(), merkle_root);
+        self.add_xonly_tweak(secp, &tweak.to_scalar())
+    }
+
+    /// Applies a tweak (`ApplyTweak` in BIP 327).
+    fn add_tweak<C: Verification>(
+        mut self,
+        secp: &Secp256k1<C>,
+        tweak: &Scalar,
+        is_xonly: bool,
+    ) -> Result<KeyAggContext, Error> {
+        let g = if is_xonly && !has_even_y(&self.agg_pk) {
+            ModScalar::one().negate()
+        } else {
+            ModScalar::one()
+        };
+        let t = ModScalar::from(*tweak);
+
+        let agg_pk = add_points(
+            mul_point(secp, Some(self.agg_pk), g),
+            mul_point(secp, Some(generator()), t),
+        );
+        self.agg_pk = agg_pk.ok_or(Error::InfiniteAggregatedKey)?;
+        self.gacc = g.mul(self.gacc);
+        self.tacc = t.add(g.mul(self.tacc));
+        Ok(self)
+    }
+
+    /// Returns the key aggregation coefficient of `pk` (`KeyAggCoeffInternal` in BIP 327).
+    fn coefficient(&self, pk: &PublicKey) -> ModScalar {
+        if Some(*pk) == self.second_key {
+            return ModScalar::one();
+        }
+        let mut eng = KeyAggCoeffHash::engine();
+        eng.input(self.keys_hash.as_ref());
+        eng.input(&pk.serialize());
+        ModScalar::reduce(KeyAggCoeffHash::from_engine(eng).to_byte_array())
+    }
+}
+
+/// A signer's secret nonce pair for a single signing session.
+///
+/// Never use a secret nonce to sign more than once and never persist it where it could be restored
+/// from, reusing a nonce leaks the secret key.
+#[derive(Debug)]
+pub struct SecretNonce {
+    k1: SecretKey,
+    k2: SecretKey,
+    /// The public key of the signer this nonce was generated for.
+    pubkey: PublicKey,
+}
+
+impl SecretNonce {
+    /// Generates a nonce pair for the signer with `pubkey` (`NonceGen` in BIP 327).
+    ///
+    /// `session_rand` must be 32 bytes of fresh randomness, never used before. The remaining
+    /// arguments are optional and only strengthen the nonce against weak randomness: the signer's
+    /// secret key, the aggregated public key, the message and any extra data.
+    pub fn generate<C: Signing>(
+        secp: &Secp256k1<C>,
+        session_rand: [u8; 32],
+        secret_key: Option<&SecretKey>,
+        pubkey: &PublicKey,
+        agg_pubkey: Option<&XOnlyPublicKey>,
+        msg: Option<&[u8]>,
+        extra_input: Option<&[u8]>,
+    ) -> (SecretNonce, PublicNonce) {
+        let mut rand = session_rand;
+        if let Some(sk) = secret_key {
+            let (sk, mask) = (sk.secret_bytes(), MusigAuxHash::hash(&session_rand).to_byte_array());
+            for (i, byte) in rand.iter_mut().enumerate() {
+                *byte = sk[i] ^ mask[i];
+            }
+        }
+        let agg_pubkey = agg_pubkey.map(|pk| pk.serialize());
+        let agg_pubkey: &[u8] = agg_pubkey.as_ref().map_or(&[], |pk| &pk[..]);
+        let extra_input = extra_input.unwrap_or(&[]);
+
+        let k = |i: u8| {
+            let mut eng = MusigNonceHash::engine();
+            eng.input(&rand);
+            eng.input(&[constants::PUBLIC_KEY_SIZE as u8]);
+            eng.input(&pubkey.serialize());
+            eng.input(&[agg_pubkey.len() as u8]);
+            eng.input(agg_pubkey);
+            match msg {
+                Some(msg) => {
+                    eng.input(&[1]);
+                    eng.input(&(msg.len() as u64).to_be_bytes());
+                    eng.input(msg);
+                }
+                None => eng.input(&[0]),
+            }
+            eng.input(&(extra_input.len() as u32).to_be_bytes());
+            eng.input(extra_input);
+            eng.input(&[i]);
+            let k = ModScalar::reduce(MusigNonceHash::from_engine(eng).to_byte_array());
+            // This is statistically extremely unlikely to panic.
+            k.0.expect("nonce is zero")
+        };
+
+        let sec_nonce = SecretNonce { k1: k(0), k2: k(1), pubkey: *pubkey };
+        let pub_nonce = PublicNonce {
+            r1: PublicKey::from_secret_key(secp, &sec_nonce.k1),
+            r2: PublicKey::from_secret_key(secp, &sec_nonce.k2),
+        };
+        (sec_nonce, pub_nonce)
+    }
+
+    /// Returns the public nonce corresponding to this secret nonce.
+    pub fn public_nonce<C: Signing>(&self, secp: &Secp256k1<C>) -> PublicNonce {
+        PublicNonce {
+            r1: PublicKey::from_secret_key(secp, &self.k1),
+            r2: PublicKey::from_secret_key(secp, &self.k2),
+        }
+    }
+
+    /// Deserializes a secret nonce from the 97 byte encoding of BIP 327.
+    pub fn from_slice(data: &[u8]) -> Result<SecretNonce, Error> {
+        if data.len() != 97 {
+            return Err(Error::InvalidSecretNonce);
+        }
+        Ok(SecretNonce {
+            k1: SecretKey::from_slice(&data[..32]).map_err(|_| Error::InvalidSecretNonce)?,
+            k2: SecretKey::from_slice(&data[32..64]).map_err(|_| Error::InvalidSecretNonce)?,
+            pubkey: PublicKey::from_slice(&data[64..]).map_err(|_| Error::InvalidSecretNonce)?,
+        })
+    }
+
+    /// Serializes this secret nonce using the 97 byte encoding of BIP 327.
+    pub fn serialize(&self) -> [u8; 97] {
+        let mut ser = [0; 97];
+        ser[..32].copy_from_slice(&self.k1.secret_bytes());
+        ser[32..64].copy_from_slice(&self.k2.secret_bytes());
+        ser[64..].copy_from_slice(&self.pubkey.serialize());
+        ser
+    }
+}
+
+/// A signer's public nonce pair, shared with the other signers before signing.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PublicNonce {
+    r1: PublicKey,
+    r2: PublicKey,
+}
+
+impl PublicNonce {
+    /// Deserializes a public nonce from its 66 byte encoding.
+    pub fn from_slice(data: &[u8]) -> Result<PublicNonce, Error> {
+        if data.len() != 66 {
+            return Err(Error::InvalidPublicNonce);
+        }
+        Ok(PublicNonce {
+            r1: PublicKey::from_slice(&data[..33]).map_err(|_| Error::InvalidPublicNonce)?,
+            r2: PublicKey::from_slice(&data[33..]).map_err(|_| Error::InvalidPublicNonce)?,
+        })
+    }
+
+    /// Serializes this public nonce as the two compressed nonce points.
+    pub fn serialize(&self) -> [u8; 66] {
+        let mut ser = [0; 66];
+        ser[..33].copy_from_slice(&self.r1.serialize());
+        ser[33..].copy_from_slice(&self.r2.serialize());
+        ser
+    }
+}
+
+impl fmt::Display for PublicNonce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.serialize().as_hex(), f)
+    }
+}
+
+impl FromStr for PublicNonce {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = Vec::from_hex(s).map_err(|_| Error::InvalidPublicNonce)?;
+        PublicNonce::from_slice(&bytes)
+    }
+}
+
+#[cfg(feature = "serde")]
+crate::serde_utils::serde_string_impl!(PublicNonce, "a hex encoded MuSig2 public nonce");
+
+/// The sum of the public nonces of all signers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AggregatedNonce {
+    /// The first nonce point, `None` for the point at infinity.
+    r1: Option<PublicKey>,
+    /// The second nonce point, `None` for the point at infinity.
+    r2: Option<PublicKey>,
+}
+
+impl AggregatedNonce {
+    /// Aggregates the public nonces of all signers (`NonceAgg` in BIP 327).
+    pub fn new(pub_nonces: &[PublicNonce]) -> AggregatedNonce {
+        pub_nonces.iter().fold(AggregatedNonce { r1: None, r2: None }, |acc, nonce| {
+            AggregatedNonce {
+                r1: add_points(acc.r1, Some(nonce.r1)),
+                r2: add_points(acc.r2, Some(nonce.r2)),
+            }
+        })
+    }
+
+    /// Deserializes an aggregated nonce from its 66 byte encoding, in which the point at infinity
+    /// is encoded as 33 zero bytes.
+    pub fn from_slice(data: &[u8]) -> Result<AggregatedNonce, Error> {
+        if data.len() != 66 {
+            return Err(Error::InvalidAggregatedNonce);
+        }
+        let point = |data: &[u8]| {
+            if data.iter().all(|b| *b == 0) {
+                Ok(None)
+            } else {
+                PublicKey::from_slice(data).map(Some).map_err(|_| Error::InvalidAggregatedNonce)
+            }
+        };
+        Ok(AggregatedNonce { r1: point(&data[..33])?, r2: point(&data[33..])? })
+    }
+
+    /// Serializes this aggregated nonce as the two compressed nonce points, encoding the point at
+    /// infinity as 33 zero bytes.
+    pub fn serialize(&self) -> [u8; 66] {
+        let mut ser = [0; 66];
+        if let Some(r1) = self.r1 {
+            ser[..33].copy_from_slice(&r1.serialize());
+        }
+        if let Some(r2) = self.r2 {
+            ser[33..].copy_from_slice(&r2.serialize());
+        }
+        ser
+    }
+}
+
+/// A signer's partial signature.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PartialSignature(ModScalar);
+
+impl PartialSignature {
+    /// Deserializes a partial signature from its 32 byte encoding.
+    pub fn from_slice(data: &[u8]) -> Result<PartialSignature, Error> {
+        if data.len() != 32 {
+            return Err(Error::InvalidPartialSignature);
+        }
+        let mut bytes = [0; 32];
+        bytes.copy_from_slice(data);
+        ModScalar::from_be_bytes(bytes).map(PartialSignature).ok_or(Error::InvalidPartialSignature)
+    }
+
+    /// Serializes this partial signature as a 32 byte big endian integer.
+    pub fn serialize(&self) -> [u8; 32] { self.0.to_be_bytes() }
+}
+
+impl core::hash::Hash for PartialSignature {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        core::hash::Hash::hash(&self.serialize(), state)
+    }
+}
+
+impl fmt::Display for PartialSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.serialize().as_hex(), f)
+    }
+}
+
+impl FromStr for PartialSignature {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = Vec::from_hex(s).map_err(|_| Error::InvalidPartialSignature)?;
+        PartialSignature::from_slice(&bytes)
+    }
+}
+
+#[cfg(feature = "serde")]
+crate::serde_utils::serde_string_impl!(PartialSignature, "a hex encoded MuSig2 partial signature");
+
+/// The state shared by all signers for signing a message with an aggregated key and nonce.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Session {
+    key_agg: KeyAggContext,
+    /// The nonce coefficient.
+    b: ModScalar,
+    /// The final nonce point.
+    r: PublicKey,
+    /// The signature challenge.
+    e: ModScalar,
+}
+
+impl Session {
+    /// Creates the signing session for `msg` (`GetSessionValues` in BIP 327).
+    ///
+    /// `msg` is usually a 32 byte hash such as a [`TapSighash`](crate::sighash::TapSighash) but
+    /// can be of any length.
+    pub fn new<C: Verification>(
+        secp: &Secp256k1<C>,
+        key_agg: &KeyAggContext,
+        agg_nonce: &AggregatedNonce,
+        msg: &[u8],
+    ) -> Session {
+        let agg_xonly = key_agg.x_only_public_key().serialize();
+
+        let mut eng = MusigNonceCoeffHash::engine();
+        eng.input(&agg_nonce.serialize());
+        eng.input(&agg_xonly);
+        eng.input(msg);
+        let b = ModScalar::reduce(MusigNonceCoeffHash::from_engine(eng).to_byte_array());
+
+        let r =
+            add_points(agg_nonce.r1, mul_point(secp, agg_nonce.r2, b)).unwrap_or_else(generator);
+
+        let mut eng = Bip340ChallengeHash::engine();
+        eng.input(&r.x_only_public_key().0.serialize());
+        eng.input(&agg_xonly);
+        eng.input(msg);
+        let e = ModScalar::reduce(Bip340ChallengeHash::from_engine(eng).to_byte_array());
+
+        Session { key_agg: key_agg.clone(), b, r, e }
+    }
+
+    /// Creates a partial signature with `secret_key` and the nonce generated for it (`Sign` in
+    /// BIP 327).
+    ///
+    /// The nonce is consumed, it must not be used again.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::SecretKeyMismatch`] if the nonce was generated for a different public key.
+    /// - [`Error::UnknownSigner`] if the signer's public key is not part of the aggregated key.
+    pub fn partial_sign<C: Signing + Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        sec_nonce: SecretNonce,
+        secret_key: &SecretKey,
+    ) -> Result<PartialSignature, Error> {
+        let pubkey = PublicKey::from_secret_key(secp, secret_key);
+        if pubkey != sec_nonce.pubkey {
+            return Err(Error::SecretKeyMismatch);
+        }
+        if !self.key_agg.pubkeys.contains(&pubkey) {
+            return Err(Error::UnknownSigner);
+        }
+        let a = self.key_agg.coefficient(&pubkey);
+
+        let (mut k1, mut k2) = (ModScalar(Some(sec_nonce.k1)), ModScalar(Some(sec_nonce.k2)));
+        if !has_even_y(&self.r) {
+            k1 = k1.negate();
+            k2 = k2.negate();
+        }
+        let d = self.g().mul(self.key_agg.gacc).mul(ModScalar(Some(*secret_key)));
+        let s = k1.add(self.b.mul(k2)).add(self.e.mul(a).mul(d));
+
+        let psig = PartialSignature(s);
+        self.verify_internal(secp, &psig, &sec_nonce.public_nonce(secp), &pubkey)?;
+        Ok(psig)
+    }
+
+    /// Verifies the partial signature of the signer with `pubkey` and `pub_nonce`
+    /// (`PartialSigVerify` in BIP 327).
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::UnknownSigner`] if `pubkey` is not part of the aggregated key.
+    /// - [`Error::InvalidPartialSignature`] if the partial signature is not valid.
+    pub fn partial_verify<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        psig: &PartialSignature,
+        pub_nonce: &PublicNonce,
+        pubkey: &PublicKey,
+    ) -> Result<(), Error> {
+        if !self.key_agg.pubkeys.contains(pubkey) {
+            return Err(Error::UnknownSigner);
+        }
+        self.verify_internal(secp, psig, pub_nonce, pubkey)
+    }
+
+    /// Aggregates the partial signatures of all signers into a Schnorr signature valid for the
+    /// aggregated public key (`PartialSigAgg` in BIP 327).
+    ///
+    /// The partial signatures are not verified, an invalid one results in an invalid signature.
+    pub fn aggregate(
+        &self,
+        psigs: &[PartialSignature],
+        hash_ty: TapSighashType,
+    ) -> taproot::Signature {
+        let s = psigs.iter().fold(ModScalar::ZERO, |acc, psig| acc.add(psig.0));
+        let s = s.add(self.e.mul(self.g()).mul(self.key_agg.tacc));
+
+        let mut ser = [0; constants::SCHNORR_SIGNATURE_SIZE];
+        ser[..32].copy_from_slice(&self.r.x_only_public_key().0.serialize());
+        ser[32..].copy_from_slice(&s.to_be_bytes());
+        let sig = schnorr::Signature::from_slice(&ser).expect("signature is 64 bytes");
+        taproot::Signature { sig, hash_ty }
+    }
+
+    /// Returns the partial signature verification result (`PartialSigVerifyInternal` in BIP 327).
+    fn verify_internal<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        psig: &PartialSignature,
+        pub_nonce: &PublicNonce,
+        pubkey: &PublicKey,
+    ) -> Result<(), Error> {
+        let mut r = add_points(Some(pub_nonce.r1), mul_point(secp, Some(pub_nonce.r2), self.b));
+        if !has_even_y(&self.r) {
+            r = r.map(|r| r.negate(secp));
+        }
+        let g = self.g().mul(self.key_agg.gacc);
+        let ea = self.e.mul(self.key_agg.coefficient(pubkey)).mul(g);
+
+        let expected = add_points(r, mul_point(secp, Some(*pubkey), ea));
+        if mul_point(secp, Some(generator()), psig.0) == expected {
+            Ok(())
+        } else {
+            Err(Error::InvalidPartialSignature)
+        }
+    }
+
+    /// Returns 1 if the aggregated public key has an even Y coordinate, -1 otherwise.
+    fn g(&self) -> ModScalar {
+        if has_even_y(&self.key_agg.agg_pk) {
+            ModScalar::one()
+        } else {
+            ModScalar::one().negate()
+        }
+    }
+}
+
+/// Integer modulo the curve order, unlike [`SecretKey`] it can be zero.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct ModScalar(Option<SecretKey>);
+
+impl ModScalar {
+    const ZERO: ModScalar = ModScalar(None);
+
+    fn one() -> ModScalar {
+        ModScalar(Some(SecretKey::from_slice(&Scalar::ONE.to_be_bytes()).expect("one is valid")))
+    }
+
+    /// Returns `None` if `bytes` is not smaller than the curve order.
+    fn from_be_bytes(bytes: [u8; 32]) -> Option<ModScalar> {
+        if bytes == [0; 32] {
+            return Some(ModScalar::ZERO);
+        }
+        SecretKey::from_slice(&bytes).ok().map(|sk| ModScalar(Some(sk)))
+    }
+
+    /// Interprets `bytes` as a big endian integer and reduces it modulo the curve order.
+    fn reduce(mut bytes: [u8; 32]) -> ModScalar {
+        if let Some(scalar) = ModScalar::from_be_bytes(bytes) {
+            return scalar;
+        }
+        // 2^256 is less than twice the curve order so subtracting it once is enough.
+        let mut borrow = 0;
+        for (byte, n) in bytes.iter_mut().zip(constants::CURVE_ORDER.iter()).rev() {
+            let (diff, b1) = byte.overflowing_sub(*n);
+            let (diff, b2) = diff.overflowing_sub(borrow);
+            *byte = diff;
+            borrow = (b1 || b2) as u8;
+        }
+        ModScalar::from_be_bytes(bytes).expect("reduced value is smaller than the curve order")
+    }
+
+    fn to_be_bytes(self) -> [u8; 32] { self.0.map_or([0; 32], |sk| sk.secret_bytes()) }
+
+    fn add(self, other: ModScalar) -> ModScalar {
+        match (self.0, other.0) {
+            (Some(a), Some(b)) => ModScalar(a.add_tweak(&Scalar::from(b)).ok()),
+            (Some(a), None) | (None, Some(a)) => ModScalar(Some(a)),
+            (None, None) => ModScalar::ZERO,
+        }
+    }
+
+    fn mul(self, other: ModScalar) -> ModScalar {
+        match (self.0, other.0) {
+            // The curve order is prime so the product of non-zero values is non-zero.
+            (Some(a), Some(b)) =>
+                ModScalar(Some(a.mul_tweak(&Scalar::from(b)).expect("non-zero product"))),
+            _ => ModScalar::ZERO,
+        }
+    }
+
+    fn negate(self) -> ModScalar { ModScalar(self.0.map(SecretKey::negate)) }
+}
+
+impl From<Scalar> for ModScalar {
+    fn from(scalar: Scalar) -> ModScalar {
+        ModScalar::from_be_bytes(scalar.to_be_bytes())
+            .expect("scalar is smaller than the curve order")
+    }
+}
+
+/// Returns the secp256k1 generator point.
+fn generator() -> PublicKey {
+    let mut ser = [2; constants::PUBLIC_KEY_SIZE];
+    ser[1..].copy_from_slice(&constants::GENERATOR_X);
+    PublicKey::from_slice(&ser).expect("generator is a valid point")
+}
+
+/// Returns true if the Y coordinate of `pk` is even.
+fn has_even_y(pk: &PublicKey) -> bool { pk.serialize()[0] == 0x02 }
+
+/// Adds two points, `None` being the point at infinity.
+fn add_points(a: Option<PublicKey>, b: Option<PublicKey>) -> Option<PublicKey> {
+    match (a, b) {
+        (Some(a), Some(b)) => a.combine(&b).ok(),
+        (Some(p), None) | (None, Some(p)) => Some(p),
+        (None, None) => None,
+    }
+}
+
+/// Multiplies a point by a scalar, `None` being the point at infinity.
+fn mul_point<C: Verification>(
+    secp: &Secp256k1<C>,
+    point: Option<PublicKey>,
+    scalar: ModScalar,
+) -> Option<PublicKey> {
+    match (point, scalar.0) {
+        (Some(point), Some(scalar)) =>
+            Some(point.mul_tweak(secp, &Scalar::from(scalar)).expect("non-zero scalar")),
+        _ => None,
+    }
+}
+
+/// Errors encountered while creating MuSig2 signatures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Error {
+    /// The aggregated public key is the point at infinity.
+    InfiniteAggregatedKey,
+    /// The signer's public key is not one of the aggregated public keys.
+    UnknownSigner,
+    /// The secret key does not belong to the public key the secret nonce was generated for.
+    SecretKeyMismatch,
+    /// Invalid secret nonce encoding.
+    InvalidSecretNonce,
+    /// Invalid public nonce encoding.
+    InvalidPublicNonce,
+    /// Invalid aggregated nonce encoding.
+    InvalidAggregatedNonce,
+    /// Invalid partial signature encoding, or the partial signature does not verify.
+    InvalidPartialSignature,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::Error::*;
+
+        match *self {
+            InfiniteAggregatedKey => f.write_str("aggregated public key is the point at infinity"),
+            UnknownSigner => f.write_str("signer is not one of the aggregated public keys"),
+            SecretKeyMismatch =>
+                f.write_str("secret key does not match the public key of the secret nonce"),
+            InvalidSecretNonce => f.write_str("invalid secret nonce"),
+            InvalidPublicNonce => f.write_str("invalid public nonce"),
+            InvalidAggregatedNonce => f.write_str("invalid aggregated nonce"),
+            InvalidPartialSignature => f.write_str("invalid partial signature"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use self::Error::*;
+
+        match *self {
+            InfiniteAggregatedKey
+            | UnknownSigner
+            | SecretKeyMismatch
+            | InvalidSecretNonce
+            | InvalidPublicNonce
+            | InvalidAggregatedNonce
+            | InvalidPartialSignature => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::TryFrom;
+
+    use secp256k1::Message;
+
+    use super::*;
+    use crate::crypto::key::TapTweak;
+    use crate::hashes::sha256;
+    use crate::hashes::sha256t::Tag;
+    use crate::internal_macros::hex;
+
+    // Test vectors from BIP 327.
+    const SECRET_KEY: &str = "7FB9E0E687ADA1EEBF7ECFE2F21E73EBDB51A7D450948DFE8D76D7F2D1007671";
+    const SECRET_NONCE: &str = "508B81A611F100A6B2B6B29656590898AF488BCF2E1F55CF22E5CFB84421FE61FA27FD49B1D50085B481285E1CA205D55C82CC1B31FF5CD54A489829355901F703935F972DA013F80AE011890FA89B67A27B7BE6CCB24D3274D18B2D4067F261A9";
+    const PUBLIC_NONCES: [&str; 4] = [
+        "0337C87821AFD50A8644D820A8F3E02E499C931865C2360FB43D0A0D20DAFE07EA0287BF891D2A6DEAEBADC909352AA9405D1428C15F4B75F04DAE642A95C2548480",
+        "0279BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F817980279BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F81798",
+        "032DE2662628C90B03F5E720284EB52FF7D71F4284F627B68A853D78C78E1FFE9303E4C5524E83FFE1493B9077CF1CA6BEB2090C93D930321071AD40B2F44E599046",
+        "0237C87821AFD50A8644D820A8F3E02E499C931865C2360FB43D0A0D20DAFE07EA0387BF891D2A6DEAEBADC909352AA9405D1428C15F4B75F04DAE642A95C2548480",
+    ];
+    const MSG: &str = "F95466D086770E689964664219266FE5ED215C92AE20BAB5C9D79ADDDDF3C0CF";
+
+    fn pubkey(s: &str) -> PublicKey { PublicKey::from_slice(&hex!(s)).unwrap() }
+
+    fn pub_nonces(indices: &[usize]) -> Vec<PublicNonce> {
+        indices.iter().map(|i| PublicNonce::from_slice(&hex!(PUBLIC_NONCES[*i])).unwrap()).collect()
+    }
+
+    fn scalar(s: &str) -> Scalar {
+        Scalar::from_be_bytes(<[u8; 32]>::try_from(&hex!(s)[..]).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn midstates() {
+        fn tag_engine(tag_name: &str) -> sha256::HashEngine {
+            let mut engine = sha256::Hash::engine();
+            let tag_hash = sha256::Hash::hash(tag_name.as_bytes());
+            engine.input(tag_hash.as_ref());
+            engine.input(tag_hash.as_ref());
+            engine
+        }
+
+        assert_eq!(KeyAggListTag::engine().midstate(), tag_engine("KeyAgg list").midstate());
+        assert_eq!(
+            KeyAggCoeffTag::engine().midstate(),
+            tag_engine("KeyAgg coefficient").midstate()
+        );
+        assert_eq!(MusigAuxTag::engine().midstate(), tag_engine("MuSig/aux").midstate());
+        assert_eq!(MusigNonceTag::engine().midstate(), tag_engine("MuSig/nonce").midstate());
+        assert_eq!(
+            MusigNonceCoeffTag::engine().midstate(),
+            tag_engine("MuSig/noncecoef").midstate()
+        );
+        assert_eq!(
+            Bip340ChallengeTag::engine().midstate(),
+            tag_engine("BIP0340/challenge").midstate()
+        );
+    }
+
+    #[test]
+    fn key_agg_vectors() {
+        let secp = Secp256k1::verification_only();
+        let pubkeys = [
+            pubkey("02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9"),
+            pubkey("03DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659"),
+            pubkey("023590A94E768F8E1815C2F24B4D80A8E3149316C3518CE7B7AD338368D038CA66"),
+        ];
+        let vectors: [(&[usize], &str); 4] = [
+            (&[0, 1, 2], "90539EEDE565F5D054F32CC0C220126889ED1E5D193BAF15AEF344FE59D4610C"),
+            (&[2, 1, 0], "6204DE8B083426DC6EAF9502D27024D53FC826BF7D2012148A0575435DF54B2B"),
+            (&[0, 0, 0], "B436E3BAD62B8CD409969A224731C193D051162D8C5AE8B109306127DA3AA935"),
+            (&[0, 0, 1, 1], "69BC22BFA5D106306E48A20679DE1D7389386124D07571D0D872686028C26A3E"),
+        ];
+        for (indices, expected) in vectors.iter() {
+            let keys = indices.iter().map(|i| pubkeys[*i]).collect::<Vec<_>>();
+            let ctx = KeyAggContext::new(&secp, &keys).unwrap();
+            assert_eq!(ctx.x_only_public_key().serialize()[..], hex!(expected)[..]);
+        }
+
+        let mut sorted = pubkeys;
+        sort_public_keys(&mut sorted);
+        assert_eq!(sorted, [pubkeys[2], pubkeys[0], pubkeys[1]]);
+
+        assert_eq!(KeyAggContext::new(&secp, &[]), Err(Error::InfiniteAggregatedKey));
+        // The tweak is the negated discrete logarithm of the aggregated key.
+        let sk = SecretKey::from_slice(&hex!(SECRET_KEY)).unwrap();
+        let pk = sk.public_key(&Secp256k1::signing_only());
+        let ctx = KeyAggContext::new(&secp, &[pk]).unwrap();
+        let tweak = ctx.coefficient(&pk).mul(ModScalar(Some(sk))).negate();
+        let tweak = Scalar::from_be_bytes(tweak.to_be_bytes()).unwrap();
+        assert_eq!(ctx.add_plain_tweak(&secp, &tweak), Err(Error::InfiniteAggregatedKey));
+    }
+
+    #[test]
+    fn nonce_agg_vectors() {
+        let nonces = [
+            "020151C80F435648DF67A22B749CD798CE54E0321D034B92B709B567D60A42E66603BA47FBC1834437B3212E89A84D8425E7BF12E0245D98262268EBDCB385D50641",
+            "03FF406FFD8ADB9CD29877E4985014F66A59F6CD01C0E88CAA8E5F3166B1F676A60248C264CDD57D3C24D79990B0F865674EB62A0F9018277A95011B41BFC193B833",
+        ];
+        let nonces =
+            nonces.iter().map(|s| PublicNonce::from_slice(&hex!(s)).unwrap()).collect::<Vec<_>>();
+        let agg_nonce = AggregatedNonce::new(&nonces);
+        assert_eq!(agg_nonce.serialize()[..], hex!("035FE1873B4F2967F52FEA4A06AD5A8ECCBE9D0FD73068012C894E2E87CCB5804B024725377345BDE0E9C33AF3C43C0A29A9249F2F2956FA8CFEB55C8573D0262DC8")[..]);
+        assert_eq!(AggregatedNonce::from_slice(&agg_nonce.serialize()), Ok(agg_nonce));
+
+        // A nonce and its negation sum to the point at infinity.
+        let agg_nonce = AggregatedNonce::new(&pub_nonces(&[0, 3]));
+        assert_eq!(agg_nonce.serialize(), [0; 66]);
+        assert_eq!(AggregatedNonce::from_slice(&[0; 66]), Ok(agg_nonce));
+
+        assert_eq!(PublicNonce::from_slice(&[0; 66]), Err(Error::InvalidPublicNonce));
+        assert_eq!(AggregatedNonce::from_slice(&[0; 65]), Err(Error::InvalidAggregatedNonce));
+    }
+
+    #[test]
+    fn sign_verify_vectors() {
+        let secp = Secp256k1::new();
+        let sk = SecretKey::from_slice(&hex!(SECRET_KEY)).unwrap();
+        let pubkeys = [
+            pubkey("03935F972DA013F80AE011890FA89B67A27B7BE6CCB24D3274D18B2D4067F261A9"),
+            pubkey("02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9"),
+            pubkey("02DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA661"),
+        ];
+        let long_msg = [0x26; 38];
+        let msgs: [&[u8]; 3] = [&hex!(MSG), &[], &long_msg];
+        let vectors: [(&[usize], &[usize], usize, &str); 6] = [
+            (
+                &[0, 1, 2],
+                &[0, 1, 2],
+                0,
+                "012ABBCB52B3016AC03AD82395A1A415C48B93DEF78718E62A7A90052FE224FB",
+            ),
+            (
+                &[1, 0, 2],
+                &[1, 0, 2],
+                0,
+                "9FF2F7AAA856150CC8819254218D3ADEEB0535269051897724F9DB3789513A52",
+            ),
+            (
+                &[1, 2, 0],
+                &[1, 2, 0],
+                0,
+                "FA23C359F6FAC4E7796BB93BC9F0532A95468C539BA20FF86D7C76ED92227900",
+            ),
+            // The aggregated nonce is the point at infinity.
+            (
+                &[0, 1],
+                &[0, 3],
+                0,
+                "AE386064B26105404798F75DE2EB9AF5EDA5387B064B83D049CB7C5E08879531",
+            ),
+            (
+                &[0, 1, 2],
+                &[0, 1, 2],
+                1,
+                "D7D63FFD644CCDA4E62BC2BC0B1D02DD32A1DC3030E155195810231D1037D82D",
+            ),
+            (
+                &[0, 1, 2],
+                &[0, 1, 2],
+                2,
+                "E184351828DA5094A97C79CABDAAA0BFB87608C32E8829A4DF5340A6F243B78C",
+            ),
+        ];
+        for (key_indices, nonce_indices, msg_index, expected) in vectors.iter() {
+            let keys = key_indices.iter().map(|i| pubkeys[*i]).collect::<Vec<_>>();
+            let ctx = KeyAggContext::new(&secp, &keys).unwrap();
+            let nonces = pub_nonces(nonce_indices);
+            let session =
+                Session::new(&secp, &ctx, &AggregatedNonce::new(&nonces), msgs[*msg_index]);
+
+            let sec_nonce = SecretNonce::from_slice(&hex!(SECRET_NONCE)).unwrap();
+            assert_eq!(
+                sec_nonce.public_nonce(&secp),
+                nonces[key_indices.iter().position(|i| *i == 0).unwrap()]
+            );
+            let psig = session.partial_sign(&secp, sec_nonce, &sk).unwrap();
+            assert_eq!(psig.serialize()[..], hex!(expected)[..]);
+
+            let signer = key_indices.iter().position(|i| *i == 0).unwrap();
+            session.partial_verify(&secp, &psig, &nonces[signer], &pubkeys[0]).unwrap();
+            let other = (signer + 1) % keys.len();
+            assert_eq!(
+                session.partial_verify(&secp, &psig, &nonces[other], &keys[other]),
+                Err(Error::InvalidPartialSignature)
+            );
+        }
+    }
+
+    #[test]
+    fn sign_errors() {
+        let secp = Secp256k1::new();
+        let sk = SecretKey::from_slice(&hex!(SECRET_KEY)).unwrap();
+        let pubkeys = [
+            pubkey("02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9"),
+            pubkey("02DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA661"),
+        ];
+        let ctx = KeyAggContext::new(&secp, &pubkeys).unwrap();
+        let session =
+            Session::new(&secp, &ctx, &AggregatedNonce::new(&pub_nonces(&[0, 1])), &hex!(MSG));
+
+        let sec_nonce = SecretNonce::from_slice(&hex!(SECRET_NONCE)).unwrap();
+        assert_eq!(session.partial_sign(&secp, sec_nonce, &sk), Err(Error::UnknownSigner));
+        let other_sk = SecretKey::from_slice(&[1; 32]).unwrap();
+        let sec_nonce = SecretNonce::from_slice(&hex!(SECRET_NONCE)).unwrap();
+        assert_eq!(
+            session.partial_sign(&secp, sec_nonce, &other_sk),
+            Err(Error::SecretKeyMismatch)
+        );
+
+        // A secret nonce with `k1` zeroed, as done after signing by the reference implementation.
+        let mut zeroed = hex!(SECRET_NONCE);
+        zeroed[..32].copy_from_slice(&[0; 32]);
+        assert_eq!(SecretNonce::from_slice(&zeroed).unwrap_err(), Error::InvalidSecretNonce);
+        // Partial signatures must be smaller than the curve order.
+        assert_eq!(
+            PartialSignature::from_slice(&constants::CURVE_ORDER),
+            Err(Error::InvalidPartialSignature)
+        );
+    }
+
+    #[test]
+    fn tweak_vectors() {
+        let secp = Secp256k1::new();
+        let sk = SecretKey::from_slice(&hex!(SECRET_KEY)).unwrap();
+        let pubkeys = [
+            pubkey("03935F972DA013F80AE011890FA89B67A27B7BE6CCB24D3274D18B2D4067F261A9"),
+            pubkey("02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9"),
+            pubkey("02DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659"),
+        ];
+        let tweaks = [
+            scalar("E8F791FF9225A2AF0102AFFF4A9A723D9612A682A25EBE79802B263CDFCD83BB"),
+            scalar("AE2EA797CC0FE72AC5B97B97F3C6957D7E4199A167A58EB08BCAFFDA70AC0455"),
+            scalar("F52ECBC565B3D8BEA2DFD5B75A4F457E54369809322E4120831626F290FA87E0"),
+            scalar("1969AD73CC177FA0B4FCED6DF1F7BF9907E665FDE9BA196A74FED0A3CF5AEF9D"),
+        ];
+        let vectors: [(&[(usize, bool)], &str); 4] = [
+            (&[(0, true)], "E28A5C66E61E178C2BA19DB77B6CF9F7E2F0F56C17918CD13135E60CC848FE91"),
+            (&[(0, false)], "38B0767798252F21BF5702C48028B095428320F73A4B14DB1E25DE58543D2D2D"),
+            (
+                &[(0, false), (1, true)],
+                "408A0A21C4A0F5DACAF9646AD6EB6FECD7F7A11F03ED1F48DFFF2185BC2C2408",
+            ),
+            (
+                &[(0, false), (1, false), (2, true), (3, true)],
+                "45ABD206E61E3DF2EC9E264A6FEC8292141A633C28586388235541F9ADE75435",
+            ),
+        ];
+        let keys = [pubkeys[1], pubkeys[2], pubkeys[0]];
+        let nonces = pub_nonces(&[1, 2, 0]);
+        for (applied, expected) in vectors.iter() {
+            let mut ctx = KeyAggContext::new(&secp, &keys).unwrap();
+            for (i, is_xonly) in applied.iter() {
+                ctx = if *is_xonly {
+                    ctx.add_xonly_tweak(&secp, &tweaks[*i]).unwrap()
+                } else {
+                    ctx.add_plain_tweak(&secp, &tweaks[*i]).unwrap()
+                };
+            }
+            let session = Session::new(&secp, &ctx, &AggregatedNonce::new(&nonces), &hex!(MSG));
+            let sec_nonce = SecretNonce::from_slice(&hex!(SECRET_NONCE)).unwrap();
+            let psig = session.partial_sign(&secp, sec_nonce, &sk).unwrap();
+            assert_eq!(psig.serialize()[..], hex!(expected)[..]);
+        }
+    }
+
+    #[test]
+    fn taproot_key_spend() {
+        let secp = Secp256k1::new();
+        let sks = (1..=3u8).map(|i| SecretKey::from_slice(&[i; 32]).unwrap()).collect::<Vec<_>>();
+        let mut pubkeys = sks.iter().map(|sk| sk.public_key(&secp)).collect::<Vec<_>>();
+        sort_public_keys(&mut pubkeys);
+
+        let merkle_root = Some(TapNodeHash::from_byte_array([0xcd; 32]));
+        let ctx =
+            KeyAggContext::new(&secp, &pubkeys).unwrap().tap_tweak(&secp, merkle_root).unwrap();
+        let internal_key = KeyAggContext::new(&secp, &pubkeys).unwrap().x_only_public_key();
+        let (output_key, _) = internal_key.tap_tweak(&secp, merkle_root);
+        assert_eq!(ctx.x_only_public_key(), output_key.to_inner());
+
+        let msg = hex!(MSG);
+        let nonces = sks
+            .iter()
+            .enumerate()
+            .map(|(i, sk)| {
+                let pk = sk.public_key(&secp);
+                let agg_pk = ctx.x_only_public_key();
+                SecretNonce::generate(
+                    &secp,
+                    [i as u8; 32],
+                    Some(sk),
+                    &pk,
+                    Some(&agg_pk),
+                    Some(&msg),
+                    None,
+                )
+            })
+            .collect::<Vec<_>>();
+        let pub_nonces = nonces.iter().map(|(_, pub_nonce)| *pub_nonce).collect::<Vec<_>>();
+        let session = Session::new(&secp, &ctx, &AggregatedNonce::new(&pub_nonces), &msg);
+
+        let mut psigs = vec![];
+        for ((sec_nonce, pub_nonce), sk) in nonces.into_iter().zip(&sks) {
+            assert_eq!(sec_nonce.public_nonce(&secp), pub_nonce);
+            assert_eq!(
+                SecretNonce::from_slice(&sec_nonce.serialize()).unwrap().serialize()[..],
+                sec_nonce.serialize()[..]
+            );
+            let psig = session.partial_sign(&secp, sec_nonce, sk).unwrap();
+            session.partial_verify(&secp, &psig, &pub_nonce, &sk.public_key(&secp)).unwrap();
+            psigs.push(psig);
+        }
+
+        let sig = session.aggregate(&psigs, TapSighashType::All);
+        assert_eq!(sig.hash_ty, TapSighashType::All);
+        let msg = Message::from_slice(&msg).unwrap();
+        secp.verify_schnorr(&sig.sig, &msg, &output_key.to_inner()).unwrap();
+
+        let sig = session.aggregate(&psigs[1..], TapSighashType::Default);
+        assert!(secp.verify_schnorr(&sig.sig, &msg, &output_key.to_inner()).is_err());
+    }
+}
@@ -756,15 +756,35 @@ impl<R: Borrow<Transaction>> SighashCache<R> {
     /// [`std::io::Write`] trait.
     pub fn segwit_encode_signing_data_to<Write: io::Write>(
         &mut self,
-        mut writer: Write,
+        writer: Write,
         input_index: usize,
         script_code: &Script,
         value: u64,
         sighash_type: EcdsaSighashType,
+    ) -> Result<(), Error> {
+        self.segwit_encode_signing_data_to_raw(
+            writer,
+            input_index,
+            script_code,
+            value,
+            sighash_type.to_u32(),
+        )
+    }
+
+    /// Encodes the BIP143 signing data committing to an arbitrary `u32` sighash type, as required
+    /// when verifying signatures with non-standard sighash bytes.
+    fn segwit_encode_signing_data_to_raw<Write: io::Write>(
+        &mut self,
+        mut writer: Write,
+        input_index: usize,
+        script_code: &Script,
+        value: u64,
+        sighash_type: u32,
     ) -> Result<(), Error> {
         let zero_hash = sha256d::Hash::all_zeros();
 
-        let (sighash, anyone_can_pay) = sighash_type.split_anyonecanpay_flag();
+        let (sighash, anyone_can_pay) =
+            EcdsaSighashType::from_consensus(sighash_type).split_anyonecanpay_flag();
 
         self.tx.borrow().version.consensus_encode(&mut writer)?;
 
@@ -809,7 +829,7 @@ impl<R: Borrow<Transaction>> SighashCache<R> {
         }
 
         self.tx.borrow().lock_time.consensus_encode(&mut writer)?;
-        sighash_type.to_u32().consensus_encode(&mut writer)?;
+        sighash_type.consensus_encode(&mut writer)?;
         Ok(())
     }
 
@@ -832,6 +852,25 @@ impl<R: Borrow<Transaction>> SighashCache<R> {
         Ok(SegwitV0Sighash::from_engine(enc))
     }
 
+    /// Computes the BIP143 sighash for an arbitrary `u32` sighash type.
+    pub(crate) fn segwit_signature_hash_raw(
+        &mut self,
+        input_index: usize,
+        script_code: &Script,
+        value: u64,
+        sighash_type: u32,
+    ) -> Result<SegwitV0Sighash, Error> {
+        let mut enc = SegwitV0Sighash::engine();
+        self.segwit_encode_signing_data_to_raw(
+            &mut enc,
+            input_index,
+            script_code,
+            value,
+            sighash_type,
+        )?;
+        Ok(SegwitV0Sighash::from_engine(enc))
+    }
+
     /// Encodes the legacy signing data from which a signature hash for a given input index with a
     /// given sighash flag can be computed.
     ///
@@ -0,0 +1,533 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! In-memory chain of block headers.
+//!
+//! [`HeaderChain`] validates block headers against the consensus rules which only depend on other
+//! headers, like the header processing of Bitcoin Core, and tracks the chain with the most work
+//! including reorganizations. This is what an SPV client verifies before trusting merkle proofs.
+//!
+//! # Examples
+//!
+//! ```
+//! use bitcoin::consensus::Params;
+//! use bitcoin::header_chain::HeaderChain;
+//! use bitcoin::Network;
+//!
+//! let chain = HeaderChain::new(Params::new(Network::Bitcoin));
+//! assert_eq!(chain.tip().height, 0);
+//! ```
+//!
+
+use core::fmt;
+
+use crate::blockdata::block::{BlockHash, Header};
+use crate::blockdata::constants::genesis_block;
+use crate::consensus::Params;
+use crate::pow::{CompactTarget, Work};
+use crate::prelude::*;
+
+/// How far the time of a header may be ahead of the current time, in seconds.
+pub const MAX_FUTURE_BLOCK_TIME: u32 = 2 * 60 * 60;
+
+/// The number of previous headers whose median time the time of a header has to exceed.
+pub const MEDIAN_TIME_SPAN: usize = 11;
+
+/// How far the time of the first header of a retarget period may be behind the time of its parent,
+/// in seconds, if [`Params::enforce_bip94`] is set.
+pub const MAX_TIMEWARP: u32 = 600;
+
+/// A header stored in a [`HeaderChain`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainEntry {
+    /// The block header.
+    pub header: Header,
+    /// The height of the block.
+    pub height: u32,
+    /// The total work of the chain up to and including the block.
+    pub chainwork: Work,
+}
+
+/// A tree of valid block headers starting at the genesis block, tracking the chain with the most
+/// work.
+///
+/// Headers are accepted if they connect to a known header, so they have to be added parents first.
+/// The rules are:
+///
+/// - the header bits encode the target required by the retargeting rules of the network, see
+///   [`Self::next_work_required`], and the block hash meets the target,
+/// - the time is greater than the median time of the previous [`MEDIAN_TIME_SPAN`] headers,
+/// - the time of the first header of a retarget period is at most [`MAX_TIMEWARP`] behind the
+///   time of its parent if [`Params::enforce_bip94`] is set,
+/// - the time is at most [`MAX_FUTURE_BLOCK_TIME`] ahead of the current time.
+///
+/// The block version and checkpoints aren't checked.
+#[derive(Debug, Clone)]
+pub struct HeaderChain {
+    params: Params,
+    entries: BTreeMap<BlockHash, ChainEntry>,
+    /// The hashes of the chain with the most work, indexed by height.
+    best_chain: Vec<BlockHash>,
+}
+
+impl HeaderChain {
+    /// Creates a chain containing only the genesis block of the network of `params`.
+    pub fn new(params: Params) -> Self {
+        let header = genesis_block(params.network).header;
+        let hash = header.block_hash();
+        let entry = ChainEntry { header, height: 0, chainwork: header.work() };
+        let mut entries = BTreeMap::new();
+        entries.insert(hash, entry);
+        HeaderChain { params, entries, best_chain: vec![hash] }
+    }
+
+    /// Returns the consensus parameters of the chain.
+    pub fn params(&self) -> &Params { &self.params }
+
+    /// Returns the tip of the chain with the most work.
+    pub fn tip(&self) -> &ChainEntry {
+        let hash = self.best_chain.last().expect("contains the genesis block");
+        &self.entries[hash]
+    }
+
+    /// Returns the header with hash `hash`, including headers not in the chain with the most work.
+    pub fn get(&self, hash: &BlockHash) -> Option<&ChainEntry> { self.entries.get(hash) }
+
+    /// Returns the hash of the header at `height` in the chain with the most work.
+    pub fn best_hash_at(&self, height: u32) -> Option<BlockHash> {
+        self.best_chain.get(height as usize).copied()
+    }
+
+    /// Returns true if the header with hash `hash` is in the chain with the most work.
+    pub fn is_in_best_chain(&self, hash: &BlockHash) -> bool {
+        self.entries.get(hash).map_or(false, |entry| self.best_hash_at(entry.height) == Some(*hash))
+    }
+
+    /// Returns the ancestor at `height` of the header with hash `hash`.
+    pub(crate) fn ancestor(&self, hash: &BlockHash, height: u32) -> Option<&ChainEntry> {
+        let mut entry = self.entries.get(hash)?;
+        if entry.height < height {
+            return None;
+        }
+        while entry.height > height {
+            if self.is_in_best_chain(&entry.header.block_hash()) {
+                return self.entries.get(&self.best_chain[height as usize]);
+            }
+            entry = &self.entries[&entry.header.prev_blockhash];
+        }
+        Some(entry)
+    }
+
+    /// Returns the median time of the header with hash `hash` and its ancestors, up to
+    /// [`MEDIAN_TIME_SPAN`] headers, like `GetMedianTimePast` of Bitcoin Core.
+    pub fn median_time_past(&self, hash: &BlockHash) -> Option<u32> {
+        let mut entry = self.entries.get(hash)?;
+        let mut times = Vec::with_capacity(MEDIAN_TIME_SPAN);
+        loop {
+            times.push(entry.header.time);
+            if times.len() == MEDIAN_TIME_SPAN || entry.height == 0 {
+                break;
+            }
+            entry = &self.entries[&entry.header.prev_blockhash];
+        }
+        times.sort_unstable();
+        Some(times[times.len() / 2])
+    }
+
+    /// Returns the bits required for a header with time `time` following the header with hash
+    /// `prev`, like `GetNextWorkRequired` of Bitcoin Core.
+    ///
+    /// The target is adjusted every [`Params::difficulty_adjustment_interval`] blocks unless
+    /// [`Params::no_pow_retargeting`] is set. If [`Params::allow_min_difficulty_blocks`] is set, a
+    /// header more than twice the target spacing after its parent may use the minimum difficulty,
+    /// and headers after it are required to use the last target not using this rule. If
+    /// [`Params::enforce_bip94`] is set, the target is adjusted from the target of the first header
+    /// of the retarget period, which can't use the minimum difficulty, instead of the last one.
+    ///
+    /// Returns `None` if `prev` is unknown.
+    pub fn next_work_required(&self, prev: &BlockHash, time: u32) -> Option<CompactTarget> {
+        let last = self.entries.get(prev)?;
+        let interval = self.params.difficulty_adjustment_interval() as u32;
+
+        if (last.height + 1) % interval != 0 {
+            if !self.params.allow_min_difficulty_blocks {
+                return Some(last.header.bits);
+            }
+            let min_difficulty =
+                CompactTarget::from_min_difficulty_rule(last.header.time, time, &self.params);
+            if let Some(bits) = min_difficulty {
+                return Some(bits);
+            }
+            let pow_limit = CompactTarget::from_pow_limit(&self.params);
+            let mut entry = last;
+            while entry.height % interval != 0 && entry.header.bits == pow_limit {
+                entry = &self.entries[&entry.header.prev_blockhash];
+            }
+            return Some(entry.header.bits);
+        }
+
+        let first = self.ancestor(prev, last.height + 1 - interval).expect("height is lower");
+        let timespan = last.header.time.saturating_sub(first.header.time);
+        let bits = if self.params.enforce_bip94 { first.header.bits } else { last.header.bits };
+        Some(CompactTarget::from_next_work_required(bits, u64::from(timespan), &self.params))
+    }
+
+    /// Validates `header` and adds it to the chain, `now` being the current time.
+    ///
+    /// # Errors
+    ///
+    /// If the header doesn't connect to a known header or breaks a rule.
+    pub fn accept(&mut self, header: Header, now: u32) -> Result<Accepted, HeaderError> {
+        let hash = header.block_hash();
+        if self.entries.contains_key(&hash) {
+            return Ok(Accepted::Known);
+        }
+        let prev = match self.entries.get(&header.prev_blockhash) {
+            Some(prev) => *prev,
+            None => return Err(HeaderError::UnknownPrevious(header.prev_blockhash)),
+        };
+
+        let required = self
+            .next_work_required(&header.prev_blockhash, header.time)
+            .expect("previous header is known");
+        if header.bits != required {
+            return Err(HeaderError::BadTarget { required, bits: header.bits });
+        }
+        if !header.target().is_met_by(hash) {
+            return Err(HeaderError::BadProofOfWork);
+        }
+        let median_time_past =
+            self.median_time_past(&header.prev_blockhash).expect("previous header is known");
+        if header.time <= median_time_past {
+            return Err(HeaderError::TimeTooOld { median_time_past });
+        }
+        let interval = self.params.difficulty_adjustment_interval() as u32;
+        if self.params.enforce_bip94
+            && (prev.height + 1) % interval == 0
+            && header.time < prev.header.time.saturating_sub(MAX_TIMEWARP)
+        {
+            return Err(HeaderError::TimewarpAttack);
+        }
+        if header.time > now.saturating_add(MAX_FUTURE_BLOCK_TIME) {
+            return Err(HeaderError::TimeTooNew);
+        }
+
+        let entry = ChainEntry {
+            header,
+            height: prev.height + 1,
+            chainwork: prev.chainwork + header.work(),
+        };
+        self.entries.insert(hash, entry);
+        if entry.chainwork <= self.tip().chainwork {
+            return Ok(Accepted::SideChain);
+        }
+        if header.prev_blockhash == *self.best_chain.last().expect("contains the genesis block") {
+            self.best_chain.push(hash);
+            return Ok(Accepted::Extended);
+        }
+
+        // Connect the new chain from the fork point.
+        let mut connected = vec![hash];
+        let mut fork = header.prev_blockhash;
+        while !self.is_in_best_chain(&fork) {
+            connected.push(fork);
+            fork = self.entries[&fork].header.prev_blockhash;
+        }
+        let fork_height = self.entries[&fork].height;
+        let disconnected = self.best_chain.split_off(fork_height as usize + 1);
+        self.best_chain.extend(connected.into_iter().rev());
+        Ok(Accepted::Reorg { fork_height, disconnected })
+    }
+}
+
+/// The result of adding a valid header to a [`HeaderChain`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Accepted {
+    /// The header is known already.
+    Known,
+    /// The header extends the chain with the most work.
+    Extended,
+    /// The header is on a chain with less work than the best chain.
+    SideChain,
+    /// The header made another chain the chain with the most work.
+    Reorg {
+        /// The height of the last header both chains have in common.
+        fork_height: u32,
+        /// The hashes of the headers no longer in the chain with the most work, lowest first.
+        disconnected: Vec<BlockHash>,
+    },
+}
+
+/// An invalid header passed to [`HeaderChain::accept`].
+///
+/// The variants document the reject reason used by Bitcoin Core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HeaderError {
+    /// The previous header is unknown (`prev-blk-not-found`).
+    UnknownPrevious(BlockHash),
+    /// The header bits don't encode the required target (`bad-diffbits`).
+    BadTarget {
+        /// The required bits.
+        required: CompactTarget,
+        /// The bits of the header.
+        bits: CompactTarget,
+    },
+    /// The block hash doesn't meet the target (`high-hash`).
+    BadProofOfWork,
+    /// The time isn't greater than the median time of the previous headers (`time-too-old`).
+    TimeTooOld {
+        /// The median time of the previous headers.
+        median_time_past: u32,
+    },
+    /// The time of the first header of a retarget period is too far behind the time of its parent
+    /// (`time-timewarp-attack`).
+    TimewarpAttack,
+    /// The time is too far in the future (`time-too-new`).
+    TimeTooNew,
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use HeaderError::*;
+
+        match *self {
+            UnknownPrevious(hash) => write!(f, "previous header {} is unknown", hash),
+            BadTarget { required, bits } => write!(
+                f,
+                "header bits {:#010x} don't match the required bits {:#010x}",
+                bits.to_consensus(),
+                required.to_consensus()
+            ),
+            BadProofOfWork => f.write_str("block hash doesn't meet the target"),
+            TimeTooOld { median_time_past } =>
+                write!(f, "header time not after the median time past {}", median_time_past),
+            TimewarpAttack => f.write_str("header time too far behind the previous header time"),
+            TimeTooNew => f.write_str("header time too far in the future"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+impl std::error::Error for HeaderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockdata::block::Version;
+    use crate::hash_types::TxMerkleNode;
+    use crate::hashes::Hash;
+    use crate::Network;
+
+    /// Regtest parameters with a retarget period of ten blocks.
+    fn params(no_pow_retargeting: bool, allow_min_difficulty_blocks: bool) -> Params {
+        let mut params = Params::new(Network::Regtest);
+        params.pow_target_timespan = 10 * params.pow_target_spacing;
+        params.no_pow_retargeting = no_pow_retargeting;
+        params.allow_min_difficulty_blocks = allow_min_difficulty_blocks;
+        params
+    }
+
+    /// Mines a header on top of `prev` at `time`, `tag` distinguishes forks.
+    fn mine_at(chain: &HeaderChain, prev: BlockHash, time: u32, tag: u8) -> Header {
+        let mut header = Header {
+            version: Version::TWO,
+            prev_blockhash: prev,
+            merkle_root: TxMerkleNode::hash(&[tag]),
+            time,
+            bits: chain.next_work_required(&prev, time).unwrap(),
+            nonce: 0,
+        };
+        while !header.target().is_met_by(header.block_hash()) {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    /// Mines a header on top of `prev` with `delay` seconds after it.
+    fn mine(chain: &HeaderChain, prev: BlockHash, delay: u32, tag: u8) -> Header {
+        mine_at(chain, prev, chain.get(&prev).unwrap().header.time + delay, tag)
+    }
+
+    /// Mines `count` headers on top of `prev` and returns the last hash.
+    fn extend(chain: &mut HeaderChain, mut prev: BlockHash, count: usize, tag: u8) -> BlockHash {
+        for _ in 0..count {
+            let header = mine(chain, prev, 600, tag);
+            chain.accept(header, u32::max_value()).unwrap();
+            prev = header.block_hash();
+        }
+        prev
+    }
+
+    #[test]
+    fn genesis() {
+        for network in [
+            Network::Bitcoin,
+            Network::Testnet,
+            Network::Testnet4,
+            Network::Signet,
+            Network::Regtest,
+        ]
+        .iter()
+        {
+            let chain = HeaderChain::new(Params::new(*network));
+            let genesis = genesis_block(*network);
+            assert_eq!(chain.tip().header, genesis.header);
+            assert_eq!(chain.best_hash_at(0), Some(genesis.block_hash()));
+            assert_eq!(chain.median_time_past(&genesis.block_hash()), Some(genesis.header.time));
+        }
+    }
+
+    #[test]
+    fn reorg() {
+        let mut chain = HeaderChain::new(params(true, false));
+        let genesis = chain.tip().header.block_hash();
+        let a2 = extend(&mut chain, genesis, 2, 0);
+        assert_eq!(chain.tip().height, 2);
+        let a1 = chain.best_hash_at(1).unwrap();
+
+        // A side chain with the same work doesn't replace the best chain.
+        let b1 = extend(&mut chain, genesis, 1, 1);
+        let header = mine(&chain, b1, 600, 1);
+        assert_eq!(chain.accept(header, u32::max_value()), Ok(Accepted::SideChain));
+        assert_eq!(chain.accept(header, u32::max_value()), Ok(Accepted::Known));
+        assert_eq!(chain.tip().header.block_hash(), a2);
+        assert!(!chain.is_in_best_chain(&b1));
+
+        let b2 = header.block_hash();
+        let header = mine(&chain, b2, 600, 1);
+        let result = chain.accept(header, u32::max_value());
+        assert_eq!(result, Ok(Accepted::Reorg { fork_height: 0, disconnected: vec![a1, a2] }));
+        assert_eq!(chain.tip().header, header);
+        assert_eq!(chain.tip().height, 3);
+        assert_eq!(chain.best_hash_at(1), Some(b1));
+        assert!(chain.is_in_best_chain(&b2));
+        let work = chain.get(&b1).unwrap().chainwork + header.work() + header.work();
+        assert_eq!(chain.tip().chainwork, work);
+
+        let header = mine(&chain, chain.tip().header.block_hash(), 600, 0);
+        assert_eq!(chain.accept(header, u32::max_value()), Ok(Accepted::Extended));
+    }
+
+    #[test]
+    fn invalid_headers() {
+        let mut chain = HeaderChain::new(params(true, false));
+        let genesis = chain.tip().header.block_hash();
+        let tip = extend(&mut chain, genesis, 11, 0);
+
+        let mut header = mine(&chain, tip, 600, 0);
+        header.prev_blockhash = BlockHash::all_zeros();
+        assert_eq!(
+            chain.accept(header, u32::max_value()),
+            Err(HeaderError::UnknownPrevious(BlockHash::all_zeros()))
+        );
+
+        let mut header = mine(&chain, tip, 600, 0);
+        while header.target().is_met_by(header.block_hash()) {
+            header.nonce += 1;
+        }
+        assert_eq!(chain.accept(header, u32::max_value()), Err(HeaderError::BadProofOfWork));
+
+        let mut header = mine(&chain, tip, 600, 0);
+        let required = header.bits;
+        header.bits = CompactTarget::from_consensus(0x1d00ffff);
+        let err = chain.accept(header, u32::max_value()).unwrap_err();
+        assert_eq!(err, HeaderError::BadTarget { required, bits: header.bits });
+
+        // The median of the last eleven headers is the sixth last.
+        let median_time_past = chain.median_time_past(&tip).unwrap();
+        assert_eq!(median_time_past, chain.get(&tip).unwrap().header.time - 5 * 600);
+        let header = mine_at(&chain, tip, median_time_past, 0);
+        assert_eq!(
+            chain.accept(header, u32::max_value()),
+            Err(HeaderError::TimeTooOld { median_time_past })
+        );
+
+        let header = mine(&chain, tip, 600, 0);
+        let now = header.time - MAX_FUTURE_BLOCK_TIME - 1;
+        assert_eq!(chain.accept(header, now), Err(HeaderError::TimeTooNew));
+        assert_eq!(chain.accept(header, now + 1), Ok(Accepted::Extended));
+    }
+
+    #[test]
+    fn retarget() {
+        let mut chain = HeaderChain::new(params(false, false));
+        let genesis = chain.tip().header;
+        // Blocks twice as fast as the target spacing.
+        let mut prev = genesis.block_hash();
+        for _ in 0..9 {
+            let header = mine(&chain, prev, 300, 0);
+            assert_eq!(header.bits, genesis.bits);
+            chain.accept(header, u32::max_value()).unwrap();
+            prev = header.block_hash();
+        }
+        let header = mine(&chain, prev, 300, 0);
+        let required =
+            CompactTarget::from_next_work_required(genesis.bits, 9 * 300, chain.params());
+        assert_eq!(header.bits, required);
+        assert!(header.target() < genesis.target());
+        assert_eq!(chain.accept(header, u32::max_value()), Ok(Accepted::Extended));
+        assert_eq!(
+            chain.next_work_required(&header.block_hash(), header.time + 600),
+            Some(header.bits)
+        );
+    }
+
+    #[test]
+    fn min_difficulty_blocks() {
+        let mut chain = HeaderChain::new(params(false, true));
+        let genesis = chain.tip().header;
+        let mut prev = genesis.block_hash();
+        for _ in 0..10 {
+            let header = mine(&chain, prev, 300, 0);
+            chain.accept(header, u32::max_value()).unwrap();
+            prev = header.block_hash();
+        }
+        let bits = chain.tip().header.bits;
+        assert_ne!(bits, genesis.bits);
+
+        // A block more than twice the spacing after its parent may use the minimum difficulty.
+        let header = mine(&chain, prev, 1201, 0);
+        assert_eq!(header.bits, genesis.bits);
+        chain.accept(header, u32::max_value()).unwrap();
+        // Then the last regular target is required again.
+        let header = mine(&chain, header.block_hash(), 600, 0);
+        assert_eq!(header.bits, bits);
+        assert_eq!(chain.accept(header, u32::max_value()), Ok(Accepted::Extended));
+    }
+
+    #[test]
+    fn bip94() {
+        let mut params = params(false, true);
+        params.enforce_bip94 = true;
+        let mut chain = HeaderChain::new(params);
+        let genesis = chain.tip().header;
+        let mut prev = extend(&mut chain, genesis.block_hash(), 9, 0);
+        let header = mine(&chain, prev, 300, 0);
+        chain.accept(header, u32::max_value()).unwrap();
+        let bits = header.bits;
+        assert_ne!(bits, genesis.bits);
+        prev = extend(&mut chain, header.block_hash(), 8, 0);
+        let header = mine(&chain, prev, 1201, 0);
+        assert_eq!(header.bits, genesis.bits);
+        chain.accept(header, u32::max_value()).unwrap();
+        prev = header.block_hash();
+
+        // The target is adjusted from the first block of the period, not the minimum difficulty.
+        let required = chain.next_work_required(&prev, header.time + 600).unwrap();
+        assert_eq!(
+            required,
+            CompactTarget::from_next_work_required(bits, 8 * 600 + 1201, chain.params())
+        );
+        assert_ne!(required, genesis.bits);
+
+        // The first block of a period may not be much older than its parent.
+        let header = mine_at(&chain, prev, header.time - MAX_TIMEWARP - 1, 0);
+        let err = chain.accept(header, u32::max_value()).unwrap_err();
+        assert_eq!(err, HeaderError::TimewarpAttack);
+        let header = mine_at(&chain, prev, header.time + 1, 0);
+        assert_eq!(chain.accept(header, u32::max_value()), Ok(Accepted::Extended));
+    }
+}
@@ -87,11 +87,13 @@ pub mod bip152;
 pub mod bip158;
 pub mod bip32;
 pub mod blockdata;
+pub mod coin_selection;
 pub mod consensus;
 // Private until we either make this a crate or flatten it - still to be decided.
 pub(crate) mod crypto;
 pub mod error;
 pub mod hash_types;
+pub mod header_chain;
 pub mod merkle_tree;
 pub mod policy;
 pub mod pow;
@@ -100,6 +102,7 @@ pub mod sign_message;
 pub mod string;
 pub mod taproot;
 pub mod util;
+pub mod versionbits;
 
 // May depend on crate features and we don't want to bother with it
 #[allow(unused)]
@@ -126,7 +129,7 @@ pub use crate::blockdata::witness::{self, Witness};
 pub use crate::blockdata::{constants, opcodes};
 pub use crate::consensus::encode::VarInt;
 pub use crate::crypto::key::{self, PrivateKey, PublicKey};
-pub use crate::crypto::{ecdsa, sighash};
+pub use crate::crypto::{ecdsa, musig2, sighash};
 pub use crate::error::Error;
 pub use crate::hash_types::{
     BlockHash, PubkeyHash, ScriptHash, Txid, WPubkeyHash, WScriptHash, Wtxid,
@@ -75,6 +75,8 @@ pub enum Network {
     Signet,
     /// Bitcoin's regtest network.
     Regtest,
+    /// Bitcoin's testnet4 network, see BIP 94.
+    Testnet4,
 }
 
 impl Network {
@@ -107,11 +109,11 @@ impl Network {
     /// Converts a `Network` to its equivalent `bitcoind -chain` argument name.
     ///
     /// ```bash
-    /// $ bitcoin-23.0/bin/bitcoind --help | grep -C 3 '\-chain=<chain>'
+    /// $ bitcoin-28.0/bin/bitcoind --help | grep -C 3 '\-chain=<chain>'
     /// Chain selection options:
     ///
     /// -chain=<chain>
-    /// Use the chain <chain> (default: main). Allowed values: main, test, signet, regtest
+    /// Use the chain <chain> (default: main). Allowed values: main, test, testnet4, signet, regtest
     /// ```
     pub fn to_core_arg(self) -> &'static str {
         match self {
@@ -119,17 +121,18 @@ impl Network {
             Network::Testnet => "test",
             Network::Signet => "signet",
             Network::Regtest => "regtest",
+            Network::Testnet4 => "testnet4",
         }
     }
 
     /// Converts a `bitcoind -chain` argument name to its equivalent `Network`.
     ///
     /// ```bash
-    /// $ bitcoin-23.0/bin/bitcoind --help | grep -C 3 '\-chain=<chain>'
+    /// $ bitcoin-28.0/bin/bitcoind --help | grep -C 3 '\-chain=<chain>'
     /// Chain selection options:
     ///
     /// -chain=<chain>
-    /// Use the chain <chain> (default: main). Allowed values: main, test, signet, regtest
+    /// Use the chain <chain> (default: main). Allowed values: main, test, testnet4, signet, regtest
     /// ```
     pub fn from_core_arg(core_arg: &str) -> Result<Self, ParseNetworkError> {
         use Network::*;
@@ -139,6 +142,7 @@ impl Network {
             "test" => Testnet,
             "signet" => Signet,
             "regtest" => Regtest,
+            "testnet4" => Testnet4,
             _ => return Err(ParseNetworkError(core_arg.to_owned())),
         };
         Ok(network)
@@ -196,6 +200,7 @@ impl FromStr for Network {
             "testnet" => Testnet,
             "signet" => Signet,
             "regtest" => Regtest,
+            "testnet4" => Testnet4,
             _ => return Err(ParseNetworkError(s.to_owned())),
         };
         Ok(network)
@@ -211,6 +216,7 @@ impl fmt::Display for Network {
             Testnet => "testnet",
             Signet => "signet",
             Regtest => "regtest",
+            Testnet4 => "testnet4",
         };
         write!(f, "{}", s)
     }
@@ -238,6 +244,7 @@ impl TryFrom<ChainHash> for Network {
             ChainHash::TESTNET => Ok(Network::Testnet),
             ChainHash::SIGNET => Ok(Network::Signet),
             ChainHash::REGTEST => Ok(Network::Regtest),
+            ChainHash::TESTNET4 => Ok(Network::Testnet4),
             _ => Err(UnknownChainHash(chain_hash)),
         }
     }
@@ -256,6 +263,8 @@ impl Magic {
     pub const SIGNET: Self = Self([0x0A, 0x03, 0xCF, 0x40]);
     /// Bitcoin regtest network magic bytes.
     pub const REGTEST: Self = Self([0xFA, 0xBF, 0xB5, 0xDA]);
+    /// Bitcoin testnet4 network magic bytes.
+    pub const TESTNET4: Self = Self([0x1C, 0x16, 0x3F, 0x28]);
 
     /// Create network magic from bytes.
     pub fn from_bytes(bytes: [u8; 4]) -> Magic { Magic(bytes) }
@@ -292,6 +301,7 @@ impl From<Network> for Magic {
             Network::Testnet => Magic::TESTNET,
             Network::Signet => Magic::SIGNET,
             Network::Regtest => Magic::REGTEST,
+            Network::Testnet4 => Magic::TESTNET4,
         }
     }
 }
@@ -310,6 +320,7 @@ impl TryFrom<Magic> for Network {
             Magic::TESTNET => Ok(Network::Testnet),
             Magic::SIGNET => Ok(Network::Signet),
             Magic::REGTEST => Ok(Network::Regtest),
+            Magic::TESTNET4 => Ok(Network::Testnet4),
             _ => Err(UnknownMagic(magic)),
         }
     }
@@ -557,11 +568,13 @@ mod tests {
         assert_eq!(serialize(&Network::Testnet.magic()), &[0x0b, 0x11, 0x09, 0x07]);
         assert_eq!(serialize(&Network::Signet.magic()), &[0x0a, 0x03, 0xcf, 0x40]);
         assert_eq!(serialize(&Network::Regtest.magic()), &[0xfa, 0xbf, 0xb5, 0xda]);
+        assert_eq!(serialize(&Network::Testnet4.magic()), &[0x1c, 0x16, 0x3f, 0x28]);
 
         assert_eq!(deserialize(&[0xf9, 0xbe, 0xb4, 0xd9]).ok(), Some(Network::Bitcoin.magic()));
         assert_eq!(deserialize(&[0x0b, 0x11, 0x09, 0x07]).ok(), Some(Network::Testnet.magic()));
         assert_eq!(deserialize(&[0x0a, 0x03, 0xcf, 0x40]).ok(), Some(Network::Signet.magic()));
         assert_eq!(deserialize(&[0xfa, 0xbf, 0xb5, 0xda]).ok(), Some(Network::Regtest.magic()));
+        assert_eq!(deserialize(&[0x1c, 0x16, 0x3f, 0x28]).ok(), Some(Network::Testnet4.magic()));
     }
 
     #[test]
@@ -570,11 +583,13 @@ mod tests {
         assert_eq!(Network::Testnet.to_string(), "testnet");
         assert_eq!(Network::Regtest.to_string(), "regtest");
         assert_eq!(Network::Signet.to_string(), "signet");
+        assert_eq!(Network::Testnet4.to_string(), "testnet4");
 
         assert_eq!("bitcoin".parse::<Network>().unwrap(), Network::Bitcoin);
         assert_eq!("testnet".parse::<Network>().unwrap(), Network::Testnet);
         assert_eq!("regtest".parse::<Network>().unwrap(), Network::Regtest);
         assert_eq!("signet".parse::<Network>().unwrap(), Network::Signet);
+        assert_eq!("testnet4".parse::<Network>().unwrap(), Network::Testnet4);
         assert!("fakenet".parse::<Network>().is_err());
     }
 
@@ -627,6 +642,7 @@ mod tests {
             (Testnet, "testnet"),
             (Signet, "signet"),
             (Regtest, "regtest"),
+            (Testnet4, "testnet4"),
         ];
 
         for tc in tests {
@@ -648,6 +664,7 @@ mod tests {
             ("0b110907", Network::Testnet),
             ("fabfb5da", Network::Regtest),
             ("0a03cf40", Network::Signet),
+            ("1c163f28", Network::Testnet4),
         ];
 
         for (magic_str, network) in &known_network_magic_strs {
@@ -664,6 +681,7 @@ mod tests {
             (Network::Testnet, "test"),
             (Network::Regtest, "regtest"),
             (Network::Signet, "signet"),
+            (Network::Testnet4, "testnet4"),
         ];
 
         for (net, core_arg) in &expected_pairs {
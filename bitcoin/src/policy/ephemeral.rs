@@ -0,0 +1,153 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Ephemeral dust.
+//!
+//! Bitcoin Core relays a transaction with a single output below the dust limit, typically a zero
+//! value P2A anchor, if the dust is spent right away: the transaction has to pay no fee, so a
+//! miner has no reason to mine it without a child, and it has to be relayed in a package with a
+//! child spending the dust, see [`check_ephemeral_tx`] and [`check_ephemeral_spends`].
+//!
+
+use core::fmt;
+
+use super::is_dust;
+use crate::blockdata::transaction::{OutPoint, Transaction};
+use crate::policy::package::TxGraph;
+use crate::prelude::*;
+use crate::Amount;
+
+/// Checks that a transaction with a dust output doesn't pay a fee, like `PreCheckEphemeralTx` of
+/// Bitcoin Core.
+///
+/// The number of dust outputs is limited by
+/// [`check_standard_tx`](crate::policy::check_standard_tx).
+pub fn check_ephemeral_tx(tx: &Transaction, fee: Amount) -> Result<(), EphemeralError> {
+    if fee != Amount::ZERO && tx.output.iter().any(is_dust) {
+        return Err(EphemeralError::DustWithFee);
+    }
+    Ok(())
+}
+
+/// Checks that every transaction in `graph` spends all dust outputs of its unconfirmed parents,
+/// like `CheckEphemeralSpends` of Bitcoin Core.
+///
+/// `graph` contains the package together with the unconfirmed parents of its transactions.
+///
+/// # Errors
+///
+/// With the first dust output a child leaves unspent.
+pub fn check_ephemeral_spends(graph: &TxGraph) -> Result<(), EphemeralError> {
+    let entries = graph.entries();
+    for (index, entry) in entries.iter().enumerate() {
+        let spent = entry.tx.input.iter().map(|txin| txin.previous_output).collect::<BTreeSet<_>>();
+        for &parent in graph.parents(index) {
+            let parent = entries[parent].tx;
+            let txid = parent.txid();
+            for (vout, output) in parent.output.iter().enumerate() {
+                let outpoint = OutPoint::new(txid, vout as u32);
+                if is_dust(output) && !spent.contains(&outpoint) {
+                    return Err(EphemeralError::MissingSpend { index, outpoint });
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The reason a transaction breaks the ephemeral dust rules.
+///
+/// Formatting with `Display` gives the reject reason used by Bitcoin Core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EphemeralError {
+    /// A transaction with a dust output pays a fee (`dust`).
+    DustWithFee,
+    /// A child doesn't spend a dust output of its parent (`missing-ephemeral-spends`).
+    MissingSpend {
+        /// The index of the offending child.
+        index: usize,
+        /// The dust output it doesn't spend.
+        outpoint: OutPoint,
+    },
+}
+
+impl fmt::Display for EphemeralError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use EphemeralError::*;
+
+        let reason = match *self {
+            DustWithFee => "dust",
+            MissingSpend { .. } => "missing-ephemeral-spends",
+        };
+        f.write_str(reason)
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+impl std::error::Error for EphemeralError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockdata::locktime::absolute;
+    use crate::blockdata::script::ScriptBuf;
+    use crate::blockdata::transaction::{Sequence, TxIn, TxOut};
+    use crate::blockdata::witness::Witness;
+    use crate::hash_types::{Txid, WPubkeyHash};
+    use crate::hashes::Hash;
+    use crate::policy::package::Entry;
+
+    fn tx(inputs: &[OutPoint], output: Vec<TxOut>) -> Transaction {
+        Transaction {
+            version: 3,
+            lock_time: absolute::LockTime::ZERO,
+            input: inputs
+                .iter()
+                .map(|outpoint| TxIn {
+                    previous_output: *outpoint,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output,
+        }
+    }
+
+    fn payment() -> TxOut {
+        TxOut { value: 10_000, script_pubkey: ScriptBuf::new_v0_p2wpkh(&WPubkeyHash::all_zeros()) }
+    }
+
+    fn anchor() -> TxOut { TxOut { value: 0, script_pubkey: ScriptBuf::new_p2a() } }
+
+    #[test]
+    fn ephemeral_dust() {
+        let parent = tx(&[OutPoint::new(Txid::all_zeros(), 0)], vec![payment(), anchor()]);
+        assert_eq!(check_ephemeral_tx(&parent, Amount::ZERO), Ok(()));
+        let err = check_ephemeral_tx(&parent, Amount::from_sat(1)).unwrap_err();
+        assert_eq!(err, EphemeralError::DustWithFee);
+        assert_eq!(err.to_string(), "dust");
+        let other = tx(&[OutPoint::new(Txid::all_zeros(), 1)], vec![payment()]);
+        assert_eq!(check_ephemeral_tx(&other, Amount::from_sat(1)), Ok(()));
+
+        let anchor = OutPoint::new(parent.txid(), 1);
+        let child = tx(&[anchor], vec![payment()]);
+        let entries = vec![
+            Entry { tx: &parent, fee: Amount::ZERO },
+            Entry { tx: &child, fee: Amount::from_sat(1_000) },
+        ];
+        assert_eq!(check_ephemeral_spends(&TxGraph::new(entries).unwrap()), Ok(()));
+
+        let child = tx(&[OutPoint::new(parent.txid(), 0)], vec![payment()]);
+        let entries = vec![
+            Entry { tx: &parent, fee: Amount::ZERO },
+            Entry { tx: &child, fee: Amount::from_sat(1_000) },
+        ];
+        let err = check_ephemeral_spends(&TxGraph::new(entries).unwrap()).unwrap_err();
+        assert_eq!(err, EphemeralError::MissingSpend { index: 1, outpoint: anchor });
+        assert_eq!(err.to_string(), "missing-ephemeral-spends");
+    }
+}
@@ -0,0 +1,725 @@
+// Written in 2014 by Andrew Poelstra <apoelstra@wpsoftware.net>
+// SPDX-License-Identifier: CC0-1.0
+
+//! Bitcoin policy.
+//!
+//! This module exposes some constants and functions used in the reference
+//! implementation and which, as a consequence, define some network rules.
+//!
+//! The standardness checks ([`check_standard_tx`], [`check_standard_inputs`] and
+//! [`check_standard_witness`]) mirror `IsStandardTx`, `AreInputsStandard` and
+//! `IsWitnessStandard` of Bitcoin Core with its default settings, transactions failing them are
+//! valid but won't be relayed by most nodes. The rules for replacing mempool transactions are in
+//! [`rbf`], the ones for packages of unconfirmed transactions in [`package`], the topology
+//! restrictions of version 3 transactions in [`truc`] and the rules for dust outputs in
+//! [`ephemeral`].
+//!
+//! # *Warning*
+//! While the constants present in this module are very unlikely to change, they do not define
+//! Bitcoin. As such they must not be relied upon as if they were consensus rules.
+//!
+//! These values follow the defaults of Bitcoin Core v28.0.
+//!
+
+use core::{cmp, fmt};
+
+use super::blockdata::constants::{MAX_BLOCK_SIGOPS_COST, WITNESS_SCALE_FACTOR};
+use crate::address::WitnessVersion;
+use crate::blockdata::opcodes::all::*;
+use crate::blockdata::script::interpreter::{eval_script, is_push_only, VerifyFlags};
+use crate::blockdata::script::{Instruction, Script};
+use crate::blockdata::transaction::{OutPoint, Transaction, TxOut};
+use crate::prelude::*;
+use crate::taproot::{TAPROOT_ANNEX_PREFIX, TAPROOT_LEAF_MASK, TAPROOT_LEAF_TAPSCRIPT};
+
+pub mod ephemeral;
+pub mod package;
+pub mod rbf;
+pub mod truc;
+
+/// Maximum weight of a transaction for it to be relayed by most nodes on the network
+pub const MAX_STANDARD_TX_WEIGHT: u32 = 400_000;
+
+/// Minimum non-witness size for a standard transaction, one byte larger than an inner node of the
+/// merkle tree to rule out transactions of 64 bytes (CVE-2017-12842).
+pub const MIN_STANDARD_TX_NONWITNESS_SIZE: u32 = 65;
+
+/// Maximum transaction version considered standard.
+pub const TX_MAX_STANDARD_VERSION: i32 = 3;
+
+/// Maximum size of a standard scriptSig, large enough for a 15-of-15 P2SH multisig spend.
+pub const MAX_STANDARD_SCRIPTSIG_SIZE: u32 = 1650;
+
+/// Maximum size of a standard `OP_RETURN` output script, including the `OP_RETURN` and push
+/// opcodes.
+pub const MAX_OP_RETURN_RELAY: u32 = 83;
+
+/// Maximum number of sigops in a standard P2SH redeem script.
+pub const MAX_P2SH_SIGOPS: u32 = 15;
+
+/// Maximum size of a standard P2WSH witness script.
+pub const MAX_STANDARD_P2WSH_SCRIPT_SIZE: u32 = 3600;
+
+/// Maximum number of outputs below the dust limit in a standard transaction, see [`ephemeral`].
+pub const MAX_DUST_OUTPUTS_PER_TX: usize = 1;
+
+/// Maximum number of witness stack items, excluding the witness script, of a standard P2WSH spend.
+pub const MAX_STANDARD_P2WSH_STACK_ITEMS: u32 = 100;
+
+/// Maximum size of the witness stack items, excluding the witness script, of a standard P2WSH
+/// spend.
+pub const MAX_STANDARD_P2WSH_STACK_ITEM_SIZE: u32 = 80;
+
+/// Maximum size of the witness stack items, excluding the script and control block, of a
+/// standard tapscript spend.
+pub const MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE: u32 = 80;
+
+/// Maximum number of sigops in a standard tx.
+pub const MAX_STANDARD_TX_SIGOPS_COST: u32 = MAX_BLOCK_SIGOPS_COST as u32 / 5;
+
+/// The minimum incremental *feerate* (despite the name), in sats per virtual kilobyte for RBF.
+pub const DEFAULT_INCREMENTAL_RELAY_FEE: u32 = 1_000;
+
+/// The number of bytes equivalent per signature operation. Affects transaction relay through the
+/// virtual size computation.
+pub const DEFAULT_BYTES_PER_SIGOP: u32 = 20;
+
+/// The minimum feerate, in sats per kilo-virtualbyte, for defining dust. An output is considered
+/// dust if spending it under this feerate would cost more in fee.
+pub const DUST_RELAY_TX_FEE: u32 = 3_000;
+
+/// Minimum feerate, in sats per virtual kilobyte, for a transaction to be relayed by most nodes on
+/// the network.
+pub const DEFAULT_MIN_RELAY_TX_FEE: u32 = 1_000;
+
+/// Default number of hours for an unconfirmed transaction to expire in most of the network nodes'
+/// mempools.
+pub const DEFAULT_MEMPOOL_EXPIRY: u32 = 336;
+
+/// The virtual transaction size, as computed by default by bitcoind node.
+///
+/// `n_sigops` is the sigop cost of the transaction, see
+/// [`Transaction::sigop_cost`](crate::Transaction::sigop_cost).
+pub fn get_virtual_tx_size(weight: i64, n_sigops: i64) -> i64 {
+    (cmp::max(weight, n_sigops * DEFAULT_BYTES_PER_SIGOP as i64) + WITNESS_SCALE_FACTOR as i64 - 1)
+        / WITNESS_SCALE_FACTOR as i64
+}
+
+/// The reason a transaction is not standard.
+///
+/// Formatting with `Display` gives the reject reason used by Bitcoin Core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RejectReason {
+    /// The transaction version is not between 1 and [`TX_MAX_STANDARD_VERSION`] (`version`).
+    Version,
+    /// The transaction weight exceeds [`MAX_STANDARD_TX_WEIGHT`] (`tx-size`).
+    TxSize,
+    /// The size of the transaction without witness data is below
+    /// [`MIN_STANDARD_TX_NONWITNESS_SIZE`] (`tx-size-small`).
+    TxSizeSmall,
+    /// A scriptSig exceeds [`MAX_STANDARD_SCRIPTSIG_SIZE`] (`scriptsig-size`).
+    ScriptSigSize {
+        /// The index of the offending input.
+        input_index: usize,
+    },
+    /// A scriptSig contains non-push operations (`scriptsig-not-pushonly`).
+    ScriptSigNotPushOnly {
+        /// The index of the offending input.
+        input_index: usize,
+    },
+    /// An output script is not one of the standard templates (`scriptpubkey`).
+    ScriptPubKey {
+        /// The index of the offending output.
+        output_index: usize,
+    },
+    /// An output is a bare multisig, only returned if bare multisig is not permitted
+    /// (`bare-multisig`).
+    BareMultisig {
+        /// The index of the offending output.
+        output_index: usize,
+    },
+    /// The values of more than [`MAX_DUST_OUTPUTS_PER_TX`] outputs are below
+    /// [`Script::dust_value`] (`dust`).
+    Dust {
+        /// The index of the offending output.
+        output_index: usize,
+    },
+    /// The transaction has more than one `OP_RETURN` output (`multi-op-return`).
+    MultiOpReturn,
+    /// An input spends a non-standard output or has a non-standard P2SH redeem script
+    /// (`bad-txns-nonstandard-inputs`).
+    NonStandardInputs {
+        /// The index of the offending input.
+        input_index: usize,
+    },
+    /// The witness of an input exceeds the standard limits (`bad-witness-nonstandard`).
+    NonStandardWitness {
+        /// The index of the offending input.
+        input_index: usize,
+    },
+    /// The output spent by an input is unknown (`bad-txns-inputs-missingorspent`).
+    MissingInputs(OutPoint),
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use RejectReason::*;
+
+        let reason = match *self {
+            Version => "version",
+            TxSize => "tx-size",
+            TxSizeSmall => "tx-size-small",
+            ScriptSigSize { .. } => "scriptsig-size",
+            ScriptSigNotPushOnly { .. } => "scriptsig-not-pushonly",
+            ScriptPubKey { .. } => "scriptpubkey",
+            BareMultisig { .. } => "bare-multisig",
+            Dust { .. } => "dust",
+            MultiOpReturn => "multi-op-return",
+            NonStandardInputs { .. } => "bad-txns-nonstandard-inputs",
+            NonStandardWitness { .. } => "bad-witness-nonstandard",
+            MissingInputs(_) => "bad-txns-inputs-missingorspent",
+        };
+        f.write_str(reason)
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+impl std::error::Error for RejectReason {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
+}
+
+/// Runs all standardness checks in the order Bitcoin Core's mempool does.
+///
+/// The `spent` closure is called once for every input and should return the output spent by it.
+pub fn check_standard<S>(tx: &Transaction, spent: S) -> Result<(), RejectReason>
+where
+    S: FnMut(&OutPoint) -> Option<TxOut>,
+{
+    check_standard_tx(tx)?;
+    let prevouts = collect_prevouts(tx, spent)?;
+    are_inputs_standard(tx, &prevouts)?;
+    is_witness_standard(tx, &prevouts)
+}
+
+/// Checks the transaction on its own, without looking at the outputs it spends.
+///
+/// This is `IsStandardTx` of Bitcoin Core, followed by the minimum size check done before it is
+/// accepted to the mempool. Bare multisig outputs are permitted and `OP_RETURN` outputs can be
+/// up to [`MAX_OP_RETURN_RELAY`] bytes, which are Bitcoin Core's defaults. A single dust output is
+/// permitted, the transaction has to follow the rules in [`ephemeral`] in that case.
+pub fn check_standard_tx(tx: &Transaction) -> Result<(), RejectReason> {
+    if tx.version > TX_MAX_STANDARD_VERSION || tx.version < 1 {
+        return Err(RejectReason::Version);
+    }
+    if tx.weight().to_wu() > u64::from(MAX_STANDARD_TX_WEIGHT) {
+        return Err(RejectReason::TxSize);
+    }
+    for (input_index, input) in tx.input.iter().enumerate() {
+        // Enough for a 15-of-15 CHECKMULTISIG P2SH spend with compressed keys.
+        if input.script_sig.len() > MAX_STANDARD_SCRIPTSIG_SIZE as usize {
+            return Err(RejectReason::ScriptSigSize { input_index });
+        }
+        if !is_push_only(&input.script_sig) {
+            return Err(RejectReason::ScriptSigNotPushOnly { input_index });
+        }
+    }
+
+    let mut data_outputs = 0;
+    for (output_index, output) in tx.output.iter().enumerate() {
+        match solve(&output.script_pubkey) {
+            OutputType::NonStandard => return Err(RejectReason::ScriptPubKey { output_index }),
+            OutputType::Multisig { required, keys } if keys > 3 || required > keys =>
+                return Err(RejectReason::ScriptPubKey { output_index }),
+            OutputType::NullData =>
+                if output.script_pubkey.len() > MAX_OP_RETURN_RELAY as usize {
+                    return Err(RejectReason::ScriptPubKey { output_index });
+                } else {
+                    data_outputs += 1;
+                },
+            _ => {}
+        }
+    }
+    // Data outputs are never dust, their scripts are unspendable.
+    let mut dust = tx.output.iter().enumerate().filter(|(_, output)| is_dust(output));
+    if let Some((output_index, _)) = dust.nth(MAX_DUST_OUTPUTS_PER_TX) {
+        return Err(RejectReason::Dust { output_index });
+    }
+    // Only one OP_RETURN output is permitted.
+    if data_outputs > 1 {
+        return Err(RejectReason::MultiOpReturn);
+    }
+
+    if tx.strippedsize() < MIN_STANDARD_TX_NONWITNESS_SIZE as usize {
+        return Err(RejectReason::TxSizeSmall);
+    }
+    Ok(())
+}
+
+/// Checks that the outputs spent by the transaction are standard.
+///
+/// This is `AreInputsStandard` of Bitcoin Core, spending non-standard outputs and future witness
+/// versions is not standard and the P2SH redeem scripts must not have more than
+/// [`MAX_P2SH_SIGOPS`] sigops. The `spent` closure is called once for every input.
+pub fn check_standard_inputs<S>(tx: &Transaction, spent: S) -> Result<(), RejectReason>
+where
+    S: FnMut(&OutPoint) -> Option<TxOut>,
+{
+    are_inputs_standard(tx, &collect_prevouts(tx, spent)?)
+}
+
+/// Checks the witnesses of the transaction against the standard limits.
+///
+/// This is `IsWitnessStandard` of Bitcoin Core, it limits the sizes of P2WSH witnesses and
+/// tapscript stack items and rejects annexes. The `spent` closure is called once for every input.
+pub fn check_standard_witness<S>(tx: &Transaction, spent: S) -> Result<(), RejectReason>
+where
+    S: FnMut(&OutPoint) -> Option<TxOut>,
+{
+    is_witness_standard(tx, &collect_prevouts(tx, spent)?)
+}
+
+fn collect_prevouts<S>(tx: &Transaction, mut spent: S) -> Result<Vec<TxOut>, RejectReason>
+where
+    S: FnMut(&OutPoint) -> Option<TxOut>,
+{
+    if tx.is_coin_base() {
+        return Ok(Vec::new());
+    }
+    tx.input
+        .iter()
+        .map(|input| {
+            spent(&input.previous_output).ok_or(RejectReason::MissingInputs(input.previous_output))
+        })
+        .collect()
+}
+
+fn are_inputs_standard(tx: &Transaction, prevouts: &[TxOut]) -> Result<(), RejectReason> {
+    if tx.is_coin_base() {
+        return Ok(());
+    }
+    for (input_index, (input, prevout)) in tx.input.iter().zip(prevouts).enumerate() {
+        let err = RejectReason::NonStandardInputs { input_index };
+        match solve(&prevout.script_pubkey) {
+            // Unknown witness versions would also be caught by the interpreter with the
+            // `DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM` flag, catch them early.
+            OutputType::NonStandard | OutputType::WitnessUnknown => return Err(err),
+            OutputType::ScriptHash => {
+                let stack = eval_script(&input.script_sig, VerifyFlags::NONE).map_err(|_| err)?;
+                let redeem_script = stack.last().ok_or(err)?;
+                if Script::from_bytes(redeem_script).count_sigops() > MAX_P2SH_SIGOPS as usize {
+                    return Err(err);
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn is_witness_standard(tx: &Transaction, prevouts: &[TxOut]) -> Result<(), RejectReason> {
+    if tx.is_coin_base() {
+        return Ok(());
+    }
+    for (input_index, (input, prevout)) in tx.input.iter().zip(prevouts).enumerate() {
+        if input.witness.is_empty() {
+            continue;
+        }
+        let err = RejectReason::NonStandardWitness { input_index };
+
+        // Anchors are spent with an empty witness.
+        if prevout.script_pubkey.is_p2a() {
+            return Err(err);
+        }
+
+        let mut script_pubkey = Cow::Borrowed(&*prevout.script_pubkey);
+        let mut p2sh = false;
+        if script_pubkey.is_p2sh() {
+            let mut stack = eval_script(&input.script_sig, VerifyFlags::NONE).map_err(|_| err)?;
+            let redeem_script = stack.pop().ok_or(err)?;
+            script_pubkey = Cow::Owned(redeem_script.into());
+            p2sh = true;
+        }
+        // A non-witness program must not be associated with any witness.
+        if !script_pubkey.is_witness_program() {
+            return Err(err);
+        }
+
+        let mut stack = input.witness.iter().collect::<Vec<_>>();
+        if script_pubkey.is_v0_p2wsh() {
+            let witness_script = stack.pop().expect("witness is not empty");
+            if witness_script.len() > MAX_STANDARD_P2WSH_SCRIPT_SIZE as usize
+                || stack.len() > MAX_STANDARD_P2WSH_STACK_ITEMS as usize
+                || stack.iter().any(|item| item.len() > MAX_STANDARD_P2WSH_STACK_ITEM_SIZE as usize)
+            {
+                return Err(err);
+            }
+        }
+        if script_pubkey.is_v1_p2tr() && !p2sh {
+            if stack.len() >= 2
+                && stack.last().and_then(|annex| annex.first()) == Some(&TAPROOT_ANNEX_PREFIX)
+            {
+                return Err(err);
+            }
+            if stack.len() >= 2 {
+                // Script path spend.
+                let control_block = stack.pop().expect("at least two items");
+                stack.pop(); // The script.
+                let leaf_version = control_block.first().ok_or(err)? & TAPROOT_LEAF_MASK;
+                if leaf_version == TAPROOT_LEAF_TAPSCRIPT
+                    && stack
+                        .iter()
+                        .any(|item| item.len() > MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE as usize)
+                {
+                    return Err(err);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The output script templates, this is `TxoutType` of Bitcoin Core.
+enum OutputType {
+    NonStandard,
+    PubKey,
+    PubKeyHash,
+    ScriptHash,
+    Multisig { required: u8, keys: u8 },
+    NullData,
+    WitnessV0KeyHash,
+    WitnessV0ScriptHash,
+    WitnessV1Taproot,
+    Anchor,
+    WitnessUnknown,
+}
+
+/// Classifies `script` by its template, this is `Solver` of Bitcoin Core.
+fn solve(script: &Script) -> OutputType {
+    if script.is_p2sh() {
+        return OutputType::ScriptHash;
+    }
+    if script.is_witness_program() {
+        return match script.witness_version() {
+            Some(WitnessVersion::V0) if script.is_v0_p2wpkh() => OutputType::WitnessV0KeyHash,
+            Some(WitnessVersion::V0) if script.is_v0_p2wsh() => OutputType::WitnessV0ScriptHash,
+            Some(WitnessVersion::V0) => OutputType::NonStandard,
+            _ if script.is_v1_p2tr() => OutputType::WitnessV1Taproot,
+            _ if script.is_p2a() => OutputType::Anchor,
+            _ => OutputType::WitnessUnknown,
+        };
+    }
+    if script.is_op_return() && is_push_only(Script::from_bytes(&script.as_bytes()[1..])) {
+        return OutputType::NullData;
+    }
+    if script.p2pk_pubkey_bytes().map(is_valid_pubkey_size).unwrap_or(false) {
+        return OutputType::PubKey;
+    }
+    if script.is_p2pkh() {
+        return OutputType::PubKeyHash;
+    }
+    match match_multisig(script) {
+        Some((required, keys)) => OutputType::Multisig { required, keys },
+        None => OutputType::NonStandard,
+    }
+}
+
+/// Checks whether the value of `output` is below the dust limit of its script, this is `IsDust` of
+/// Bitcoin Core.
+fn is_dust(output: &TxOut) -> bool { output.value < output.script_pubkey.dust_value().to_sat() }
+
+/// Returns the number of required signatures and keys if `script` is a bare multisig.
+fn match_multisig(script: &Script) -> Option<(u8, u8)> {
+    let mut instructions = script.instructions();
+    let required = small_int(instructions.next()?.ok()?)?;
+    let mut count = 0;
+    let keys = loop {
+        match instructions.next()?.ok()? {
+            Instruction::PushBytes(key) if is_valid_pubkey_size(key.as_bytes()) => count += 1,
+            instruction => break small_int(instruction)?,
+        }
+    };
+    if keys < required || usize::from(keys) != count {
+        return None;
+    }
+    match (instructions.next(), instructions.next()) {
+        (Some(Ok(Instruction::Op(OP_CHECKMULTISIG))), None) => Some((required, keys)),
+        _ => None,
+    }
+}
+
+/// Returns the value of `OP_PUSHNUM_1` to `OP_PUSHNUM_16`.
+fn small_int(instruction: Instruction) -> Option<u8> {
+    match instruction {
+        Instruction::Op(op)
+            if op.to_u8() >= OP_PUSHNUM_1.to_u8() && op.to_u8() <= OP_PUSHNUM_16.to_u8() =>
+            Some(op.to_u8() - OP_PUSHNUM_1.to_u8() + 1),
+        _ => None,
+    }
+}
+
+/// Checks that the size of `key` matches the size implied by its first byte.
+fn is_valid_pubkey_size(key: &[u8]) -> bool {
+    match key.first() {
+        Some(0x02) | Some(0x03) => key.len() == 33,
+        Some(0x04) | Some(0x06) | Some(0x07) => key.len() == 65,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::TryFrom;
+
+    use super::*;
+    use crate::blockdata::locktime::absolute;
+    use crate::blockdata::opcodes::OP_TRUE;
+    use crate::blockdata::script::{Builder, PushBytesBuf, ScriptBuf};
+    use crate::blockdata::transaction::{Sequence, TxIn};
+    use crate::blockdata::witness::Witness;
+    use crate::hashes::Hash;
+    use crate::{Txid, WPubkeyHash};
+
+    fn p2wpkh() -> ScriptBuf { ScriptBuf::new_v0_p2wpkh(&WPubkeyHash::all_zeros()) }
+
+    fn input(script_sig: ScriptBuf, witness: Witness) -> TxIn {
+        TxIn {
+            previous_output: OutPoint::new(Txid::all_zeros(), 0),
+            script_sig,
+            sequence: Sequence::MAX,
+            witness,
+        }
+    }
+
+    fn output(script_pubkey: ScriptBuf) -> TxOut { TxOut { value: 10_000, script_pubkey } }
+
+    fn tx(input: TxIn, output: Vec<TxOut>) -> Transaction {
+        Transaction { version: 2, lock_time: absolute::LockTime::ZERO, input: vec![input], output }
+    }
+
+    fn p2wpkh_spend() -> Transaction {
+        tx(input(ScriptBuf::new(), Witness::from_slice(&[[0; 72]])), vec![output(p2wpkh())])
+    }
+
+    fn op_return(len: usize) -> ScriptBuf {
+        let data = PushBytesBuf::try_from(vec![0; len]).unwrap();
+        Builder::new().push_opcode(OP_RETURN).push_slice(data).into_script()
+    }
+
+    fn multisig(required: u8, keys: usize) -> ScriptBuf {
+        let mut builder = Builder::new().push_int(required.into());
+        for _ in 0..keys {
+            builder = builder.push_slice([2; 33]);
+        }
+        builder.push_int(keys as i64).push_opcode(OP_CHECKMULTISIG).into_script()
+    }
+
+    #[test]
+    fn standard_tx() {
+        let tx = p2wpkh_spend();
+        assert_eq!(check_standard(&tx, |_| Some(output(p2wpkh()))), Ok(()));
+
+        let mut tx = p2wpkh_spend();
+        tx.output.push(TxOut { value: 0, script_pubkey: op_return(80) });
+        tx.output.push(output(multisig(1, 3)));
+        assert_eq!(check_standard_tx(&tx), Ok(()));
+    }
+
+    #[test]
+    fn version() {
+        let mut tx = p2wpkh_spend();
+        tx.version = 3;
+        assert_eq!(check_standard_tx(&tx), Ok(()));
+        tx.version = 4;
+        assert_eq!(check_standard_tx(&tx), Err(RejectReason::Version));
+        tx.version = 0;
+        assert_eq!(check_standard_tx(&tx), Err(RejectReason::Version));
+        assert_eq!(RejectReason::Version.to_string(), "version");
+    }
+
+    #[test]
+    fn tx_size() {
+        let mut tx = p2wpkh_spend();
+        tx.output = vec![output(ScriptBuf::from(vec![OP_TRUE.to_u8(); 10_000])); 10];
+        assert_eq!(check_standard_tx(&tx), Err(RejectReason::TxSize));
+
+        let mut tx = p2wpkh_spend();
+        tx.output[0].script_pubkey = op_return(2);
+        tx.output[0].value = 0;
+        assert_eq!(tx.strippedsize(), 64);
+        assert_eq!(check_standard_tx(&tx), Err(RejectReason::TxSizeSmall));
+        tx.output[0].script_pubkey = op_return(3);
+        assert_eq!(tx.strippedsize(), 65);
+        assert_eq!(check_standard_tx(&tx), Ok(()));
+    }
+
+    #[test]
+    fn script_sig() {
+        let mut tx = p2wpkh_spend();
+        tx.input[0].script_sig = ScriptBuf::from(vec![OP_PUSHNUM_1.to_u8(); 1651]);
+        assert_eq!(check_standard_tx(&tx), Err(RejectReason::ScriptSigSize { input_index: 0 }));
+
+        tx.input[0].script_sig = Builder::new().push_opcode(OP_NOP).into_script();
+        let err = check_standard_tx(&tx).unwrap_err();
+        assert_eq!(err, RejectReason::ScriptSigNotPushOnly { input_index: 0 });
+        assert_eq!(err.to_string(), "scriptsig-not-pushonly");
+    }
+
+    #[test]
+    fn script_pubkey() {
+        let nonstandard = [
+            Builder::new().push_opcode(OP_TRUE).into_script(),
+            op_return(81),
+            multisig(1, 4),
+            multisig(3, 2),
+            // Witness v0 programs must have one of the defined lengths.
+            Builder::new().push_int(0).push_slice([0; 24]).into_script(),
+            // P2PK with a key of invalid size.
+            Builder::new().push_slice([4; 33]).push_opcode(OP_CHECKSIG).into_script(),
+        ];
+        for script_pubkey in nonstandard.iter() {
+            let mut tx = p2wpkh_spend();
+            tx.output.push(output(script_pubkey.clone()));
+            assert_eq!(
+                check_standard_tx(&tx),
+                Err(RejectReason::ScriptPubKey { output_index: 1 }),
+                "{}",
+                script_pubkey
+            );
+        }
+    }
+
+    #[test]
+    fn dust_and_op_return() {
+        let mut tx = p2wpkh_spend();
+        tx.output.push(output(p2wpkh()));
+        tx.output[0].value = 293;
+        // A single dust output is permitted.
+        assert_eq!(check_standard_tx(&tx), Ok(()));
+        tx.output[1].value = 293;
+        assert_eq!(check_standard_tx(&tx), Err(RejectReason::Dust { output_index: 1 }));
+        // Scripts are checked before dust.
+        tx.output.push(output(Builder::new().push_opcode(OP_TRUE).into_script()));
+        assert_eq!(check_standard_tx(&tx), Err(RejectReason::ScriptPubKey { output_index: 2 }));
+        tx.output.pop();
+        tx.output[1].value = 294;
+        assert_eq!(check_standard_tx(&tx), Ok(()));
+
+        tx.output.push(TxOut { value: 0, script_pubkey: op_return(20) });
+        tx.output.push(TxOut { value: 0, script_pubkey: op_return(20) });
+        let err = check_standard_tx(&tx).unwrap_err();
+        assert_eq!(err, RejectReason::MultiOpReturn);
+        assert_eq!(err.to_string(), "multi-op-return");
+    }
+
+    #[test]
+    fn inputs() {
+        let redeem_script = multisig(15, 15);
+        let script_pubkey = redeem_script.to_p2sh();
+        let script_sig = Builder::new()
+            .push_int(0)
+            .push_slice(PushBytesBuf::try_from(redeem_script.into_bytes()).unwrap())
+            .into_script();
+        let tx = tx(input(script_sig, Witness::new()), vec![output(p2wpkh())]);
+        assert_eq!(check_standard_inputs(&tx, |_| Some(output(script_pubkey.clone()))), Ok(()));
+
+        // 16 sigops in the redeem script.
+        let redeem_script =
+            Builder::new().push_opcode(OP_CHECKSIG).into_script().into_bytes().repeat(16);
+        let script_pubkey = ScriptBuf::from(redeem_script.clone()).to_p2sh();
+        let mut tx = tx;
+        tx.input[0].script_sig =
+            Builder::new().push_slice(PushBytesBuf::try_from(redeem_script).unwrap()).into_script();
+        let err = check_standard_inputs(&tx, |_| Some(output(script_pubkey.clone()))).unwrap_err();
+        assert_eq!(err, RejectReason::NonStandardInputs { input_index: 0 });
+        assert_eq!(err.to_string(), "bad-txns-nonstandard-inputs");
+
+        // Future witness versions.
+        let script_pubkey = Builder::new().push_int(2).push_slice([0; 32]).into_script();
+        assert_eq!(
+            check_standard_inputs(&p2wpkh_spend(), |_| Some(output(script_pubkey.clone()))),
+            Err(RejectReason::NonStandardInputs { input_index: 0 })
+        );
+
+        let err = check_standard_inputs(&p2wpkh_spend(), |_| None).unwrap_err();
+        assert_eq!(err, RejectReason::MissingInputs(OutPoint::new(Txid::all_zeros(), 0)));
+    }
+
+    #[test]
+    fn p2wsh_witness() {
+        let witness_script = multisig(1, 1);
+        let script_pubkey = witness_script.to_v0_p2wsh();
+        let spent = |_: &OutPoint| Some(output(script_pubkey.clone()));
+        let witness = |items: &[&[u8]]| {
+            let mut witness = Witness::from_slice(items);
+            witness.push(witness_script.as_bytes());
+            witness
+        };
+
+        let tx = tx(input(ScriptBuf::new(), witness(&[&[], &[0; 80]])), vec![output(p2wpkh())]);
+        assert_eq!(check_standard_witness(&tx, spent), Ok(()));
+
+        let mut tx = tx;
+        tx.input[0].witness = witness(&[&[], &[0; 81]]);
+        let err = check_standard_witness(&tx, spent).unwrap_err();
+        assert_eq!(err, RejectReason::NonStandardWitness { input_index: 0 });
+        assert_eq!(err.to_string(), "bad-witness-nonstandard");
+
+        tx.input[0].witness = witness(&[&[][..]; 101]);
+        assert!(check_standard_witness(&tx, spent).is_err());
+
+        // Witness data spending a non-witness output.
+        let spent = |_: &OutPoint| Some(output(witness_script.clone()));
+        tx.input[0].witness = witness(&[&[]]);
+        assert!(check_standard_witness(&tx, spent).is_err());
+    }
+
+    #[test]
+    fn taproot_witness() {
+        let script_pubkey = Builder::new().push_int(1).push_slice([2; 32]).into_script();
+        let spent = |_: &OutPoint| Some(output(script_pubkey.clone()));
+        let mut tx = p2wpkh_spend();
+
+        tx.input[0].witness = Witness::from_slice(&[[1; 64]]);
+        assert_eq!(check_standard_witness(&tx, spent), Ok(()));
+
+        // An annex.
+        tx.input[0].witness = Witness::from_slice(&[vec![1; 64], vec![TAPROOT_ANNEX_PREFIX]]);
+        assert!(check_standard_witness(&tx, spent).is_err());
+
+        let control_block = [TAPROOT_LEAF_TAPSCRIPT; 33];
+        tx.input[0].witness =
+            Witness::from_slice(&[&[0; 80][..], &[OP_TRUE.to_u8()], &control_block]);
+        assert_eq!(check_standard_witness(&tx, spent), Ok(()));
+        tx.input[0].witness =
+            Witness::from_slice(&[&[0; 81][..], &[OP_TRUE.to_u8()], &control_block]);
+        assert!(check_standard_witness(&tx, spent).is_err());
+        tx.input[0].witness = Witness::from_slice(&[&[OP_TRUE.to_u8()][..], &[]]);
+        assert!(check_standard_witness(&tx, spent).is_err());
+    }
+
+    #[test]
+    fn anchor() {
+        let mut tx = tx(input(ScriptBuf::new(), Witness::new()), vec![output(p2wpkh())]);
+        tx.output.push(TxOut { value: 0, script_pubkey: ScriptBuf::new_p2a() });
+        assert_eq!(check_standard_tx(&tx), Ok(()));
+
+        let spent = |_: &OutPoint| Some(TxOut { value: 0, script_pubkey: ScriptBuf::new_p2a() });
+        assert_eq!(check_standard(&tx, spent), Ok(()));
+        tx.input[0].witness = Witness::from_slice(&[[1]]);
+        assert_eq!(
+            check_standard_witness(&tx, spent),
+            Err(RejectReason::NonStandardWitness { input_index: 0 })
+        );
+
+        // Only the spent script itself is checked, not a P2SH redeem script.
+        let redeem_script = ScriptBuf::new_p2a();
+        let script_pubkey = redeem_script.to_p2sh();
+        tx.input[0].script_sig = Builder::new()
+            .push_slice(PushBytesBuf::try_from(redeem_script.into_bytes()).unwrap())
+            .into_script();
+        let spent = |_: &OutPoint| Some(output(script_pubkey.clone()));
+        assert_eq!(check_standard_witness(&tx, spent), Ok(()));
+    }
+}
@@ -0,0 +1,487 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Packages of unconfirmed transactions.
+//!
+//! [`TxGraph`] links unconfirmed transactions spending each other's outputs and computes the
+//! ancestor and descendant feerates miners use to select them, e.g. to work out the fee of a
+//! child paying for its parents (CPFP). [`check_package`] and [`is_child_with_parents`] mirror
+//! the package relay checks of Bitcoin Core.
+//!
+
+use core::fmt;
+
+use crate::blockdata::fee_rate::FeeRate;
+use crate::blockdata::transaction::Transaction;
+use crate::blockdata::weight::Weight;
+use crate::hash_types::Txid;
+use crate::prelude::*;
+use crate::Amount;
+
+/// Maximum number of transactions in a package.
+pub const MAX_PACKAGE_COUNT: usize = 25;
+
+/// Maximum total weight of the transactions in a package.
+pub const MAX_PACKAGE_WEIGHT: u32 = 404_000;
+
+/// Maximum number of unconfirmed ancestors of a transaction, including itself.
+pub const DEFAULT_ANCESTOR_LIMIT: usize = 25;
+
+/// Maximum total virtual size, in kilobytes, of the unconfirmed ancestors of a transaction,
+/// including itself.
+pub const DEFAULT_ANCESTOR_SIZE_LIMIT_KVB: u32 = 101;
+
+/// Maximum number of unconfirmed descendants of a transaction, including itself.
+pub const DEFAULT_DESCENDANT_LIMIT: usize = 25;
+
+/// Maximum total virtual size, in kilobytes, of the unconfirmed descendants of a transaction,
+/// including itself.
+pub const DEFAULT_DESCENDANT_SIZE_LIMIT_KVB: u32 = 101;
+
+/// An unconfirmed transaction with its fee.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry<'a> {
+    /// The transaction.
+    pub tx: &'a Transaction,
+    /// The fee paid by the transaction.
+    pub fee: Amount,
+}
+
+/// The graph of unconfirmed transactions spending each other's outputs.
+///
+/// Transactions are referred to by their index in the entries the graph is created from.
+#[derive(Debug, Clone)]
+pub struct TxGraph<'a> {
+    entries: Vec<Entry<'a>>,
+    parents: Vec<Vec<usize>>,
+    children: Vec<Vec<usize>>,
+}
+
+impl<'a> TxGraph<'a> {
+    /// Creates the graph of `entries`, which may be in any order.
+    ///
+    /// Outputs of transactions which are not in `entries` are considered confirmed.
+    ///
+    /// # Errors
+    ///
+    /// If a transaction is contained twice.
+    pub fn new(entries: Vec<Entry<'a>>) -> Result<Self, PackageError> {
+        let mut indices = BTreeMap::new();
+        for (index, entry) in entries.iter().enumerate() {
+            if indices.insert(entry.tx.txid(), index).is_some() {
+                return Err(PackageError::Duplicates);
+            }
+        }
+
+        let mut parents = vec![Vec::new(); entries.len()];
+        let mut children = vec![Vec::new(); entries.len()];
+        for (index, entry) in entries.iter().enumerate() {
+            for txin in &entry.tx.input {
+                if let Some(&parent) = indices.get(&txin.previous_output.txid) {
+                    if !parents[index].contains(&parent) {
+                        parents[index].push(parent);
+                        children[parent].push(index);
+                    }
+                }
+            }
+        }
+        Ok(TxGraph { entries, parents, children })
+    }
+
+    /// Returns the entries of the graph.
+    pub fn entries(&self) -> &[Entry<'a>] { &self.entries }
+
+    /// Returns the transactions the transaction at `index` spends outputs of.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    pub fn parents(&self, index: usize) -> &[usize] { &self.parents[index] }
+
+    /// Returns the transactions spending outputs of the transaction at `index`.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    pub fn children(&self, index: usize) -> &[usize] { &self.children[index] }
+
+    /// Returns all the unconfirmed ancestors of the transaction at `index`, in ascending order
+    /// and not including itself.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    pub fn ancestors(&self, index: usize) -> Vec<usize> { self.walk(index, &self.parents) }
+
+    /// Returns all the descendants of the transaction at `index`, in ascending order and not
+    /// including itself.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    pub fn descendants(&self, index: usize) -> Vec<usize> { self.walk(index, &self.children) }
+
+    /// Returns the feerate of the transaction at `index` together with its unconfirmed
+    /// ancestors, which miners have to include to include the transaction.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    pub fn ancestor_fee_rate(&self, index: usize) -> FeeRate {
+        let (fee, weight) = self.sum(Some(index).into_iter().chain(self.ancestors(index)));
+        fee / weight
+    }
+
+    /// Returns the feerate of the transaction at `index` together with its descendants.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    pub fn descendant_fee_rate(&self, index: usize) -> FeeRate {
+        let (fee, weight) = self.sum(Some(index).into_iter().chain(self.descendants(index)));
+        fee / weight
+    }
+
+    /// Returns the total weight of all transactions.
+    pub fn package_weight(&self) -> Weight { self.sum(0..self.entries.len()).1 }
+
+    /// Returns the feerate of all transactions together, or `None` if the graph is empty.
+    pub fn package_fee_rate(&self) -> Option<FeeRate> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let (fee, weight) = self.sum(0..self.entries.len());
+        Some(fee / weight)
+    }
+
+    /// Returns the fee a child spending outputs of the transactions at `parents` has to pay to
+    /// raise the feerate of the child and all its unconfirmed ancestors to `fee_rate` (CPFP).
+    ///
+    /// `child_weight` is the predicted weight of the child, e.g. computed by
+    /// [`predict_weight`](crate::blockdata::transaction::predict_weight). The returned fee is
+    /// zero if the ancestors already pay `fee_rate`.
+    ///
+    /// # Panics
+    ///
+    /// If any of `parents` is out of bounds.
+    pub fn cpfp_fee(&self, parents: &[usize], child_weight: Weight, fee_rate: FeeRate) -> Amount {
+        let mut ancestors = BTreeSet::new();
+        for &parent in parents {
+            ancestors.insert(parent);
+            ancestors.extend(self.ancestors(parent));
+        }
+        let (fee, weight) = self.sum(ancestors);
+        (fee_rate * (weight + child_weight)).checked_sub(fee).unwrap_or(Amount::ZERO)
+    }
+
+    /// Checks the ancestor and descendant limits of Bitcoin Core's mempool for every
+    /// transaction.
+    ///
+    /// # Errors
+    ///
+    /// If a transaction has more than [`DEFAULT_ANCESTOR_LIMIT`] ancestors or
+    /// [`DEFAULT_DESCENDANT_LIMIT`] descendants, or their virtual size exceeds
+    /// [`DEFAULT_ANCESTOR_SIZE_LIMIT_KVB`] or [`DEFAULT_DESCENDANT_SIZE_LIMIT_KVB`], counting the
+    /// transaction itself.
+    pub fn check_limits(&self) -> Result<(), PackageError> {
+        for index in 0..self.entries.len() {
+            let limits = [
+                (self.ancestors(index), DEFAULT_ANCESTOR_LIMIT, DEFAULT_ANCESTOR_SIZE_LIMIT_KVB),
+                (
+                    self.descendants(index),
+                    DEFAULT_DESCENDANT_LIMIT,
+                    DEFAULT_DESCENDANT_SIZE_LIMIT_KVB,
+                ),
+            ];
+            for (related, count_limit, size_limit) in limits.iter() {
+                let vsize = related
+                    .iter()
+                    .chain(Some(&index))
+                    .map(|&i| self.entries[i].tx.vsize())
+                    .sum::<usize>();
+                if related.len() + 1 > *count_limit || vsize > *size_limit as usize * 1000 {
+                    return Err(PackageError::TooLongMempoolChain { index });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the transactions reachable from `index` by following `edges`, excluding `index`.
+    fn walk(&self, index: usize, edges: &[Vec<usize>]) -> Vec<usize> {
+        let mut visited = BTreeSet::new();
+        let mut stack = edges[index].clone();
+        while let Some(next) = stack.pop() {
+            if visited.insert(next) {
+                stack.extend(&edges[next]);
+            }
+        }
+        visited.into_iter().collect()
+    }
+
+    /// Returns the total fee and weight of the transactions at `indices`.
+    fn sum<I: IntoIterator<Item = usize>>(&self, indices: I) -> (Amount, Weight) {
+        indices.into_iter().fold((Amount::ZERO, Weight::ZERO), |(fee, weight), index| {
+            let entry = &self.entries[index];
+            (fee + entry.fee, weight + entry.tx.weight())
+        })
+    }
+}
+
+/// Checks that `txs` form a well-formed package, like `IsWellFormedPackage` of Bitcoin Core.
+///
+/// The package has at most [`MAX_PACKAGE_COUNT`] transactions of at most
+/// [`MAX_PACKAGE_WEIGHT`] total weight, without duplicates, sorted such that parents come before
+/// their children and without two transactions spending the same output.
+pub fn check_package(txs: &[Transaction]) -> Result<(), PackageError> {
+    if txs.len() > MAX_PACKAGE_COUNT {
+        return Err(PackageError::TooManyTransactions);
+    }
+    let weight = txs.iter().map(Transaction::weight).fold(Weight::ZERO, |a, b| a + b);
+    if txs.len() > 1 && weight > Weight::from_wu(MAX_PACKAGE_WEIGHT as u64) {
+        return Err(PackageError::TooLarge);
+    }
+
+    let mut later_txids = txs.iter().map(Transaction::txid).collect::<BTreeSet<_>>();
+    if later_txids.len() != txs.len() {
+        return Err(PackageError::Duplicates);
+    }
+    for tx in txs {
+        if tx.input.iter().any(|txin| later_txids.contains(&txin.previous_output.txid)) {
+            return Err(PackageError::NotSorted);
+        }
+        later_txids.remove(&tx.txid());
+    }
+
+    // Inputs are checked against earlier transactions only, a transaction spending the same
+    // output twice is invalid rather than a conflict.
+    let mut spent = BTreeSet::new();
+    for tx in txs {
+        if tx.input.iter().any(|txin| spent.contains(&txin.previous_output)) {
+            return Err(PackageError::Conflict);
+        }
+        spent.extend(tx.input.iter().map(|txin| txin.previous_output));
+    }
+    Ok(())
+}
+
+/// Checks whether `txs` consist of a child and some of its parents, the child being last, like
+/// `IsChildWithParents` of Bitcoin Core.
+///
+/// This is the package topology accepted by the package relay of Bitcoin Core.
+pub fn is_child_with_parents(txs: &[Transaction]) -> bool {
+    let (child, parents) = match txs.split_last() {
+        Some(split) if txs.len() >= 2 => split,
+        _ => return false,
+    };
+    let input_txids =
+        child.input.iter().map(|txin| txin.previous_output.txid).collect::<BTreeSet<_>>();
+    parents.iter().all(|parent| input_txids.contains(&parent.txid()))
+}
+
+/// Checks whether `txs` consist of a child and some of its parents, like
+/// [`is_child_with_parents`], and the parents don't spend each other's outputs.
+pub fn is_child_with_parents_tree(txs: &[Transaction]) -> bool {
+    if !is_child_with_parents(txs) {
+        return false;
+    }
+    let parents = &txs[..txs.len() - 1];
+    let parent_txids = parents.iter().map(Transaction::txid).collect::<BTreeSet<Txid>>();
+    parents
+        .iter()
+        .flat_map(|parent| &parent.input)
+        .all(|txin| !parent_txids.contains(&txin.previous_output.txid))
+}
+
+/// The reason a package is rejected.
+///
+/// Formatting with `Display` gives the reject reason used by Bitcoin Core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PackageError {
+    /// The package has more than [`MAX_PACKAGE_COUNT`] transactions
+    /// (`package-too-many-transactions`).
+    TooManyTransactions,
+    /// The total weight exceeds [`MAX_PACKAGE_WEIGHT`] (`package-too-large`).
+    TooLarge,
+    /// A transaction is contained twice (`package-contains-duplicates`).
+    Duplicates,
+    /// A transaction comes before its parent (`package-not-sorted`).
+    NotSorted,
+    /// Two transactions spend the same output (`conflict-in-package`).
+    Conflict,
+    /// The ancestors or descendants of a transaction exceed the mempool limits
+    /// (`too-long-mempool-chain`).
+    TooLongMempoolChain {
+        /// The index of the offending transaction.
+        index: usize,
+    },
+}
+
+impl fmt::Display for PackageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use PackageError::*;
+
+        let reason = match *self {
+            TooManyTransactions => "package-too-many-transactions",
+            TooLarge => "package-too-large",
+            Duplicates => "package-contains-duplicates",
+            NotSorted => "package-not-sorted",
+            Conflict => "conflict-in-package",
+            TooLongMempoolChain { .. } => "too-long-mempool-chain",
+        };
+        f.write_str(reason)
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+impl std::error::Error for PackageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockdata::locktime::absolute;
+    use crate::blockdata::script::ScriptBuf;
+    use crate::blockdata::transaction::{OutPoint, Sequence, TxIn, TxOut};
+    use crate::blockdata::witness::Witness;
+    use crate::hashes::Hash;
+
+    fn tx(inputs: &[OutPoint], outputs: usize) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::ZERO,
+            input: inputs
+                .iter()
+                .map(|outpoint| TxIn {
+                    previous_output: *outpoint,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: vec![
+                TxOut { value: 10_000, script_pubkey: ScriptBuf::new_op_return(&[0; 20]) };
+                outputs
+            ],
+        }
+    }
+
+    fn confirmed(n: u8) -> OutPoint { OutPoint::new(Txid::hash(&[n]), 0) }
+
+    fn spend(tx: &Transaction, vout: u32) -> OutPoint { OutPoint::new(tx.txid(), vout) }
+
+    #[test]
+    fn graph() {
+        // a -> b -> d, a -> c -> d
+        let a = tx(&[confirmed(0)], 2);
+        let b = tx(&[spend(&a, 0)], 1);
+        let c = tx(&[spend(&a, 1), confirmed(1)], 1);
+        let d = tx(&[spend(&b, 0), spend(&c, 0)], 1);
+        let entry = |tx, fee| Entry { tx, fee: Amount::from_sat(fee) };
+        let graph =
+            TxGraph::new(vec![entry(&d, 400), entry(&c, 300), entry(&b, 200), entry(&a, 100)])
+                .unwrap();
+
+        assert_eq!(graph.parents(0), [2, 1]);
+        assert_eq!(graph.children(3), [1, 2]);
+        assert_eq!(graph.ancestors(0), [1, 2, 3]);
+        assert!(graph.ancestors(3).is_empty());
+        assert_eq!(graph.descendants(3), [0, 1, 2]);
+        assert_eq!(graph.descendants(1), [0]);
+
+        // b and a.
+        assert_eq!(graph.ancestor_fee_rate(2), Amount::from_sat(300) / (a.weight() + b.weight()));
+        // a and all its descendants.
+        assert_eq!(graph.descendant_fee_rate(3), Amount::from_sat(1_000) / graph.package_weight());
+        assert_eq!(graph.package_fee_rate(), Some(graph.descendant_fee_rate(3)));
+        assert_eq!(graph.check_limits(), Ok(()));
+
+        assert_eq!(TxGraph::new(vec![]).unwrap().package_fee_rate(), None);
+        assert_eq!(
+            TxGraph::new(vec![entry(&a, 1), entry(&a, 1)]).unwrap_err(),
+            PackageError::Duplicates
+        );
+    }
+
+    #[test]
+    fn cpfp_fee() {
+        let parent = tx(&[confirmed(0)], 1);
+        let grandparent_spent = tx(&[confirmed(1)], 1);
+        let other_parent = tx(&[spend(&grandparent_spent, 0)], 1);
+        let entries = vec![
+            Entry { tx: &parent, fee: Amount::from_sat(100) },
+            Entry { tx: &grandparent_spent, fee: Amount::from_sat(200) },
+            Entry { tx: &other_parent, fee: Amount::from_sat(300) },
+        ];
+        let graph = TxGraph::new(entries).unwrap();
+        let child_weight = Weight::from_wu(600);
+        let fee_rate = FeeRate::from_sat_per_vb_unchecked(10);
+
+        let weight = parent.weight() + child_weight;
+        assert_eq!(
+            graph.cpfp_fee(&[0], child_weight, fee_rate),
+            fee_rate * weight - Amount::from_sat(100)
+        );
+        let weight = graph.package_weight() + child_weight;
+        assert_eq!(
+            graph.cpfp_fee(&[0, 2], child_weight, fee_rate),
+            fee_rate * weight - Amount::from_sat(600)
+        );
+        assert_eq!(graph.cpfp_fee(&[0], Weight::ZERO, FeeRate::ZERO), Amount::ZERO);
+    }
+
+    #[test]
+    fn limits() {
+        let mut txs = vec![tx(&[confirmed(0)], 1)];
+        for _ in 0..DEFAULT_ANCESTOR_LIMIT {
+            let next = tx(&[spend(txs.last().unwrap(), 0)], 1);
+            txs.push(next);
+        }
+        let entries = txs.iter().map(|tx| Entry { tx, fee: Amount::ZERO }).collect::<Vec<_>>();
+        let graph = TxGraph::new(entries[..DEFAULT_ANCESTOR_LIMIT].to_vec()).unwrap();
+        assert_eq!(graph.check_limits(), Ok(()));
+        let graph = TxGraph::new(entries).unwrap();
+        assert_eq!(graph.check_limits(), Err(PackageError::TooLongMempoolChain { index: 0 }));
+    }
+
+    #[test]
+    fn package_topology() {
+        let parent = tx(&[confirmed(0)], 2);
+        let other_parent = tx(&[confirmed(1)], 1);
+        let child = tx(&[spend(&parent, 0), spend(&other_parent, 0)], 1);
+        let package = [parent.clone(), other_parent.clone(), child.clone()];
+        assert_eq!(check_package(&package), Ok(()));
+        assert!(is_child_with_parents(&package));
+        assert!(is_child_with_parents_tree(&package));
+
+        assert_eq!(
+            check_package(&[child.clone(), parent.clone(), other_parent.clone()]),
+            Err(PackageError::NotSorted)
+        );
+        assert_eq!(check_package(&[parent.clone(), parent.clone()]), Err(PackageError::Duplicates));
+        let conflict = tx(&[spend(&parent, 0)], 2);
+        assert_eq!(
+            check_package(&[parent.clone(), child.clone(), conflict]),
+            Err(PackageError::Conflict)
+        );
+        // Duplicate inputs within a transaction are not a package conflict.
+        let duplicate_inputs = tx(&[spend(&parent, 1), spend(&parent, 1)], 1);
+        assert_eq!(check_package(&[parent.clone(), duplicate_inputs]), Ok(()));
+        assert_eq!(
+            check_package(&vec![parent.clone(); 26]),
+            Err(PackageError::TooManyTransactions)
+        );
+
+        // A child with a single parent is a tree, a parent spending the other one is not.
+        assert!(is_child_with_parents_tree(&[parent.clone(), child.clone()]));
+        assert!(!is_child_with_parents(&package[2..]));
+        assert!(!is_child_with_parents(&[parent.clone(), other_parent.clone()]));
+        let spending_parent = tx(&[spend(&parent, 1)], 1);
+        let child = tx(&[spend(&parent, 0), spend(&spending_parent, 0)], 1);
+        let package = [parent, spending_parent, child];
+        assert!(is_child_with_parents(&package));
+        assert!(!is_child_with_parents_tree(&package));
+    }
+}
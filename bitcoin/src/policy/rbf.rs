@@ -0,0 +1,311 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Replace-by-fee.
+//!
+//! [`check_replacement`] mirrors the `ReplacementChecks` of Bitcoin Core's mempool, the rules of
+//! BIP 125 a transaction spending the same outputs as mempool transactions has to satisfy to
+//! replace them.
+//!
+
+use core::fmt;
+
+use super::DEFAULT_INCREMENTAL_RELAY_FEE;
+use crate::blockdata::transaction::Transaction;
+use crate::hash_types::Txid;
+use crate::prelude::*;
+use crate::Amount;
+
+/// Maximum number of transactions a replacement may evict from the mempool, including the
+/// descendants of the directly conflicting transactions.
+pub const MAX_REPLACEMENT_CANDIDATES: usize = 100;
+
+/// A mempool transaction evicted by a replacement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Original<'a> {
+    /// The evicted transaction.
+    pub tx: &'a Transaction,
+    /// The fee paid by the transaction.
+    pub fee: Amount,
+}
+
+/// Checks whether `replacement`, paying `fee`, may replace the transactions it conflicts with.
+///
+/// `conflicts` are the mempool transactions spending any of the outputs spent by `replacement`
+/// and `descendants` are all their mempool descendants, which are evicted along with them.
+/// `is_unconfirmed` returns whether the transaction with the given txid is in the mempool.
+///
+/// The rules are checked in the order of Bitcoin Core:
+///
+/// - All `conflicts` signal replaceability with an input sequence number, see
+///   [`Transaction::is_explicitly_rbf`], or are TRUC transactions, see
+///   [`Transaction::is_truc`]. Replaceability inherited from an ancestor isn't taken into account.
+/// - The feerate of `replacement` exceeds the feerate of every one of the `conflicts`.
+/// - At most [`MAX_REPLACEMENT_CANDIDATES`] transactions are evicted.
+/// - `replacement` only spends unconfirmed outputs of transactions whose outputs are spent by the
+///   `conflicts` as well.
+/// - `replacement` doesn't spend the outputs of the transactions it evicts.
+/// - `fee` covers the fees of all evicted transactions plus the relay of `replacement` at
+///   [`DEFAULT_INCREMENTAL_RELAY_FEE`].
+///
+/// Feerates are based on [`Transaction::vsize`], ignoring the sigop adjustment of Bitcoin Core.
+pub fn check_replacement<U>(
+    replacement: &Transaction,
+    fee: Amount,
+    conflicts: &[Original],
+    descendants: &[Original],
+    mut is_unconfirmed: U,
+) -> Result<(), ReplacementError>
+where
+    U: FnMut(&Txid) -> bool,
+{
+    for conflict in conflicts {
+        if !conflict.tx.is_explicitly_rbf() && !conflict.tx.is_truc() {
+            return Err(ReplacementError::NotReplaceable(conflict.tx.txid()));
+        }
+    }
+
+    // Bitcoin Core compares feerates in satoshis per virtual kilobyte, rounded down.
+    let vsize = replacement.vsize() as u64;
+    let fee_rate = fee.to_sat() * 1000 / vsize;
+    for conflict in conflicts {
+        if fee_rate <= conflict.fee.to_sat() * 1000 / conflict.tx.vsize() as u64 {
+            return Err(ReplacementError::FeeRateTooLow(conflict.tx.txid()));
+        }
+    }
+
+    let evicted = conflicts.len() + descendants.len();
+    if evicted > MAX_REPLACEMENT_CANDIDATES {
+        return Err(ReplacementError::TooManyReplacements(evicted));
+    }
+
+    let parents_of_conflicts = conflicts
+        .iter()
+        .flat_map(|conflict| conflict.tx.input.iter().map(|txin| txin.previous_output.txid))
+        .collect::<BTreeSet<_>>();
+    for (input_index, txin) in replacement.input.iter().enumerate() {
+        let txid = &txin.previous_output.txid;
+        if !parents_of_conflicts.contains(txid) && is_unconfirmed(txid) {
+            return Err(ReplacementError::NewUnconfirmedInput { input_index });
+        }
+    }
+
+    let evicted_txids = conflicts
+        .iter()
+        .chain(descendants)
+        .map(|original| original.tx.txid())
+        .collect::<BTreeSet<_>>();
+    for txin in &replacement.input {
+        if evicted_txids.contains(&txin.previous_output.txid) {
+            return Err(ReplacementError::SpendsConflictingTx(txin.previous_output.txid));
+        }
+    }
+
+    let evicted_fees = conflicts.iter().chain(descendants).map(|original| original.fee).sum();
+    let relay_fee = Amount::from_sat((vsize * DEFAULT_INCREMENTAL_RELAY_FEE as u64 + 999) / 1000);
+    let required = relay_fee + evicted_fees;
+    if fee < required {
+        return Err(ReplacementError::InsufficientFee { required });
+    }
+    Ok(())
+}
+
+/// The reason a transaction can't replace the transactions it conflicts with.
+///
+/// Formatting with `Display` gives the reject reason used by Bitcoin Core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReplacementError {
+    /// A directly conflicting transaction doesn't signal replaceability (`txn-mempool-conflict`).
+    NotReplaceable(Txid),
+    /// The feerate of the replacement doesn't exceed the feerate of a directly conflicting
+    /// transaction (`insufficient fee`).
+    FeeRateTooLow(Txid),
+    /// The replacement would evict more than [`MAX_REPLACEMENT_CANDIDATES`] transactions
+    /// (`too many potential replacements`).
+    TooManyReplacements(usize),
+    /// An input spends an unconfirmed output of a transaction none of the replaced transactions
+    /// spends from (`replacement-adds-unconfirmed`).
+    NewUnconfirmedInput {
+        /// The index of the offending input.
+        input_index: usize,
+    },
+    /// The replacement spends an output of a transaction it evicts
+    /// (`bad-txns-spends-conflicting-tx`).
+    SpendsConflictingTx(Txid),
+    /// The fee doesn't pay for the evicted transactions and the relay of the replacement
+    /// (`insufficient fee`).
+    InsufficientFee {
+        /// The minimum fee of the replacement.
+        required: Amount,
+    },
+}
+
+impl fmt::Display for ReplacementError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ReplacementError::*;
+
+        let reason = match *self {
+            NotReplaceable(_) => "txn-mempool-conflict",
+            FeeRateTooLow(_) | InsufficientFee { .. } => "insufficient fee",
+            TooManyReplacements(_) => "too many potential replacements",
+            NewUnconfirmedInput { .. } => "replacement-adds-unconfirmed",
+            SpendsConflictingTx(_) => "bad-txns-spends-conflicting-tx",
+        };
+        f.write_str(reason)
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+impl std::error::Error for ReplacementError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockdata::locktime::absolute;
+    use crate::blockdata::script::ScriptBuf;
+    use crate::blockdata::transaction::{OutPoint, Sequence, TxIn, TxOut};
+    use crate::blockdata::witness::Witness;
+    use crate::hashes::Hash;
+
+    fn tx(inputs: &[OutPoint], sequence: Sequence, value: u64) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::ZERO,
+            input: inputs
+                .iter()
+                .map(|outpoint| TxIn {
+                    previous_output: *outpoint,
+                    script_sig: ScriptBuf::new(),
+                    sequence,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: vec![TxOut { value, script_pubkey: ScriptBuf::new_op_return(&[0; 20]) }],
+        }
+    }
+
+    fn outpoint(n: u8) -> OutPoint { OutPoint::new(Txid::hash(&[n]), 0) }
+
+    fn never(_: &Txid) -> bool { false }
+
+    #[test]
+    fn signaling() {
+        let original = tx(&[outpoint(0)], Sequence::ENABLE_LOCKTIME_NO_RBF, 10_000);
+        let conflicts = [Original { tx: &original, fee: Amount::from_sat(100) }];
+        let replacement = tx(&[outpoint(0)], Sequence::ENABLE_RBF_NO_LOCKTIME, 9_000);
+        let txid = original.txid();
+        assert_eq!(
+            check_replacement(&replacement, Amount::from_sat(1_100), &conflicts, &[], never),
+            Err(ReplacementError::NotReplaceable(txid))
+        );
+
+        let original = tx(&[outpoint(0)], Sequence::ENABLE_RBF_NO_LOCKTIME, 10_000);
+        let conflicts = [Original { tx: &original, fee: Amount::from_sat(100) }];
+        assert_eq!(
+            check_replacement(&replacement, Amount::from_sat(1_100), &conflicts, &[], never),
+            Ok(())
+        );
+
+        // TRUC transactions are always replaceable.
+        let mut original = tx(&[outpoint(0)], Sequence::ENABLE_LOCKTIME_NO_RBF, 10_000);
+        original.version = 3;
+        let conflicts = [Original { tx: &original, fee: Amount::from_sat(100) }];
+        assert_eq!(
+            check_replacement(&replacement, Amount::from_sat(1_100), &conflicts, &[], never),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn fees() {
+        let original = tx(&[outpoint(0)], Sequence::ENABLE_RBF_NO_LOCKTIME, 10_000);
+        let child = tx(&[OutPoint::new(original.txid(), 0)], Sequence::MAX, 9_000);
+        let conflicts = [Original { tx: &original, fee: Amount::from_sat(100) }];
+        let descendants = [Original { tx: &child, fee: Amount::from_sat(1_000) }];
+        let replacement = tx(&[outpoint(0)], Sequence::ENABLE_RBF_NO_LOCKTIME, 9_000);
+        let vsize = replacement.vsize() as u64;
+
+        // Same feerate.
+        assert_eq!(
+            check_replacement(&replacement, Amount::from_sat(100), &conflicts, &[], never),
+            Err(ReplacementError::FeeRateTooLow(original.txid()))
+        );
+        // Higher feerate but not paying for the relay of the replacement.
+        let required = Amount::from_sat(100 + vsize);
+        assert_eq!(
+            check_replacement(&replacement, Amount::from_sat(101), &conflicts, &[], never),
+            Err(ReplacementError::InsufficientFee { required })
+        );
+        assert_eq!(check_replacement(&replacement, required, &conflicts, &[], never), Ok(()));
+
+        // The fee of the evicted child has to be paid as well.
+        let required = Amount::from_sat(1_100 + vsize);
+        assert_eq!(
+            check_replacement(
+                &replacement,
+                required - Amount::from_sat(1),
+                &conflicts,
+                &descendants,
+                never
+            ),
+            Err(ReplacementError::InsufficientFee { required })
+        );
+        assert_eq!(
+            check_replacement(&replacement, required, &conflicts, &descendants, never),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn inputs() {
+        let original = tx(&[outpoint(0)], Sequence::ENABLE_RBF_NO_LOCKTIME, 10_000);
+        let conflicts = [Original { tx: &original, fee: Amount::from_sat(100) }];
+        let fee = Amount::from_sat(10_000);
+        let unconfirmed = |txid: &Txid| *txid == outpoint(0).txid || *txid == outpoint(1).txid;
+
+        // Spending another output of the parent of the conflict is fine.
+        let replacement = tx(
+            &[outpoint(0), OutPoint::new(outpoint(0).txid, 1), outpoint(2)],
+            Sequence::ENABLE_RBF_NO_LOCKTIME,
+            9_000,
+        );
+        assert_eq!(check_replacement(&replacement, fee, &conflicts, &[], unconfirmed), Ok(()));
+
+        let replacement = tx(&[outpoint(0), outpoint(1)], Sequence::ENABLE_RBF_NO_LOCKTIME, 9_000);
+        assert_eq!(
+            check_replacement(&replacement, fee, &conflicts, &[], unconfirmed),
+            Err(ReplacementError::NewUnconfirmedInput { input_index: 1 })
+        );
+
+        let conflicting_output = OutPoint::new(original.txid(), 0);
+        let replacement =
+            tx(&[outpoint(0), conflicting_output], Sequence::ENABLE_RBF_NO_LOCKTIME, 9_000);
+        assert_eq!(
+            check_replacement(&replacement, fee, &conflicts, &[], never),
+            Err(ReplacementError::SpendsConflictingTx(original.txid()))
+        );
+    }
+
+    #[test]
+    fn too_many_replacements() {
+        let originals = (0..=MAX_REPLACEMENT_CANDIDATES as u8)
+            .map(|n| tx(&[outpoint(n)], Sequence::ENABLE_RBF_NO_LOCKTIME, 10_000))
+            .collect::<Vec<_>>();
+        let conflicts = originals
+            .iter()
+            .map(|tx| Original { tx, fee: Amount::from_sat(100) })
+            .collect::<Vec<_>>();
+        let inputs = (0..=MAX_REPLACEMENT_CANDIDATES as u8).map(outpoint).collect::<Vec<_>>();
+        let replacement = tx(&inputs, Sequence::ENABLE_RBF_NO_LOCKTIME, 10_000);
+        let fee = Amount::from_sat(1_000_000);
+
+        assert_eq!(
+            check_replacement(&replacement, fee, &conflicts, &[], never),
+            Err(ReplacementError::TooManyReplacements(MAX_REPLACEMENT_CANDIDATES + 1))
+        );
+        assert_eq!(check_replacement(&replacement, fee, &conflicts[1..], &[], never), Ok(()));
+    }
+}
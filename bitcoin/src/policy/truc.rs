@@ -0,0 +1,300 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Topologically restricted until confirmation (TRUC) transactions.
+//!
+//! Transactions with version 3 opt in to the TRUC policy of Bitcoin Core described in BIP 431:
+//! they have at most one unconfirmed parent and one unconfirmed child, which have to be TRUC
+//! transactions as well, and their size is limited. In return they are replaceable without
+//! signaling it, and a child can replace the other child of its parent (sibling eviction). This
+//! allows a parent paying no fee at all to be mined by a child paying for both (CPFP), which can't
+//! be pinned by a large child spending the parent first.
+//!
+
+use core::fmt;
+
+use crate::policy::package::TxGraph;
+use crate::prelude::*;
+
+/// The transaction version of TRUC transactions.
+pub const TRUC_VERSION: i32 = 3;
+
+/// Maximum number of unconfirmed ancestors of a TRUC transaction, including itself.
+pub const TRUC_ANCESTOR_LIMIT: usize = 2;
+
+/// Maximum number of unconfirmed descendants of a TRUC transaction, including itself.
+pub const TRUC_DESCENDANT_LIMIT: usize = 2;
+
+/// Maximum virtual size of a TRUC transaction.
+pub const TRUC_MAX_VSIZE: usize = 10_000;
+
+/// Maximum virtual size of a TRUC transaction with an unconfirmed parent.
+pub const TRUC_CHILD_MAX_VSIZE: usize = 1_000;
+
+/// Checks the transaction at `index` against the TRUC rules, like `SingleTRUCChecks` of Bitcoin
+/// Core.
+///
+/// `graph` contains the transaction together with the unconfirmed transactions it is relayed
+/// with or which are in the mempool already. Transactions the transaction replaces must not be
+/// contained. The rules are:
+///
+/// - TRUC transactions only spend outputs of unconfirmed TRUC transactions and other
+///   transactions don't spend outputs of unconfirmed TRUC transactions.
+/// - TRUC transactions are at most [`TRUC_MAX_VSIZE`] virtual bytes large.
+/// - TRUC transactions have at most one unconfirmed ancestor, which has no other descendant.
+/// - TRUC transactions with an unconfirmed ancestor are at most [`TRUC_CHILD_MAX_VSIZE`] virtual
+///   bytes large.
+///
+/// The virtual size doesn't account for the sigops of the transaction.
+///
+/// # Errors
+///
+/// If the transaction breaks a rule. If it is the second child of its parent and the other child
+/// could be evicted instead, the returned [`TrucError::TooManyDescendants`] contains the other
+/// child. Replacing it is subject to [`check_replacement`](crate::policy::rbf::check_replacement).
+///
+/// # Panics
+///
+/// If `index` is out of bounds.
+pub fn check_truc(graph: &TxGraph, index: usize) -> Result<(), TrucError> {
+    let entries = graph.entries();
+    let tx = entries[index].tx;
+    let ancestors = graph.ancestors(index);
+    for &ancestor in &ancestors {
+        match (tx.is_truc(), entries[ancestor].tx.is_truc()) {
+            (true, false) => return Err(TrucError::SpendsNonTruc { index, ancestor }),
+            (false, true) => return Err(TrucError::NonTrucSpendsTruc { index, ancestor }),
+            _ => {}
+        }
+    }
+    if !tx.is_truc() {
+        return Ok(());
+    }
+
+    let vsize = tx.vsize();
+    if vsize > TRUC_MAX_VSIZE {
+        return Err(TrucError::TooBig { index, vsize });
+    }
+    if ancestors.len() + 1 > TRUC_ANCESTOR_LIMIT {
+        return Err(TrucError::TooManyAncestors { index });
+    }
+    if let Some(&parent) = ancestors.first() {
+        if vsize > TRUC_CHILD_MAX_VSIZE {
+            return Err(TrucError::ChildTooBig { index, vsize });
+        }
+        let own = graph.descendants(index);
+        let others = graph
+            .descendants(parent)
+            .into_iter()
+            .filter(|other| *other != index && !own.contains(other))
+            .collect::<Vec<_>>();
+        if others.len() + 2 > TRUC_DESCENDANT_LIMIT {
+            // Only a lone sibling without other unconfirmed ancestors is considered for eviction.
+            let sibling = if others.len() == 1 && graph.ancestors(others[0]).len() == 1 {
+                Some(others[0])
+            } else {
+                None
+            };
+            return Err(TrucError::TooManyDescendants { index, sibling });
+        }
+    }
+    Ok(())
+}
+
+/// Checks all transactions in `graph` against the TRUC rules, see [`check_truc`].
+///
+/// # Errors
+///
+/// With the error of the first transaction breaking a rule.
+pub fn check_truc_package(graph: &TxGraph) -> Result<(), TrucError> {
+    (0..graph.entries().len()).try_for_each(|index| check_truc(graph, index))
+}
+
+/// The reason a transaction breaks the TRUC rules.
+///
+/// Bitcoin Core rejects all of them with `TRUC-violation`, formatting with `Display` gives the
+/// details it adds to the reason.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TrucError {
+    /// A TRUC transaction spends an output of an unconfirmed transaction which isn't a TRUC
+    /// transaction.
+    SpendsNonTruc {
+        /// The index of the offending transaction.
+        index: usize,
+        /// The index of the ancestor which isn't a TRUC transaction.
+        ancestor: usize,
+    },
+    /// A transaction which isn't a TRUC transaction spends an output of an unconfirmed TRUC
+    /// transaction.
+    NonTrucSpendsTruc {
+        /// The index of the offending transaction.
+        index: usize,
+        /// The index of the TRUC ancestor.
+        ancestor: usize,
+    },
+    /// A TRUC transaction exceeds [`TRUC_MAX_VSIZE`].
+    TooBig {
+        /// The index of the offending transaction.
+        index: usize,
+        /// The virtual size of the transaction.
+        vsize: usize,
+    },
+    /// A TRUC transaction has more than one unconfirmed ancestor.
+    TooManyAncestors {
+        /// The index of the offending transaction.
+        index: usize,
+    },
+    /// A TRUC transaction with an unconfirmed parent exceeds [`TRUC_CHILD_MAX_VSIZE`].
+    ChildTooBig {
+        /// The index of the offending transaction.
+        index: usize,
+        /// The virtual size of the transaction.
+        vsize: usize,
+    },
+    /// The parent of a TRUC transaction has another descendant.
+    TooManyDescendants {
+        /// The index of the offending transaction.
+        index: usize,
+        /// The other child of the parent if it can be evicted instead.
+        sibling: Option<usize>,
+    },
+}
+
+impl fmt::Display for TrucError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use TrucError::*;
+
+        match *self {
+            SpendsNonTruc { .. } => f.write_str("version=3 tx cannot spend from non-version=3 tx"),
+            NonTrucSpendsTruc { .. } =>
+                f.write_str("non-version=3 tx cannot spend from version=3 tx"),
+            TooBig { vsize, .. } =>
+                write!(f, "version=3 tx is too big: {} > {} virtual bytes", vsize, TRUC_MAX_VSIZE),
+            TooManyAncestors { .. } => f.write_str("tx would have too many ancestors"),
+            ChildTooBig { vsize, .. } => write!(
+                f,
+                "version=3 child tx is too big: {} > {} virtual bytes",
+                vsize, TRUC_CHILD_MAX_VSIZE
+            ),
+            TooManyDescendants { .. } => f.write_str("tx would exceed descendant count limit"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+impl std::error::Error for TrucError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockdata::locktime::absolute;
+    use crate::blockdata::script::ScriptBuf;
+    use crate::blockdata::transaction::{OutPoint, Sequence, Transaction, TxIn, TxOut};
+    use crate::blockdata::witness::Witness;
+    use crate::hash_types::Txid;
+    use crate::hashes::Hash;
+    use crate::policy::package::Entry;
+    use crate::Amount;
+
+    fn tx(version: i32, inputs: &[OutPoint], outputs: usize) -> Transaction {
+        Transaction {
+            version,
+            lock_time: absolute::LockTime::ZERO,
+            input: inputs
+                .iter()
+                .map(|outpoint| TxIn {
+                    previous_output: *outpoint,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: vec![
+                TxOut { value: 10_000, script_pubkey: ScriptBuf::new_op_return(&[0; 20]) };
+                outputs
+            ],
+        }
+    }
+
+    fn confirmed(n: u8) -> OutPoint { OutPoint::new(Txid::hash(&[n]), 0) }
+
+    fn spend(tx: &Transaction, vout: u32) -> OutPoint { OutPoint::new(tx.txid(), vout) }
+
+    fn graph(txs: &[Transaction]) -> TxGraph<'_> {
+        TxGraph::new(txs.iter().map(|tx| Entry { tx, fee: Amount::ZERO }).collect()).unwrap()
+    }
+
+    #[test]
+    fn inheritance() {
+        let parent = tx(3, &[confirmed(0)], 2);
+        let child = tx(3, &[spend(&parent, 0)], 1);
+        assert!(parent.is_truc());
+        assert_eq!(check_truc_package(&graph(&[parent.clone(), child])), Ok(()));
+
+        let child = tx(2, &[spend(&parent, 0)], 1);
+        let err = check_truc_package(&graph(&[parent.clone(), child])).unwrap_err();
+        assert_eq!(err, TrucError::NonTrucSpendsTruc { index: 1, ancestor: 0 });
+        assert_eq!(err.to_string(), "non-version=3 tx cannot spend from version=3 tx");
+
+        let parent = tx(2, &[confirmed(0)], 1);
+        let child = tx(3, &[spend(&parent, 0)], 1);
+        assert_eq!(
+            check_truc_package(&graph(&[parent, child])),
+            Err(TrucError::SpendsNonTruc { index: 1, ancestor: 0 })
+        );
+
+        // Outputs of confirmed transactions may be spent by anyone.
+        let parent = tx(3, &[confirmed(0)], 1);
+        assert_eq!(check_truc(&graph(&[tx(2, &[spend(&parent, 0)], 1)]), 0), Ok(()));
+    }
+
+    #[test]
+    fn size() {
+        let txs = [tx(3, &[confirmed(0)], 400)];
+        let vsize = txs[0].vsize();
+        assert!(vsize > TRUC_MAX_VSIZE);
+        assert_eq!(check_truc(&graph(&txs), 0), Err(TrucError::TooBig { index: 0, vsize }));
+
+        let parent = tx(3, &[confirmed(0)], 300);
+        let child = tx(3, &[spend(&parent, 0)], 40);
+        assert!(child.vsize() > TRUC_CHILD_MAX_VSIZE);
+        let vsize = child.vsize();
+        let txs = [parent, child];
+        let graph = graph(&txs);
+        assert_eq!(check_truc(&graph, 0), Ok(()));
+        assert_eq!(check_truc(&graph, 1), Err(TrucError::ChildTooBig { index: 1, vsize }));
+    }
+
+    #[test]
+    fn topology() {
+        let grandparent = tx(3, &[confirmed(0)], 1);
+        let parent = tx(3, &[spend(&grandparent, 0)], 2);
+        let child = tx(3, &[spend(&parent, 0)], 1);
+        let txs = [grandparent, parent.clone(), child];
+        assert_eq!(check_truc(&graph(&txs), 2), Err(TrucError::TooManyAncestors { index: 2 }));
+
+        let other_parent = tx(3, &[confirmed(1)], 1);
+        let child = tx(3, &[spend(&parent, 0), spend(&other_parent, 0)], 1);
+        assert_eq!(
+            check_truc(&graph(&[parent.clone(), other_parent, child]), 2),
+            Err(TrucError::TooManyAncestors { index: 2 })
+        );
+
+        // The second child may evict the first one.
+        let parent = tx(3, &[confirmed(0)], 2);
+        let sibling = tx(3, &[spend(&parent, 0)], 1);
+        let child = tx(3, &[spend(&parent, 1)], 1);
+        let txs = [parent, sibling, child];
+        let graph = graph(&txs);
+        let err = check_truc(&graph, 2).unwrap_err();
+        assert_eq!(err, TrucError::TooManyDescendants { index: 2, sibling: Some(1) });
+        assert_eq!(err.to_string(), "tx would exceed descendant count limit");
+        assert_eq!(
+            check_truc_package(&graph),
+            Err(TrucError::TooManyDescendants { index: 1, sibling: Some(2) })
+        );
+    }
+}
@@ -14,7 +14,6 @@ use core::ops::{Add, Div, Mul, Not, Rem, Shl, Shr, Sub};
 use mutagen::mutate;
 
 use crate::consensus::encode::{self, Decodable, Encodable};
-#[cfg(doc)]
 use crate::consensus::Params;
 use crate::hash_types::BlockHash;
 use crate::io::{self, Read, Write};
@@ -82,6 +81,9 @@ impl Work {
     /// Lowest possible work value for Regtest. See comment on [`Params::pow_limit`] for more info.
     pub const REGTEST_MIN: Work = Work(U256(0x7fff_ff00_0000_0000_0000_0000_0000_0000_u128, 0));
 
+    /// Lowest possible work value for Testnet4. See comment on [`Params::pow_limit`] for more info.
+    pub const TESTNET4_MIN: Work = Work(U256(0x0000_0000_ffff_0000_0000_0000_0000_0000_u128, 0));
+
     /// Converts this [`Work`] to [`Target`].
     pub fn to_target(self) -> Target { Target(self.0.inverse()) }
 
@@ -256,8 +258,61 @@ impl CompactTarget {
 
     /// Returns the consensus encoded `u32` representation of this [`CompactTarget`].
     pub fn to_consensus(self) -> u32 { self.0 }
+
+    /// Computes the target of the next retarget period, like `CalculateNextWorkRequired` of Bitcoin
+    /// Core.
+    ///
+    /// `last` is the target of the last block of the period, or of its first block if
+    /// [`Params::enforce_bip94`] is set, and `timespan` the number of seconds between the
+    /// timestamps of its first and last block. The timespan is clamped to a quarter and
+    /// four times [`Params::pow_target_timespan`], and the target doesn't exceed the maximum target
+    /// of `params`. The result is rounded to the compact encoding exactly like Bitcoin Core does.
+    ///
+    /// Returns `last` if [`Params::no_pow_retargeting`] is set.
+    pub fn from_next_work_required(
+        last: CompactTarget,
+        timespan: u64,
+        params: &Params,
+    ) -> CompactTarget {
+        if params.no_pow_retargeting {
+            return last;
+        }
+        let target_timespan = params.pow_target_timespan;
+        let timespan = timespan.max(target_timespan / 4).min(target_timespan * 4);
+        // Like Bitcoin Core the product is truncated to 256 bits.
+        let target = Target::from_compact(last).0.mul_u64(timespan).0 / U256::from(target_timespan);
+        Target(target.min(max_target(params).0)).to_compact_lossy()
+    }
+
+    /// Returns the bits of a block with the lowest difficulty allowed by `params`.
+    pub fn from_pow_limit(params: &Params) -> CompactTarget {
+        max_target(params).to_compact_lossy()
+    }
+
+    /// Returns the bits of the lowest difficulty if a block with time `time` whose parent has time
+    /// `prev_time` may use them, like the `fPowAllowMinDifficultyBlocks` rule of Bitcoin Core.
+    ///
+    /// If [`Params::allow_min_difficulty_blocks`] is set, as it is for testnet, a block more than
+    /// twice the target spacing, 20 minutes, after its parent may use the lowest difficulty. This
+    /// doesn't apply to the first block of a retarget period, which is always retargeted.
+    pub fn from_min_difficulty_rule(
+        prev_time: u32,
+        time: u32,
+        params: &Params,
+    ) -> Option<CompactTarget> {
+        let spacing = params.pow_target_spacing.saturating_mul(2);
+        if params.allow_min_difficulty_blocks && u64::from(time) > u64::from(prev_time) + spacing {
+            Some(CompactTarget::from_pow_limit(params))
+        } else {
+            None
+        }
+    }
 }
 
+/// Returns the maximum target of `params`.
+// Despite its type `pow_limit` is the maximum target.
+pub(crate) fn max_target(params: &Params) -> Target { Target(params.pow_limit.0) }
+
 impl From<CompactTarget> for Target {
     fn from(c: CompactTarget) -> Self { Target::from_compact(c) }
 }
@@ -1585,6 +1640,43 @@ mod tests {
         assert_eq!(back.to_consensus(), consensus);
     }
 
+    #[test]
+    fn next_work_required() {
+        use crate::Network;
+
+        // Test vectors from `pow_tests.cpp` of Bitcoin Core: (nBits, timespan, next nBits).
+        let tests = [
+            (0x1d00ffff_u32, 1262152739 - 1261130161, 0x1d00d86a_u32),
+            (0x1d00ffff, 1233061996 - 1231006505, 0x1d00ffff), // Limited by the pow limit.
+            (0x1c05a3f4, 1279297671 - 1279008237, 0x1c0168fd), // Timespan limited downwards.
+            (0x1c387f6f, 1269211443 - 1263163443, 0x1d00e1fd), // Timespan limited upwards.
+        ];
+        let params = Params::new(Network::Bitcoin);
+        for &(last, timespan, next) in tests.iter() {
+            let last = CompactTarget::from_consensus(last);
+            let got = CompactTarget::from_next_work_required(last, timespan, &params);
+            assert_eq!(got, CompactTarget::from_consensus(next));
+        }
+
+        let params = Params::new(Network::Regtest);
+        let last = CompactTarget::from_consensus(0x1c05a3f4);
+        assert_eq!(CompactTarget::from_next_work_required(last, 1, &params), last);
+    }
+
+    #[test]
+    fn min_difficulty_rule() {
+        use crate::Network;
+
+        let params = Params::new(Network::Testnet);
+        let pow_limit = CompactTarget::from_pow_limit(&params);
+        assert_eq!(pow_limit.to_consensus(), 0x1d00ffff);
+        assert_eq!(CompactTarget::from_min_difficulty_rule(1000, 2200, &params), None);
+        assert_eq!(CompactTarget::from_min_difficulty_rule(1000, 2201, &params), Some(pow_limit));
+
+        let params = Params::new(Network::Bitcoin);
+        assert_eq!(CompactTarget::from_min_difficulty_rule(1000, 5000, &params), None);
+    }
+
     #[test]
     fn roundtrip_target_work() {
         let target = Target::from(0xdeadbeef_u32);
@@ -0,0 +1,489 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! PSBT analysis.
+//!
+//! Works out how far along the BIP 174 workflow a PSBT is, what is still missing from each input
+//! and what the final transaction is expected to weigh and pay, similar to Bitcoin Core's
+//! `analyzepsbt` RPC.
+//!
+
+use core::fmt;
+
+use crate::bip32::Fingerprint;
+use crate::blockdata::fee_rate::FeeRate;
+use crate::blockdata::script::Script;
+use crate::blockdata::transaction::{predict_weight, InputWeightPrediction, TxIn, TxOut};
+use crate::prelude::*;
+use crate::psbt::finalize::{parse_multi_a, parse_multisig};
+use crate::psbt::{Input, PartiallySignedTransaction};
+use crate::sighash::TapSighashType;
+use crate::Amount;
+
+/// Maximum length of a DER encoded ECDSA signature including the sighash byte.
+const ECDSA_SIG_LEN: usize = 73;
+
+/// Length of a compressed public key.
+const PUBKEY_LEN: usize = 33;
+
+impl PartiallySignedTransaction {
+    /// Analyzes this PSBT, reporting the next BIP 174 role for each input and for the PSBT as a
+    /// whole, what data each input is missing and the estimated size and fee rate of the final
+    /// transaction.
+    ///
+    /// An input is considered ready for the finalizer if [`Self::finalize_input`] would succeed,
+    /// the analysis is therefore limited to the script types supported by the finalizer.
+    ///
+    /// The size estimate uses the final scriptSig and witness for inputs that are finalized (or
+    /// could be) and assumes maximum size ECDSA signatures and compressed public keys for inputs
+    /// that still need signatures. No estimate is made if the satisfaction of any input is unknown.
+    pub fn analyze(&self) -> PsbtAnalysis {
+        // Finalize a copy so that finalizable inputs contribute their real size.
+        let mut finalized = self.clone();
+
+        let mut inputs = Vec::with_capacity(self.inputs.len());
+        let mut predictions = Some(Vec::with_capacity(self.inputs.len()));
+        for (i, (input, txin)) in self.inputs.iter().zip(&self.unsigned_tx.input).enumerate() {
+            let utxo = match funding_utxo(input, txin) {
+                Some(utxo) => utxo,
+                None => {
+                    inputs.push(InputAnalysis::missing_utxo());
+                    predictions = None;
+                    continue;
+                }
+            };
+            let spk = &utxo.script_pubkey;
+
+            let is_final = input.final_script_sig.is_some() || input.final_script_witness.is_some();
+            let analysis = if is_final || finalized.finalize_input(i).is_ok() {
+                InputAnalysis {
+                    next: if is_final { Role::Extractor } else { Role::Finalizer },
+                    is_final,
+                    ..Default::default()
+                }
+            } else {
+                let missing_redeem_script = spk.is_p2sh() && input.redeem_script.is_none();
+                let is_p2wsh = spk.is_v0_p2wsh()
+                    || input.redeem_script.as_ref().map_or(false, |s| s.is_v0_p2wsh());
+                let missing_witness_script = is_p2wsh && input.witness_script.is_none();
+                let next = if missing_redeem_script || missing_witness_script {
+                    Role::Updater
+                } else {
+                    Role::Signer
+                };
+                InputAnalysis {
+                    next,
+                    is_final,
+                    missing_utxo: false,
+                    missing_redeem_script,
+                    missing_witness_script,
+                    missing_signatures: missing_signatures(input, spk),
+                }
+            };
+            inputs.push(analysis);
+
+            let prediction = predict_input(&finalized.inputs[i], spk);
+            match (predictions.as_mut(), prediction) {
+                (Some(predictions), Some(prediction)) => predictions.push(prediction),
+                _ => predictions = None,
+            }
+        }
+
+        let estimated_weight = predictions
+            .map(|predictions| predict_weight(predictions, self.unsigned_tx.script_pubkey_lens()));
+        // `Psbt::fee` requires an input map for every input of the unsigned transaction.
+        let fee =
+            if self.inputs.len() == self.unsigned_tx.input.len() { self.fee().ok() } else { None };
+        let estimated_fee_rate = match (fee, estimated_weight) {
+            (Some(fee), Some(weight)) => Some(fee / weight),
+            _ => None,
+        };
+
+        PsbtAnalysis {
+            next: inputs.iter().map(|input| input.next).min().unwrap_or(Role::Updater),
+            inputs,
+            fee,
+            estimated_vsize: estimated_weight.map(|weight| weight.to_vbytes_ceil()),
+            estimated_fee_rate,
+        }
+    }
+}
+
+/// The result of analyzing a PSBT, see [`PartiallySignedTransaction::analyze`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PsbtAnalysis {
+    /// The analysis of each input, in the order of the PSBT input maps.
+    pub inputs: Vec<InputAnalysis>,
+    /// The role that has to process the PSBT next, the earliest role any input needs.
+    ///
+    /// A PSBT without inputs needs the [`Role::Updater`].
+    pub next: Role,
+    /// The fee paid by the transaction, if the UTXO of every input is known.
+    pub fee: Option<Amount>,
+    /// The estimated virtual size of the final transaction.
+    pub estimated_vsize: Option<u64>,
+    /// The estimated fee rate of the final transaction.
+    pub estimated_fee_rate: Option<FeeRate>,
+}
+
+impl PsbtAnalysis {
+    /// Returns true if every input is finalized and the transaction can be extracted.
+    pub fn is_ready_to_extract(&self) -> bool { self.next == Role::Extractor }
+}
+
+/// The analysis of a single PSBT input.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InputAnalysis {
+    /// The role that has to process this input next.
+    pub next: Role,
+    /// True if the input has a final scriptSig or witness.
+    pub is_final: bool,
+    /// True if neither `witness_utxo` nor `non_witness_utxo` provide the spent output.
+    pub missing_utxo: bool,
+    /// True if the input spends a P2SH output and has no `redeem_script`.
+    pub missing_redeem_script: bool,
+    /// True if the input spends a (possibly P2SH wrapped) P2WSH output and has no
+    /// `witness_script`.
+    pub missing_witness_script: bool,
+    /// Master key fingerprints from `bip32_derivation` (or `tap_key_origins` for taproot inputs)
+    /// of the keys that have not signed yet.
+    pub missing_signatures: BTreeSet<Fingerprint>,
+}
+
+impl InputAnalysis {
+    fn missing_utxo() -> Self {
+        InputAnalysis { next: Role::Updater, missing_utxo: true, ..Default::default() }
+    }
+}
+
+/// The roles described in BIP 174, in the order they process a PSBT.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Role {
+    /// Adds UTXOs, scripts and key origins to the PSBT.
+    Updater,
+    /// Adds signatures to the PSBT.
+    Signer,
+    /// Constructs the final scriptSig and witness of the inputs.
+    Finalizer,
+    /// Extracts the final transaction from the PSBT.
+    Extractor,
+}
+
+impl Default for Role {
+    fn default() -> Self { Role::Updater }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Role::Updater => f.write_str("updater"),
+            Role::Signer => f.write_str("signer"),
+            Role::Finalizer => f.write_str("finalizer"),
+            Role::Extractor => f.write_str("extractor"),
+        }
+    }
+}
+
+/// Returns the output spent by `txin`, without panicking on an out of range `non_witness_utxo`.
+fn funding_utxo<'a>(input: &'a Input, txin: &TxIn) -> Option<&'a TxOut> {
+    match (&input.witness_utxo, &input.non_witness_utxo) {
+        (Some(witness_utxo), _) => Some(witness_utxo),
+        (None, Some(tx)) => tx.output.get(txin.previous_output.vout as usize),
+        (None, None) => None,
+    }
+}
+
+/// Returns the fingerprints of the keys with a known origin that have not signed `input` yet.
+fn missing_signatures(input: &Input, spk: &Script) -> BTreeSet<Fingerprint> {
+    if spk.is_v1_p2tr() {
+        let mut missing = BTreeSet::new();
+        for (pk, (leaf_hashes, (fingerprint, _))) in &input.tap_key_origins {
+            let key_path = Some(*pk) == input.tap_internal_key && input.tap_key_sig.is_none();
+            let script_path =
+                leaf_hashes.iter().any(|leaf| !input.tap_script_sigs.contains_key(&(*pk, *leaf)));
+            if key_path || script_path {
+                missing.insert(*fingerprint);
+            }
+        }
+        missing
+    } else {
+        input
+            .bip32_derivation
+            .iter()
+            .filter(|(pk, _)| !input.partial_sigs.keys().any(|key| key.inner == **pk))
+            .map(|(_, (fingerprint, _))| *fingerprint)
+            .collect()
+    }
+}
+
+/// Predicts the weight of `input` once it is finalized.
+fn predict_input(input: &Input, spk: &Script) -> Option<InputWeightPrediction> {
+    if input.final_script_sig.is_some() || input.final_script_witness.is_some() {
+        let script_sig_len = input.final_script_sig.as_ref().map_or(0, |s| s.len());
+        let witness = input.final_script_witness.as_ref().map(|w| w.iter().map(<[u8]>::len));
+        return Some(InputWeightPrediction::new(script_sig_len, witness.into_iter().flatten()));
+    }
+
+    if spk.is_p2sh() {
+        let redeem_script = input.redeem_script.as_ref()?;
+        let script_sig_len = push_len(redeem_script.len());
+        if redeem_script.is_v0_p2wpkh() {
+            Some(InputWeightPrediction::new(script_sig_len, &[ECDSA_SIG_LEN, PUBKEY_LEN]))
+        } else if redeem_script.is_v0_p2wsh() {
+            Some(InputWeightPrediction::new(script_sig_len, wsh_stack(input)?))
+        } else {
+            let mut stack = legacy_stack(redeem_script)?;
+            stack.push(redeem_script.len());
+            Some(legacy_prediction(&stack))
+        }
+    } else if spk.is_v0_p2wpkh() {
+        // Not `P2WPKH_MAX`, the constants leave out the length of the empty scriptSig.
+        Some(InputWeightPrediction::new(0, &[ECDSA_SIG_LEN, PUBKEY_LEN]))
+    } else if spk.is_v0_p2wsh() {
+        Some(InputWeightPrediction::new(0, wsh_stack(input)?))
+    } else if spk.is_v1_p2tr() {
+        predict_taproot(input)
+    } else if spk.is_witness_program() {
+        None
+    } else {
+        Some(legacy_prediction(&legacy_stack(spk)?))
+    }
+}
+
+/// Predicts the weight of a taproot input, preferring a key path spend.
+fn predict_taproot(input: &Input) -> Option<InputWeightPrediction> {
+    let non_default_sighash = input.taproot_hash_ty() != Ok(TapSighashType::Default);
+    let sig_len = if non_default_sighash { 65 } else { 64 };
+
+    let key_path_signer =
+        input.tap_internal_key.map_or(false, |key| input.tap_key_origins.contains_key(&key));
+    if key_path_signer || input.tap_scripts.is_empty() {
+        return Some(InputWeightPrediction::new(0, &[sig_len]));
+    }
+
+    let stack = input
+        .tap_scripts
+        .iter()
+        .filter_map(|(control_block, (script, _))| {
+            let (required, keys) = parse_multi_a(script)?;
+            let mut stack = vec![sig_len; required];
+            stack.resize(keys.len(), 0);
+            stack.push(script.len());
+            stack.push(control_block.size());
+            Some(stack)
+        })
+        .min_by_key(|stack| stack.iter().sum::<usize>())?;
+    Some(InputWeightPrediction::new(0, stack))
+}
+
+/// Returns the witness element lengths satisfying the witness script of a P2WSH input.
+fn wsh_stack(input: &Input) -> Option<Vec<usize>> {
+    let witness_script = input.witness_script.as_ref()?;
+    let mut stack = legacy_stack(witness_script)?;
+    stack.push(witness_script.len());
+    Some(stack)
+}
+
+/// Returns the stack element lengths satisfying a P2PK, P2PKH or `OP_CHECKMULTISIG` script.
+fn legacy_stack(script: &Script) -> Option<Vec<usize>> {
+    if script.p2pk_public_key().is_some() {
+        return Some(vec![ECDSA_SIG_LEN]);
+    }
+    if script.is_p2pkh() {
+        return Some(vec![ECDSA_SIG_LEN, PUBKEY_LEN]);
+    }
+    let (required, _) = parse_multisig(script)?;
+    // The extra element popped by `OP_CHECKMULTISIG` is an empty push.
+    let mut stack = vec![0];
+    stack.resize(required + 1, ECDSA_SIG_LEN);
+    Some(stack)
+}
+
+/// Predicts the weight of an input with a scriptSig pushing elements of the given lengths.
+fn legacy_prediction(stack: &[usize]) -> InputWeightPrediction {
+    let script_sig_len = stack.iter().map(|len| push_len(*len)).sum();
+    InputWeightPrediction::new(script_sig_len, core::iter::empty::<usize>())
+}
+
+/// Returns the length of the script pushing `len` bytes.
+fn push_len(len: usize) -> usize {
+    match len {
+        0..=75 => 1 + len,
+        76..=0xff => 2 + len,
+        0x100..=0xffff => 3 + len,
+        _ => 5 + len,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::{KeyPair, Secp256k1, SecretKey};
+
+    use super::*;
+    use crate::bip32::DerivationPath;
+    use crate::blockdata::locktime::absolute;
+    use crate::blockdata::opcodes::all::OP_CHECKMULTISIG;
+    use crate::blockdata::script::{self, ScriptBuf};
+    use crate::blockdata::transaction::{OutPoint, Transaction};
+    use crate::crypto::key::PublicKey;
+    use crate::crypto::{ecdsa, taproot};
+    use crate::hash_types::Txid;
+    use crate::hashes::Hash;
+
+    fn psbt_spending(spks: Vec<ScriptBuf>) -> PartiallySignedTransaction {
+        let tx = Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::ZERO,
+            input: (0..spks.len())
+                .map(|vout| TxIn {
+                    previous_output: OutPoint::new(Txid::all_zeros(), vout as u32),
+                    ..Default::default()
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: 9_000 * spks.len() as u64,
+                script_pubkey: ScriptBuf::new_v0_p2wpkh(&Hash::all_zeros()),
+            }],
+        };
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+        for (input, script_pubkey) in psbt.inputs.iter_mut().zip(spks) {
+            input.witness_utxo = Some(TxOut { value: 10_000, script_pubkey });
+        }
+        psbt
+    }
+
+    fn key(i: u8) -> (PublicKey, ecdsa::Signature) {
+        let secp = Secp256k1::new();
+        let sk = SecretKey::from_slice(&[i; 32]).unwrap();
+        let msg = secp256k1::Message::from_slice(&[0xab; 32]).unwrap();
+        (
+            PublicKey::new(sk.public_key(&secp)),
+            ecdsa::Signature::sighash_all(secp.sign_ecdsa(&msg, &sk)),
+        )
+    }
+
+    fn origin(i: u8) -> (Fingerprint, DerivationPath) {
+        (Fingerprint::from([i; 4]), DerivationPath::master())
+    }
+
+    #[test]
+    fn analyze_p2wpkh_workflow() {
+        let (pk, sig) = key(1);
+        let mut psbt = psbt_spending(vec![ScriptBuf::new_v0_p2wpkh(&pk.wpubkey_hash().unwrap())]);
+        psbt.inputs[0].witness_utxo = None;
+
+        let analysis = psbt.analyze();
+        assert_eq!(analysis.next, Role::Updater);
+        assert!(analysis.inputs[0].missing_utxo);
+        assert_eq!(analysis.fee, None);
+        assert_eq!(analysis.estimated_vsize, None);
+
+        let spk = ScriptBuf::new_v0_p2wpkh(&pk.wpubkey_hash().unwrap());
+        psbt.inputs[0].witness_utxo = Some(TxOut { value: 10_000, script_pubkey: spk });
+        psbt.inputs[0].bip32_derivation.insert(pk.inner, origin(1));
+
+        let analysis = psbt.analyze();
+        assert_eq!(analysis.next, Role::Signer);
+        assert_eq!(
+            analysis.inputs[0].missing_signatures,
+            vec![Fingerprint::from([1; 4])].into_iter().collect()
+        );
+        assert_eq!(analysis.fee, Some(Amount::from_sat(1_000)));
+        // 10.5 vB of overhead, 41 vB of input, 31 vB of output and 27.25 vB of witness data.
+        assert_eq!(analysis.estimated_vsize, Some(110));
+        assert_eq!(analysis.estimated_fee_rate, Some(FeeRate::from_sat_per_kwu(2277)));
+
+        psbt.inputs[0].partial_sigs.insert(pk, sig);
+        let analysis = psbt.analyze();
+        assert_eq!(analysis.next, Role::Finalizer);
+        assert!(analysis.inputs[0].missing_signatures.is_empty());
+        let signed_vsize = analysis.estimated_vsize.unwrap();
+        assert!(signed_vsize <= 110);
+
+        psbt.finalize_mut().unwrap();
+        let analysis = psbt.analyze();
+        assert!(analysis.is_ready_to_extract());
+        assert!(analysis.inputs[0].is_final);
+        assert_eq!(analysis.estimated_vsize, Some(signed_vsize));
+        assert_eq!(analysis.estimated_vsize, Some(psbt.extract_tx().vsize() as u64));
+    }
+
+    #[test]
+    fn analyze_missing_scripts() {
+        let (pk1, sig1) = key(1);
+        let (pk2, _) = key(2);
+        let witness_script = script::Builder::new()
+            .push_int(1)
+            .push_key(&pk1)
+            .push_key(&pk2)
+            .push_int(2)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script();
+        let redeem_script = witness_script.to_v0_p2wsh();
+        let mut psbt = psbt_spending(vec![
+            redeem_script.to_p2sh(),
+            ScriptBuf::new_v0_p2wpkh(&pk1.wpubkey_hash().unwrap()),
+        ]);
+        psbt.inputs[1].partial_sigs.insert(pk1, sig1);
+
+        let analysis = psbt.analyze();
+        assert_eq!(analysis.next, Role::Updater);
+        assert!(analysis.inputs[0].missing_redeem_script);
+        assert!(!analysis.inputs[0].missing_witness_script);
+        assert_eq!(analysis.inputs[1].next, Role::Finalizer);
+        assert_eq!(analysis.fee, Some(Amount::from_sat(2_000)));
+        assert_eq!(analysis.estimated_vsize, None);
+
+        psbt.inputs[0].redeem_script = Some(redeem_script);
+        let analysis = psbt.analyze();
+        assert_eq!(analysis.inputs[0].next, Role::Updater);
+        assert!(!analysis.inputs[0].missing_redeem_script);
+        assert!(analysis.inputs[0].missing_witness_script);
+
+        psbt.inputs[0].witness_script = Some(witness_script);
+        psbt.inputs[0].bip32_derivation.insert(pk1.inner, origin(1));
+        psbt.inputs[0].bip32_derivation.insert(pk2.inner, origin(2));
+        let analysis = psbt.analyze();
+        assert_eq!(analysis.next, Role::Signer);
+        assert_eq!(analysis.inputs[0].missing_signatures.len(), 2);
+        assert!(analysis.estimated_vsize.is_some());
+
+        psbt.inputs[0].partial_sigs.insert(pk1, sig1);
+        let analysis = psbt.analyze();
+        assert_eq!(analysis.next, Role::Finalizer);
+        let estimated_vsize = analysis.estimated_vsize;
+
+        psbt.finalize_mut().unwrap();
+        assert_eq!(estimated_vsize, Some(psbt.extract_tx().vsize() as u64));
+    }
+
+    #[test]
+    fn analyze_taproot() {
+        let secp = Secp256k1::new();
+        let keypair = KeyPair::from_seckey_slice(&secp, &[1; 32]).unwrap();
+        let (internal_key, _) = keypair.x_only_public_key();
+        let spk = ScriptBuf::new_v1_p2tr(&secp, internal_key, None);
+        let mut psbt = psbt_spending(vec![spk]);
+        psbt.inputs[0].tap_internal_key = Some(internal_key);
+        psbt.inputs[0].tap_key_origins.insert(internal_key, (vec![], origin(1)));
+
+        let analysis = psbt.analyze();
+        assert_eq!(analysis.next, Role::Signer);
+        assert_eq!(
+            analysis.inputs[0].missing_signatures,
+            vec![Fingerprint::from([1; 4])].into_iter().collect()
+        );
+        let estimated_vsize = analysis.estimated_vsize;
+
+        let msg = secp256k1::Message::from_slice(&[0xab; 32]).unwrap();
+        let sig = secp.sign_schnorr_no_aux_rand(&msg, &keypair);
+        psbt.inputs[0].tap_key_sig =
+            Some(taproot::Signature { sig, hash_ty: TapSighashType::Default });
+        let analysis = psbt.analyze();
+        assert_eq!(analysis.next, Role::Finalizer);
+        assert!(analysis.inputs[0].missing_signatures.is_empty());
+        assert_eq!(analysis.estimated_vsize, estimated_vsize);
+
+        psbt.finalize_mut().unwrap();
+        assert_eq!(estimated_vsize, Some(psbt.extract_tx().vsize() as u64));
+    }
+}
@@ -0,0 +1,1013 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! PSBT construction.
+//!
+//! Implementation of the Creator and Updater roles described in BIP 174 for simple payments:
+//! [`TransactionBuilder`] selects the outputs paying for the recipients, adds change and returns
+//! the unsigned transaction as a PSBT ready to be passed to the signers.
+//!
+
+use core::{cmp, fmt};
+
+use bitcoin_internals::write_err;
+use secp256k1::rand::Rng;
+
+use crate::address::Address;
+use crate::blockdata::fee_rate::FeeRate;
+use crate::blockdata::locktime::{absolute, anti_fee_sniping};
+use crate::blockdata::script::ScriptBuf;
+use crate::blockdata::transaction::{
+    predict_weight, InputWeightPrediction, OutPoint, Sequence, Transaction, TxIn, TxOut,
+};
+use crate::blockdata::weight::Weight;
+use crate::blockdata::witness::Witness;
+use crate::coin_selection::{self, select_coins, Candidate, ChangePolicy, Params};
+use crate::policy::truc::{TRUC_CHILD_MAX_VSIZE, TRUC_MAX_VSIZE, TRUC_VERSION};
+use crate::policy::DEFAULT_INCREMENTAL_RELAY_FEE;
+use crate::prelude::*;
+use crate::psbt::{Error, Input, PartiallySignedTransaction};
+use crate::Amount;
+
+/// The long term feerate used unless set otherwise, the default `-consolidatefeerate` of Bitcoin
+/// Core (10 sat/vB).
+const DEFAULT_LONG_TERM_FEE_RATE: FeeRate = FeeRate::from_sat_per_kwu(2_500);
+
+/// An unspent output the builder may spend.
+#[derive(Debug, Clone)]
+pub struct Utxo {
+    /// The outpoint of the output.
+    pub outpoint: OutPoint,
+    /// The output itself.
+    pub txout: TxOut,
+    /// The predicted weight of the scriptSig and witness spending the output.
+    pub satisfaction_weight: InputWeightPrediction,
+    /// The transaction containing the output, required to spend non-segwit outputs.
+    pub previous_tx: Option<Transaction>,
+}
+
+impl Utxo {
+    /// Creates an unspent output without its previous transaction.
+    ///
+    /// Only segwit outputs can be spent without the previous transaction.
+    pub fn new(
+        outpoint: OutPoint,
+        txout: TxOut,
+        satisfaction_weight: InputWeightPrediction,
+    ) -> Self {
+        Utxo { outpoint, txout, satisfaction_weight, previous_tx: None }
+    }
+
+    /// Creates the unspent output `vout` of `previous_tx`, `None` if there is no such output.
+    pub fn from_previous_tx(
+        previous_tx: Transaction,
+        vout: u32,
+        satisfaction_weight: InputWeightPrediction,
+    ) -> Option<Self> {
+        let txout = previous_tx.output.get(vout as usize)?.clone();
+        Some(Utxo {
+            outpoint: OutPoint::new(previous_tx.txid(), vout),
+            txout,
+            satisfaction_weight,
+            previous_tx: Some(previous_tx),
+        })
+    }
+
+    /// Returns whether the output is spent with a witness, including P2SH wrapped segwit.
+    fn is_segwit(&self) -> bool {
+        self.txout.script_pubkey.is_witness_program() || self.satisfaction_weight.witness_size > 0
+    }
+
+    /// Checks that the previous transaction is present if required and matches the output.
+    fn check(&self) -> Result<(), BuildError> {
+        match self.previous_tx {
+            Some(ref tx) => {
+                let vout = self.outpoint.vout as usize;
+                if tx.txid() != self.outpoint.txid || tx.output.get(vout) != Some(&self.txout) {
+                    return Err(BuildError::PreviousTxMismatch(self.outpoint));
+                }
+                Ok(())
+            }
+            None if !self.is_segwit() => Err(BuildError::MissingPreviousTx(self.outpoint)),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the PSBT input spending the output, with the UTXO fields set.
+    fn into_psbt_input(self) -> Input {
+        Input {
+            witness_utxo: if self.is_segwit() { Some(self.txout) } else { None },
+            non_witness_utxo: self.previous_tx,
+            ..Default::default()
+        }
+    }
+}
+
+/// How the lock time of the transaction is chosen.
+#[derive(Debug, Clone, Copy)]
+enum LockTimePolicy {
+    Fixed(absolute::LockTime),
+    AntiFeeSniping(absolute::Height),
+}
+
+/// Builds a PSBT paying to a set of recipients.
+///
+/// The builder runs [`select_coins`] on the available UTXOs and adds a change output if that is
+/// less wasteful than giving the excess to the miners. The resulting PSBT has the
+/// `witness_utxo` of segwit inputs and the `non_witness_utxo` of all inputs with a known previous
+/// transaction set, so [`PartiallySignedTransaction::fee`] returns the exact fee paid.
+///
+/// By default the transaction has version 2, signals replaceability (BIP 125) and has a lock time
+/// of zero.
+///
+/// # TRUC transactions
+///
+/// Transactions with version 3 follow the TRUC policy, see [`policy::truc`], and the builder
+/// fails if they exceed its size limits. This allows a parent paying no fee, built with a zero
+/// feerate, to be mined by a child paying for both: the child spends an output of the parent,
+/// added with [`add_required_utxo`], and [`pay_for_ancestors`] makes it pay the missing fee of its
+/// parent.
+///
+/// [`policy::truc`]: crate::policy::truc
+/// [`add_required_utxo`]: TransactionBuilder::add_required_utxo
+/// [`pay_for_ancestors`]: TransactionBuilder::pay_for_ancestors
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "rand")] {
+/// use std::str::FromStr;
+///
+/// use bitcoin::blockdata::transaction::InputWeightPrediction;
+/// use bitcoin::hashes::Hash;
+/// use bitcoin::psbt::{TransactionBuilder, Utxo};
+/// use bitcoin::{absolute, Address, Amount, FeeRate, Network, OutPoint, ScriptBuf, TxOut, WPubkeyHash};
+///
+/// let recipient = Address::from_str("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq")
+///     .unwrap()
+///     .require_network(Network::Bitcoin)
+///     .unwrap();
+/// let utxo = Utxo::new(
+///     OutPoint::null(),
+///     TxOut {
+///         value: 100_000,
+///         script_pubkey: ScriptBuf::new_v0_p2wpkh(&WPubkeyHash::all_zeros()),
+///     },
+///     InputWeightPrediction::P2WPKH_MAX,
+/// );
+/// let change = ScriptBuf::new_v0_p2wpkh(&WPubkeyHash::all_zeros());
+///
+/// let psbt = TransactionBuilder::new(FeeRate::from_sat_per_vb_unchecked(5))
+///     .add_utxo(utxo)
+///     .add_recipient(&recipient, Amount::from_sat(50_000))
+///     .change(change, InputWeightPrediction::P2WPKH_MAX)
+///     .anti_fee_sniping(absolute::Height::from_consensus(800_000).unwrap())
+///     .build(&mut bitcoin::secp256k1::rand::thread_rng())
+///     .unwrap();
+/// assert_eq!(psbt.unsigned_tx.output.len(), 2);
+/// assert!(psbt.fee().unwrap() < Amount::from_sat(1_000));
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TransactionBuilder {
+    required_utxos: Vec<Utxo>,
+    utxos: Vec<Utxo>,
+    outputs: Vec<TxOut>,
+    fee_rate: FeeRate,
+    long_term_fee_rate: FeeRate,
+    change: Option<(ScriptBuf, InputWeightPrediction)>,
+    ancestors: Option<(Amount, Weight)>,
+    version: i32,
+    rbf: bool,
+    lock_time: LockTimePolicy,
+    sort_bip69: bool,
+}
+
+impl TransactionBuilder {
+    /// Creates a builder for a transaction paying `fee_rate`.
+    pub fn new(fee_rate: FeeRate) -> Self {
+        TransactionBuilder {
+            required_utxos: Vec::new(),
+            utxos: Vec::new(),
+            outputs: Vec::new(),
+            fee_rate,
+            long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
+            change: None,
+            ancestors: None,
+            version: 2,
+            rbf: true,
+            lock_time: LockTimePolicy::Fixed(absolute::LockTime::ZERO),
+            sort_bip69: false,
+        }
+    }
+
+    /// Adds an unspent output that may be spent.
+    pub fn add_utxo(mut self, utxo: Utxo) -> Self {
+        self.utxos.push(utxo);
+        self
+    }
+
+    /// Adds unspent outputs that may be spent.
+    pub fn add_utxos<I: IntoIterator<Item = Utxo>>(mut self, utxos: I) -> Self {
+        self.utxos.extend(utxos);
+        self
+    }
+
+    /// Adds an unspent output that is spent in any case, e.g. an output of an unconfirmed parent
+    /// to bump with this transaction.
+    ///
+    /// Coin selection adds inputs from the other unspent outputs only if the required ones don't
+    /// cover the outputs and the fee.
+    pub fn add_required_utxo(mut self, utxo: Utxo) -> Self {
+        self.required_utxos.push(utxo);
+        self
+    }
+
+    /// Pays `amount` to `address`.
+    pub fn add_recipient(self, address: &Address, amount: Amount) -> Self {
+        self.add_output(TxOut { value: amount.to_sat(), script_pubkey: address.script_pubkey() })
+    }
+
+    /// Adds an arbitrary output, e.g. an `OP_RETURN` output.
+    pub fn add_output(mut self, txout: TxOut) -> Self {
+        self.outputs.push(txout);
+        self
+    }
+
+    /// Sends the change to `script_pubkey`, which is later spent by an input with the predicted
+    /// weight.
+    ///
+    /// Without a change script all the excess value is added to the fee.
+    pub fn change(mut self, script_pubkey: ScriptBuf, spend_weight: InputWeightPrediction) -> Self {
+        self.change = Some((script_pubkey, spend_weight));
+        self
+    }
+
+    /// Sets the feerate expected to be paid for spending the change output later on.
+    ///
+    /// The default is 10 sat/vB, like the `-consolidatefeerate` of Bitcoin Core. Coin selection
+    /// prefers spending more inputs if `fee_rate` is lower than this.
+    pub fn long_term_fee_rate(mut self, fee_rate: FeeRate) -> Self {
+        self.long_term_fee_rate = fee_rate;
+        self
+    }
+
+    /// Sets the fee and weight of the unconfirmed ancestors of the transaction, which it pays for
+    /// (CPFP).
+    ///
+    /// The transaction pays the fee its ancestors lack to reach the feerate together with it, see
+    /// [`TxGraph::cpfp_fee`](crate::policy::package::TxGraph::cpfp_fee).
+    pub fn pay_for_ancestors(mut self, fee: Amount, weight: Weight) -> Self {
+        self.ancestors = Some((fee, weight));
+        self
+    }
+
+    /// Sets the version of the transaction, 2 by default.
+    ///
+    /// Version 3 opts in to the TRUC policy, see [`Transaction::is_truc`].
+    pub fn version(mut self, version: i32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Sets whether the transaction signals replaceability (BIP 125), enabled by default.
+    pub fn rbf(mut self, enable: bool) -> Self {
+        self.rbf = enable;
+        self
+    }
+
+    /// Sets the lock time of the transaction.
+    pub fn lock_time(mut self, lock_time: absolute::LockTime) -> Self {
+        self.lock_time = LockTimePolicy::Fixed(lock_time);
+        self
+    }
+
+    /// Sets the lock time to the height of the chain tip to discourage fee sniping.
+    ///
+    /// Like Bitcoin Core, one in ten times the lock time is set up to 99 blocks earlier so that
+    /// transactions delayed before broadcasting don't stand out, see
+    /// [`anti_fee_sniping::lock_time`].
+    pub fn anti_fee_sniping(mut self, tip_height: absolute::Height) -> Self {
+        self.lock_time = LockTimePolicy::AntiFeeSniping(tip_height);
+        self
+    }
+
+    /// Sets whether inputs and outputs are sorted as described in BIP 69.
+    ///
+    /// If not set inputs are ordered as selected and the change output is appended to the other
+    /// outputs.
+    pub fn sort_bip69(mut self, sort: bool) -> Self {
+        self.sort_bip69 = sort;
+        self
+    }
+
+    /// Selects the inputs, adds change and returns the PSBT.
+    ///
+    /// # Errors
+    ///
+    /// If there are no outputs, the previous transaction of an unspent output is missing or
+    /// doesn't match it, coin selection fails or a TRUC transaction is too large.
+    pub fn build<R: Rng + ?Sized>(
+        self,
+        rng: &mut R,
+    ) -> Result<PartiallySignedTransaction, BuildError> {
+        if self.outputs.is_empty() {
+            return Err(BuildError::NoOutputs);
+        }
+        for utxo in self.required_utxos.iter().chain(&self.utxos) {
+            utxo.check()?;
+        }
+
+        let fee_rate = self.fee_rate;
+        let has_witness = self.required_utxos.iter().chain(&self.utxos).any(Utxo::is_segwit);
+        let candidate = |utxo: &Utxo| {
+            let mut candidate =
+                Candidate::new(Amount::from_sat(utxo.txout.value), utxo.satisfaction_weight);
+            if has_witness && !utxo.is_segwit() {
+                // The empty witness in a segwit transaction.
+                candidate.weight += Weight::from_wu(1);
+            }
+            candidate
+        };
+        let mut base_weight =
+            predict_weight(None, self.outputs.iter().map(|txout| txout.script_pubkey.len()));
+        if has_witness {
+            // The segwit marker and flag.
+            base_weight += Weight::from_wu(2);
+        }
+        base_weight +=
+            self.required_utxos.iter().map(|utxo| candidate(utxo).weight).sum::<Weight>();
+        let ancestor_fee = match self.ancestors {
+            Some((fee, weight)) => (fee_rate * weight).checked_sub(fee).unwrap_or(Amount::ZERO),
+            None => Amount::ZERO,
+        };
+        let output_value: Amount =
+            self.outputs.iter().map(|txout| Amount::from_sat(txout.value)).sum();
+        let required_value: Amount =
+            self.required_utxos.iter().map(|utxo| Amount::from_sat(utxo.txout.value)).sum();
+        let change_policy = match self.change {
+            Some((ref script_pubkey, spend_weight)) =>
+                ChangePolicy::new(script_pubkey, spend_weight),
+            None => no_change_policy(),
+        };
+
+        let mut selected = self.required_utxos;
+        let spent = output_value + ancestor_fee + fee_rate * base_weight;
+        let mut change = match spent.checked_sub(required_value) {
+            Some(target) if target > Amount::ZERO => {
+                let candidates = self.utxos.iter().map(candidate).collect::<Vec<_>>();
+                let params = Params {
+                    target,
+                    base_weight: Weight::ZERO,
+                    fee_rate,
+                    long_term_fee_rate: self.long_term_fee_rate,
+                    change_policy,
+                };
+                let selection = select_coins(&candidates, &params, rng)?;
+                for (index, utxo) in self.utxos.into_iter().enumerate() {
+                    if selection.selected.binary_search(&index).is_ok() {
+                        selected.push(utxo);
+                    }
+                }
+                selection.change
+            }
+            // The required inputs cover everything.
+            _ => (required_value - spent)
+                .checked_sub(fee_rate * change_policy.output_weight)
+                .filter(|value| *value > Amount::ZERO && *value >= change_policy.min_value),
+        };
+
+        let mut outputs = self.outputs;
+        let input_value: Amount =
+            selected.iter().map(|utxo| Amount::from_sat(utxo.txout.value)).sum();
+        let mut fee = input_value - output_value - change.unwrap_or(Amount::ZERO);
+        // Selection doesn't account for input count varints longer than a byte.
+        let change_script_len = self.change.as_ref().map(|(script_pubkey, _)| script_pubkey.len());
+        let weight = |change: Option<Amount>| {
+            let output_lens = outputs.iter().map(|txout| txout.script_pubkey.len());
+            let change_len = change.and(change_script_len);
+            let inputs = selected.iter().map(|utxo| utxo.satisfaction_weight);
+            predict_weight(inputs, output_lens.chain(change_len))
+        };
+        let required = fee_rate * weight(change) + ancestor_fee;
+        if let (Some(value), true) = (change, fee < required) {
+            match value.checked_sub(required - fee) {
+                Some(value) if value >= change_policy.min_value => change = Some(value),
+                _ => change = None,
+            }
+            fee += value - change.unwrap_or(Amount::ZERO);
+        }
+        let weight = weight(change);
+        let required = fee_rate * weight + ancestor_fee;
+        if fee < required {
+            return Err(BuildError::CoinSelection(coin_selection::Error::InsufficientFunds {
+                available: input_value,
+                required: output_value + required,
+            }));
+        }
+        if self.version == TRUC_VERSION {
+            let max = if self.ancestors.is_some() { TRUC_CHILD_MAX_VSIZE } else { TRUC_MAX_VSIZE };
+            let vsize = weight.to_vbytes_ceil() as usize;
+            if vsize > max {
+                return Err(BuildError::TrucTooBig { vsize, max });
+            }
+        }
+        if let (Some(value), Some((script_pubkey, _))) = (change, self.change) {
+            outputs.push(TxOut { value: value.to_sat(), script_pubkey });
+        }
+
+        let lock_time = match self.lock_time {
+            LockTimePolicy::Fixed(lock_time) => lock_time,
+            LockTimePolicy::AntiFeeSniping(tip_height) =>
+                anti_fee_sniping::lock_time(tip_height, rng),
+        };
+        let sequence = if self.rbf {
+            Sequence::ENABLE_RBF_NO_LOCKTIME
+        } else {
+            Sequence::ENABLE_LOCKTIME_NO_RBF
+        };
+
+        let input = selected
+            .iter()
+            .map(|utxo| TxIn {
+                previous_output: utxo.outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence,
+                witness: Witness::new(),
+            })
+            .collect();
+        let tx = Transaction { version: self.version, lock_time, input, output: outputs };
+
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx)
+            .expect("scriptSigs and witnesses are empty");
+        for (input, utxo) in psbt.inputs.iter_mut().zip(selected) {
+            *input = utxo.into_psbt_input();
+        }
+        if self.sort_bip69 {
+            psbt.sort_bip69();
+        }
+        Ok(psbt)
+    }
+}
+
+/// Returns the change policy adding all the excess to the fee.
+fn no_change_policy() -> ChangePolicy {
+    ChangePolicy {
+        output_weight: Weight::ZERO,
+        spend_weight: Weight::ZERO,
+        min_value: Amount::MAX_MONEY,
+    }
+}
+
+impl PartiallySignedTransaction {
+    /// Creates a replacement of this PSBT paying `fee_rate`, like the `bumpfee` RPC of Bitcoin
+    /// Core.
+    ///
+    /// `satisfaction_weights` are the predicted weights of the scriptSigs and witnesses of the
+    /// inputs and `change` the index of the change output together with the predicted weight of
+    /// spending it. The fee is paid by shrinking the change output first. If that leaves less than
+    /// its dust value the change output is removed, and if the inputs don't cover the fee after
+    /// that, inputs are added from `utxos` using [`select_coins`]. The `utxos` must be confirmed,
+    /// BIP 125 forbids adding unconfirmed inputs. Without a change output the excess of the added
+    /// inputs is added to the fee.
+    ///
+    /// `fee_rate` has to exceed the feerate of this PSBT by [`DEFAULT_INCREMENTAL_RELAY_FEE`] and
+    /// the fee of the replacement pays for its relay at that feerate as well, so it can replace
+    /// this transaction if it has no descendants, see [`check_replacement`].
+    ///
+    /// Signatures, final scriptSigs and final witnesses are removed from the inputs of the
+    /// replacement.
+    ///
+    /// [`check_replacement`]: crate::policy::rbf::check_replacement
+    pub fn bump_fee<R: Rng + ?Sized>(
+        &self,
+        fee_rate: FeeRate,
+        satisfaction_weights: &[InputWeightPrediction],
+        change: Option<(usize, InputWeightPrediction)>,
+        utxos: &[Utxo],
+        rng: &mut R,
+    ) -> Result<Self, FeeBumpError> {
+        if satisfaction_weights.len() != self.unsigned_tx.input.len() {
+            return Err(FeeBumpError::SatisfactionWeightsMismatch {
+                inputs: self.unsigned_tx.input.len(),
+                weights: satisfaction_weights.len(),
+            });
+        }
+        if let Some((index, _)) = change {
+            if index >= self.unsigned_tx.output.len() {
+                return Err(FeeBumpError::ChangeIndexOutOfBounds(index));
+            }
+        }
+
+        let fee = self.fee().map_err(FeeBumpError::Psbt)?;
+        let weight = |inputs: &[InputWeightPrediction], outputs: &[TxOut]| {
+            let output_lens = outputs.iter().map(|txout| txout.script_pubkey.len());
+            predict_weight(inputs.iter().cloned(), output_lens)
+        };
+        let original_weight = weight(satisfaction_weights, &self.unsigned_tx.output);
+        // The feerate has to increase by at least the incremental relay feerate.
+        let incremental_fee_rate =
+            FeeRate::from_sat_per_kwu(DEFAULT_INCREMENTAL_RELAY_FEE as u64 / 4);
+        let original_fee_rate = FeeRate::from_sat_per_kwu(
+            (fee.to_sat() * 1000 + original_weight.to_wu() - 1) / original_weight.to_wu(),
+        );
+        let minimum = FeeRate::from_sat_per_kwu(
+            original_fee_rate.to_sat_per_kwu() + incremental_fee_rate.to_sat_per_kwu(),
+        );
+        if fee_rate < minimum {
+            return Err(FeeBumpError::FeeRateTooLow { minimum });
+        }
+        // The replacement pays for its own relay on top of the original fee.
+        let required_fee =
+            |weight: Weight| cmp::max(fee_rate * weight, fee + incremental_fee_rate * weight);
+
+        let mut inputs = satisfaction_weights.to_vec();
+        let mut outputs = self.unsigned_tx.output.clone();
+        let mut output_maps = self.outputs.clone();
+        let mut input_value = fee + outputs.iter().map(|txout| Amount::from_sat(txout.value)).sum();
+
+        let mut removed_change = None;
+        if let Some((index, _)) = change {
+            let others = input_value - fee - Amount::from_sat(outputs[index].value);
+            let value = input_value.checked_sub(others + required_fee(weight(&inputs, &outputs)));
+            match value {
+                Some(value) if value >= outputs[index].script_pubkey.dust_value() =>
+                    outputs[index].value = value.to_sat(),
+                _ => removed_change = Some((outputs.remove(index), output_maps.remove(index))),
+            }
+        }
+
+        let mut new_inputs = Vec::new();
+        let output_value: Amount = outputs.iter().map(|txout| Amount::from_sat(txout.value)).sum();
+        let shortfall = (output_value + required_fee(weight(&inputs, &outputs)))
+            .checked_sub(input_value)
+            .filter(|shortfall| *shortfall > Amount::ZERO);
+        if let Some(shortfall) = shortfall {
+            for utxo in utxos {
+                utxo.check().map_err(FeeBumpError::Build)?;
+            }
+            let candidates = utxos
+                .iter()
+                .map(|utxo| {
+                    Candidate::new(Amount::from_sat(utxo.txout.value), utxo.satisfaction_weight)
+                })
+                .collect::<Vec<_>>();
+            let change_policy = match (change, &removed_change) {
+                (Some((_, spend_weight)), Some((txout, _))) =>
+                    ChangePolicy::new(&txout.script_pubkey, spend_weight),
+                _ => no_change_policy(),
+            };
+            let params = Params {
+                target: shortfall,
+                base_weight: Weight::ZERO,
+                fee_rate,
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
+                change_policy,
+            };
+            let selection = select_coins(&candidates, &params, rng)
+                .map_err(|e| FeeBumpError::Build(BuildError::CoinSelection(e)))?;
+            for &index in &selection.selected {
+                let utxo = &utxos[index];
+                input_value += Amount::from_sat(utxo.txout.value);
+                inputs.push(utxo.satisfaction_weight);
+                new_inputs.push(utxo.clone());
+            }
+            if let (Some(value), Some((index, _))) = (selection.change, change) {
+                let (mut txout, output) = removed_change.take().expect("change policy is set");
+                txout.value = value.to_sat();
+                outputs.insert(index, txout);
+                output_maps.insert(index, output);
+            }
+        }
+
+        // Coin selection doesn't account for the segwit marker and input count varints.
+        let output_value: Amount = outputs.iter().map(|txout| Amount::from_sat(txout.value)).sum();
+        let required = output_value + required_fee(weight(&inputs, &outputs));
+        if input_value < required {
+            let e = coin_selection::Error::InsufficientFunds { available: input_value, required };
+            return Err(FeeBumpError::Build(BuildError::CoinSelection(e)));
+        }
+
+        let mut psbt = self.clone();
+        psbt.unsigned_tx.output = outputs;
+        psbt.outputs = output_maps;
+        for input in &mut psbt.inputs {
+            input.partial_sigs.clear();
+            input.final_script_sig = None;
+            input.final_script_witness = None;
+            input.tap_key_sig = None;
+            input.tap_script_sigs.clear();
+            input.musig2_pub_nonces.clear();
+            input.musig2_partial_sigs.clear();
+        }
+        for utxo in new_inputs {
+            psbt.unsigned_tx.input.push(TxIn {
+                previous_output: utxo.outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            });
+            psbt.inputs.push(utxo.into_psbt_input());
+        }
+        Ok(psbt)
+    }
+}
+
+/// Error building a PSBT with [`TransactionBuilder`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BuildError {
+    /// The transaction has no outputs.
+    NoOutputs,
+    /// A non-segwit output is spent but its previous transaction is missing.
+    MissingPreviousTx(OutPoint),
+    /// The previous transaction doesn't contain the unspent output.
+    PreviousTxMismatch(OutPoint),
+    /// Coin selection failed.
+    CoinSelection(coin_selection::Error),
+    /// The predicted virtual size of a TRUC transaction exceeds the limit.
+    TrucTooBig {
+        /// The predicted virtual size.
+        vsize: usize,
+        /// The maximum virtual size, depending on whether the transaction has unconfirmed
+        /// ancestors.
+        max: usize,
+    },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::BuildError::*;
+
+        match *self {
+            NoOutputs => f.write_str("transaction has no outputs"),
+            MissingPreviousTx(ref outpoint) =>
+                write!(f, "missing previous transaction of non-segwit output {}", outpoint),
+            PreviousTxMismatch(ref outpoint) =>
+                write!(f, "previous transaction doesn't contain the output {}", outpoint),
+            CoinSelection(ref e) => write_err!(f, "coin selection failed"; e),
+            TrucTooBig { vsize, max } =>
+                write!(f, "version 3 transaction is too big: {} > {} virtual bytes", vsize, max),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+impl std::error::Error for BuildError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use self::BuildError::*;
+
+        match *self {
+            NoOutputs | MissingPreviousTx(_) | PreviousTxMismatch(_) | TrucTooBig { .. } => None,
+            CoinSelection(ref e) => Some(e),
+        }
+    }
+}
+
+impl From<coin_selection::Error> for BuildError {
+    fn from(e: coin_selection::Error) -> Self { BuildError::CoinSelection(e) }
+}
+
+/// Error bumping the fee of a PSBT with [`PartiallySignedTransaction::bump_fee`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum FeeBumpError {
+    /// The fee of the PSBT can't be computed.
+    Psbt(Error),
+    /// The number of satisfaction weights doesn't match the number of inputs.
+    SatisfactionWeightsMismatch {
+        /// The number of inputs.
+        inputs: usize,
+        /// The number of satisfaction weights.
+        weights: usize,
+    },
+    /// The change output index is out of bounds.
+    ChangeIndexOutOfBounds(usize),
+    /// The feerate doesn't exceed the feerate of the PSBT by the incremental relay feerate.
+    FeeRateTooLow {
+        /// The lowest feerate of the replacement.
+        minimum: FeeRate,
+    },
+    /// Adding inputs failed.
+    Build(BuildError),
+}
+
+impl fmt::Display for FeeBumpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::FeeBumpError::*;
+
+        match *self {
+            Psbt(ref e) => write_err!(f, "failed to compute the fee of the PSBT"; e),
+            SatisfactionWeightsMismatch { inputs, weights } =>
+                write!(f, "{} satisfaction weights for {} inputs", weights, inputs),
+            ChangeIndexOutOfBounds(index) =>
+                write!(f, "change output index {} is out of bounds", index),
+            FeeRateTooLow { minimum } =>
+                write!(f, "feerate too low, at least {} sat/kwu required", minimum.to_sat_per_kwu()),
+            Build(ref e) => write_err!(f, "failed to add inputs"; e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+impl std::error::Error for FeeBumpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use self::FeeBumpError::*;
+
+        match *self {
+            Psbt(ref e) => Some(e),
+            SatisfactionWeightsMismatch { .. }
+            | ChangeIndexOutOfBounds(_)
+            | FeeRateTooLow { .. } => None,
+            Build(ref e) => Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockdata::locktime::absolute::{Height, LockTime};
+    use crate::coin_selection::tests::TestRng;
+    use crate::hash_types::{PubkeyHash, Txid, WPubkeyHash};
+    use crate::hashes::Hash;
+    use crate::policy::package::{Entry, TxGraph};
+    use crate::policy::rbf::{check_replacement, Original};
+    use crate::policy::truc::check_truc_package;
+
+    fn p2wpkh_utxo(vout: u32, value: u64) -> Utxo {
+        Utxo::new(
+            OutPoint::new(Txid::all_zeros(), vout),
+            TxOut { value, script_pubkey: ScriptBuf::new_v0_p2wpkh(&WPubkeyHash::all_zeros()) },
+            InputWeightPrediction::P2WPKH_MAX,
+        )
+    }
+
+    fn recipient(value: u64) -> TxOut {
+        TxOut { value, script_pubkey: ScriptBuf::new_v0_p2wpkh(&WPubkeyHash::hash(&[1])) }
+    }
+
+    fn change_script() -> ScriptBuf { ScriptBuf::new_v0_p2wpkh(&WPubkeyHash::hash(&[2])) }
+
+    #[test]
+    fn build() {
+        let fee_rate = FeeRate::from_sat_per_vb_unchecked(10);
+        let psbt = TransactionBuilder::new(fee_rate)
+            .add_utxos(vec![p2wpkh_utxo(0, 20_000), p2wpkh_utxo(1, 50_000)])
+            .add_output(recipient(30_000))
+            .change(change_script(), InputWeightPrediction::P2WPKH_MAX)
+            // Spending fewer inputs is less wasteful at a higher feerate than the long term one.
+            .long_term_fee_rate(FeeRate::from_sat_per_vb_unchecked(1))
+            .build(&mut TestRng::new())
+            .unwrap();
+
+        let tx = &psbt.unsigned_tx;
+        assert_eq!(tx.version, 2);
+        assert_eq!(tx.lock_time, LockTime::ZERO);
+        assert_eq!(tx.input.len(), 1);
+        assert_eq!(tx.input[0].previous_output.vout, 1);
+        assert_eq!(tx.input[0].sequence, Sequence::ENABLE_RBF_NO_LOCKTIME);
+        assert_eq!(psbt.inputs[0].witness_utxo, Some(p2wpkh_utxo(1, 50_000).txout));
+        assert_eq!(tx.output[0], recipient(30_000));
+        assert_eq!(tx.output[1].script_pubkey, change_script());
+
+        let weight = predict_weight(vec![InputWeightPrediction::P2WPKH_MAX], vec![22, 22]);
+        let fee = psbt.fee().unwrap();
+        assert_eq!(fee, fee_rate * weight);
+        assert_eq!(tx.output[1].value, 20_000 - fee.to_sat());
+    }
+
+    #[test]
+    fn no_change() {
+        let psbt = TransactionBuilder::new(FeeRate::from_sat_per_vb_unchecked(1))
+            .add_utxo(p2wpkh_utxo(0, 30_500))
+            .add_output(recipient(30_000))
+            .build(&mut TestRng::new())
+            .unwrap();
+        assert_eq!(psbt.unsigned_tx.output.len(), 1);
+        assert_eq!(psbt.fee().unwrap(), Amount::from_sat(500));
+
+        let err = TransactionBuilder::new(FeeRate::from_sat_per_vb_unchecked(1))
+            .add_utxo(p2wpkh_utxo(0, 30_000))
+            .add_output(recipient(30_000))
+            .build(&mut TestRng::new())
+            .unwrap_err();
+        assert!(match err {
+            BuildError::CoinSelection(coin_selection::Error::InsufficientFunds { .. }) => true,
+            _ => false,
+        });
+
+        let err = TransactionBuilder::new(FeeRate::ZERO).build(&mut TestRng::new()).unwrap_err();
+        assert_eq!(err, BuildError::NoOutputs);
+    }
+
+    #[test]
+    fn previous_tx() {
+        let previous_tx = Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: 50_000,
+                script_pubkey: ScriptBuf::new_p2pkh(&PubkeyHash::all_zeros()),
+            }],
+        };
+        let satisfaction_weight = InputWeightPrediction::new(107, None::<usize>);
+        let utxo = Utxo::new(
+            OutPoint::new(previous_tx.txid(), 0),
+            previous_tx.output[0].clone(),
+            satisfaction_weight,
+        );
+        let builder = TransactionBuilder::new(FeeRate::from_sat_per_vb_unchecked(1))
+            .add_output(recipient(30_000));
+
+        let err = builder.clone().add_utxo(utxo.clone()).build(&mut TestRng::new()).unwrap_err();
+        assert_eq!(err, BuildError::MissingPreviousTx(utxo.outpoint));
+
+        let mut mismatch = utxo.clone();
+        mismatch.previous_tx = Some(previous_tx.clone());
+        mismatch.outpoint.vout = 1;
+        let err = builder.clone().add_utxo(mismatch).build(&mut TestRng::new()).unwrap_err();
+        assert_eq!(err, BuildError::PreviousTxMismatch(OutPoint::new(previous_tx.txid(), 1)));
+
+        let utxo = Utxo::from_previous_tx(previous_tx.clone(), 0, satisfaction_weight).unwrap();
+        let psbt = builder.add_utxo(utxo).build(&mut TestRng::new()).unwrap();
+        assert_eq!(psbt.inputs[0].witness_utxo, None);
+        assert_eq!(psbt.inputs[0].non_witness_utxo, Some(previous_tx));
+        assert!(psbt.fee().is_ok());
+    }
+
+    #[test]
+    fn lock_time() {
+        let tip = Height::from_consensus(800_000).unwrap();
+        let mut rng = TestRng::new();
+        for _ in 0..100 {
+            let psbt = TransactionBuilder::new(FeeRate::from_sat_per_vb_unchecked(1))
+                .add_utxo(p2wpkh_utxo(0, 50_000))
+                .add_output(recipient(30_000))
+                .rbf(false)
+                .anti_fee_sniping(tip)
+                .build(&mut rng)
+                .unwrap();
+            let height = psbt.unsigned_tx.lock_time.to_consensus_u32();
+            assert!(height <= 800_000 && height > 800_000 - 100);
+            assert_eq!(psbt.unsigned_tx.input[0].sequence, Sequence::ENABLE_LOCKTIME_NO_RBF);
+        }
+
+        let lock_time = LockTime::from_height(700_000).unwrap();
+        let psbt = TransactionBuilder::new(FeeRate::from_sat_per_vb_unchecked(1))
+            .add_utxo(p2wpkh_utxo(0, 50_000))
+            .add_output(recipient(30_000))
+            .lock_time(lock_time)
+            .build(&mut rng)
+            .unwrap();
+        assert_eq!(psbt.unsigned_tx.lock_time, lock_time);
+    }
+
+    #[test]
+    fn bip69() {
+        let mut utxos = Vec::new();
+        for (i, value) in [30_000, 10_000, 20_000].iter().enumerate() {
+            let mut utxo = p2wpkh_utxo(0, *value);
+            utxo.outpoint.txid = Txid::hash(&[i as u8]);
+            utxos.push(utxo);
+        }
+        let psbt = TransactionBuilder::new(FeeRate::from_sat_per_vb_unchecked(1))
+            .add_utxos(utxos.clone())
+            .add_output(recipient(59_000))
+            .add_output(recipient(200))
+            .change(change_script(), InputWeightPrediction::P2WPKH_MAX)
+            .sort_bip69(true)
+            .build(&mut TestRng::new())
+            .unwrap();
+
+        let tx = &psbt.unsigned_tx;
+        let mut txids = tx.input.iter().map(|txin| txin.previous_output.txid.to_string());
+        let mut previous = txids.next().unwrap();
+        for txid in txids {
+            assert!(txid > previous);
+            previous = txid;
+        }
+        assert!(tx.output.windows(2).all(|pair| pair[0].value <= pair[1].value));
+        for (txin, input) in tx.input.iter().zip(&psbt.inputs) {
+            let utxo = utxos.iter().find(|utxo| utxo.outpoint == txin.previous_output).unwrap();
+            assert_eq!(input.witness_utxo.as_ref(), Some(&utxo.txout));
+        }
+    }
+
+    #[test]
+    fn truc() {
+        // A parent paying no fee.
+        let psbt = TransactionBuilder::new(FeeRate::ZERO)
+            .add_utxo(p2wpkh_utxo(0, 50_000))
+            .add_output(recipient(40_000))
+            .add_output(TxOut { value: 10_000, script_pubkey: change_script() })
+            .version(3)
+            .build(&mut TestRng::new())
+            .unwrap();
+        assert!(psbt.unsigned_tx.is_truc());
+        assert_eq!(psbt.fee().unwrap(), Amount::ZERO);
+        let parent = psbt.unsigned_tx;
+        let parent_weight = predict_weight(vec![InputWeightPrediction::P2WPKH_MAX], vec![22, 22]);
+
+        // A child spending its output and paying for both.
+        let fee_rate = FeeRate::from_sat_per_vb_unchecked(10);
+        let anchor =
+            Utxo::from_previous_tx(parent.clone(), 1, InputWeightPrediction::P2WPKH_MAX).unwrap();
+        let builder = TransactionBuilder::new(fee_rate)
+            .add_required_utxo(anchor)
+            .add_utxo(p2wpkh_utxo(1, 100_000))
+            .change(change_script(), InputWeightPrediction::P2WPKH_MAX)
+            .pay_for_ancestors(Amount::ZERO, parent_weight)
+            .version(3);
+        let psbt = builder.clone().add_output(recipient(9_000)).build(&mut TestRng::new()).unwrap();
+        let child = &psbt.unsigned_tx;
+        assert_eq!(child.input[0].previous_output, OutPoint::new(parent.txid(), 1));
+        let child_weight = predict_weight(vec![InputWeightPrediction::P2WPKH_MAX; 2], vec![22, 22]);
+        assert_eq!(child.input.len(), 2);
+        assert!(psbt.fee().unwrap() >= fee_rate * (child_weight + parent_weight));
+        let entries = vec![
+            Entry { tx: &parent, fee: Amount::ZERO },
+            Entry { tx: child, fee: psbt.fee().unwrap() },
+        ];
+        assert_eq!(check_truc_package(&TxGraph::new(entries).unwrap()), Ok(()));
+
+        // The required input pays for everything.
+        let psbt = builder.clone().add_output(recipient(2_000)).build(&mut TestRng::new()).unwrap();
+        assert_eq!(psbt.unsigned_tx.input.len(), 1);
+        assert_eq!(psbt.unsigned_tx.output.len(), 2);
+
+        let builder = (0..40).fold(builder, |builder, _| builder.add_output(recipient(1_000)));
+        let err = builder.build(&mut TestRng::new());
+        assert!(match err {
+            Err(BuildError::TrucTooBig { vsize, max: TRUC_CHILD_MAX_VSIZE }) => vsize > 1_000,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn bump_fee() {
+        let mut rng = TestRng::new();
+        let psbt = TransactionBuilder::new(FeeRate::from_sat_per_vb_unchecked(2))
+            .add_utxo(p2wpkh_utxo(0, 50_000))
+            .add_output(recipient(30_000))
+            .change(change_script(), InputWeightPrediction::P2WPKH_MAX)
+            .build(&mut rng)
+            .unwrap();
+        let weights = [InputWeightPrediction::P2WPKH_MAX];
+        let change = Some((1, InputWeightPrediction::P2WPKH_MAX));
+        let utxos = [p2wpkh_utxo(1, 100_000)];
+
+        // Shrinking the change output.
+        let fee_rate = FeeRate::from_sat_per_vb_unchecked(10);
+        let bumped = psbt.bump_fee(fee_rate, &weights, change, &utxos, &mut rng).unwrap();
+        assert_eq!(bumped.unsigned_tx.input, psbt.unsigned_tx.input);
+        assert_eq!(bumped.inputs, psbt.inputs);
+        assert_eq!(bumped.unsigned_tx.output[0], recipient(30_000));
+        let weight = predict_weight(weights.iter().cloned(), vec![22, 22]);
+        assert_eq!(bumped.fee().unwrap(), fee_rate * weight);
+        let original = Original { tx: &psbt.unsigned_tx, fee: psbt.fee().unwrap() };
+        assert_eq!(
+            check_replacement(&bumped.unsigned_tx, bumped.fee().unwrap(), &[original], &[], |_| {
+                false
+            }),
+            Ok(())
+        );
+
+        // The change would be dust, it is added to the fee.
+        let fee_rate = FeeRate::from_sat_per_vb_unchecked(142);
+        let bumped = psbt.bump_fee(fee_rate, &weights, change, &utxos, &mut rng).unwrap();
+        assert_eq!(bumped.unsigned_tx.output, [recipient(30_000)]);
+        assert_eq!(bumped.outputs.len(), 1);
+        assert_eq!(bumped.fee().unwrap(), Amount::from_sat(20_000));
+
+        // Adding an input.
+        let fee_rate = FeeRate::from_sat_per_vb_unchecked(200);
+        let bumped = psbt.bump_fee(fee_rate, &weights, change, &utxos, &mut rng).unwrap();
+        assert_eq!(bumped.unsigned_tx.input.len(), 2);
+        assert_eq!(bumped.unsigned_tx.input[1].previous_output, utxos[0].outpoint);
+        assert_eq!(bumped.unsigned_tx.input[1].sequence, Sequence::ENABLE_RBF_NO_LOCKTIME);
+        assert_eq!(bumped.inputs[1].witness_utxo, Some(utxos[0].txout.clone()));
+        assert_eq!(bumped.unsigned_tx.output[1].script_pubkey, change_script());
+        let weight = predict_weight(vec![InputWeightPrediction::P2WPKH_MAX; 2], vec![22, 22]);
+        assert_eq!(bumped.fee().unwrap(), fee_rate * weight);
+
+        let err = psbt.bump_fee(fee_rate, &weights, change, &[], &mut rng).unwrap_err();
+        assert!(match err {
+            FeeBumpError::Build(BuildError::CoinSelection(_)) => true,
+            _ => false,
+        });
+        let fee_rate = FeeRate::from_sat_per_vb_unchecked(2);
+        let err = psbt.bump_fee(fee_rate, &weights, change, &utxos, &mut rng).unwrap_err();
+        assert!(match err {
+            FeeBumpError::FeeRateTooLow { minimum } => minimum > fee_rate,
+            _ => false,
+        });
+        let err = psbt.bump_fee(fee_rate, &[], change, &utxos, &mut rng).unwrap_err();
+        assert!(match err {
+            FeeBumpError::SatisfactionWeightsMismatch { inputs: 1, weights: 0 } => true,
+            _ => false,
+        });
+    }
+}
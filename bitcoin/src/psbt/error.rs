@@ -5,6 +5,7 @@ use core::fmt;
 use bitcoin_internals::write_err;
 
 use crate::bip32::ExtendedPubKey;
+use crate::blockdata::locktime::absolute;
 use crate::blockdata::transaction::Transaction;
 use crate::consensus::encode;
 use crate::prelude::*;
@@ -90,6 +91,8 @@ pub enum Error {
     InvalidControlBlock,
     /// Parsing error indicating invalid leaf version
     InvalidLeafVersion,
+    /// Parsing error indicating invalid MuSig2 nonces or partial signatures
+    InvalidMusig2Data(crate::crypto::musig2::Error),
     /// Parsing error indicating a taproot error
     Taproot(&'static str),
     /// Taproot tree deserilaization error
@@ -100,6 +103,16 @@ pub enum Error {
     Version(&'static str),
     /// PSBT data is not consumed entirely
     PartialDataConsumption,
+    /// Parsing error indicating an invalid required lock time
+    LockTime(absolute::Error),
+    /// The inputs of a version 2 PSBT require lock times of different units
+    IncompatibleLockTimes,
+    /// Attempting to add an input to a PSBT whose inputs are not modifiable
+    InputsNotModifiable,
+    /// Attempting to add an output to a PSBT whose outputs are not modifiable
+    OutputsNotModifiable,
+    /// A version 2 PSBT declares a transaction version less than 2
+    InvalidTxVersion(i32),
     /// I/O error.
     Io(io::Error),
 }
@@ -150,12 +163,20 @@ impl fmt::Display for Error {
             Error::InvalidTaprootSignature(ref e) => write_err!(f, "invalid taproot signature"; e),
             Error::InvalidControlBlock => f.write_str("invalid control block"),
             Error::InvalidLeafVersion => f.write_str("invalid leaf version"),
+            Error::InvalidMusig2Data(ref e) => write_err!(f, "invalid MuSig2 data"; e),
             Error::Taproot(s) => write!(f, "taproot error -  {}", s),
             Error::TapTree(ref e) => write_err!(f, "taproot tree error"; e),
             Error::XPubKey(s) => write!(f, "xpub key error -  {}", s),
             Error::Version(s) => write!(f, "version error {}", s),
             Error::PartialDataConsumption =>
                 f.write_str("data not consumed entirely when explicitly deserializing"),
+            Error::LockTime(ref e) => write_err!(f, "invalid required lock time"; e),
+            Error::IncompatibleLockTimes =>
+                f.write_str("inputs require lock times of incompatible units"),
+            Error::InputsNotModifiable => f.write_str("the PSBT inputs are not modifiable"),
+            Error::OutputsNotModifiable => f.write_str("the PSBT outputs are not modifiable"),
+            Error::InvalidTxVersion(v) =>
+                write!(f, "version 2 PSBTs require a transaction version of at least 2, got {}", v),
             Error::Io(ref e) => write_err!(f, "I/O error"; e),
         }
     }
@@ -171,6 +192,8 @@ impl std::error::Error for Error {
             HashParse(e) => Some(e),
             ConsensusEncoding(e) => Some(e),
             Io(e) => Some(e),
+            LockTime(e) => Some(e),
+            InvalidMusig2Data(e) => Some(e),
             InvalidMagic
             | MissingUtxo
             | InvalidSeparator
@@ -199,7 +222,11 @@ impl std::error::Error for Error {
             | TapTree(_)
             | XPubKey(_)
             | Version(_)
-            | PartialDataConsumption => None,
+            | PartialDataConsumption
+            | IncompatibleLockTimes
+            | InputsNotModifiable
+            | OutputsNotModifiable
+            | InvalidTxVersion(_) => None,
         }
     }
 }
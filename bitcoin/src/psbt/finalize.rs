@@ -0,0 +1,657 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! PSBT input finalization.
+//!
+//! Implementation of the Input Finalizer role described in BIP 174 for the standard output types.
+//! Arbitrary scripts are not supported, for those use a miniscript aware finalizer.
+//!
+
+use core::convert::TryFrom;
+use core::{cmp, fmt};
+
+use secp256k1::XOnlyPublicKey;
+
+use crate::blockdata::constants::MAX_SCRIPT_ELEMENT_SIZE;
+use crate::blockdata::opcodes::all::*;
+use crate::blockdata::opcodes::{self, Class, ClassifyContext};
+use crate::blockdata::script::{self, Instruction, PushBytes, Script, ScriptBuf};
+use crate::blockdata::witness::Witness;
+use crate::crypto::key::PublicKey;
+use crate::prelude::*;
+use crate::psbt::{Input, PartiallySignedTransaction};
+use crate::taproot::{LeafVersion, TapLeafHash};
+
+/// Map of input index -> the error encountered while attempting to finalize that input.
+pub type FinalizeErrors = BTreeMap<usize, FinalizeError>;
+
+impl PartiallySignedTransaction {
+    /// Finalizes all inputs of this PSBT, see [`Self::finalize_input`].
+    ///
+    /// Inputs that can be finalized are finalized even if finalizing some other input fails.
+    ///
+    /// # Errors
+    ///
+    /// A map of input index -> the error encountered while attempting to finalize that input.
+    pub fn finalize_mut(&mut self) -> Result<(), FinalizeErrors> {
+        let mut errors = BTreeMap::new();
+
+        for i in 0..self.inputs.len() {
+            if let Err(e) = self.finalize_input(i) {
+                errors.insert(i, e);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Finalizes the input at `input_index` by constructing its `final_script_sig` and
+    /// `final_script_witness` from the signatures and scripts present in the input.
+    ///
+    /// The supported output types are:
+    ///
+    /// - P2PK, P2PKH and bare `OP_CHECKMULTISIG`.
+    /// - P2SH, P2WSH and P2SH-P2WSH wrapping any of the above.
+    /// - P2WPKH and P2SH-P2WPKH.
+    /// - P2TR key path spends and script path spends of a single leaf that is either
+    ///   `<key> OP_CHECKSIG` or a `OP_CHECKSIGADD` based multisig. If more than one leaf can be
+    ///   satisfied the one with the smallest witness is used.
+    /// - P2A, which is spent with an empty witness.
+    ///
+    /// Signatures are not verified. As required by BIP 174 all fields other than the UTXOs, the
+    /// final fields, proprietary and unknown key-value pairs are cleared once the input is
+    /// finalized, the required lock times of version 2 PSBTs are kept as well. Inputs that already
+    /// have a final scriptSig or witness are left untouched.
+    pub fn finalize_input(&mut self, input_index: usize) -> Result<(), FinalizeError> {
+        let len = cmp::min(self.inputs.len(), self.unsigned_tx.input.len());
+        if input_index >= len {
+            return Err(FinalizeError::IndexOutOfBounds(input_index, len));
+        }
+        let input = &self.inputs[input_index];
+        if input.final_script_sig.is_some() || input.final_script_witness.is_some() {
+            return Ok(());
+        }
+        let spk = &self
+            .spend_utxo(input_index)
+            .map_err(|_| FinalizeError::MissingSpendUtxo)?
+            .script_pubkey;
+
+        let (script_sig, witness) = if spk.is_p2sh() {
+            let redeem_script =
+                input.redeem_script.as_ref().ok_or(FinalizeError::MissingRedeemScript)?;
+            if redeem_script.to_p2sh() != *spk {
+                return Err(FinalizeError::RedeemScriptMismatch);
+            }
+            if redeem_script.is_v0_p2wpkh() {
+                (push_redeem_script(redeem_script)?, Some(wpkh_witness(input, redeem_script)?))
+            } else if redeem_script.is_v0_p2wsh() {
+                (push_redeem_script(redeem_script)?, Some(wsh_witness(input, redeem_script)?))
+            } else if redeem_script.is_witness_program() {
+                return Err(FinalizeError::UnsupportedScript);
+            } else {
+                let mut stack = satisfy_legacy(input, redeem_script)?;
+                stack.push(redeem_script.to_bytes());
+                (stack_to_script_sig(&stack)?, None)
+            }
+        } else if spk.is_v0_p2wpkh() {
+            (ScriptBuf::new(), Some(wpkh_witness(input, spk)?))
+        } else if spk.is_v0_p2wsh() {
+            (ScriptBuf::new(), Some(wsh_witness(input, spk)?))
+        } else if spk.is_v1_p2tr() {
+            (ScriptBuf::new(), Some(taproot_witness(input)?))
+        } else if spk.is_p2a() {
+            (ScriptBuf::new(), Some(Witness::new()))
+        } else if spk.is_witness_program() {
+            return Err(FinalizeError::UnsupportedScript);
+        } else {
+            (stack_to_script_sig(&satisfy_legacy(input, spk)?)?, None)
+        };
+
+        let input = &mut self.inputs[input_index]; // Index checked above.
+        *input = Input {
+            non_witness_utxo: input.non_witness_utxo.take(),
+            witness_utxo: input.witness_utxo.take(),
+            final_script_sig: if script_sig.is_empty() { None } else { Some(script_sig) },
+            final_script_witness: witness,
+            required_time_lock_time: input.required_time_lock_time,
+            required_height_lock_time: input.required_height_lock_time,
+            proprietary: core::mem::take(&mut input.proprietary),
+            unknown: core::mem::take(&mut input.unknown),
+            ..Default::default()
+        };
+        Ok(())
+    }
+}
+
+/// Returns the witness for a P2WPKH `program`, either the script pubkey or the redeem script.
+fn wpkh_witness(input: &Input, program: &Script) -> Result<Witness, FinalizeError> {
+    let hash = &program.as_bytes()[2..];
+    let (pk, sig) = input
+        .partial_sigs
+        .iter()
+        .find(|(pk, _)| pk.wpubkey_hash().map(|h| h[..] == *hash).unwrap_or(false))
+        .ok_or(FinalizeError::MissingSignature)?;
+
+    Ok(Witness::from_slice(&[sig.to_vec(), pk.to_bytes()]))
+}
+
+/// Returns the witness for a P2WSH `program`, either the script pubkey or the redeem script.
+fn wsh_witness(input: &Input, program: &Script) -> Result<Witness, FinalizeError> {
+    let witness_script =
+        input.witness_script.as_ref().ok_or(FinalizeError::MissingWitnessScript)?;
+    if witness_script.to_v0_p2wsh() != *program {
+        return Err(FinalizeError::WitnessScriptMismatch);
+    }
+    let mut stack = satisfy_legacy(input, witness_script)?;
+    stack.push(witness_script.to_bytes());
+
+    Ok(Witness::from_slice(&stack))
+}
+
+/// Returns the witness for a taproot input, preferring a key path spend.
+fn taproot_witness(input: &Input) -> Result<Witness, FinalizeError> {
+    if let Some(sig) = input.tap_key_sig {
+        return Ok(Witness::from_slice(&[sig.to_vec()]));
+    }
+
+    let size = |stack: &Vec<Vec<u8>>| stack.iter().map(Vec::len).sum::<usize>();
+    let mut best: Option<Vec<Vec<u8>>> = None;
+    let mut last_error = FinalizeError::MissingTaprootSignature;
+    for (control_block, (script, leaf_version)) in &input.tap_scripts {
+        if *leaf_version != LeafVersion::TapScript {
+            continue;
+        }
+        let leaf_hash = TapLeafHash::from_script(script, *leaf_version);
+        match satisfy_tapscript(input, script, leaf_hash) {
+            Ok(mut stack) => {
+                stack.push(script.to_bytes());
+                stack.push(control_block.serialize());
+                if best.as_ref().map(|b| size(&stack) < size(b)).unwrap_or(true) {
+                    best = Some(stack);
+                }
+            }
+            Err(e) => last_error = e,
+        }
+    }
+
+    best.map(|stack| Witness::from_slice(&stack)).ok_or(last_error)
+}
+
+/// Returns the stack satisfying a P2PK, P2PKH or `OP_CHECKMULTISIG` script.
+fn satisfy_legacy(input: &Input, script: &Script) -> Result<Vec<Vec<u8>>, FinalizeError> {
+    if let Some(pk) = script.p2pk_public_key() {
+        let sig = input.partial_sigs.get(&pk).ok_or(FinalizeError::MissingSignature)?;
+        return Ok(vec![sig.to_vec()]);
+    }
+
+    if script.is_p2pkh() {
+        let hash = &script.as_bytes()[3..23];
+        let (pk, sig) = input
+            .partial_sigs
+            .iter()
+            .find(|(pk, _)| pk.pubkey_hash()[..] == *hash)
+            .ok_or(FinalizeError::MissingSignature)?;
+        return Ok(vec![sig.to_vec(), pk.to_bytes()]);
+    }
+
+    let (required, keys) = parse_multisig(script).ok_or(FinalizeError::UnsupportedScript)?;
+    let sigs =
+        keys.iter().filter_map(|pk| input.partial_sigs.get(pk)).take(required).collect::<Vec<_>>();
+    if sigs.len() < required {
+        return Err(FinalizeError::NotEnoughSignatures { required, available: sigs.len() });
+    }
+
+    // OP_CHECKMULTISIG bug pops one extra element, we satisfy it with an empty push.
+    let mut stack = vec![vec![]];
+    stack.extend(sigs.iter().map(|sig| sig.to_vec()));
+    Ok(stack)
+}
+
+/// Returns the stack satisfying a `<key> OP_CHECKSIG` or `OP_CHECKSIGADD` multisig tapscript.
+fn satisfy_tapscript(
+    input: &Input,
+    script: &Script,
+    leaf_hash: TapLeafHash,
+) -> Result<Vec<Vec<u8>>, FinalizeError> {
+    let (required, keys) = parse_multi_a(script).ok_or(FinalizeError::UnsupportedScript)?;
+
+    // Signatures are consumed in key order, the first key's signature being on top of the stack.
+    let mut stack = Vec::with_capacity(keys.len());
+    let mut available = 0;
+    for pk in keys.iter().rev() {
+        match input.tap_script_sigs.get(&(*pk, leaf_hash)) {
+            Some(sig) if available < required => {
+                stack.push(sig.to_vec());
+                available += 1;
+            }
+            _ => stack.push(vec![]),
+        }
+    }
+    if available < required {
+        return Err(FinalizeError::NotEnoughSignatures { required, available });
+    }
+    Ok(stack)
+}
+
+/// Parses `<m> <pubkey>... <n> OP_CHECKMULTISIG`, returning `m` and the public keys.
+pub(super) fn parse_multisig(script: &Script) -> Option<(usize, Vec<PublicKey>)> {
+    let mut instructions = script.instructions().collect::<Result<Vec<_>, _>>().ok()?;
+    if instructions.pop()?.opcode()? != OP_CHECKMULTISIG {
+        return None;
+    }
+    let n = push_num(instructions.pop()?.opcode()?)?;
+    let m = push_num(instructions.first()?.opcode()?)?;
+
+    let keys = instructions[1..]
+        .iter()
+        .map(|ins| ins.push_bytes().and_then(|b| PublicKey::from_slice(b.as_bytes()).ok()))
+        .collect::<Option<Vec<_>>>()?;
+    if keys.len() != n || m == 0 || m > n {
+        return None;
+    }
+    Some((m, keys))
+}
+
+/// Parses `<key> OP_CHECKSIG` and `<key> OP_CHECKSIG (<key> OP_CHECKSIGADD)* <k> OP_NUMEQUAL`,
+/// returning the number of required signatures and the x-only public keys.
+pub(super) fn parse_multi_a(script: &Script) -> Option<(usize, Vec<XOnlyPublicKey>)> {
+    let instructions = script.instructions().collect::<Result<Vec<_>, _>>().ok()?;
+    let xonly = |ins: &Instruction| {
+        ins.push_bytes().and_then(|b| XOnlyPublicKey::from_slice(b.as_bytes()).ok())
+    };
+
+    if let [key, Instruction::Op(OP_CHECKSIG)] = instructions[..] {
+        return Some((1, vec![xonly(&key)?]));
+    }
+
+    let (threshold, rest) = match instructions.split_last()? {
+        (Instruction::Op(OP_NUMEQUAL), rest) => rest.split_last()?,
+        _ => return None,
+    };
+    let required = match threshold {
+        Instruction::Op(op) => push_num(*op)?,
+        Instruction::PushBytes(b) =>
+            usize::try_from(script::read_scriptint(b.as_bytes()).ok()?).ok()?,
+    };
+
+    let mut keys = Vec::with_capacity(rest.len() / 2);
+    for (i, pair) in rest.chunks(2).enumerate() {
+        let expected = if i == 0 { OP_CHECKSIG } else { OP_CHECKSIGADD };
+        match pair {
+            [key, Instruction::Op(op)] if *op == expected => keys.push(xonly(key)?),
+            _ => return None,
+        }
+    }
+    if required == 0 || required > keys.len() {
+        return None;
+    }
+    Some((required, keys))
+}
+
+/// Returns the value pushed by `OP_PUSHNUM_1` through `OP_PUSHNUM_16`.
+fn push_num(op: opcodes::All) -> Option<usize> {
+    match op.classify(ClassifyContext::Legacy) {
+        Class::PushNum(n) if n > 0 => Some(n as usize),
+        _ => None,
+    }
+}
+
+/// Builds a push only scriptSig from `stack`.
+fn stack_to_script_sig(stack: &[Vec<u8>]) -> Result<ScriptBuf, FinalizeError> {
+    let mut builder = script::Builder::new();
+    for item in stack {
+        if item.len() > MAX_SCRIPT_ELEMENT_SIZE {
+            return Err(FinalizeError::ScriptTooLarge);
+        }
+        let push = <&PushBytes>::try_from(item.as_slice()).expect("checked length above");
+        builder = builder.push_slice(push);
+    }
+    Ok(builder.into_script())
+}
+
+/// Builds the scriptSig for a P2SH wrapped segwit input.
+fn push_redeem_script(redeem_script: &Script) -> Result<ScriptBuf, FinalizeError> {
+    stack_to_script_sig(&[redeem_script.to_bytes()])
+}
+
+/// Errors encountered while finalizing a PSBT input.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+#[non_exhaustive]
+pub enum FinalizeError {
+    /// Input index out of bounds (actual index, maximum index allowed).
+    IndexOutOfBounds(usize, usize),
+    /// Missing spending utxo.
+    MissingSpendUtxo,
+    /// Missing redeem script.
+    MissingRedeemScript,
+    /// Missing witness script.
+    MissingWitnessScript,
+    /// The redeem script does not hash to the spent P2SH script pubkey.
+    RedeemScriptMismatch,
+    /// The witness script does not hash to the spent P2WSH witness program.
+    WitnessScriptMismatch,
+    /// No signature is available for the public key in the script.
+    MissingSignature,
+    /// Not enough signatures are available to satisfy a multisig script.
+    NotEnoughSignatures {
+        /// Number of signatures required by the script.
+        required: usize,
+        /// Number of signatures available in the input.
+        available: usize,
+    },
+    /// A taproot input has neither a key path signature nor a satisfiable leaf script.
+    MissingTaprootSignature,
+    /// A script element is larger than `MAX_SCRIPT_ELEMENT_SIZE`.
+    ScriptTooLarge,
+    /// The spent script is not one of the supported standard templates.
+    UnsupportedScript,
+}
+
+impl fmt::Display for FinalizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::FinalizeError::*;
+
+        match *self {
+            IndexOutOfBounds(ind, len) => write!(f, "index {}, psbt input len: {}", ind, len),
+            MissingSpendUtxo => f.write_str("missing spend utxo in PSBT"),
+            MissingRedeemScript => f.write_str("missing redeem script"),
+            MissingWitnessScript => f.write_str("missing witness script"),
+            RedeemScriptMismatch =>
+                f.write_str("redeem script does not match the script pubkey of the spend utxo"),
+            WitnessScriptMismatch =>
+                f.write_str("witness script does not match the witness program of the spend utxo"),
+            MissingSignature => f.write_str("missing signature"),
+            NotEnoughSignatures { required, available } =>
+                write!(f, "not enough signatures, required: {} available: {}", required, available),
+            MissingTaprootSignature =>
+                f.write_str("no taproot key path signature or satisfiable script path"),
+            ScriptTooLarge => f.write_str("script element exceeds the maximum push size"),
+            UnsupportedScript => f.write_str("finalizing this script is not supported"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+impl std::error::Error for FinalizeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use self::FinalizeError::*;
+
+        match *self {
+            IndexOutOfBounds(_, _)
+            | MissingSpendUtxo
+            | MissingRedeemScript
+            | MissingWitnessScript
+            | RedeemScriptMismatch
+            | WitnessScriptMismatch
+            | MissingSignature
+            | NotEnoughSignatures { .. }
+            | MissingTaprootSignature
+            | ScriptTooLarge
+            | UnsupportedScript => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::{KeyPair, Message, Secp256k1, SecretKey};
+
+    use super::*;
+    use crate::blockdata::locktime::absolute;
+    use crate::blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
+    use crate::crypto::{ecdsa, taproot};
+    use crate::sighash::TapSighashType;
+    use crate::taproot::TaprootBuilder;
+
+    fn psbt_spending(script_pubkey: ScriptBuf) -> PartiallySignedTransaction {
+        let tx = Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![TxOut::default()],
+        };
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut { value: 10_000, script_pubkey });
+        psbt
+    }
+
+    fn keys(n: u8) -> Vec<(PublicKey, ecdsa::Signature)> {
+        let secp = Secp256k1::new();
+        let msg = Message::from_slice(&[0xab; 32]).unwrap();
+        (1..=n)
+            .map(|i| {
+                let sk = SecretKey::from_slice(&[i; 32]).unwrap();
+                let pk = PublicKey::new(sk.public_key(&secp));
+                (pk, ecdsa::Signature::sighash_all(secp.sign_ecdsa(&msg, &sk)))
+            })
+            .collect()
+    }
+
+    fn xonly_keys(n: u8) -> Vec<(XOnlyPublicKey, taproot::Signature)> {
+        let secp = Secp256k1::new();
+        let msg = Message::from_slice(&[0xab; 32]).unwrap();
+        (1..=n)
+            .map(|i| {
+                let keypair = KeyPair::from_seckey_slice(&secp, &[i; 32]).unwrap();
+                let sig = secp.sign_schnorr_no_aux_rand(&msg, &keypair);
+                (
+                    keypair.x_only_public_key().0,
+                    taproot::Signature { sig, hash_ty: TapSighashType::Default },
+                )
+            })
+            .collect()
+    }
+
+    fn multisig(m: i64, keys: &[(PublicKey, ecdsa::Signature)]) -> ScriptBuf {
+        let mut builder = script::Builder::new().push_int(m);
+        for (pk, _) in keys {
+            builder = builder.push_key(pk);
+        }
+        builder.push_int(keys.len() as i64).push_opcode(OP_CHECKMULTISIG).into_script()
+    }
+
+    #[test]
+    fn finalize_p2pkh() {
+        let keys = keys(1);
+        let (pk, sig) = keys[0];
+        let prev_tx = Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: 10_000,
+                script_pubkey: ScriptBuf::new_p2pkh(&pk.pubkey_hash()),
+            }],
+        };
+        let mut psbt = psbt_spending(ScriptBuf::new());
+        psbt.unsigned_tx.input[0].previous_output = OutPoint::new(prev_tx.txid(), 0);
+        psbt.inputs[0].witness_utxo = None;
+        psbt.inputs[0].non_witness_utxo = Some(prev_tx.clone());
+        psbt.inputs[0].partial_sigs.insert(pk, sig);
+
+        psbt.finalize_mut().unwrap();
+
+        let expected =
+            script::Builder::new().push_slice(sig.serialize()).push_key(&pk).into_script();
+        assert_eq!(psbt.inputs[0].final_script_sig, Some(expected));
+        assert_eq!(psbt.inputs[0].final_script_witness, None);
+        assert_eq!(psbt.inputs[0].non_witness_utxo, Some(prev_tx));
+        assert!(psbt.inputs[0].partial_sigs.is_empty());
+    }
+
+    #[test]
+    fn finalize_p2sh_p2wpkh() {
+        let keys = keys(1);
+        let (pk, sig) = keys[0];
+        let redeem_script = ScriptBuf::new_v0_p2wpkh(&pk.wpubkey_hash().unwrap());
+        let mut psbt = psbt_spending(redeem_script.to_p2sh());
+        psbt.inputs[0].redeem_script = Some(redeem_script.clone());
+        psbt.inputs[0].partial_sigs.insert(pk, sig);
+
+        psbt.finalize_input(0).unwrap();
+
+        let push = <&PushBytes>::try_from(redeem_script.as_bytes()).unwrap();
+        let expected = script::Builder::new().push_slice(push).into_script();
+        assert_eq!(psbt.inputs[0].final_script_sig, Some(expected));
+        assert_eq!(
+            psbt.inputs[0].final_script_witness,
+            Some(Witness::from_slice(&[sig.to_vec(), pk.to_bytes()]))
+        );
+        assert_eq!(psbt.inputs[0].redeem_script, None);
+    }
+
+    #[test]
+    fn finalize_p2wpkh_missing_signature() {
+        let keys = keys(2);
+        let mut psbt = psbt_spending(ScriptBuf::new_v0_p2wpkh(&keys[0].0.wpubkey_hash().unwrap()));
+        psbt.inputs[0].partial_sigs.insert(keys[1].0, keys[1].1);
+
+        assert_eq!(psbt.finalize_input(0), Err(FinalizeError::MissingSignature));
+        assert_eq!(psbt.inputs[0].partial_sigs.len(), 1);
+    }
+
+    #[test]
+    fn finalize_p2wsh_multisig() {
+        let keys = keys(3);
+        let witness_script = multisig(2, &keys);
+        let mut psbt = psbt_spending(witness_script.to_v0_p2wsh());
+        psbt.inputs[0].witness_script = Some(witness_script.clone());
+        psbt.inputs[0].partial_sigs.insert(keys[2].0, keys[2].1);
+
+        assert_eq!(
+            psbt.finalize_input(0),
+            Err(FinalizeError::NotEnoughSignatures { required: 2, available: 1 })
+        );
+
+        psbt.inputs[0].partial_sigs.insert(keys[0].0, keys[0].1);
+        psbt.finalize_input(0).unwrap();
+
+        let expected = Witness::from_slice(&[
+            vec![],
+            keys[0].1.to_vec(),
+            keys[2].1.to_vec(),
+            witness_script.to_bytes(),
+        ]);
+        assert_eq!(psbt.inputs[0].final_script_sig, None);
+        assert_eq!(psbt.inputs[0].final_script_witness, Some(expected));
+        assert_eq!(psbt.inputs[0].witness_script, None);
+    }
+
+    #[test]
+    fn finalize_witness_script_mismatch() {
+        let keys = keys(2);
+        let mut psbt = psbt_spending(multisig(1, &keys).to_v0_p2wsh());
+        psbt.inputs[0].witness_script = Some(multisig(2, &keys));
+
+        assert_eq!(
+            psbt.finalize_mut(),
+            Err(vec![(0, FinalizeError::WitnessScriptMismatch)].into_iter().collect())
+        );
+    }
+
+    #[test]
+    fn finalize_taproot_key_path() {
+        let secp = Secp256k1::new();
+        let keys = xonly_keys(1);
+        let (internal_key, sig) = keys[0];
+        let mut psbt = psbt_spending(ScriptBuf::new_v1_p2tr(&secp, internal_key, None));
+        psbt.inputs[0].tap_internal_key = Some(internal_key);
+        psbt.inputs[0].tap_key_sig = Some(sig);
+
+        psbt.finalize_input(0).unwrap();
+
+        assert_eq!(psbt.inputs[0].final_script_witness, Some(Witness::from_slice(&[sig.to_vec()])));
+        assert_eq!(psbt.inputs[0].tap_key_sig, None);
+        assert_eq!(psbt.inputs[0].tap_internal_key, None);
+    }
+
+    #[test]
+    fn finalize_p2a() {
+        let mut psbt = psbt_spending(ScriptBuf::new_p2a());
+        psbt.finalize_input(0).unwrap();
+        assert_eq!(psbt.inputs[0].final_script_sig, None);
+        assert_eq!(psbt.inputs[0].final_script_witness, Some(Witness::new()));
+        assert!(psbt.inputs[0].witness_utxo.is_some());
+    }
+
+    #[test]
+    fn finalize_taproot_script_path() {
+        let secp = Secp256k1::new();
+        let keys = xonly_keys(4);
+        let internal_key = keys[0].0;
+
+        let single = script::Builder::new()
+            .push_x_only_key(&keys[1].0)
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let multi_a = script::Builder::new()
+            .push_x_only_key(&keys[2].0)
+            .push_opcode(OP_CHECKSIG)
+            .push_x_only_key(&keys[3].0)
+            .push_opcode(OP_CHECKSIGADD)
+            .push_int(2)
+            .push_opcode(OP_NUMEQUAL)
+            .into_script();
+        let spend_info = TaprootBuilder::new()
+            .add_leaf(1, single.clone())
+            .unwrap()
+            .add_leaf(1, multi_a.clone())
+            .unwrap()
+            .finalize(&secp, internal_key)
+            .unwrap();
+
+        let mut psbt = psbt_spending(ScriptBuf::new_v1_p2tr_tweaked(spend_info.output_key()));
+        for script in &[&single, &multi_a] {
+            let script_ver = ((*script).clone(), LeafVersion::TapScript);
+            let control_block = spend_info.control_block(&script_ver).unwrap();
+            psbt.inputs[0].tap_scripts.insert(control_block, script_ver);
+        }
+        assert_eq!(
+            psbt.finalize_input(0),
+            Err(FinalizeError::NotEnoughSignatures { required: 2, available: 0 })
+        );
+
+        // Only the multisig leaf can be satisfied.
+        let multi_a_hash = TapLeafHash::from_script(&multi_a, LeafVersion::TapScript);
+        psbt.inputs[0].tap_script_sigs.insert((keys[2].0, multi_a_hash), keys[2].1);
+        psbt.inputs[0].tap_script_sigs.insert((keys[3].0, multi_a_hash), keys[3].1);
+        let mut multi_a_psbt = psbt.clone();
+        multi_a_psbt.finalize_input(0).unwrap();
+
+        let control_block =
+            spend_info.control_block(&(multi_a.clone(), LeafVersion::TapScript)).unwrap();
+        let expected = Witness::from_slice(&[
+            keys[3].1.to_vec(),
+            keys[2].1.to_vec(),
+            multi_a.to_bytes(),
+            control_block.serialize(),
+        ]);
+        assert_eq!(multi_a_psbt.inputs[0].final_script_witness, Some(expected));
+
+        // Both leaves can be satisfied, the single key leaf has the smaller witness.
+        let single_hash = TapLeafHash::from_script(&single, LeafVersion::TapScript);
+        psbt.inputs[0].tap_script_sigs.insert((keys[1].0, single_hash), keys[1].1);
+        psbt.finalize_input(0).unwrap();
+
+        let control_block =
+            spend_info.control_block(&(single.clone(), LeafVersion::TapScript)).unwrap();
+        let expected = Witness::from_slice(&[
+            keys[1].1.to_vec(),
+            single.to_bytes(),
+            control_block.serialize(),
+        ]);
+        assert_eq!(psbt.inputs[0].final_script_witness, Some(expected));
+        assert!(psbt.inputs[0].tap_scripts.is_empty());
+        assert!(psbt.inputs[0].tap_script_sigs.is_empty());
+    }
+
+    #[test]
+    fn finalize_index_out_of_bounds() {
+        let mut psbt = psbt_spending(ScriptBuf::new());
+        assert_eq!(psbt.finalize_input(1), Err(FinalizeError::IndexOutOfBounds(1, 1)));
+    }
+}
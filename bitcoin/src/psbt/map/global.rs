@@ -3,40 +3,119 @@
 use core::convert::TryFrom;
 
 use crate::bip32::{ChildNumber, DerivationPath, ExtendedPubKey, Fingerprint};
+use crate::blockdata::locktime::absolute;
 use crate::blockdata::transaction::Transaction;
 use crate::consensus::encode::MAX_VEC_SIZE;
 use crate::consensus::{encode, Decodable};
 use crate::io::{self, Cursor, Read};
 use crate::prelude::*;
-use crate::psbt::map::Map;
+use crate::psbt::map::{insert_unkeyed, Map};
+use crate::psbt::serialize::{Deserialize, Serialize};
 use crate::psbt::{raw, Error, PartiallySignedTransaction};
+use crate::VarInt;
 
 /// Type: Unsigned Transaction PSBT_GLOBAL_UNSIGNED_TX = 0x00
 const PSBT_GLOBAL_UNSIGNED_TX: u8 = 0x00;
 /// Type: Extended Public Key PSBT_GLOBAL_XPUB = 0x01
 const PSBT_GLOBAL_XPUB: u8 = 0x01;
+/// Type: Transaction Version PSBT_GLOBAL_TX_VERSION = 0x02
+const PSBT_GLOBAL_TX_VERSION: u8 = 0x02;
+/// Type: Fallback Locktime PSBT_GLOBAL_FALLBACK_LOCKTIME = 0x03
+const PSBT_GLOBAL_FALLBACK_LOCKTIME: u8 = 0x03;
+/// Type: Input Count PSBT_GLOBAL_INPUT_COUNT = 0x04
+const PSBT_GLOBAL_INPUT_COUNT: u8 = 0x04;
+/// Type: Output Count PSBT_GLOBAL_OUTPUT_COUNT = 0x05
+const PSBT_GLOBAL_OUTPUT_COUNT: u8 = 0x05;
+/// Type: Transaction Modifiable Flags PSBT_GLOBAL_TX_MODIFIABLE = 0x06
+const PSBT_GLOBAL_TX_MODIFIABLE: u8 = 0x06;
 /// Type: Version Number PSBT_GLOBAL_VERSION = 0xFB
 const PSBT_GLOBAL_VERSION: u8 = 0xFB;
 /// Type: Proprietary Use Type PSBT_GLOBAL_PROPRIETARY = 0xFC
 const PSBT_GLOBAL_PROPRIETARY: u8 = 0xFC;
 
+/// The transaction modifiable flags of a version 2 PSBT, as defined by BIP 370.
+///
+/// Bits that are not defined by BIP 370 are preserved.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(crate = "actual_serde"))]
+pub struct TxModifiable(u8);
+
+impl TxModifiable {
+    const INPUTS: u8 = 0x01;
+    const OUTPUTS: u8 = 0x02;
+    const SIGHASH_SINGLE: u8 = 0x04;
+
+    /// Flags allowing both inputs and outputs to be added or removed.
+    pub fn inputs_and_outputs() -> Self { TxModifiable(Self::INPUTS | Self::OUTPUTS) }
+
+    /// Creates flags from their serialized byte.
+    pub fn from_bits(bits: u8) -> Self { TxModifiable(bits) }
+
+    /// Returns the serialized byte of these flags.
+    pub fn to_bits(self) -> u8 { self.0 }
+
+    /// Returns true if inputs may be added or removed.
+    pub fn inputs_modifiable(self) -> bool { self.0 & Self::INPUTS != 0 }
+
+    /// Returns true if outputs may be added or removed.
+    pub fn outputs_modifiable(self) -> bool { self.0 & Self::OUTPUTS != 0 }
+
+    /// Returns true if some input has a signature using `SIGHASH_SINGLE`, in which case the
+    /// input-output pairs it commits to must not be reordered.
+    pub fn has_sighash_single(self) -> bool { self.0 & Self::SIGHASH_SINGLE != 0 }
+
+    /// Sets whether inputs may be added or removed.
+    pub fn set_inputs_modifiable(&mut self, modifiable: bool) { self.set(Self::INPUTS, modifiable) }
+
+    /// Sets whether outputs may be added or removed.
+    pub fn set_outputs_modifiable(&mut self, modifiable: bool) {
+        self.set(Self::OUTPUTS, modifiable)
+    }
+
+    /// Sets whether some input has a signature using `SIGHASH_SINGLE`.
+    pub fn set_has_sighash_single(&mut self, has: bool) { self.set(Self::SIGHASH_SINGLE, has) }
+
+    fn set(&mut self, flag: u8, value: bool) {
+        if value {
+            self.0 |= flag;
+        } else {
+            self.0 &= !flag;
+        }
+    }
+}
+
+impl Serialize for TxModifiable {
+    fn serialize(&self) -> Vec<u8> { vec![self.0] }
+}
+
+impl Deserialize for TxModifiable {
+    fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
+        let bits: u8 = encode::deserialize(bytes)?;
+        Ok(TxModifiable(bits))
+    }
+}
+
 impl Map for PartiallySignedTransaction {
     fn get_pairs(&self) -> Vec<raw::Pair> {
         let mut rv: Vec<raw::Pair> = Default::default();
 
-        rv.push(raw::Pair {
-            key: raw::Key { type_value: PSBT_GLOBAL_UNSIGNED_TX, key: vec![] },
-            value: {
-                // Manually serialized to ensure 0-input txs are serialized
-                // without witnesses.
-                let mut ret = Vec::new();
-                ret.extend(encode::serialize(&self.unsigned_tx.version));
-                ret.extend(encode::serialize(&self.unsigned_tx.input));
-                ret.extend(encode::serialize(&self.unsigned_tx.output));
-                ret.extend(encode::serialize(&self.unsigned_tx.lock_time));
-                ret
-            },
-        });
+        // Version 2 PSBTs spread the unsigned transaction across the global, input and output maps.
+        if self.version < 2 {
+            rv.push(raw::Pair {
+                key: raw::Key { type_value: PSBT_GLOBAL_UNSIGNED_TX, key: vec![] },
+                value: {
+                    // Manually serialized to ensure 0-input txs are serialized
+                    // without witnesses.
+                    let mut ret = Vec::new();
+                    ret.extend(encode::serialize(&self.unsigned_tx.version));
+                    ret.extend(encode::serialize(&self.unsigned_tx.input));
+                    ret.extend(encode::serialize(&self.unsigned_tx.output));
+                    ret.extend(encode::serialize(&self.unsigned_tx.lock_time));
+                    ret
+                },
+            });
+        }
 
         for (xpub, (fingerprint, derivation)) in &self.xpub {
             rv.push(raw::Pair {
@@ -50,6 +129,31 @@ impl Map for PartiallySignedTransaction {
             });
         }
 
+        if self.version >= 2 {
+            rv.push(raw::Pair {
+                key: raw::Key { type_value: PSBT_GLOBAL_TX_VERSION, key: vec![] },
+                value: encode::serialize(&self.unsigned_tx.version),
+            });
+
+            impl_psbt_get_pair! {
+                rv.push(self.fallback_lock_time, PSBT_GLOBAL_FALLBACK_LOCKTIME)
+            }
+
+            rv.push(raw::Pair {
+                key: raw::Key { type_value: PSBT_GLOBAL_INPUT_COUNT, key: vec![] },
+                value: encode::serialize(&VarInt(self.unsigned_tx.input.len() as u64)),
+            });
+
+            rv.push(raw::Pair {
+                key: raw::Key { type_value: PSBT_GLOBAL_OUTPUT_COUNT, key: vec![] },
+                value: encode::serialize(&VarInt(self.unsigned_tx.output.len() as u64)),
+            });
+
+            impl_psbt_get_pair! {
+                rv.push(self.tx_modifiable, PSBT_GLOBAL_TX_MODIFIABLE)
+            }
+        }
+
         // Serializing version only for non-default value; otherwise test vectors fail
         if self.version > 0 {
             rv.push(raw::Pair {
@@ -71,10 +175,22 @@ impl Map for PartiallySignedTransaction {
 }
 
 impl PartiallySignedTransaction {
-    pub(crate) fn decode_global<R: io::Read + ?Sized>(r: &mut R) -> Result<Self, Error> {
+    /// Decodes the global map, returning the PSBT without its inputs and outputs together with
+    /// the number of input and output maps that follow.
+    ///
+    /// For version 2 PSBTs the inputs and outputs of the unsigned transaction are left empty, they
+    /// are filled in from the input and output maps.
+    pub(crate) fn decode_global<R: io::Read + ?Sized>(
+        r: &mut R,
+    ) -> Result<(Self, usize, usize), Error> {
         let mut r = r.take(MAX_VEC_SIZE as u64);
         let mut tx: Option<Transaction> = None;
         let mut version: Option<u32> = None;
+        let mut tx_version: Option<i32> = None;
+        let mut fallback_lock_time: Option<absolute::LockTime> = None;
+        let mut input_count: Option<VarInt> = None;
+        let mut output_count: Option<VarInt> = None;
+        let mut tx_modifiable: Option<TxModifiable> = None;
         let mut unknowns: BTreeMap<raw::Key, Vec<u8>> = Default::default();
         let mut xpub_map: BTreeMap<ExtendedPubKey, (Fingerprint, DerivationPath)> =
             Default::default();
@@ -162,11 +278,12 @@ impl PartiallySignedTransaction {
                                         ));
                                     }
                                     version = Some(Decodable::consensus_decode(&mut decoder)?);
-                                    // We only understand version 0 PSBTs. According to BIP-174 we
-                                    // should throw an error if we see anything other than version 0.
-                                    if version != Some(0) {
+                                    // We only understand version 0 (BIP-174) and version 2
+                                    // (BIP-370) PSBTs, both require us to throw an error if we
+                                    // see any other version.
+                                    if version != Some(0) && version != Some(2) {
                                         return Err(Error::Version(
-                                            "PSBT versions greater than 0 are not supported",
+                                            "PSBT versions other than 0 and 2 are not supported",
                                         ));
                                     }
                                 } else {
@@ -176,6 +293,16 @@ impl PartiallySignedTransaction {
                                 return Err(Error::InvalidKey(pair.key));
                             }
                         }
+                        PSBT_GLOBAL_TX_VERSION =>
+                            insert_unkeyed(&mut tx_version, pair.key, &pair.value)?,
+                        PSBT_GLOBAL_FALLBACK_LOCKTIME =>
+                            insert_unkeyed(&mut fallback_lock_time, pair.key, &pair.value)?,
+                        PSBT_GLOBAL_INPUT_COUNT =>
+                            insert_unkeyed(&mut input_count, pair.key, &pair.value)?,
+                        PSBT_GLOBAL_OUTPUT_COUNT =>
+                            insert_unkeyed(&mut output_count, pair.key, &pair.value)?,
+                        PSBT_GLOBAL_TX_MODIFIABLE =>
+                            insert_unkeyed(&mut tx_modifiable, pair.key, &pair.value)?,
                         PSBT_GLOBAL_PROPRIETARY => match proprietary
                             .entry(raw::ProprietaryKey::try_from(pair.key.clone())?)
                         {
@@ -199,18 +326,72 @@ impl PartiallySignedTransaction {
             }
         }
 
-        if let Some(tx) = tx {
-            Ok(PartiallySignedTransaction {
+        let version = version.unwrap_or(0);
+        if version < 2 {
+            if tx_version.is_some()
+                || fallback_lock_time.is_some()
+                || input_count.is_some()
+                || output_count.is_some()
+                || tx_modifiable.is_some()
+            {
+                return Err(Error::Version("version 0 PSBTs must not contain version 2 fields"));
+            }
+
+            let tx = tx.ok_or(Error::MustHaveUnsignedTx)?;
+            let (input_count, output_count) = (tx.input.len(), tx.output.len());
+            let psbt = PartiallySignedTransaction {
                 unsigned_tx: tx,
-                version: version.unwrap_or(0),
+                version,
+                fallback_lock_time: None,
+                tx_modifiable: None,
                 xpub: xpub_map,
                 proprietary,
                 unknown: unknowns,
                 inputs: vec![],
                 outputs: vec![],
-            })
+            };
+            Ok((psbt, input_count, output_count))
         } else {
-            Err(Error::MustHaveUnsignedTx)
+            if tx.is_some() {
+                return Err(Error::Version(
+                    "version 2 PSBTs must not contain an unsigned transaction",
+                ));
+            }
+
+            let (tx_version, input_count, output_count) =
+                match (tx_version, input_count, output_count) {
+                    (Some(v), Some(i), Some(o)) => (v, i.0, o.0),
+                    _ =>
+                        return Err(Error::Version(
+                            "version 2 PSBTs must contain the transaction version and the input and output counts",
+                        )),
+                };
+            if tx_version < 2 {
+                return Err(Error::InvalidTxVersion(tx_version));
+            }
+            let input_count = usize::try_from(input_count)
+                .map_err(|_| Error::Version("too many inputs for this platform"))?;
+            let output_count = usize::try_from(output_count)
+                .map_err(|_| Error::Version("too many outputs for this platform"))?;
+
+            let psbt = PartiallySignedTransaction {
+                unsigned_tx: Transaction {
+                    version: tx_version,
+                    // Determined once the inputs have been decoded.
+                    lock_time: fallback_lock_time.unwrap_or(absolute::LockTime::ZERO),
+                    input: vec![],
+                    output: vec![],
+                },
+                version,
+                fallback_lock_time,
+                tx_modifiable,
+                xpub: xpub_map,
+                proprietary,
+                unknown: unknowns,
+                inputs: vec![],
+                outputs: vec![],
+            };
+            Ok((psbt, input_count, output_count))
         }
     }
 }
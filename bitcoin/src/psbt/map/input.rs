@@ -7,15 +7,18 @@ use core::str::FromStr;
 use secp256k1::XOnlyPublicKey;
 
 use crate::bip32::KeySource;
+use crate::blockdata::locktime::absolute;
 use crate::blockdata::script::ScriptBuf;
-use crate::blockdata::transaction::{Transaction, TxOut};
+use crate::blockdata::transaction::{OutPoint, Sequence, Transaction, TxIn, TxOut};
 use crate::blockdata::witness::Witness;
 use crate::crypto::key::PublicKey;
-use crate::crypto::{ecdsa, taproot};
+use crate::crypto::{ecdsa, musig2, taproot};
+use crate::hash_types::Txid;
 use crate::hashes::{self, hash160, ripemd160, sha256, sha256d};
+use crate::io;
 use crate::prelude::*;
-use crate::psbt::map::Map;
-use crate::psbt::serialize::Deserialize;
+use crate::psbt::map::{insert_unkeyed, serialize_pairs, Map};
+use crate::psbt::serialize::{Deserialize, Serialize};
 use crate::psbt::{self, error, raw, Error};
 use crate::sighash::{
     self, EcdsaSighashType, NonStandardSighashType, SighashTypeParseError, TapSighashType,
@@ -48,6 +51,16 @@ const PSBT_IN_SHA256: u8 = 0x0b;
 const PSBT_IN_HASH160: u8 = 0x0c;
 /// Type: HASH256 preimage PSBT_IN_HASH256 = 0x0d
 const PSBT_IN_HASH256: u8 = 0x0d;
+/// Type: Previous TXID PSBT_IN_PREVIOUS_TXID = 0x0e
+const PSBT_IN_PREVIOUS_TXID: u8 = 0x0e;
+/// Type: Spent Output Index PSBT_IN_OUTPUT_INDEX = 0x0f
+const PSBT_IN_OUTPUT_INDEX: u8 = 0x0f;
+/// Type: Sequence Number PSBT_IN_SEQUENCE = 0x10
+const PSBT_IN_SEQUENCE: u8 = 0x10;
+/// Type: Required Time-based Locktime PSBT_IN_REQUIRED_TIME_LOCKTIME = 0x11
+const PSBT_IN_REQUIRED_TIME_LOCKTIME: u8 = 0x11;
+/// Type: Required Height-based Locktime PSBT_IN_REQUIRED_HEIGHT_LOCKTIME = 0x12
+const PSBT_IN_REQUIRED_HEIGHT_LOCKTIME: u8 = 0x12;
 /// Type: Taproot Signature in Key Spend PSBT_IN_TAP_KEY_SIG = 0x13
 const PSBT_IN_TAP_KEY_SIG: u8 = 0x13;
 /// Type: Taproot Signature in Script Spend PSBT_IN_TAP_SCRIPT_SIG = 0x14
@@ -60,6 +73,12 @@ const PSBT_IN_TAP_BIP32_DERIVATION: u8 = 0x16;
 const PSBT_IN_TAP_INTERNAL_KEY: u8 = 0x17;
 /// Type: Taproot Merkle Root PSBT_IN_TAP_MERKLE_ROOT = 0x18
 const PSBT_IN_TAP_MERKLE_ROOT: u8 = 0x18;
+/// Type: MuSig2 Participant Public Keys PSBT_IN_MUSIG2_PARTICIPANT_PUBKEYS = 0x1a
+const PSBT_IN_MUSIG2_PARTICIPANT_PUBKEYS: u8 = 0x1a;
+/// Type: MuSig2 Public Nonce PSBT_IN_MUSIG2_PUB_NONCE = 0x1b
+const PSBT_IN_MUSIG2_PUB_NONCE: u8 = 0x1b;
+/// Type: MuSig2 Participant Partial Signature PSBT_IN_MUSIG2_PARTIAL_SIG = 0x1c
+const PSBT_IN_MUSIG2_PARTIAL_SIG: u8 = 0x1c;
 /// Type: Proprietary Use Type PSBT_IN_PROPRIETARY = 0xFC
 const PSBT_IN_PROPRIETARY: u8 = 0xFC;
 
@@ -110,6 +129,10 @@ pub struct Input {
     /// HAS256 hash to preimage map.
     #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::btreemap_byte_values"))]
     pub hash256_preimages: BTreeMap<sha256d::Hash, Vec<u8>>,
+    /// The minimum time based lock time this input requires, only used by version 2 PSBTs.
+    pub required_time_lock_time: Option<absolute::Time>,
+    /// The minimum height based lock time this input requires, only used by version 2 PSBTs.
+    pub required_height_lock_time: Option<absolute::Height>,
     /// Serialized taproot signature with sighash type for key spend.
     pub tap_key_sig: Option<taproot::Signature>,
     /// Map of `<xonlypubkey>|<leafhash>` with signature.
@@ -125,6 +148,23 @@ pub struct Input {
     pub tap_internal_key: Option<XOnlyPublicKey>,
     /// Taproot Merkle root.
     pub tap_merkle_root: Option<TapNodeHash>,
+    /// Map of MuSig2 aggregate public keys to the public keys of the participants.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::btreemap_as_seq"))]
+    pub musig2_participant_pubkeys: BTreeMap<secp256k1::PublicKey, Vec<secp256k1::PublicKey>>,
+    /// Map of (participant key, aggregate key, leaf hash) to the participant's MuSig2 public
+    /// nonce, the leaf hash is `None` for key path spends.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::btreemap_as_seq"))]
+    pub musig2_pub_nonces: BTreeMap<
+        (secp256k1::PublicKey, secp256k1::PublicKey, Option<TapLeafHash>),
+        musig2::PublicNonce,
+    >,
+    /// Map of (participant key, aggregate key, leaf hash) to the participant's MuSig2 partial
+    /// signature, the leaf hash is `None` for key path spends.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::btreemap_as_seq"))]
+    pub musig2_partial_sigs: BTreeMap<
+        (secp256k1::PublicKey, secp256k1::PublicKey, Option<TapLeafHash>),
+        musig2::PartialSignature,
+    >,
     /// Proprietary key-value pairs for this input.
     #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::btreemap_as_seq_byte_values"))]
     pub proprietary: BTreeMap<raw::ProprietaryKey, Vec<u8>>,
@@ -352,6 +392,21 @@ impl Input {
                     self.tap_merkle_root <= <raw_key: _>|< raw_value: TapNodeHash>
                 }
             }
+            PSBT_IN_MUSIG2_PARTICIPANT_PUBKEYS => {
+                impl_psbt_insert_pair! {
+                    self.musig2_participant_pubkeys <= <raw_key: secp256k1::PublicKey>|< raw_value: Vec<secp256k1::PublicKey>>
+                }
+            }
+            PSBT_IN_MUSIG2_PUB_NONCE => {
+                impl_psbt_insert_pair! {
+                    self.musig2_pub_nonces <= <raw_key: (secp256k1::PublicKey, secp256k1::PublicKey, Option<TapLeafHash>)>|< raw_value: musig2::PublicNonce>
+                }
+            }
+            PSBT_IN_MUSIG2_PARTIAL_SIG => {
+                impl_psbt_insert_pair! {
+                    self.musig2_partial_sigs <= <raw_key: (secp256k1::PublicKey, secp256k1::PublicKey, Option<TapLeafHash>)>|< raw_value: musig2::PartialSignature>
+                }
+            }
             PSBT_IN_PROPRIETARY => {
                 let key = raw::ProprietaryKey::try_from(raw_key.clone())?;
                 match self.proprietary.entry(key) {
@@ -372,6 +427,81 @@ impl Input {
         Ok(())
     }
 
+    /// Decodes an input map of a version 2 PSBT, returning the fields that version 0 keeps in the
+    /// unsigned transaction as a [`TxIn`].
+    ///
+    /// Version 0 input maps are decoded by `decode`, which treats the version 2 fields as unknown
+    /// key-value pairs since that is how they were parsed before BIP 370.
+    pub(crate) fn decode_v2<R: io::Read + ?Sized>(r: &mut R) -> Result<(Self, TxIn), Error> {
+        let mut rv = Input::default();
+        let mut txid: Option<Txid> = None;
+        let mut vout: Option<u32> = None;
+        let mut sequence: Option<Sequence> = None;
+
+        loop {
+            match raw::Pair::decode(r) {
+                Ok(pair) => match pair.key.type_value {
+                    PSBT_IN_PREVIOUS_TXID => insert_unkeyed(&mut txid, pair.key, &pair.value)?,
+                    PSBT_IN_OUTPUT_INDEX => insert_unkeyed(&mut vout, pair.key, &pair.value)?,
+                    PSBT_IN_SEQUENCE => insert_unkeyed(&mut sequence, pair.key, &pair.value)?,
+                    PSBT_IN_REQUIRED_TIME_LOCKTIME =>
+                        insert_unkeyed(&mut rv.required_time_lock_time, pair.key, &pair.value)?,
+                    PSBT_IN_REQUIRED_HEIGHT_LOCKTIME =>
+                        insert_unkeyed(&mut rv.required_height_lock_time, pair.key, &pair.value)?,
+                    _ => rv.insert_pair(pair)?,
+                },
+                Err(Error::NoMorePairs) => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        match (txid, vout) {
+            (Some(txid), Some(vout)) => {
+                let txin = TxIn {
+                    previous_output: OutPoint { txid, vout },
+                    script_sig: ScriptBuf::new(),
+                    sequence: sequence.unwrap_or(Sequence::MAX),
+                    witness: Witness::default(),
+                };
+                Ok((rv, txin))
+            }
+            _ => Err(Error::Version("version 2 PSBT inputs must contain the previous outpoint")),
+        }
+    }
+
+    /// Serializes this input as an input map of a version 2 PSBT, taking the fields that version 0
+    /// keeps in the unsigned transaction from `txin`.
+    pub(crate) fn serialize_map_v2(&self, txin: &TxIn) -> Vec<u8> {
+        let mut rv = vec![
+            raw::Pair {
+                key: raw::Key { type_value: PSBT_IN_PREVIOUS_TXID, key: vec![] },
+                value: txin.previous_output.txid.serialize(),
+            },
+            raw::Pair {
+                key: raw::Key { type_value: PSBT_IN_OUTPUT_INDEX, key: vec![] },
+                value: txin.previous_output.vout.serialize(),
+            },
+        ];
+        // The sequence number defaults to 0xffffffff if omitted.
+        if txin.sequence != Sequence::MAX {
+            rv.push(raw::Pair {
+                key: raw::Key { type_value: PSBT_IN_SEQUENCE, key: vec![] },
+                value: txin.sequence.serialize(),
+            });
+        }
+
+        impl_psbt_get_pair! {
+            rv.push(self.required_time_lock_time, PSBT_IN_REQUIRED_TIME_LOCKTIME)
+        }
+
+        impl_psbt_get_pair! {
+            rv.push(self.required_height_lock_time, PSBT_IN_REQUIRED_HEIGHT_LOCKTIME)
+        }
+
+        rv.extend(self.get_pairs());
+        serialize_pairs(rv)
+    }
+
     /// Combines this [`Input`] with `other` `Input` (as described by BIP 174).
     pub fn combine(&mut self, other: Self) {
         combine!(non_witness_utxo, self, other);
@@ -390,6 +520,9 @@ impl Input {
         self.tap_script_sigs.extend(other.tap_script_sigs);
         self.tap_scripts.extend(other.tap_scripts);
         self.tap_key_origins.extend(other.tap_key_origins);
+        self.musig2_participant_pubkeys.extend(other.musig2_participant_pubkeys);
+        self.musig2_pub_nonces.extend(other.musig2_pub_nonces);
+        self.musig2_partial_sigs.extend(other.musig2_partial_sigs);
         self.proprietary.extend(other.proprietary);
         self.unknown.extend(other.unknown);
 
@@ -400,6 +533,8 @@ impl Input {
         combine!(tap_key_sig, self, other);
         combine!(tap_internal_key, self, other);
         combine!(tap_merkle_root, self, other);
+        combine!(required_time_lock_time, self, other);
+        combine!(required_height_lock_time, self, other);
     }
 }
 
@@ -482,6 +617,19 @@ impl Map for Input {
         impl_psbt_get_pair! {
             rv.push(self.tap_merkle_root, PSBT_IN_TAP_MERKLE_ROOT)
         }
+
+        impl_psbt_get_pair! {
+            rv.push_map(self.musig2_participant_pubkeys, PSBT_IN_MUSIG2_PARTICIPANT_PUBKEYS)
+        }
+
+        impl_psbt_get_pair! {
+            rv.push_map(self.musig2_pub_nonces, PSBT_IN_MUSIG2_PUB_NONCE)
+        }
+
+        impl_psbt_get_pair! {
+            rv.push_map(self.musig2_partial_sigs, PSBT_IN_MUSIG2_PARTIAL_SIG)
+        }
+
         for (key, value) in self.proprietary.iter() {
             rv.push(raw::Pair { key: key.to_key(), value: value.clone() });
         }
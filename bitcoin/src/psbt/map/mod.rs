@@ -1,15 +1,16 @@
 // SPDX-License-Identifier: CC0-1.0
 
 use crate::prelude::*;
-use crate::psbt::raw;
+use crate::psbt::{raw, Error};
 
 mod global;
 mod input;
 mod output;
 
+pub use self::global::TxModifiable;
 pub use self::input::{Input, PsbtSighashType};
 pub use self::output::Output;
-use super::serialize::Serialize;
+use super::serialize::{Deserialize, Serialize};
 
 /// A trait that describes a PSBT key-value map.
 pub(super) trait Map {
@@ -23,12 +24,34 @@ pub(super) trait Map {
     /// Why is the separator here 0x00 instead of 0xff? The separator here is used to distinguish between each chunk of data.
     /// A separator of 0x00 would mean that the unserializer can read it as a key length of 0, which would never occur with
     /// actual keys. It can thus be used as a separator and allow for easier unserializer implementation.
-    fn serialize_map(&self) -> Vec<u8> {
-        let mut buf = Vec::new();
-        for pair in Map::get_pairs(self) {
-            buf.extend(&pair.serialize());
-        }
-        buf.push(0x00_u8);
-        buf
+    fn serialize_map(&self) -> Vec<u8> { serialize_pairs(Map::get_pairs(self)) }
+}
+
+/// Serializes `pairs` as a PSBT binary map, including the terminating separator.
+fn serialize_pairs(pairs: Vec<raw::Pair>) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for pair in pairs {
+        buf.extend(&pair.serialize());
+    }
+    buf.push(0x00_u8);
+    buf
+}
+
+/// Deserializes the value of a key-value pair with an empty key into `slot`.
+///
+/// Used for fields which are not stored directly in a map, e.g. the PSBT version 2 fields that are
+/// kept in the unsigned transaction.
+fn insert_unkeyed<T: Deserialize>(
+    slot: &mut Option<T>,
+    key: raw::Key,
+    value: &[u8],
+) -> Result<(), Error> {
+    if !key.key.is_empty() {
+        return Err(Error::InvalidKey(key));
+    }
+    if slot.is_some() {
+        return Err(Error::DuplicateKey(key));
     }
+    *slot = Some(Deserialize::deserialize(value)?);
+    Ok(())
 }
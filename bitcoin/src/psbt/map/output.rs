@@ -7,8 +7,11 @@ use {core, secp256k1};
 
 use crate::bip32::KeySource;
 use crate::blockdata::script::ScriptBuf;
+use crate::blockdata::transaction::TxOut;
+use crate::io;
 use crate::prelude::*;
-use crate::psbt::map::Map;
+use crate::psbt::map::{insert_unkeyed, serialize_pairs, Map};
+use crate::psbt::serialize::Serialize;
 use crate::psbt::{raw, Error};
 use crate::taproot::{TapLeafHash, TapTree};
 
@@ -18,12 +21,18 @@ const PSBT_OUT_REDEEM_SCRIPT: u8 = 0x00;
 const PSBT_OUT_WITNESS_SCRIPT: u8 = 0x01;
 /// Type: BIP 32 Derivation Path PSBT_OUT_BIP32_DERIVATION = 0x02
 const PSBT_OUT_BIP32_DERIVATION: u8 = 0x02;
+/// Type: Output Amount PSBT_OUT_AMOUNT = 0x03
+const PSBT_OUT_AMOUNT: u8 = 0x03;
+/// Type: Output Script PSBT_OUT_SCRIPT = 0x04
+const PSBT_OUT_SCRIPT: u8 = 0x04;
 /// Type: Taproot Internal Key PSBT_OUT_TAP_INTERNAL_KEY = 0x05
 const PSBT_OUT_TAP_INTERNAL_KEY: u8 = 0x05;
 /// Type: Taproot Tree PSBT_OUT_TAP_TREE = 0x06
 const PSBT_OUT_TAP_TREE: u8 = 0x06;
 /// Type: Taproot Key BIP 32 Derivation Path PSBT_OUT_TAP_BIP32_DERIVATION = 0x07
 const PSBT_OUT_TAP_BIP32_DERIVATION: u8 = 0x07;
+/// Type: MuSig2 Participant Public Keys PSBT_OUT_MUSIG2_PARTICIPANT_PUBKEYS = 0x08
+const PSBT_OUT_MUSIG2_PARTICIPANT_PUBKEYS: u8 = 0x08;
 /// Type: Proprietary Use Type PSBT_IN_PROPRIETARY = 0xFC
 const PSBT_OUT_PROPRIETARY: u8 = 0xFC;
 
@@ -48,6 +57,9 @@ pub struct Output {
     /// Map of tap root x only keys to origin info and leaf hashes contained in it.
     #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::btreemap_as_seq"))]
     pub tap_key_origins: BTreeMap<XOnlyPublicKey, (Vec<TapLeafHash>, KeySource)>,
+    /// Map of MuSig2 aggregate public keys to the public keys of the participants.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::btreemap_as_seq"))]
+    pub musig2_participant_pubkeys: BTreeMap<secp256k1::PublicKey, Vec<secp256k1::PublicKey>>,
     /// Proprietary key-value pairs for this output.
     #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::btreemap_as_seq_byte_values"))]
     pub proprietary: BTreeMap<raw::ProprietaryKey, Vec<u8>>,
@@ -100,6 +112,11 @@ impl Output {
                     self.tap_key_origins <= <raw_key: XOnlyPublicKey>|< raw_value: (Vec<TapLeafHash>, KeySource)>
                 }
             }
+            PSBT_OUT_MUSIG2_PARTICIPANT_PUBKEYS => {
+                impl_psbt_insert_pair! {
+                    self.musig2_participant_pubkeys <= <raw_key: secp256k1::PublicKey>|< raw_value: Vec<secp256k1::PublicKey>>
+                }
+            }
             _ => match self.unknown.entry(raw_key) {
                 btree_map::Entry::Vacant(empty_key) => {
                     empty_key.insert(raw_value);
@@ -111,12 +128,58 @@ impl Output {
         Ok(())
     }
 
+    /// Decodes an output map of a version 2 PSBT, returning the fields that version 0 keeps in the
+    /// unsigned transaction as a [`TxOut`].
+    ///
+    /// Version 0 output maps are decoded by `decode`, which treats the version 2 fields as unknown
+    /// key-value pairs since that is how they were parsed before BIP 370.
+    pub(crate) fn decode_v2<R: io::Read + ?Sized>(r: &mut R) -> Result<(Self, TxOut), Error> {
+        let mut rv = Output::default();
+        let mut value: Option<u64> = None;
+        let mut script_pubkey: Option<ScriptBuf> = None;
+
+        loop {
+            match raw::Pair::decode(r) {
+                Ok(pair) => match pair.key.type_value {
+                    PSBT_OUT_AMOUNT => insert_unkeyed(&mut value, pair.key, &pair.value)?,
+                    PSBT_OUT_SCRIPT => insert_unkeyed(&mut script_pubkey, pair.key, &pair.value)?,
+                    _ => rv.insert_pair(pair)?,
+                },
+                Err(Error::NoMorePairs) => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        match (value, script_pubkey) {
+            (Some(value), Some(script_pubkey)) => Ok((rv, TxOut { value, script_pubkey })),
+            _ => Err(Error::Version("version 2 PSBT outputs must contain the amount and script")),
+        }
+    }
+
+    /// Serializes this output as an output map of a version 2 PSBT, taking the fields that version
+    /// 0 keeps in the unsigned transaction from `txout`.
+    pub(crate) fn serialize_map_v2(&self, txout: &TxOut) -> Vec<u8> {
+        let mut rv = vec![
+            raw::Pair {
+                key: raw::Key { type_value: PSBT_OUT_AMOUNT, key: vec![] },
+                value: txout.value.serialize(),
+            },
+            raw::Pair {
+                key: raw::Key { type_value: PSBT_OUT_SCRIPT, key: vec![] },
+                value: txout.script_pubkey.serialize(),
+            },
+        ];
+        rv.extend(self.get_pairs());
+        serialize_pairs(rv)
+    }
+
     /// Combines this [`Output`] with `other` `Output` (as described by BIP 174).
     pub fn combine(&mut self, other: Self) {
         self.bip32_derivation.extend(other.bip32_derivation);
         self.proprietary.extend(other.proprietary);
         self.unknown.extend(other.unknown);
         self.tap_key_origins.extend(other.tap_key_origins);
+        self.musig2_participant_pubkeys.extend(other.musig2_participant_pubkeys);
 
         combine!(redeem_script, self, other);
         combine!(witness_script, self, other);
@@ -153,6 +216,10 @@ impl Map for Output {
             rv.push_map(self.tap_key_origins, PSBT_OUT_TAP_BIP32_DERIVATION)
         }
 
+        impl_psbt_get_pair! {
+            rv.push_map(self.musig2_participant_pubkeys, PSBT_OUT_MUSIG2_PARTICIPANT_PUBKEYS)
+        }
+
         for (key, value) in self.proprietary.iter() {
             rv.push(raw::Pair { key: key.to_key(), value: value.clone() });
         }
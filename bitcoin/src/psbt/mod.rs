@@ -12,16 +12,20 @@ use core::{cmp, fmt};
 use std::collections::{HashMap, HashSet};
 
 use bitcoin_internals::write_err;
-use secp256k1::{Message, Secp256k1, Signing};
+use secp256k1::{KeyPair, Message, Secp256k1, Signing, Verification, XOnlyPublicKey};
 
 use crate::bip32::{self, ExtendedPrivKey, ExtendedPubKey, KeySource};
+use crate::blockdata::locktime::absolute;
 use crate::blockdata::script::ScriptBuf;
-use crate::blockdata::transaction::{Transaction, TxOut};
-use crate::crypto::ecdsa;
-use crate::crypto::key::{PrivateKey, PublicKey};
+use crate::blockdata::transaction::{
+    cmp_bip69_outpoints, cmp_bip69_outputs, Transaction, TxIn, TxOut,
+};
+use crate::crypto::key::{PrivateKey, PublicKey, TapTweak};
+use crate::crypto::{ecdsa, taproot};
 use crate::prelude::*;
 pub use crate::sighash::Prevouts;
-use crate::sighash::{self, EcdsaSighashType, SighashCache};
+use crate::sighash::{self, EcdsaSighashType, SighashCache, TapSighashType};
+use crate::taproot::TapLeafHash;
 use crate::Amount;
 
 #[macro_use]
@@ -32,8 +36,23 @@ pub mod serialize;
 mod error;
 pub use self::error::Error;
 
+mod analyze;
+pub use self::analyze::{InputAnalysis, PsbtAnalysis, Role};
+
+#[cfg(feature = "rand")]
+mod builder;
+#[cfg(feature = "rand")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rand")))]
+pub use self::builder::{BuildError, FeeBumpError, TransactionBuilder, Utxo};
+
+mod finalize;
+pub use self::finalize::{FinalizeError, FinalizeErrors};
+
 mod map;
-pub use self::map::{Input, Output, PsbtSighashType};
+pub use self::map::{Input, Output, PsbtSighashType, TxModifiable};
+
+mod validate;
+pub use self::validate::{ValidationError, ValidationErrors};
 
 /// Partially signed transaction, commonly referred to as a PSBT.
 pub type Psbt = PartiallySignedTransaction;
@@ -44,9 +63,18 @@ pub type Psbt = PartiallySignedTransaction;
 #[cfg_attr(feature = "serde", serde(crate = "actual_serde"))]
 pub struct PartiallySignedTransaction {
     /// The unsigned transaction, scriptSigs and witnesses for each input must be empty.
+    ///
+    /// Version 2 PSBTs do not contain the unsigned transaction, it is assembled from the global,
+    /// input and output maps when deserializing.
     pub unsigned_tx: Transaction,
     /// The version number of this PSBT. If omitted, the version number is 0.
     pub version: u32,
+    /// The lock time to use if no input requires a particular lock time, only used by version 2
+    /// PSBTs.
+    pub fallback_lock_time: Option<absolute::LockTime>,
+    /// Flags indicating whether inputs and outputs may still be added, only used by version 2
+    /// PSBTs.
+    pub tx_modifiable: Option<TxModifiable>,
     /// A global map from extended public keys to the used key fingerprint and
     /// derivation path as defined by BIP 32.
     pub xpub: BTreeMap<ExtendedPubKey, KeySource>,
@@ -118,6 +146,8 @@ impl PartiallySignedTransaction {
             unsigned_tx: tx,
             xpub: Default::default(),
             version: 0,
+            fallback_lock_time: None,
+            tx_modifiable: None,
             proprietary: Default::default(),
             unknown: Default::default(),
         };
@@ -125,6 +155,207 @@ impl PartiallySignedTransaction {
         Ok(psbt)
     }
 
+    /// Creates an empty version 2 PSBT as described by BIP 370, with both inputs and outputs
+    /// modifiable.
+    ///
+    /// Inputs and outputs are then added using [`Self::add_input`] and [`Self::add_output`].
+    ///
+    /// # Errors
+    ///
+    /// If `tx_version` is less than 2.
+    pub fn new_v2(
+        tx_version: i32,
+        fallback_lock_time: Option<absolute::LockTime>,
+    ) -> Result<Self, Error> {
+        if tx_version < 2 {
+            return Err(Error::InvalidTxVersion(tx_version));
+        }
+        Ok(PartiallySignedTransaction {
+            unsigned_tx: Transaction {
+                version: tx_version,
+                lock_time: fallback_lock_time.unwrap_or(absolute::LockTime::ZERO),
+                input: vec![],
+                output: vec![],
+            },
+            xpub: Default::default(),
+            version: 2,
+            fallback_lock_time,
+            tx_modifiable: Some(TxModifiable::inputs_and_outputs()),
+            proprietary: Default::default(),
+            unknown: Default::default(),
+            inputs: vec![],
+            outputs: vec![],
+        })
+    }
+
+    /// Converts this PSBT to version 2.
+    ///
+    /// The lock time of the unsigned transaction becomes the fallback lock time and neither inputs
+    /// nor outputs are modifiable, so the transaction is unchanged and [`Self::into_v0`] gives back
+    /// the original PSBT.
+    ///
+    /// # Errors
+    ///
+    /// If the version of the unsigned transaction is less than 2, which version 2 PSBTs can't
+    /// represent.
+    pub fn into_v2(mut self) -> Result<Self, Error> {
+        if self.version < 2 {
+            if self.unsigned_tx.version < 2 {
+                return Err(Error::InvalidTxVersion(self.unsigned_tx.version));
+            }
+            self.version = 2;
+            self.fallback_lock_time = Some(self.unsigned_tx.lock_time);
+            self.tx_modifiable = None;
+        }
+        Ok(self)
+    }
+
+    /// Converts this PSBT to version 0.
+    ///
+    /// Version 0 can not represent the construction state of a PSBT: the lock time is determined
+    /// from the inputs (see [`Self::determine_lock_time`]) and the fallback lock time, modifiable
+    /// flags and required lock times are removed.
+    ///
+    /// # Errors
+    ///
+    /// If the inputs require lock times of incompatible units.
+    pub fn into_v0(mut self) -> Result<Self, Error> {
+        if self.version >= 2 {
+            self.unsigned_tx.lock_time = self.determine_lock_time()?;
+            self.version = 0;
+            self.fallback_lock_time = None;
+            self.tx_modifiable = None;
+            for input in &mut self.inputs {
+                input.required_time_lock_time = None;
+                input.required_height_lock_time = None;
+            }
+        }
+        Ok(self)
+    }
+
+    /// Determines the lock time of the transaction as described by BIP 370.
+    ///
+    /// If no input requires a lock time the fallback lock time (or zero) is used, otherwise the
+    /// greatest required lock time, preferring heights if all inputs allow both units. For version
+    /// 0 PSBTs this is the lock time of the unsigned transaction.
+    ///
+    /// # Errors
+    ///
+    /// If the inputs require lock times of incompatible units.
+    pub fn determine_lock_time(&self) -> Result<absolute::LockTime, Error> {
+        if self.version < 2 {
+            return Ok(self.unsigned_tx.lock_time);
+        }
+
+        let (mut heights_allowed, mut times_allowed) = (true, true);
+        let mut max_height: Option<absolute::Height> = None;
+        let mut max_time: Option<absolute::Time> = None;
+        for input in &self.inputs {
+            let (time, height) = (input.required_time_lock_time, input.required_height_lock_time);
+            if time.is_none() && height.is_none() {
+                continue;
+            }
+            heights_allowed &= height.is_some();
+            times_allowed &= time.is_some();
+            max_height = cmp::max(max_height, height);
+            max_time = cmp::max(max_time, time);
+        }
+
+        match (max_height, max_time) {
+            (None, None) => Ok(self.fallback_lock_time.unwrap_or(absolute::LockTime::ZERO)),
+            (Some(height), _) if heights_allowed => Ok(absolute::LockTime::Blocks(height)),
+            (_, Some(time)) if times_allowed => Ok(absolute::LockTime::Seconds(time)),
+            _ => Err(Error::IncompatibleLockTimes),
+        }
+    }
+
+    /// Adds an input to a version 2 PSBT (BIP 370 Constructor role).
+    ///
+    /// `txin` provides the previous outpoint and sequence number, its `script_sig` and `witness`
+    /// must be empty. The lock time of the unsigned transaction is updated to account for the
+    /// lock time required by `input`.
+    ///
+    /// # Errors
+    ///
+    /// If this is not a version 2 PSBT, the inputs are not modifiable, `txin` is signed or the lock
+    /// time required by `input` is incompatible with the other inputs.
+    pub fn add_input(&mut self, txin: TxIn, input: Input) -> Result<(), Error> {
+        if self.version < 2 {
+            return Err(Error::Version("only version 2 PSBTs can be constructed"));
+        }
+        if !self.tx_modifiable.map_or(false, |m| m.inputs_modifiable()) {
+            return Err(Error::InputsNotModifiable);
+        }
+        if !txin.script_sig.is_empty() {
+            return Err(Error::UnsignedTxHasScriptSigs);
+        }
+        if !txin.witness.is_empty() {
+            return Err(Error::UnsignedTxHasScriptWitnesses);
+        }
+
+        self.inputs.push(input);
+        match self.determine_lock_time() {
+            Ok(lock_time) => {
+                self.unsigned_tx.lock_time = lock_time;
+                self.unsigned_tx.input.push(txin);
+                Ok(())
+            }
+            Err(e) => {
+                self.inputs.pop();
+                Err(e)
+            }
+        }
+    }
+
+    /// Adds an output to a version 2 PSBT (BIP 370 Constructor role).
+    ///
+    /// `txout` provides the amount and script pubkey of the output.
+    ///
+    /// # Errors
+    ///
+    /// If this is not a version 2 PSBT or the outputs are not modifiable.
+    pub fn add_output(&mut self, txout: TxOut, output: Output) -> Result<(), Error> {
+        if self.version < 2 {
+            return Err(Error::Version("only version 2 PSBTs can be constructed"));
+        }
+        if !self.tx_modifiable.map_or(false, |m| m.outputs_modifiable()) {
+            return Err(Error::OutputsNotModifiable);
+        }
+
+        self.unsigned_tx.output.push(txout);
+        self.outputs.push(output);
+        Ok(())
+    }
+
+    /// Sorts the inputs and outputs canonically as described by BIP 69, see
+    /// [`Transaction::sort_bip69`].
+    ///
+    /// The input and output maps are permuted together with the unsigned transaction. Returns the
+    /// applied permutations of the inputs and of the outputs: element `i` is the index the input
+    /// (or output) now at index `i` had before sorting.
+    ///
+    /// # Panics
+    ///
+    /// If the number of input or output maps doesn't match the unsigned transaction.
+    pub fn sort_bip69(&mut self) -> (Vec<usize>, Vec<usize>) {
+        assert_eq!(self.inputs.len(), self.unsigned_tx.input.len());
+        assert_eq!(self.outputs.len(), self.unsigned_tx.output.len());
+
+        let tx = &self.unsigned_tx;
+        let mut input_order = (0..tx.input.len()).collect::<Vec<_>>();
+        input_order.sort_by(|&a, &b| {
+            cmp_bip69_outpoints(&tx.input[a].previous_output, &tx.input[b].previous_output)
+        });
+        let mut output_order = (0..tx.output.len()).collect::<Vec<_>>();
+        output_order.sort_by(|&a, &b| cmp_bip69_outputs(&tx.output[a], &tx.output[b]));
+
+        permute(&mut self.unsigned_tx.input, &input_order);
+        permute(&mut self.inputs, &input_order);
+        permute(&mut self.unsigned_tx.output, &output_order);
+        permute(&mut self.outputs, &output_order);
+        (input_order, output_order)
+    }
+
     /// Extracts the `Transaction` from a PSBT by filling in the available signature information.
     pub fn extract_tx(self) -> Transaction {
         let mut tx: Transaction = self.unsigned_tx;
@@ -154,6 +385,24 @@ impl PartiallySignedTransaction {
         // Keeping the highest version
         self.version = cmp::max(self.version, other.version);
 
+        combine!(fallback_lock_time, self, other);
+        // BIP 370: inputs and outputs stay modifiable only if both PSBTs allow it.
+        match (self.tx_modifiable.as_mut(), other.tx_modifiable) {
+            (Some(flags), Some(other_flags)) => {
+                flags.set_inputs_modifiable(
+                    flags.inputs_modifiable() && other_flags.inputs_modifiable(),
+                );
+                flags.set_outputs_modifiable(
+                    flags.outputs_modifiable() && other_flags.outputs_modifiable(),
+                );
+                flags.set_has_sighash_single(
+                    flags.has_sighash_single() || other_flags.has_sighash_single(),
+                );
+            }
+            (None, Some(other_flags)) => self.tx_modifiable = Some(other_flags),
+            (_, None) => {}
+        }
+
         // Merging xpubs
         for (xpub, (fingerprint1, derivation1)) in other.xpub {
             match self.xpub.entry(xpub) {
@@ -205,27 +454,33 @@ impl PartiallySignedTransaction {
 
     /// Attempts to create _all_ the required signatures for this PSBT using `k`.
     ///
-    /// **NOTE**: Taproot inputs are, as yet, not supported by this function. We currently only
-    /// attempt to sign ECDSA inputs.
+    /// ECDSA inputs are signed for every key in `bip32_derivation`. Taproot inputs are signed for
+    /// every key in `tap_key_origins`: a key path signature is created if the key is the
+    /// `tap_internal_key` (tweaked using `tap_merkle_root`) and a script path signature is created
+    /// for each leaf hash listed against the key.
+    ///
+    /// If you just want to sign an input with one specific key consider using `sighash_ecdsa` or
+    /// `sighash_taproot`. This function does not support scripts that contain `OP_CODESEPARATOR`.
     ///
-    /// If you just want to sign an input with one specific key consider using `sighash_ecdsa`. This
-    /// function does not support scripts that contain `OP_CODESEPARATOR`.
+    /// Inputs spending P2A outputs don't need a signature and are skipped, [`Self::finalize_input`]
+    /// adds their empty witness.
     ///
     /// # Returns
     ///
-    /// Either Ok(SigningKeys) or Err((SigningKeys, SigningErrors)), where
-    /// - SigningKeys: A map of input index -> pubkey associated with secret key used to sign.
-    /// - SigningKeys: A map of input index -> the error encountered while attempting to sign.
+    /// Either Ok(SigningKeysMap) or Err((SigningKeysMap, SigningErrors)), where
+    /// - SigningKeysMap: A map of input index -> keys associated with secret key used to sign.
+    /// - SigningErrors: A map of input index -> the error encountered while attempting to sign.
     ///
     /// If an error is returned some signatures may already have been added to the PSBT. Since
     /// `partial_sigs` is a [`BTreeMap`] it is safe to retry, previous sigs will be overwritten.
+    /// Taproot signatures that are already present are not recreated.
     pub fn sign<C, K>(
         &mut self,
         k: &K,
         secp: &Secp256k1<C>,
-    ) -> Result<SigningKeys, (SigningKeys, SigningErrors)>
+    ) -> Result<SigningKeysMap, (SigningKeysMap, SigningErrors)>
     where
-        C: Signing,
+        C: Signing + Verification,
         K: GetKey,
     {
         let tx = self.unsigned_tx.clone(); // clone because we need to mutably borrow when signing.
@@ -235,15 +490,35 @@ impl PartiallySignedTransaction {
         let mut errors = BTreeMap::new();
 
         for i in 0..self.inputs.len() {
-            if let Ok(SigningAlgorithm::Ecdsa) = self.signing_algorithm(i) {
-                match self.bip32_sign_ecdsa(k, i, &mut cache, secp) {
-                    Ok(v) => {
-                        used.insert(i, v);
-                    }
-                    Err(e) => {
-                        errors.insert(i, e);
-                    }
-                }
+            if self.spend_utxo(i).map(|utxo| utxo.script_pubkey.is_p2a()).unwrap_or(false) {
+                continue;
+            }
+            match self.signing_algorithm(i) {
+                Ok(SigningAlgorithm::Ecdsa) =>
+                    match self.bip32_sign_ecdsa(k, i, &mut cache, secp) {
+                        Ok(v) => {
+                            if !v.is_empty() {
+                                self.update_tx_modifiable(i);
+                            }
+                            used.insert(i, SigningKeys::Ecdsa(v));
+                        }
+                        Err(e) => {
+                            errors.insert(i, e);
+                        }
+                    },
+                Ok(SigningAlgorithm::Schnorr) =>
+                    match self.bip32_sign_schnorr(k, i, &mut cache, secp) {
+                        Ok(v) => {
+                            if !v.is_empty() {
+                                self.update_tx_modifiable(i);
+                            }
+                            used.insert(i, SigningKeys::Schnorr(v));
+                        }
+                        Err(e) => {
+                            errors.insert(i, e);
+                        }
+                    },
+                Err(_) => {}
             };
         }
         if errors.is_empty() {
@@ -253,6 +528,24 @@ impl PartiallySignedTransaction {
         }
     }
 
+    /// Clears the modifiable flags of a version 2 PSBT that a signature on `input_index` commits
+    /// to (BIP 370 Signer role).
+    fn update_tx_modifiable(&mut self, input_index: usize) {
+        let sighash = self.inputs[input_index].sighash_type.map_or(0x01, |t| t.to_u32());
+        if let Some(flags) = self.tx_modifiable.as_mut() {
+            let base = sighash & 0x1f;
+            if sighash & 0x80 == 0 {
+                flags.set_inputs_modifiable(false); // Not ANYONECANPAY.
+            }
+            if base != 0x02 {
+                flags.set_outputs_modifiable(false); // Not SIGHASH_NONE.
+            }
+            if base == 0x03 {
+                flags.set_has_sighash_single(true);
+            }
+        }
+    }
+
     /// Attempts to create all signatures required by this PSBT's `bip32_derivation` field, adding
     /// them to `partial_sigs`.
     ///
@@ -305,6 +598,77 @@ impl PartiallySignedTransaction {
         Ok(used)
     }
 
+    /// Attempts to create all signatures required by this PSBT's `tap_key_origins` field, adding
+    /// them to `tap_key_sig` and `tap_script_sigs`.
+    ///
+    /// # Returns
+    ///
+    /// - Ok: A list of the x-only public keys used in signing. For a key path spend this is the
+    ///   internal key, not the tweaked output key.
+    /// - Err: Error encountered trying to calculate the sighash AND we had the signing key.
+    fn bip32_sign_schnorr<C, K, T>(
+        &mut self,
+        k: &K,
+        input_index: usize,
+        cache: &mut SighashCache<T>,
+        secp: &Secp256k1<C>,
+    ) -> Result<Vec<XOnlyPublicKey>, SignError>
+    where
+        C: Signing + Verification,
+        T: Borrow<Transaction>,
+        K: GetKey,
+    {
+        let mut input = self.checked_input(input_index)?.clone();
+
+        let mut used = vec![]; // List of pubkeys used to sign the input.
+
+        for (&xonly, (leaf_hashes, key_source)) in input.tap_key_origins.iter() {
+            let sk = if let Ok(Some(sk)) = k.get_key(KeyRequest::Bip32(key_source.clone()), secp) {
+                sk
+            } else if let Ok(Some(sk)) = k.get_key(KeyRequest::XOnlyPubkey(xonly), secp) {
+                sk
+            } else {
+                continue;
+            };
+            let keypair = KeyPair::from_secret_key(secp, &sk.inner);
+            let mut signed = false;
+
+            // BIP-371: the internal key has no leaf hashes, it is only used for key path spends.
+            if input.tap_internal_key == Some(xonly)
+                && leaf_hashes.is_empty()
+                && input.tap_key_sig.is_none()
+            {
+                let (msg, hash_ty) = self.sighash_taproot(input_index, cache, None)?;
+                let tweaked = keypair.tap_tweak(secp, input.tap_merkle_root).to_inner();
+                let sig = secp.sign_schnorr_no_aux_rand(&msg, &tweaked);
+
+                input.tap_key_sig = Some(taproot::Signature { sig, hash_ty });
+                signed = true;
+            }
+
+            for &leaf_hash in leaf_hashes {
+                if input.tap_script_sigs.contains_key(&(xonly, leaf_hash)) {
+                    continue;
+                }
+                let (msg, hash_ty) = self.sighash_taproot(input_index, cache, Some(leaf_hash))?;
+                let sig = secp.sign_schnorr_no_aux_rand(&msg, &keypair);
+
+                input
+                    .tap_script_sigs
+                    .insert((xonly, leaf_hash), taproot::Signature { sig, hash_ty });
+                signed = true;
+            }
+
+            if signed {
+                used.push(xonly);
+            }
+        }
+
+        self.inputs[input_index] = input; // Index checked in call to `checked_input`.
+
+        Ok(used)
+    }
+
     /// Returns the sighash message to sign an ECDSA input along with the sighash type.
     ///
     /// Uses the [`EcdsaSighashType`] from this input if one is specified. If no sighash type is
@@ -362,12 +726,55 @@ impl PartiallySignedTransaction {
                 Ok((Message::from(sighash), hash_ty))
             }
             Tr => {
-                // This PSBT signing API is WIP, taproot to come shortly.
-                Err(SignError::Unsupported)
+                // Taproot inputs are signed using `sighash_taproot`.
+                Err(SignError::WrongSigningAlgorithm)
             }
         }
     }
 
+    /// Returns the sighash message to sign a taproot input along with the sighash type.
+    ///
+    /// Uses the [`TapSighashType`] from this input if one is specified. If no sighash type is
+    /// specified uses [`TapSighashType::Default`]. Computes the key path sighash if `leaf_hash` is
+    /// `None` and the script path sighash for the given leaf otherwise. This function does not
+    /// support scripts that contain `OP_CODESEPARATOR`.
+    ///
+    /// Unless the sighash type is `ANYONECANPAY` the spent utxos of _all_ inputs are required.
+    pub fn sighash_taproot<T: Borrow<Transaction>>(
+        &self,
+        input_index: usize,
+        cache: &mut SighashCache<T>,
+        leaf_hash: Option<TapLeafHash>,
+    ) -> Result<(Message, TapSighashType), SignError> {
+        if self.signing_algorithm(input_index)? != SigningAlgorithm::Schnorr {
+            return Err(SignError::WrongSigningAlgorithm);
+        }
+
+        let input = self.checked_input(input_index)?;
+        let hash_ty = input.taproot_hash_ty().map_err(|_| SignError::InvalidSighashType)?;
+
+        let utxos;
+        let prevouts = if (hash_ty as u8) & 0x80 == 0x80 {
+            Prevouts::One(input_index, self.spend_utxo(input_index)?)
+        } else {
+            utxos = (0..self.inputs.len())
+                .map(|i| self.spend_utxo(i))
+                .collect::<Result<Vec<_>, _>>()?;
+            Prevouts::All(&utxos)
+        };
+
+        let sighash = match leaf_hash {
+            Some(leaf_hash) => cache.taproot_script_spend_signature_hash(
+                input_index,
+                &prevouts,
+                leaf_hash,
+                hash_ty,
+            )?,
+            None => cache.taproot_key_spend_signature_hash(input_index, &prevouts, hash_ty)?,
+        };
+        Ok((Message::from(sighash), hash_ty))
+    }
+
     /// Returns the spending utxo for this PSBT's input at `input_index`.
     pub fn spend_utxo(&self, input_index: usize) -> Result<&TxOut, SignError> {
         let input = self.checked_input(input_index)?;
@@ -469,6 +876,12 @@ impl PartiallySignedTransaction {
     }
 }
 
+/// Reorders `items` so that the item at index `i` is the one previously at `order[i]`.
+fn permute<T>(items: &mut Vec<T>, order: &[usize]) {
+    let mut old = core::mem::take(items).into_iter().map(Some).collect::<Vec<_>>();
+    *items = order.iter().map(|&i| old[i].take().expect("order is a permutation")).collect();
+}
+
 /// Data required to call [`GetKey`] to get the private key to sign an input.
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[non_exhaustive]
@@ -477,6 +890,8 @@ pub enum KeyRequest {
     Pubkey(PublicKey),
     /// Request a private key using BIP-32 fingerprint and derivation path.
     Bip32(KeySource),
+    /// Request a private key using the associated x-only public key (taproot).
+    XOnlyPubkey(XOnlyPublicKey),
 }
 
 /// Trait to get a private key from a key request, key is then used to sign an input.
@@ -506,7 +921,7 @@ impl GetKey for ExtendedPrivKey {
         secp: &Secp256k1<C>,
     ) -> Result<Option<PrivateKey>, Self::Error> {
         match key_request {
-            KeyRequest::Pubkey(_) => Err(GetKeyError::NotSupported),
+            KeyRequest::Pubkey(_) | KeyRequest::XOnlyPubkey(_) => Err(GetKeyError::NotSupported),
             KeyRequest::Bip32((fingerprint, path)) => {
                 let key = if self.fingerprint(secp) == fingerprint {
                     let k = self.derive_priv(secp, &path)?;
@@ -520,8 +935,20 @@ impl GetKey for ExtendedPrivKey {
     }
 }
 
-/// Map of input index -> pubkey associated with secret key used to create signature for that input.
-pub type SigningKeys = BTreeMap<usize, Vec<PublicKey>>;
+/// Map of input index -> keys associated with secret key used to create signature for that input.
+pub type SigningKeysMap = BTreeMap<usize, SigningKeys>;
+
+/// The public keys associated with the secret keys used to sign an input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SigningKeys {
+    /// Keys used to sign an ECDSA input.
+    Ecdsa(Vec<PublicKey>),
+    /// Keys used to sign a taproot input.
+    ///
+    /// For a key path spend this is the internal key, for a script path spend this is the key
+    /// committed to in the leaf script.
+    Schnorr(Vec<XOnlyPublicKey>),
+}
 
 /// Map of input index -> the error encountered while attempting to sign that input.
 pub type SigningErrors = BTreeMap<usize, SignError>;
@@ -539,7 +966,7 @@ impl GetKey for $set<ExtendedPrivKey> {
         secp: &Secp256k1<C>
     ) -> Result<Option<PrivateKey>, Self::Error> {
         match key_request {
-            KeyRequest::Pubkey(_) => Err(GetKeyError::NotSupported),
+            KeyRequest::Pubkey(_) | KeyRequest::XOnlyPubkey(_) => Err(GetKeyError::NotSupported),
             KeyRequest::Bip32((fingerprint, path)) => {
                 for xpriv in self.iter() {
                     if xpriv.parent_fingerprint == fingerprint {
@@ -570,6 +997,12 @@ impl GetKey for $map<PublicKey, PrivateKey> {
     ) -> Result<Option<PrivateKey>, Self::Error> {
         match key_request {
             KeyRequest::Pubkey(pk) => Ok(self.get(&pk).cloned()),
+            KeyRequest::XOnlyPubkey(xonly) => {
+                // The map is keyed by full public keys so look up both possible parities.
+                let even = PublicKey::new(xonly.public_key(secp256k1::Parity::Even));
+                let odd = PublicKey::new(xonly.public_key(secp256k1::Parity::Odd));
+                Ok(self.get(&even).or_else(|| self.get(&odd)).cloned())
+            }
             KeyRequest::Bip32(_) => Err(GetKeyError::NotSupported),
         }
     }
@@ -830,6 +1263,7 @@ mod tests {
     use crate::blockdata::script::ScriptBuf;
     use crate::blockdata::transaction::{OutPoint, Sequence, Transaction, TxIn, TxOut};
     use crate::blockdata::witness::Witness;
+    use crate::hash_types::Txid;
     use crate::hashes::{hash160, ripemd160, sha256, Hash};
     use crate::internal_macros::hex;
     use crate::network::constants::Network::Bitcoin;
@@ -848,6 +1282,8 @@ mod tests {
             },
             xpub: Default::default(),
             version: 0,
+            fallback_lock_time: None,
+            tx_modifiable: None,
             proprietary: BTreeMap::new(),
             unknown: BTreeMap::new(),
 
@@ -946,6 +1382,8 @@ mod tests {
             },
             xpub: Default::default(),
             version: 0,
+            fallback_lock_time: None,
+            tx_modifiable: None,
             proprietary: Default::default(),
             unknown: Default::default(),
             inputs: vec![Input::default()],
@@ -1033,6 +1471,8 @@ mod tests {
 
         let psbt = PartiallySignedTransaction {
             version: 0,
+            fallback_lock_time: None,
+            tx_modifiable: None,
             xpub: {
                 let xpub: ExtendedPubKey =
                     "xpub661MyMwAqRbcGoRVtwfvzZsq2VBJR1LAHfQstHUoxqDorV89vRoMxUZ27kLrraAj6MPi\
@@ -1207,6 +1647,8 @@ mod tests {
                 },
                 xpub: Default::default(),
                 version: 0,
+                fallback_lock_time: None,
+                tx_modifiable: None,
                 proprietary: BTreeMap::new(),
                 unknown: BTreeMap::new(),
 
@@ -1538,6 +1980,8 @@ mod tests {
                 ],
             },
             version: 0,
+            fallback_lock_time: None,
+            tx_modifiable: None,
             xpub: Default::default(),
             proprietary: Default::default(),
             unknown: BTreeMap::new(),
@@ -1624,6 +2068,70 @@ mod tests {
         assert!(!rtt.proprietary.is_empty());
     }
 
+    #[test]
+    fn serialize_then_deserialize_musig2() {
+        use secp256k1::SecretKey;
+
+        use crate::crypto::musig2::{KeyAggContext, PartialSignature, SecretNonce};
+        use crate::taproot::TapLeafHash;
+
+        let secp = Secp256k1::new();
+        let sk1 = SecretKey::from_slice(&[1; 32]).unwrap();
+        let sk2 = SecretKey::from_slice(&[2; 32]).unwrap();
+        let pk1 = sk1.public_key(&secp);
+        let pk2 = sk2.public_key(&secp);
+        let agg_pk = KeyAggContext::new(&secp, &[pk1, pk2]).unwrap().public_key();
+        let leaf_hash = TapLeafHash::from_byte_array([3; 32]);
+
+        let (_, nonce1) = SecretNonce::generate(&secp, [4; 32], Some(&sk1), &pk1, None, None, None);
+        let (_, nonce2) = SecretNonce::generate(&secp, [5; 32], Some(&sk2), &pk2, None, None, None);
+        let psig = PartialSignature::from_slice(&[6; 32]).unwrap();
+
+        let mut input = Input::default();
+        input.musig2_participant_pubkeys.insert(agg_pk, vec![pk1, pk2]);
+        input.musig2_pub_nonces.insert((pk1, agg_pk, None), nonce1);
+        input.musig2_pub_nonces.insert((pk2, agg_pk, Some(leaf_hash)), nonce2);
+        input.musig2_partial_sigs.insert((pk1, agg_pk, None), psig);
+        let output = Output {
+            musig2_participant_pubkeys: input.musig2_participant_pubkeys.clone(),
+            ..Default::default()
+        };
+
+        let rtt = Input::deserialize(&input.serialize()).unwrap();
+        assert!(rtt.unknown.is_empty());
+        assert_eq!(rtt, input);
+        let rtt = Output::deserialize(&output.serialize()).unwrap();
+        assert!(rtt.unknown.is_empty());
+        assert_eq!(rtt, output);
+
+        // Each signer contributes its own nonce, combining collects both.
+        let mut combined = Input::default();
+        combined.musig2_pub_nonces.insert((pk1, agg_pk, None), nonce1);
+        let mut other = Input::default();
+        other.musig2_pub_nonces.insert((pk2, agg_pk, Some(leaf_hash)), nonce2);
+        combined.combine(other);
+        assert_eq!(combined.musig2_pub_nonces, input.musig2_pub_nonces);
+
+        // Public nonces must be 66 bytes.
+        let pair = raw::Pair {
+            key: raw::Key { type_value: 0x1b, key: [pk1.serialize(), agg_pk.serialize()].concat() },
+            value: vec![2; 65],
+        };
+        let mut bytes = pair.serialize();
+        bytes.push(0x00);
+        let err = Input::deserialize(&bytes).unwrap_err();
+        assert_eq!(err.to_string(), "invalid MuSig2 data");
+
+        // Keys must be two compressed public keys and an optional leaf hash.
+        let pair = raw::Pair {
+            key: raw::Key { type_value: 0x1c, key: pk1.serialize().to_vec() },
+            value: psig.serialize().to_vec(),
+        };
+        let mut bytes = pair.serialize();
+        bytes.push(0x00);
+        assert!(Input::deserialize(&bytes).is_err());
+    }
+
     // PSBTs taken from BIP 174 test vectors.
     #[test]
     fn combine_psbts() {
@@ -1674,6 +2182,41 @@ mod tests {
         assert_eq!(got.unwrap(), priv_key)
     }
 
+    #[test]
+    fn sort_bip69() {
+        let txin = |n: u8| TxIn {
+            previous_output: OutPoint::new(Txid::hash(&[n]), 0),
+            ..Default::default()
+        };
+        let txout = |value| TxOut { value, ..Default::default() };
+        let tx = Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::ZERO,
+            input: (0..3).map(txin).collect(),
+            output: vec![txout(3), txout(1), txout(2)],
+        };
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx.clone()).unwrap();
+        for (i, input) in psbt.inputs.iter_mut().enumerate() {
+            input.witness_utxo = Some(txout(i as u64));
+        }
+        for (i, output) in psbt.outputs.iter_mut().enumerate() {
+            output.redeem_script = Some(ScriptBuf::from_bytes(vec![i as u8]));
+        }
+
+        let (input_order, output_order) = psbt.sort_bip69();
+        let mut sorted = tx.clone();
+        sorted.sort_bip69();
+        assert_eq!(psbt.unsigned_tx, sorted);
+        assert_eq!(output_order, vec![1, 2, 0]);
+        for (i, &old) in input_order.iter().enumerate() {
+            assert_eq!(psbt.unsigned_tx.input[i], tx.input[old]);
+            assert_eq!(psbt.inputs[i].witness_utxo, Some(txout(old as u64)));
+        }
+        for (i, &old) in output_order.iter().enumerate() {
+            assert_eq!(psbt.outputs[i].redeem_script, Some(ScriptBuf::from_bytes(vec![old as u8])));
+        }
+    }
+
     #[test]
     fn test_fee() {
         let output_0_val = 99999699;
@@ -1707,6 +2250,8 @@ mod tests {
             },
             xpub: Default::default(),
             version: 0,
+            fallback_lock_time: None,
+            tx_modifiable: None,
             proprietary: BTreeMap::new(),
             unknown: BTreeMap::new(),
 
@@ -1826,6 +2371,235 @@ mod tests {
         let sigs = psbt.sign(&key_map, &secp).unwrap();
 
         assert!(sigs.len() == 1);
-        assert!(sigs[&0] == vec![pk]);
+        assert!(sigs[&0] == SigningKeys::Ecdsa(vec![pk]));
+    }
+
+    #[test]
+    #[cfg(feature = "rand-std")]
+    fn sign_psbt_taproot() {
+        use crate::bip32::{DerivationPath, Fingerprint};
+        use crate::blockdata::opcodes::all::OP_CHECKSIG;
+        use crate::blockdata::script::Builder;
+        use crate::taproot::{LeafVersion, TaprootBuilder};
+
+        let unsigned_tx = Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default(), TxIn::default()],
+            output: vec![TxOut::default()],
+        };
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned_tx).unwrap();
+
+        let (internal_priv, internal_pk, secp) = gen_keys();
+        let (leaf_priv, leaf_pk, _) = gen_keys();
+        let internal_key = XOnlyPublicKey::from(internal_pk.inner);
+        let leaf_key = XOnlyPublicKey::from(leaf_pk.inner);
+
+        let mut key_map = BTreeMap::new();
+        key_map.insert(internal_pk, internal_priv);
+        key_map.insert(leaf_pk, leaf_priv);
+
+        let leaf_script =
+            Builder::new().push_x_only_key(&leaf_key).push_opcode(OP_CHECKSIG).into_script();
+        let spend_info = TaprootBuilder::new()
+            .add_leaf(0, leaf_script.clone())
+            .unwrap()
+            .finalize(&secp, internal_key)
+            .unwrap();
+        let leaf_hash = TapLeafHash::from_script(&leaf_script, LeafVersion::TapScript);
+        let txout = TxOut {
+            value: 10,
+            script_pubkey: ScriptBuf::new_v1_p2tr_tweaked(spend_info.output_key()),
+        };
+        let origin = (Fingerprint::default(), DerivationPath::default());
+
+        // First input is a key path spend.
+        psbt.inputs[0].witness_utxo = Some(txout.clone());
+        psbt.inputs[0].tap_internal_key = Some(internal_key);
+        psbt.inputs[0].tap_merkle_root = spend_info.merkle_root();
+        psbt.inputs[0].tap_key_origins.insert(internal_key, (vec![], origin.clone()));
+
+        // Second input is a script path spend.
+        psbt.inputs[1].witness_utxo = Some(txout.clone());
+        psbt.inputs[1].tap_internal_key = Some(internal_key);
+        psbt.inputs[1].tap_merkle_root = spend_info.merkle_root();
+        psbt.inputs[1].tap_key_origins.insert(leaf_key, (vec![leaf_hash], origin));
+
+        let sigs = psbt.sign(&key_map, &secp).unwrap();
+
+        assert_eq!(sigs.len(), 2);
+        assert_eq!(sigs[&0], SigningKeys::Schnorr(vec![internal_key]));
+        assert_eq!(sigs[&1], SigningKeys::Schnorr(vec![leaf_key]));
+
+        let prevouts = [txout.clone(), txout];
+        let mut cache = SighashCache::new(&psbt.unsigned_tx);
+
+        let sig = psbt.inputs[0].tap_key_sig.expect("key path signature");
+        let msg = cache
+            .taproot_key_spend_signature_hash(0, &Prevouts::All(&prevouts), sig.hash_ty)
+            .unwrap();
+        let output_key = spend_info.output_key().to_inner();
+        secp.verify_schnorr(&sig.sig, &Message::from(msg), &output_key).unwrap();
+        assert!(psbt.inputs[0].tap_script_sigs.is_empty());
+
+        let sig = psbt.inputs[1].tap_script_sigs[&(leaf_key, leaf_hash)];
+        let msg = cache
+            .taproot_script_spend_signature_hash(
+                1,
+                &Prevouts::All(&prevouts),
+                leaf_hash,
+                sig.hash_ty,
+            )
+            .unwrap();
+        secp.verify_schnorr(&sig.sig, &Message::from(msg), &leaf_key).unwrap();
+        assert!(psbt.inputs[1].tap_key_sig.is_none());
+    }
+
+    #[test]
+    fn psbt_v2_conversion_round_trip() {
+        let hex = include_str!("../../tests/data/update_2_psbt_hex");
+        let v0 = hex_psbt!(hex.trim()).unwrap();
+
+        let v2 = v0.clone().into_v2().unwrap();
+        assert_eq!(v2.version, 2);
+        let decoded = Psbt::deserialize(&v2.serialize()).unwrap();
+        assert_eq!(decoded, v2);
+        assert_eq!(decoded.unsigned_tx, v0.unsigned_tx);
+
+        let back = decoded.into_v0().unwrap();
+        assert_eq!(back, v0);
+        assert_eq!(back.serialize_hex(), hex.trim());
+
+        let mut v1 = v0;
+        v1.unsigned_tx.version = 1;
+        let err = v1.into_v2().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "version 2 PSBTs require a transaction version of at least 2, got 1"
+        );
+    }
+
+    #[test]
+    fn psbt_v2_constructor() {
+        let height = |n| absolute::Height::from_consensus(n).unwrap();
+        let time = |n| absolute::Time::from_consensus(n).unwrap();
+        let txin = |vout| TxIn {
+            previous_output: OutPoint { txid: Txid::all_zeros(), vout },
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            ..Default::default()
+        };
+
+        assert!(Psbt::new_v2(1, None).is_err());
+        let mut psbt = Psbt::new_v2(2, Some(absolute::LockTime::from_consensus(100))).unwrap();
+        assert_eq!(psbt.determine_lock_time().unwrap().to_consensus_u32(), 100);
+
+        let both = Input {
+            required_height_lock_time: Some(height(200)),
+            required_time_lock_time: Some(time(500_000_100)),
+            ..Default::default()
+        };
+        psbt.add_input(txin(0), both).unwrap();
+        assert_eq!(psbt.unsigned_tx.lock_time.to_consensus_u32(), 200);
+
+        let time_only =
+            Input { required_time_lock_time: Some(time(500_000_200)), ..Default::default() };
+        psbt.add_input(txin(1), time_only).unwrap();
+        assert_eq!(psbt.unsigned_tx.lock_time.to_consensus_u32(), 500_000_200);
+
+        let height_only =
+            Input { required_height_lock_time: Some(height(300)), ..Default::default() };
+        let err = psbt.add_input(txin(2), height_only).unwrap_err();
+        assert_eq!(err.to_string(), "inputs require lock times of incompatible units");
+        assert_eq!(psbt.inputs.len(), 2);
+        assert_eq!(psbt.unsigned_tx.input.len(), 2);
+
+        let txout = TxOut { value: 1_000, script_pubkey: ScriptBuf::new() };
+        psbt.add_output(txout.clone(), Output::default()).unwrap();
+
+        let decoded = Psbt::deserialize(&psbt.serialize()).unwrap();
+        assert_eq!(decoded, psbt);
+        assert_eq!(decoded.unsigned_tx.input[1].previous_output.vout, 1);
+        assert_eq!(decoded.unsigned_tx.input[1].sequence, Sequence::ENABLE_RBF_NO_LOCKTIME);
+
+        let mut fixed = decoded.clone();
+        fixed.tx_modifiable = None;
+        let err = fixed.add_input(txin(3), Input::default()).unwrap_err();
+        assert_eq!(err.to_string(), "the PSBT inputs are not modifiable");
+        let err = fixed.add_output(txout, Output::default()).unwrap_err();
+        assert_eq!(err.to_string(), "the PSBT outputs are not modifiable");
+
+        let v0 = decoded.into_v0().unwrap();
+        assert_eq!(v0.unsigned_tx.lock_time.to_consensus_u32(), 500_000_200);
+        assert!(v0.inputs.iter().all(|i| i.required_time_lock_time.is_none()));
+        let err = v0.clone().add_output(TxOut::default(), Output::default()).unwrap_err();
+        assert_eq!(err.to_string(), "version error only version 2 PSBTs can be constructed");
+    }
+
+    #[test]
+    fn psbt_v2_invalid_fields() {
+        fn global(pairs: &[(u8, Vec<u8>)]) -> Vec<u8> {
+            let mut buf = b"psbt\xff".to_vec();
+            for (type_value, value) in pairs {
+                let pair = raw::Pair {
+                    key: raw::Key { type_value: *type_value, key: vec![] },
+                    value: value.clone(),
+                };
+                buf.extend(pair.serialize());
+            }
+            buf.push(0x00);
+            buf
+        }
+
+        // Version 2 without input and output counts.
+        let err = Psbt::deserialize(&global(&[(0x02, vec![2, 0, 0, 0]), (0xfb, vec![2, 0, 0, 0])]))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "version error version 2 PSBTs must contain the transaction version and the input and output counts"
+        );
+
+        // Version 0 with a version 2 field.
+        let v0 = hex_psbt!(include_str!("../../tests/data/create_psbt_hex").trim()).unwrap();
+        let mut bytes = global(&[(0x02, vec![2, 0, 0, 0])]);
+        bytes.pop();
+        bytes.extend(&v0.serialize()[5..]);
+        let err = Psbt::deserialize(&bytes).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "version error version 0 PSBTs must not contain version 2 fields"
+        );
+
+        // Version 2 with a transaction version less than 2.
+        let err = Psbt::deserialize(&global(&[
+            (0x02, vec![1, 0, 0, 0]),
+            (0x04, vec![0]),
+            (0x05, vec![0]),
+            (0xfb, vec![2, 0, 0, 0]),
+        ]))
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "version 2 PSBTs require a transaction version of at least 2, got 1"
+        );
+
+        // Unsupported version.
+        let err = Psbt::deserialize(&global(&[(0xfb, vec![1, 0, 0, 0])])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "version error PSBT versions other than 0 and 2 are not supported"
+        );
+
+        // Version 2 input without the previous outpoint.
+        let mut psbt = Psbt::new_v2(2, None).unwrap();
+        psbt.add_input(TxIn::default(), Input::default()).unwrap();
+        let mut bytes = psbt.serialize();
+        let outpoint_len = (3 + 32) + (3 + 4); // TXID and output index pairs.
+        let start = bytes.len() - 1 - outpoint_len;
+        bytes.drain(start..start + outpoint_len);
+        let err = Psbt::deserialize(&bytes).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "version error version 2 PSBT inputs must contain the previous outpoint"
+        );
     }
 }
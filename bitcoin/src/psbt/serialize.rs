@@ -13,12 +13,14 @@ use secp256k1::{self, XOnlyPublicKey};
 use super::map::{Input, Map, Output, PsbtSighashType};
 use super::Psbt;
 use crate::bip32::{ChildNumber, Fingerprint, KeySource};
+use crate::blockdata::locktime::absolute;
 use crate::blockdata::script::ScriptBuf;
-use crate::blockdata::transaction::{Transaction, TxOut};
+use crate::blockdata::transaction::{Sequence, Transaction, TxOut};
 use crate::blockdata::witness::Witness;
 use crate::consensus::encode::{self, deserialize_partial, serialize, Decodable, Encodable};
 use crate::crypto::key::PublicKey;
-use crate::crypto::{ecdsa, taproot};
+use crate::crypto::{ecdsa, musig2, taproot};
+use crate::hash_types::Txid;
 use crate::hashes::{hash160, ripemd160, sha256, sha256d, Hash};
 use crate::prelude::*;
 use crate::psbt::{Error, PartiallySignedTransaction};
@@ -44,6 +46,10 @@ impl PartiallySignedTransaction {
     pub fn serialize_hex(&self) -> String { self.serialize().to_lower_hex_string() }
 
     /// Serialize as raw binary data
+    ///
+    /// The input and output maps must match the inputs and outputs of the unsigned transaction,
+    /// see [`Self::validate`]. This holds for PSBTs created by this library, which also never
+    /// give version 0 PSBTs the version 2 fields that can't be serialized in version 0.
     pub fn serialize(&self) -> Vec<u8> {
         let mut buf: Vec<u8> = Vec::new();
 
@@ -54,12 +60,22 @@ impl PartiallySignedTransaction {
 
         buf.extend(self.serialize_map());
 
-        for i in &self.inputs {
-            buf.extend(i.serialize_map());
-        }
+        if self.version < 2 {
+            for i in &self.inputs {
+                buf.extend(i.serialize_map());
+            }
+
+            for i in &self.outputs {
+                buf.extend(i.serialize_map());
+            }
+        } else {
+            for (i, txin) in self.inputs.iter().zip(&self.unsigned_tx.input) {
+                buf.extend(i.serialize_map_v2(txin));
+            }
 
-        for i in &self.outputs {
-            buf.extend(i.serialize_map());
+            for (i, txout) in self.outputs.iter().zip(&self.unsigned_tx.output) {
+                buf.extend(i.serialize_map_v2(txout));
+            }
         }
 
         buf
@@ -79,41 +95,51 @@ impl PartiallySignedTransaction {
 
         let mut d = bytes.get(5..).ok_or(Error::NoMorePairs)?;
 
-        let mut global = Psbt::decode_global(&mut d)?;
+        let (mut global, inputs_len, outputs_len) = Psbt::decode_global(&mut d)?;
         global.unsigned_tx_checks()?;
 
-        let inputs: Vec<Input> = {
-            let inputs_len: usize = (global.unsigned_tx.input).len();
-
-            let mut inputs: Vec<Input> = Vec::with_capacity(inputs_len);
-
-            for _ in 0..inputs_len {
+        // Version 2 PSBTs carry their own input and output counts, so we can't trust them for
+        // preallocation; every map consumes at least one byte so decoding stops at the end of data.
+        let mut inputs: Vec<Input> = Vec::new();
+        for _ in 0..inputs_len {
+            if global.version < 2 {
                 inputs.push(Input::decode(&mut d)?);
+            } else {
+                let (input, txin) = Input::decode_v2(&mut d)?;
+                global.unsigned_tx.input.push(txin);
+                inputs.push(input);
             }
+        }
 
-            inputs
-        };
-
-        let outputs: Vec<Output> = {
-            let outputs_len: usize = (global.unsigned_tx.output).len();
-
-            let mut outputs: Vec<Output> = Vec::with_capacity(outputs_len);
-
-            for _ in 0..outputs_len {
+        let mut outputs: Vec<Output> = Vec::new();
+        for _ in 0..outputs_len {
+            if global.version < 2 {
                 outputs.push(Output::decode(&mut d)?);
+            } else {
+                let (output, txout) = Output::decode_v2(&mut d)?;
+                global.unsigned_tx.output.push(txout);
+                outputs.push(output);
             }
-
-            outputs
-        };
+        }
 
         global.inputs = inputs;
         global.outputs = outputs;
+        if global.version >= 2 {
+            global.unsigned_tx.lock_time = global.determine_lock_time()?;
+        }
         Ok(global)
     }
 }
 impl_psbt_de_serialize!(Transaction);
 impl_psbt_de_serialize!(TxOut);
 impl_psbt_de_serialize!(Witness);
+impl_psbt_de_serialize!(Sequence);
+impl_psbt_de_serialize!(absolute::LockTime);
+impl_psbt_de_serialize!(VarInt);
+impl_psbt_de_serialize!(i32);
+impl_psbt_de_serialize!(u32);
+impl_psbt_de_serialize!(u64);
+impl_psbt_hash_de_serialize!(Txid);
 impl_psbt_hash_de_serialize!(ripemd160::Hash);
 impl_psbt_hash_de_serialize!(sha256::Hash);
 impl_psbt_hash_de_serialize!(TapLeafHash);
@@ -241,6 +267,28 @@ impl Deserialize for PsbtSighashType {
     }
 }
 
+impl Serialize for absolute::Time {
+    fn serialize(&self) -> Vec<u8> { serialize(&self.to_consensus_u32()) }
+}
+
+impl Deserialize for absolute::Time {
+    fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
+        let n: u32 = encode::deserialize(bytes)?;
+        absolute::Time::from_consensus(n).map_err(Error::LockTime)
+    }
+}
+
+impl Serialize for absolute::Height {
+    fn serialize(&self) -> Vec<u8> { serialize(&self.to_consensus_u32()) }
+}
+
+impl Deserialize for absolute::Height {
+    fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
+        let n: u32 = encode::deserialize(bytes)?;
+        absolute::Height::from_consensus(n).map_err(Error::LockTime)
+    }
+}
+
 // Taproot related ser/deser
 impl Serialize for XOnlyPublicKey {
     fn serialize(&self) -> Vec<u8> { XOnlyPublicKey::serialize(self).to_vec() }
@@ -382,6 +430,75 @@ impl Deserialize for TapTree {
     }
 }
 
+impl Serialize for Vec<secp256k1::PublicKey> {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.len() * secp256k1::constants::PUBLIC_KEY_SIZE);
+        for pk in self {
+            buf.extend(&pk.serialize());
+        }
+        buf
+    }
+}
+
+impl Deserialize for Vec<secp256k1::PublicKey> {
+    fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.is_empty() || bytes.len() % secp256k1::constants::PUBLIC_KEY_SIZE != 0 {
+            return Err(Error::InvalidSecp256k1PublicKey(secp256k1::Error::InvalidPublicKey));
+        }
+        bytes.chunks(secp256k1::constants::PUBLIC_KEY_SIZE).map(Deserialize::deserialize).collect()
+    }
+}
+
+// MuSig2 participant key, aggregate key and optional leaf hash
+impl Serialize for (secp256k1::PublicKey, secp256k1::PublicKey, Option<TapLeafHash>) {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(2 * secp256k1::constants::PUBLIC_KEY_SIZE + 32);
+        buf.extend(&self.0.serialize());
+        buf.extend(&self.1.serialize());
+        if let Some(leaf_hash) = self.2 {
+            buf.extend(leaf_hash.as_byte_array());
+        }
+        buf
+    }
+}
+
+impl Deserialize for (secp256k1::PublicKey, secp256k1::PublicKey, Option<TapLeafHash>) {
+    fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
+        const KEYS_LEN: usize = 2 * secp256k1::constants::PUBLIC_KEY_SIZE;
+        if bytes.len() != KEYS_LEN && bytes.len() != KEYS_LEN + 32 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        let participant: secp256k1::PublicKey = Deserialize::deserialize(&bytes[..33])?;
+        let aggregate: secp256k1::PublicKey = Deserialize::deserialize(&bytes[33..KEYS_LEN])?;
+        let leaf_hash = if bytes.len() > KEYS_LEN {
+            Some(Deserialize::deserialize(&bytes[KEYS_LEN..])?)
+        } else {
+            None
+        };
+        Ok((participant, aggregate, leaf_hash))
+    }
+}
+
+impl Serialize for musig2::PublicNonce {
+    fn serialize(&self) -> Vec<u8> { musig2::PublicNonce::serialize(self).to_vec() }
+}
+
+impl Deserialize for musig2::PublicNonce {
+    fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
+        musig2::PublicNonce::from_slice(bytes).map_err(Error::InvalidMusig2Data)
+    }
+}
+
+impl Serialize for musig2::PartialSignature {
+    fn serialize(&self) -> Vec<u8> { musig2::PartialSignature::serialize(self).to_vec() }
+}
+
+impl Deserialize for musig2::PartialSignature {
+    fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
+        musig2::PartialSignature::from_slice(bytes).map_err(Error::InvalidMusig2Data)
+    }
+}
+
 // Helper function to compute key source len
 fn key_source_len(key_source: &KeySource) -> usize { 4 + 4 * (key_source.1).as_ref().len() }
 
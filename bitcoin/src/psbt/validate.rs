@@ -0,0 +1,542 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! PSBT consistency validation.
+//!
+//! Deserialization only checks that a PSBT is well formed, the checks here make sure that the data
+//! in the key-value maps agrees with itself and with the unsigned transaction (BIP 174, BIP 371).
+//!
+
+use core::fmt;
+
+use secp256k1::{Secp256k1, Verification, XOnlyPublicKey};
+
+use crate::blockdata::script::{Script, ScriptBuf};
+use crate::blockdata::transaction::{TxIn, TxOut};
+use crate::crypto::key::TapTweak;
+use crate::hash_types::Txid;
+use crate::prelude::*;
+use crate::psbt::{Input, Output, PartiallySignedTransaction, PsbtSighashType};
+use crate::taproot::{ControlBlock, LeafVersion, TapLeafHash, TapNodeHash};
+
+impl PartiallySignedTransaction {
+    /// Checks that the data in this PSBT is consistent, so that it is safe to sign.
+    ///
+    /// The following is checked:
+    ///
+    /// - There is one input and one output map per input and output of the unsigned transaction.
+    /// - `non_witness_utxo` is the transaction spent by the input and agrees with `witness_utxo`.
+    /// - `redeem_script` and `witness_script` hash to the spent script pubkey of an input, or to
+    ///   the script pubkey of an output.
+    /// - All signatures of an input use its `sighash_type`, if one is set.
+    /// - `tap_internal_key` tweaked with `tap_merkle_root` (or the root of `tap_tree`) is the
+    ///   taproot output key, and every control block in `tap_scripts` commits to its script using
+    ///   `tap_internal_key`. Inputs with `tap_scripts` but without `tap_merkle_root` are only
+    ///   checked by their control blocks.
+    ///
+    /// Missing data is not a violation and signatures are not verified.
+    ///
+    /// # Errors
+    ///
+    /// All violations found, grouped by input and output.
+    pub fn validate<C: Verification>(&self, secp: &Secp256k1<C>) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+
+        if self.inputs.len() != self.unsigned_tx.input.len() {
+            errors.global.push(ValidationError::InputCountMismatch {
+                inputs: self.inputs.len(),
+                tx_inputs: self.unsigned_tx.input.len(),
+            });
+        }
+        if self.outputs.len() != self.unsigned_tx.output.len() {
+            errors.global.push(ValidationError::OutputCountMismatch {
+                outputs: self.outputs.len(),
+                tx_outputs: self.unsigned_tx.output.len(),
+            });
+        }
+
+        for (i, (input, txin)) in self.inputs.iter().zip(&self.unsigned_tx.input).enumerate() {
+            let violations = validate_input(secp, input, txin);
+            if !violations.is_empty() {
+                errors.inputs.insert(i, violations);
+            }
+        }
+
+        for (i, (output, txout)) in self.outputs.iter().zip(&self.unsigned_tx.output).enumerate() {
+            let violations = validate_output(secp, output, txout);
+            if !violations.is_empty() {
+                errors.outputs.insert(i, violations);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+fn validate_input<C: Verification>(
+    secp: &Secp256k1<C>,
+    input: &Input,
+    txin: &TxIn,
+) -> Vec<ValidationError> {
+    let mut errors = vec![];
+    let prevout = txin.previous_output;
+
+    let mut spent = input.witness_utxo.as_ref();
+    if let Some(ref tx) = input.non_witness_utxo {
+        let txid = tx.txid();
+        if txid != prevout.txid {
+            errors.push(ValidationError::NonWitnessUtxoTxidMismatch {
+                expected: prevout.txid,
+                actual: txid,
+            });
+        } else {
+            match tx.output.get(prevout.vout as usize) {
+                None => errors.push(ValidationError::NonWitnessUtxoMissingOutput(prevout.vout)),
+                Some(txout) => match input.witness_utxo {
+                    Some(ref witness_utxo) if witness_utxo != txout => {
+                        // We don't know which one is spent, so skip the script checks.
+                        errors.push(ValidationError::WitnessUtxoMismatch);
+                        spent = None;
+                    }
+                    Some(_) => {}
+                    None => spent = Some(txout),
+                },
+            }
+        }
+    }
+
+    if let Some(spent) = spent {
+        check_scripts(
+            &spent.script_pubkey,
+            input.redeem_script.as_ref(),
+            input.witness_script.as_ref(),
+            &mut errors,
+        );
+        if let Some(internal_key) = input.tap_internal_key {
+            // Without the optional merkle root script path spends are checked by the control
+            // blocks only.
+            if input.tap_merkle_root.is_some() || input.tap_scripts.is_empty() {
+                check_tap_output_key(
+                    secp,
+                    &spent.script_pubkey,
+                    internal_key,
+                    input.tap_merkle_root,
+                    &mut errors,
+                );
+            }
+        }
+        for (control_block, (script, leaf_version)) in &input.tap_scripts {
+            check_control_block(
+                secp,
+                &spent.script_pubkey,
+                input.tap_internal_key,
+                control_block,
+                script,
+                *leaf_version,
+                &mut errors,
+            );
+        }
+    }
+
+    if let Some(expected) = input.sighash_type {
+        let sighash_types = input
+            .partial_sigs
+            .values()
+            .map(|sig| PsbtSighashType::from(sig.hash_ty))
+            .chain(input.tap_key_sig.iter().map(|sig| PsbtSighashType::from(sig.hash_ty)))
+            .chain(input.tap_script_sigs.values().map(|sig| PsbtSighashType::from(sig.hash_ty)));
+        for actual in sighash_types {
+            if actual != expected {
+                errors.push(ValidationError::SighashTypeMismatch { expected, actual });
+                break;
+            }
+        }
+    }
+
+    errors
+}
+
+fn validate_output<C: Verification>(
+    secp: &Secp256k1<C>,
+    output: &Output,
+    txout: &TxOut,
+) -> Vec<ValidationError> {
+    let mut errors = vec![];
+
+    check_scripts(
+        &txout.script_pubkey,
+        output.redeem_script.as_ref(),
+        output.witness_script.as_ref(),
+        &mut errors,
+    );
+    if let Some(internal_key) = output.tap_internal_key {
+        let merkle_root = output.tap_tree.as_ref().map(|tree| tree.node_info().hash);
+        check_tap_output_key(secp, &txout.script_pubkey, internal_key, merkle_root, &mut errors);
+    }
+
+    errors
+}
+
+/// Checks that the redeem and witness scripts hash to `script_pubkey`.
+fn check_scripts(
+    script_pubkey: &Script,
+    redeem_script: Option<&ScriptBuf>,
+    witness_script: Option<&ScriptBuf>,
+    errors: &mut Vec<ValidationError>,
+) {
+    let mut witness_program = script_pubkey;
+    if let Some(redeem_script) = redeem_script {
+        if redeem_script.to_p2sh() == *script_pubkey {
+            witness_program = redeem_script;
+        } else {
+            errors.push(ValidationError::RedeemScriptMismatch);
+            return;
+        }
+    }
+    if let Some(witness_script) = witness_script {
+        if witness_script.to_v0_p2wsh() != *witness_program {
+            errors.push(ValidationError::WitnessScriptMismatch);
+        }
+    }
+}
+
+/// Returns the output key of a P2TR `script_pubkey`.
+fn tap_output_key(script_pubkey: &Script) -> Option<XOnlyPublicKey> {
+    if script_pubkey.is_v1_p2tr() {
+        XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..]).ok()
+    } else {
+        None
+    }
+}
+
+/// Checks that `internal_key` tweaked with `merkle_root` is the output key of `script_pubkey`.
+fn check_tap_output_key<C: Verification>(
+    secp: &Secp256k1<C>,
+    script_pubkey: &Script,
+    internal_key: XOnlyPublicKey,
+    merkle_root: Option<TapNodeHash>,
+    errors: &mut Vec<ValidationError>,
+) {
+    let (tweaked, _) = internal_key.tap_tweak(secp, merkle_root);
+    if tap_output_key(script_pubkey) != Some(tweaked.to_inner()) {
+        errors.push(ValidationError::TapOutputKeyMismatch);
+    }
+}
+
+/// Checks that `control_block` proves `script` is committed to by the output key of
+/// `script_pubkey`, using `internal_key`.
+fn check_control_block<C: Verification>(
+    secp: &Secp256k1<C>,
+    script_pubkey: &Script,
+    internal_key: Option<XOnlyPublicKey>,
+    control_block: &ControlBlock,
+    script: &Script,
+    leaf_version: LeafVersion,
+    errors: &mut Vec<ValidationError>,
+) {
+    let leaf_hash = TapLeafHash::from_script(script, leaf_version);
+    if internal_key.map_or(false, |key| key != control_block.internal_key) {
+        errors.push(ValidationError::ControlBlockInternalKeyMismatch(leaf_hash));
+    }
+    let verified = control_block.leaf_version == leaf_version
+        && tap_output_key(script_pubkey).map_or(false, |output_key| {
+            control_block.verify_taproot_commitment(secp, output_key, script)
+        });
+    if !verified {
+        errors.push(ValidationError::InvalidControlBlock(leaf_hash));
+    }
+}
+
+/// The violations found by [`PartiallySignedTransaction::validate`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationErrors {
+    /// Violations that do not concern a single input or output.
+    pub global: Vec<ValidationError>,
+    /// Map of input index -> the violations found in that input.
+    pub inputs: BTreeMap<usize, Vec<ValidationError>>,
+    /// Map of output index -> the violations found in that output.
+    pub outputs: BTreeMap<usize, Vec<ValidationError>>,
+}
+
+impl ValidationErrors {
+    /// Returns true if no violations were found.
+    pub fn is_empty(&self) -> bool {
+        self.global.is_empty() && self.inputs.is_empty() && self.outputs.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("inconsistent PSBT")?;
+        for e in &self.global {
+            write!(f, "; {}", e)?;
+        }
+        for (i, errors) in &self.inputs {
+            for e in errors {
+                write!(f, "; input {}: {}", i, e)?;
+            }
+        }
+        for (i, errors) in &self.outputs {
+            for e in errors {
+                write!(f, "; output {}: {}", i, e)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+impl std::error::Error for ValidationErrors {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
+}
+
+/// A violation of the consistency rules checked by [`PartiallySignedTransaction::validate`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum ValidationError {
+    /// The number of input maps differs from the number of inputs of the unsigned transaction.
+    InputCountMismatch {
+        /// Number of input maps.
+        inputs: usize,
+        /// Number of inputs of the unsigned transaction.
+        tx_inputs: usize,
+    },
+    /// The number of output maps differs from the number of outputs of the unsigned transaction.
+    OutputCountMismatch {
+        /// Number of output maps.
+        outputs: usize,
+        /// Number of outputs of the unsigned transaction.
+        tx_outputs: usize,
+    },
+    /// The non-witness UTXO is not the transaction spent by the input.
+    NonWitnessUtxoTxidMismatch {
+        /// The txid of the previous outpoint of the input.
+        expected: Txid,
+        /// The txid of the non-witness UTXO.
+        actual: Txid,
+    },
+    /// The non-witness UTXO has no output with the index spent by the input.
+    NonWitnessUtxoMissingOutput(u32),
+    /// The witness UTXO differs from the spent output of the non-witness UTXO.
+    WitnessUtxoMismatch,
+    /// The redeem script does not hash to the P2SH script pubkey.
+    RedeemScriptMismatch,
+    /// The witness script does not hash to the P2WSH witness program.
+    WitnessScriptMismatch,
+    /// A signature uses a different sighash type than the `sighash_type` of the input.
+    SighashTypeMismatch {
+        /// The `sighash_type` of the input.
+        expected: PsbtSighashType,
+        /// The sighash type of the signature.
+        actual: PsbtSighashType,
+    },
+    /// The taproot internal key and merkle root do not commit to the script pubkey.
+    TapOutputKeyMismatch,
+    /// The internal key of the control block for a leaf differs from the taproot internal key.
+    ControlBlockInternalKeyMismatch(TapLeafHash),
+    /// The control block for a leaf does not prove its inclusion in the spent output.
+    InvalidControlBlock(TapLeafHash),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::ValidationError::*;
+
+        match *self {
+            InputCountMismatch { inputs, tx_inputs } =>
+                write!(f, "{} input maps for {} unsigned transaction inputs", inputs, tx_inputs),
+            OutputCountMismatch { outputs, tx_outputs } =>
+                write!(f, "{} output maps for {} unsigned transaction outputs", outputs, tx_outputs),
+            NonWitnessUtxoTxidMismatch { expected, actual } =>
+                write!(f, "non-witness utxo txid {} does not match spent txid {}", actual, expected),
+            NonWitnessUtxoMissingOutput(vout) =>
+                write!(f, "non-witness utxo has no output {}", vout),
+            WitnessUtxoMismatch =>
+                f.write_str("witness utxo does not match the spent output of the non-witness utxo"),
+            RedeemScriptMismatch => f.write_str("redeem script does not match the script pubkey"),
+            WitnessScriptMismatch =>
+                f.write_str("witness script does not match the witness program"),
+            SighashTypeMismatch { expected, actual } =>
+                write!(f, "signature uses sighash type {} instead of {}", actual, expected),
+            TapOutputKeyMismatch =>
+                f.write_str("taproot internal key and merkle root do not match the output key"),
+            ControlBlockInternalKeyMismatch(leaf_hash) =>
+                write!(f, "control block for leaf {} uses a different internal key", leaf_hash),
+            InvalidControlBlock(leaf_hash) =>
+                write!(f, "control block for leaf {} does not verify", leaf_hash),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+impl std::error::Error for ValidationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use self::ValidationError::*;
+
+        match *self {
+            InputCountMismatch { .. }
+            | OutputCountMismatch { .. }
+            | NonWitnessUtxoTxidMismatch { .. }
+            | NonWitnessUtxoMissingOutput(_)
+            | WitnessUtxoMismatch
+            | RedeemScriptMismatch
+            | WitnessScriptMismatch
+            | SighashTypeMismatch { .. }
+            | TapOutputKeyMismatch
+            | ControlBlockInternalKeyMismatch(_)
+            | InvalidControlBlock(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::{KeyPair, SecretKey};
+
+    use super::*;
+    use crate::blockdata::locktime::absolute;
+    use crate::blockdata::transaction::{OutPoint, Transaction};
+    use crate::sighash::{EcdsaSighashType, TapSighashType};
+    use crate::taproot::{LeafVersion, TaprootBuilder};
+
+    fn bip174_psbt() -> PartiallySignedTransaction {
+        let hex = include_str!("../../tests/data/update_2_psbt_hex");
+        hex_psbt!(hex.trim()).unwrap()
+    }
+
+    #[test]
+    fn valid_psbt() {
+        let secp = Secp256k1::verification_only();
+        bip174_psbt().validate(&secp).unwrap();
+    }
+
+    #[test]
+    fn utxo_violations() {
+        let secp = Secp256k1::verification_only();
+        let mut psbt = bip174_psbt();
+        // Input 0 spends a non-witness output, input 1 a P2SH-P2WSH output.
+        let other_tx = psbt.inputs[0].non_witness_utxo.clone().unwrap();
+        psbt.inputs[1].non_witness_utxo = Some(other_tx.clone());
+        psbt.inputs[0].witness_utxo = Some(TxOut::default());
+        psbt.inputs.push(Input::default());
+
+        let errors = psbt.validate(&secp).unwrap_err();
+        assert_eq!(
+            errors.global,
+            vec![ValidationError::InputCountMismatch { inputs: 3, tx_inputs: 2 }]
+        );
+        assert_eq!(errors.inputs[&0], vec![ValidationError::WitnessUtxoMismatch]);
+        assert_eq!(
+            errors.inputs[&1],
+            vec![ValidationError::NonWitnessUtxoTxidMismatch {
+                expected: psbt.unsigned_tx.input[1].previous_output.txid,
+                actual: other_tx.txid(),
+            }]
+        );
+        assert!(errors.outputs.is_empty());
+    }
+
+    #[test]
+    fn script_and_sighash_violations() {
+        let secp = Secp256k1::verification_only();
+        let mut psbt = bip174_psbt();
+        psbt.inputs[0].redeem_script = Some(ScriptBuf::from(vec![0x51]));
+        psbt.inputs[1].witness_script = Some(ScriptBuf::from(vec![0x51]));
+        psbt.outputs[0].witness_script = Some(ScriptBuf::from(vec![0x51]));
+
+        let sig = "304402204f67e2afb76142d44fae58a2495d33a3419daa26cd0db8d04f3452b63289ac0f022010762a9fb67e94cc5cad9026f6dc99ff7f070f4278d30fbc7d0c869dd38c7fe701";
+        let pk = "0339880dc92394b7355e3d0439fa283c31de7590812ea011c4245c0674a685e883";
+        psbt.inputs[1].partial_sigs.insert(pk.parse().unwrap(), sig.parse().unwrap());
+        psbt.inputs[1].sighash_type = Some(EcdsaSighashType::None.into());
+
+        let errors = psbt.validate(&secp).unwrap_err();
+        assert!(errors.global.is_empty());
+        assert_eq!(errors.inputs[&0], vec![ValidationError::RedeemScriptMismatch]);
+        assert_eq!(
+            errors.inputs[&1],
+            vec![
+                ValidationError::WitnessScriptMismatch,
+                ValidationError::SighashTypeMismatch {
+                    expected: EcdsaSighashType::None.into(),
+                    actual: EcdsaSighashType::All.into(),
+                },
+            ]
+        );
+        assert_eq!(errors.outputs[&0], vec![ValidationError::WitnessScriptMismatch]);
+    }
+
+    #[test]
+    fn taproot_violations() {
+        let secp = Secp256k1::new();
+        let keypair =
+            |b| KeyPair::from_secret_key(&secp, &SecretKey::from_slice(&[b; 32]).unwrap());
+        let internal_key = keypair(1).x_only_public_key().0;
+        let other_key = keypair(2).x_only_public_key().0;
+
+        let leaf = ScriptBuf::from(vec![0x51]);
+        let spend_info = TaprootBuilder::new()
+            .add_leaf(1, leaf.clone())
+            .unwrap()
+            .add_leaf(1, ScriptBuf::from(vec![0x52]))
+            .unwrap()
+            .finalize(&secp, internal_key)
+            .unwrap();
+        let control_block =
+            spend_info.control_block(&(leaf.clone(), LeafVersion::TapScript)).unwrap();
+        let leaf_hash = TapLeafHash::from_script(&leaf, LeafVersion::TapScript);
+        let script_pubkey = ScriptBuf::new_v1_p2tr_tweaked(spend_info.output_key());
+
+        let tx = Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn { previous_output: OutPoint::null(), ..Default::default() }],
+            output: vec![TxOut { value: 1_000, script_pubkey: script_pubkey.clone() }],
+        };
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+        let input = &mut psbt.inputs[0];
+        input.witness_utxo = Some(TxOut { value: 2_000, script_pubkey });
+        input.tap_internal_key = Some(internal_key);
+        input.tap_merkle_root = spend_info.merkle_root();
+        input.tap_scripts.insert(control_block.clone(), (leaf.clone(), LeafVersion::TapScript));
+        input.sighash_type = Some(TapSighashType::All.into());
+        psbt.validate(&secp).unwrap();
+
+        // The merkle root is optional for script path spends.
+        psbt.inputs[0].tap_merkle_root = None;
+        psbt.validate(&secp).unwrap();
+        psbt.inputs[0].tap_merkle_root = spend_info.merkle_root();
+
+        // Output is missing the tap tree, input uses the wrong internal key and a forged leaf.
+        let mut bad_control_block = control_block;
+        bad_control_block.internal_key = other_key;
+        let input = &mut psbt.inputs[0];
+        input.tap_internal_key = Some(other_key);
+        input.tap_scripts.clear();
+        input.tap_scripts.insert(bad_control_block, (leaf, LeafVersion::TapScript));
+        psbt.outputs[0].tap_internal_key = Some(internal_key);
+
+        let errors = psbt.validate(&secp).unwrap_err();
+        assert_eq!(
+            errors.inputs[&0],
+            vec![
+                ValidationError::TapOutputKeyMismatch,
+                ValidationError::InvalidControlBlock(leaf_hash),
+            ]
+        );
+        assert_eq!(errors.outputs[&0], vec![ValidationError::TapOutputKeyMismatch]);
+        assert_eq!(
+            errors.to_string(),
+            format!(
+                "inconsistent PSBT; input 0: taproot internal key and merkle root do not match the \
+                 output key; input 0: control block for leaf {} does not verify; output 0: taproot \
+                 internal key and merkle root do not match the output key",
+                leaf_hash
+            )
+        );
+    }
+}
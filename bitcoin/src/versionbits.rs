@@ -0,0 +1,322 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Version bits soft fork deployments.
+//!
+//! Soft forks are deployed by miners signalling readiness with a bit of the block version as
+//! described in [BIP-9]. If enough blocks of a period of [`Params::miner_confirmation_window`]
+//! blocks signal, the deployment locks in and becomes active a period later, or at its minimum
+//! activation height like in Speedy Trial. [BIP-8] schedules the signalling by block height instead
+//! of median time past. This module computes the state of a deployment over a [`HeaderChain`] like
+//! the version bits logic of Bitcoin Core.
+//!
+//! # Examples
+//!
+//! ```
+//! use bitcoin::consensus::Params;
+//! use bitcoin::header_chain::HeaderChain;
+//! use bitcoin::versionbits::{Deployment, Schedule, ThresholdState};
+//! use bitcoin::Network;
+//!
+//! let chain = HeaderChain::new(Params::new(Network::Bitcoin));
+//! let taproot = Deployment {
+//!     bit: 2,
+//!     schedule: Schedule::MedianTime { start: 1619222400, timeout: 1628640000 },
+//!     min_activation_height: 709632,
+//! };
+//! let tip = chain.tip().header.block_hash();
+//! assert_eq!(taproot.state(&chain, &tip), Some(ThresholdState::Defined));
+//! ```
+//!
+//! [BIP-8]: https://github.com/bitcoin/bips/blob/master/bip-0008.mediawiki
+//! [BIP-9]: https://github.com/bitcoin/bips/blob/master/bip-0009.mediawiki
+//!
+
+use crate::blockdata::block::BlockHash;
+#[cfg(doc)]
+use crate::consensus::Params;
+use crate::header_chain::{ChainEntry, HeaderChain};
+use crate::prelude::*;
+
+/// A soft fork deployed with version bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Deployment {
+    /// The version bit signalling the deployment, from 0 to 28.
+    pub bit: u8,
+    /// When signalling starts and times out.
+    pub schedule: Schedule,
+    /// The lowest height at which the deployment becomes active once it locked in.
+    pub min_activation_height: u32,
+}
+
+/// When signalling for a [`Deployment`] starts and times out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Schedule {
+    /// Signalling starts with the first period whose previous block has a median time past of at
+    /// least `start`, and fails with the first period where it is at least `timeout`, see BIP 9.
+    MedianTime {
+        /// The median time past signalling starts at.
+        start: u32,
+        /// The median time past signalling times out at.
+        timeout: u32,
+    },
+    /// Signalling starts with the period starting at height `start`, and fails with the period
+    /// starting at height `timeout`, see BIP 8.
+    Height {
+        /// The height signalling starts at, a multiple of the period.
+        start: u32,
+        /// The height signalling times out at, a multiple of the period.
+        timeout: u32,
+    },
+    /// The deployment is active from the genesis block on.
+    AlwaysActive,
+    /// The deployment never activates.
+    NeverActive,
+}
+
+impl Schedule {
+    /// Returns the point in time or height of the period following the block `entry`.
+    fn position(&self, chain: &HeaderChain, hash: &BlockHash, entry: &ChainEntry) -> u32 {
+        match *self {
+            Schedule::MedianTime { .. } =>
+                chain.median_time_past(hash).expect("the block is in the chain"),
+            _ => entry.height + 1,
+        }
+    }
+}
+
+/// The state of a [`Deployment`], the same for all blocks of a period.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ThresholdState {
+    /// Signalling hasn't started yet.
+    Defined,
+    /// Blocks signal for the deployment.
+    Started,
+    /// Enough blocks signalled, the deployment becomes active soon.
+    LockedIn,
+    /// The deployment is active.
+    Active,
+    /// Signalling timed out before the deployment locked in.
+    Failed,
+}
+
+/// Signalling statistics of the current period of a [`Deployment`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Statistics {
+    /// The number of blocks of a period.
+    pub period: u32,
+    /// The number of signalling blocks required to lock in.
+    pub threshold: u32,
+    /// The number of blocks of the period so far.
+    pub elapsed: u32,
+    /// The number of signalling blocks of the period so far.
+    pub count: u32,
+    /// Whether enough blocks can still signal in the period to lock in.
+    pub possible: bool,
+}
+
+impl Deployment {
+    /// Returns the state of the deployment for the block following the header with hash `prev`,
+    /// like `GetStateFor` of Bitcoin Core.
+    ///
+    /// The state of a period depends on the state of the previous period:
+    ///
+    /// - [`ThresholdState::Defined`] becomes [`ThresholdState::Started`] once the [`Schedule`]
+    ///   starts.
+    /// - [`ThresholdState::Started`] becomes [`ThresholdState::LockedIn`] if at least
+    ///   [`Params::rule_change_activation_threshold`] blocks of the period signalled, and
+    ///   [`ThresholdState::Failed`] otherwise once the schedule times out.
+    /// - [`ThresholdState::LockedIn`] becomes [`ThresholdState::Active`] if the height is at least
+    ///   [`Deployment::min_activation_height`].
+    ///
+    /// Returns `None` if `prev` is unknown.
+    pub fn state(&self, chain: &HeaderChain, prev: &BlockHash) -> Option<ThresholdState> {
+        let entry = chain.get(prev)?;
+        let (start, timeout) = match self.schedule {
+            Schedule::MedianTime { start, timeout } | Schedule::Height { start, timeout } =>
+                (start, timeout),
+            Schedule::AlwaysActive => return Some(ThresholdState::Active),
+            Schedule::NeverActive => return Some(ThresholdState::Failed),
+        };
+        let params = chain.params();
+        let period = params.miner_confirmation_window;
+
+        // The last blocks of the previous periods back to the one before signalling started.
+        let mut period_ends = Vec::new();
+        let next_height = entry.height + 1;
+        let mut end = if next_height < period {
+            None
+        } else {
+            let end = chain.ancestor(prev, next_height - next_height % period - 1);
+            end.map(|entry| (entry.header.block_hash(), entry))
+        };
+        while let Some((hash, entry)) = end {
+            if self.schedule.position(chain, &hash, entry) < start {
+                break;
+            }
+            period_ends.push((hash, entry));
+            end = entry.height.checked_sub(period).map(|height| {
+                let entry = chain.ancestor(&hash, height).expect("height is lower");
+                (entry.header.block_hash(), entry)
+            });
+        }
+
+        let mut state = ThresholdState::Defined;
+        while let Some((hash, entry)) = period_ends.pop() {
+            state = match state {
+                ThresholdState::Defined => ThresholdState::Started,
+                ThresholdState::Started =>
+                    if self.count_signalling(chain, hash, period)
+                        >= params.rule_change_activation_threshold
+                    {
+                        ThresholdState::LockedIn
+                    } else if self.schedule.position(chain, &hash, entry) >= timeout {
+                        ThresholdState::Failed
+                    } else {
+                        ThresholdState::Started
+                    },
+                ThresholdState::LockedIn =>
+                    if entry.height + 1 >= self.min_activation_height {
+                        ThresholdState::Active
+                    } else {
+                        ThresholdState::LockedIn
+                    },
+                state @ ThresholdState::Active | state @ ThresholdState::Failed => state,
+            };
+        }
+        Some(state)
+    }
+
+    /// Returns the signalling statistics of the period containing the header with hash `block`,
+    /// like `GetStateStatisticsFor` of Bitcoin Core.
+    ///
+    /// The statistics cover the blocks of the period up to and including `block`, so the last
+    /// block of a period gives the statistics of the complete period.
+    ///
+    /// Returns `None` if `block` is unknown.
+    pub fn statistics(&self, chain: &HeaderChain, block: &BlockHash) -> Option<Statistics> {
+        let entry = chain.get(block)?;
+        let params = chain.params();
+        let period = params.miner_confirmation_window;
+        let threshold = params.rule_change_activation_threshold;
+        let elapsed = entry.height % period + 1;
+        let count = self.count_signalling(chain, *block, elapsed);
+        let possible = period - threshold >= elapsed - count;
+        Some(Statistics { period, threshold, elapsed, count, possible })
+    }
+
+    /// Returns the number of signalling headers of the `blocks` headers up to and including the
+    /// header with hash `hash`.
+    fn count_signalling(&self, chain: &HeaderChain, mut hash: BlockHash, blocks: u32) -> u32 {
+        let mut count = 0;
+        for _ in 0..blocks {
+            let header = chain.get(&hash).expect("ancestors are in the chain").header;
+            if header.version.is_signalling_soft_fork(self.bit) {
+                count += 1;
+            }
+            hash = header.prev_blockhash;
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockdata::block::{Header, Version};
+    use crate::consensus::Params;
+    use crate::hash_types::TxMerkleNode;
+    use crate::hashes::Hash;
+    use crate::Network;
+
+    /// Mines `count` headers on top of the tip, the version of each chosen by its height.
+    fn extend(chain: &mut HeaderChain, count: u32, version: impl Fn(u32) -> Version) {
+        for _ in 0..count {
+            let tip = *chain.tip();
+            let prev = tip.header.block_hash();
+            let time = tip.header.time + 600;
+            let mut header = Header {
+                version: version(tip.height + 1),
+                prev_blockhash: prev,
+                merkle_root: TxMerkleNode::all_zeros(),
+                time,
+                bits: chain.next_work_required(&prev, time).unwrap(),
+                nonce: 0,
+            };
+            while !header.target().is_met_by(header.block_hash()) {
+                header.nonce += 1;
+            }
+            chain.accept(header, u32::max_value()).unwrap();
+        }
+    }
+
+    #[test]
+    fn state_and_statistics() {
+        let mut params = Params::new(Network::Regtest);
+        params.miner_confirmation_window = 10;
+        params.rule_change_activation_threshold = 8;
+        let mut chain = HeaderChain::new(params);
+        let genesis_time = chain.tip().header.time;
+        let schedule = Schedule::MedianTime {
+            start: genesis_time + 20 * 600,
+            timeout: genesis_time + 40 * 600,
+        };
+        let locking_in = Deployment { bit: 0, schedule, min_activation_height: 70 };
+        let failing = Deployment { bit: 1, schedule, min_activation_height: 0 };
+        let by_height = Deployment {
+            bit: 2,
+            schedule: Schedule::Height { start: 20, timeout: 40 },
+            min_activation_height: 0,
+        };
+        // The first period after the start has too few signalling blocks, the second enough.
+        extend(&mut chain, 70, |height| {
+            if (30..37).contains(&height) || (40..48).contains(&height) {
+                Version::from_consensus(0x2000_0001)
+            } else {
+                Version::TWO
+            }
+        });
+
+        use ThresholdState::*;
+        // The state of the block following each height.
+        let tests = [
+            (0, Defined, Defined, Defined),
+            (19, Defined, Defined, Started),
+            (29, Started, Started, Started),
+            (39, Started, Started, Failed),
+            (49, LockedIn, Failed, Failed),
+            (59, LockedIn, Failed, Failed),
+            (69, Active, Failed, Failed),
+        ];
+        for &(height, first, second, third) in tests.iter() {
+            let hash = chain.best_hash_at(height).unwrap();
+            assert_eq!(locking_in.state(&chain, &hash), Some(first), "height {}", height);
+            assert_eq!(failing.state(&chain, &hash), Some(second), "height {}", height);
+            assert_eq!(by_height.state(&chain, &hash), Some(third), "height {}", height);
+            // The state is the same for all blocks of a period.
+            let hash = chain.best_hash_at(height + 1).unwrap();
+            assert_eq!(locking_in.state(&chain, &hash), Some(first), "height {}", height);
+        }
+        let always = Deployment { schedule: Schedule::AlwaysActive, ..by_height };
+        let never = Deployment { schedule: Schedule::NeverActive, ..by_height };
+        let genesis = chain.best_hash_at(0).unwrap();
+        assert_eq!(always.state(&chain, &genesis), Some(Active));
+        assert_eq!(never.state(&chain, &genesis), Some(Failed));
+        assert_eq!(always.state(&chain, &BlockHash::all_zeros()), None);
+
+        let hash = chain.best_hash_at(35).unwrap();
+        let stats = locking_in.statistics(&chain, &hash).unwrap();
+        let want = Statistics { period: 10, threshold: 8, elapsed: 6, count: 6, possible: true };
+        assert_eq!(stats, want);
+        let hash = chain.best_hash_at(38).unwrap();
+        let stats = locking_in.statistics(&chain, &hash).unwrap();
+        assert_eq!((stats.elapsed, stats.count, stats.possible), (9, 7, true));
+        let stats = failing.statistics(&chain, &hash).unwrap();
+        assert_eq!((stats.elapsed, stats.count, stats.possible), (9, 0, false));
+        let hash = chain.best_hash_at(39).unwrap();
+        let stats = locking_in.statistics(&chain, &hash).unwrap();
+        assert_eq!((stats.elapsed, stats.count, stats.possible), (10, 7, false));
+        let hash = chain.best_hash_at(40).unwrap();
+        let stats = locking_in.statistics(&chain, &hash).unwrap();
+        assert_eq!((stats.elapsed, stats.count, stats.possible), (1, 1, true));
+    }
+}
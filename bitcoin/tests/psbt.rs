@@ -371,6 +371,10 @@ fn finalize(psbt: Psbt) -> Psbt {
     let expected_psbt_hex = include_str!("data/finalize_psbt_hex");
     let expected_psbt = hex_psbt!(expected_psbt_hex).unwrap();
 
+    let mut finalized = psbt.clone();
+    finalized.finalize_mut().expect("failed to finalize PSBT");
+    assert_eq!(finalized, expected_psbt);
+
     let psbt = finalize_psbt(psbt);
 
     assert_eq!(psbt, expected_psbt);